@@ -19,6 +19,8 @@ pub enum AllocatorTy {
     Layout,
     Ptr,
     ResultPtr,
+    /// An `Option<Layout>`, returned as its size, or 0 for `None`.
+    ResultSize,
     Unit,
     Usize,
 }
@@ -50,4 +52,9 @@ pub struct AllocatorMethod {
         inputs: &[AllocatorTy::Layout],
         output: AllocatorTy::ResultPtr,
     },
+    AllocatorMethod {
+        name: sym::allocation_layout,
+        inputs: &[AllocatorTy::Ptr],
+        output: AllocatorTy::ResultSize,
+    },
 ];
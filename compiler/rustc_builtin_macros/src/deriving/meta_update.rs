@@ -0,0 +1,402 @@
+//! `#[derive(MetaUpdate)]`, for containers and smart pointers outside of the standard library.
+//!
+//! ```ignore (illustrative)
+//! #[derive(MetaUpdate)]
+//! struct Arena<T> {
+//!     buf: *mut T,
+//!     cap: usize,
+//!     #[metadata(len, bound = "self.cap")]
+//!     len: usize,
+//! }
+//! ```
+//!
+//! expands to a field enum and the impls of `MetadataField` and `MetaUpdate`:
+//!
+//! ```ignore (illustrative)
+//! #[derive(Clone, Copy)]
+//! enum ArenaMetadataField { Len }
+//!
+//! impl MetadataField for ArenaMetadataField {
+//!     const FIELDS: &'static [Self] = &[Self::Len];
+//!     fn name(self) -> &'static str { match self { Self::Len => "len" } }
+//! }
+//!
+//! impl<T> MetaUpdate for Arena<T> {
+//!     type Field = ArenaMetadataField;
+//!     fn synchronize(&self, field: ArenaMetadataField, old: usize, new: usize) -> bool {
+//!         match field { ArenaMetadataField::Len => new <= (self.cap) }
+//!     }
+//! }
+//! ```
+//!
+//! The field enum is hygienic, so it can only be named as `<Arena<T> as MetaUpdate>::Field`.
+//! Fields annotated with `#[metadata]` are protected like `#[rustc_protected_metadata]` fields,
+//! see `protected_metadata_fields`, so writes to them are synchronized and wrapped in the
+//! protection window.
+
+use crate::deriving::generic::ty::*;
+use crate::deriving::generic::*;
+use crate::deriving::{bounds, clone, path_local, path_std};
+
+use rustc_ast::mut_visit::MutVisitor;
+use rustc_ast::ptr::P;
+use rustc_ast::token;
+use rustc_ast::{self as ast, AttrVec, BinOpKind, EnumDef, ItemKind, MetaItem, VariantData};
+use rustc_expand::base::{Annotatable, DummyResult, ExtCtxt};
+use rustc_span::source_map::respan;
+use rustc_span::symbol::{kw, sym, Ident, Symbol};
+use rustc_span::{FileName, Span};
+use thin_vec::thin_vec;
+
+/// What the `synchronize` of the derived impl accepts for a field.
+enum Check {
+    /// `#[metadata]`: any update, the field is only protected.
+    Any,
+    /// `#[metadata(len)]` or `#[metadata(len, bound = "..")]`: a length, which may not exceed
+    /// the bound, if any.
+    Len(Option<P<ast::Expr>>),
+    /// `#[metadata(count)]`: a counter, which may only be incremented or decremented by one,
+    /// without wrapping around.
+    Count,
+}
+
+/// A field annotated with `#[metadata]`.
+struct MetadataFieldDef {
+    name: Symbol,
+    variant: Ident,
+    check: Check,
+}
+
+pub fn expand_deriving_meta_update(
+    cx: &mut ExtCtxt<'_>,
+    span: Span,
+    mitem: &MetaItem,
+    item: &Annotatable,
+    push: &mut dyn FnMut(Annotatable),
+) {
+    let Annotatable::Item(struct_item) = item else {
+        cx.span_err(span, "`#[derive(MetaUpdate)]` can only be used on structs");
+        return;
+    };
+    let ItemKind::Struct(VariantData::Struct(fields, _), _) = &struct_item.kind else {
+        cx.span_err(span, "`#[derive(MetaUpdate)]` can only be used on structs with named fields");
+        return;
+    };
+    // Every field is looked at, so that all malformed attributes are reported.
+    let fields: Vec<_> =
+        fields.iter().filter_map(|field| metadata_field(cx, span, field)).collect();
+    let Ok(fields) = fields.into_iter().collect::<Result<Vec<_>, ()>>() else {
+        return;
+    };
+    if fields.is_empty() {
+        cx.span_err(span, "`#[derive(MetaUpdate)]` needs a field annotated with `#[metadata]`");
+        return;
+    }
+
+    let enum_name = Symbol::intern(&format!("{}MetadataField", struct_item.ident));
+    let enum_ident = Ident::new(enum_name, span);
+    push_field_enum(cx, span, mitem, struct_item, enum_ident, &fields, push);
+
+    let usize_ty = || Path(path_local!(usize));
+    let trait_def = TraitDef {
+        span,
+        path: path_std!(ptr::metadata_update::MetaUpdate),
+        // The metadata of a container does not depend on what it contains.
+        skip_path_as_bound: true,
+        additional_bounds: Vec::new(),
+        generics: Bounds::empty(),
+        supports_unions: false,
+        methods: vec![MethodDef {
+            name: sym::synchronize,
+            generics: Bounds::empty(),
+            explicit_self: true,
+            nonself_args: vec![
+                (Path(Path::new_local(enum_name)), sym::field),
+                (usize_ty(), sym::old),
+                (usize_ty(), sym::new),
+            ],
+            ret_ty: Path(path_local!(bool)),
+            attributes: AttrVec::new(),
+            unify_fieldless_variants: false,
+            combine_substructure: combine_substructure(Box::new(|cx, span, substr| {
+                synchronize_substructure(cx, span, substr, enum_ident, &fields)
+            })),
+        }],
+        associated_types: vec![(Ident::new(sym::Field, span), Path(Path::new_local(enum_name)))],
+    };
+    trait_def.expand(cx, mitem, item, push);
+}
+
+/// Reads the `#[metadata]` attribute of `field`, if it has one.
+fn metadata_field(
+    cx: &ExtCtxt<'_>,
+    span: Span,
+    field: &ast::FieldDef,
+) -> Option<Result<MetadataFieldDef, ()>> {
+    let attr = cx.sess.find_by_name(&field.attrs, sym::metadata)?;
+    // Named fields always have an ident.
+    let name = field.ident?.name;
+    let variant = Ident::new(Symbol::intern(&camel_case(name.as_str())), span);
+    if attr.is_word() {
+        return Some(Ok(MetadataFieldDef { name, variant, check: Check::Any }));
+    }
+    let Some(items) = attr.meta_item_list() else {
+        cx.span_err(attr.span, "malformed `#[metadata]` attribute");
+        return Some(Err(()));
+    };
+    let mut kind = None;
+    let mut bound = None;
+    for item in &items {
+        match item.name_or_empty() {
+            sym::len | sym::count if item.is_word() && kind.is_none() => {
+                kind = Some(item.name_or_empty())
+            }
+            sym::bound if bound.is_none() => {
+                let Some(source) = item.value_str() else {
+                    cx.span_err(item.span(), "`bound` must be a string holding an expression");
+                    return Some(Err(()));
+                };
+                bound = Some(parse_bound(cx, span, item.span(), source));
+            }
+            _ => {
+                cx.struct_span_err(item.span(), "unexpected `#[metadata]` argument")
+                    .help("expected `len`, `count` or `bound = \"..\"`")
+                    .emit();
+                return Some(Err(()));
+            }
+        }
+    }
+    let check = match kind {
+        Some(sym::len) => Check::Len(bound),
+        Some(sym::count) if bound.is_none() => Check::Count,
+        Some(_) => {
+            cx.span_err(attr.span, "`bound` is only supported for `len` metadata");
+            return Some(Err(()));
+        }
+        None => {
+            cx.span_err(
+                attr.span,
+                "`#[metadata]` needs the kind of the metadata, `len` or `count`",
+            );
+            return Some(Err(()));
+        }
+    };
+    Some(Ok(MetadataFieldDef { name, variant, check }))
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_ascii_uppercase()).into_iter().chain(chars)
+        })
+        .collect()
+}
+
+/// Parses the expression of a `bound = ".."` argument. Its `self` refers to the receiver of the
+/// derived `synchronize`, everything else is resolved where the type is defined.
+fn parse_bound(cx: &ExtCtxt<'_>, span: Span, bound_span: Span, source: Symbol) -> P<ast::Expr> {
+    let mut parser = rustc_parse::new_parser_from_source_str(
+        &cx.sess.parse_sess,
+        FileName::anon_source_code(source.as_str()),
+        source.to_string(),
+    );
+    let mut expr = match parser.parse_expr() {
+        Ok(expr) => expr,
+        Err(err) => {
+            err.emit();
+            return DummyResult::raw_expr(bound_span, true);
+        }
+    };
+    if parser.token != token::Eof {
+        cx.span_err(bound_span, "`bound` must hold a single expression");
+        return DummyResult::raw_expr(bound_span, true);
+    }
+    RespanBound { span: bound_span, self_span: span }.visit_expr(&mut expr);
+    cx.expr(bound_span, ast::ExprKind::Paren(expr))
+}
+
+/// Moves an expression parsed from a string to the span of that string, except for `self`,
+/// which gets the span of the derived code so that it resolves to its receiver.
+struct RespanBound {
+    span: Span,
+    self_span: Span,
+}
+
+impl MutVisitor for RespanBound {
+    fn visit_span(&mut self, span: &mut Span) {
+        *span = self.span;
+    }
+
+    fn visit_ident(&mut self, ident: &mut Ident) {
+        ident.span = if ident.name == kw::SelfLower { self.self_span } else { self.span };
+    }
+}
+
+/// Pushes the field enum, and its `Clone`, `Copy` and `MetadataField` impls.
+fn push_field_enum(
+    cx: &mut ExtCtxt<'_>,
+    span: Span,
+    mitem: &MetaItem,
+    struct_item: &ast::Item,
+    enum_ident: Ident,
+    fields: &[MetadataFieldDef],
+    push: &mut dyn FnMut(Annotatable),
+) {
+    let variants = fields
+        .iter()
+        .map(|field| ast::Variant {
+            attrs: AttrVec::new(),
+            id: ast::DUMMY_NODE_ID,
+            span,
+            vis: ast::Visibility {
+                span: span.shrink_to_lo(),
+                kind: ast::VisibilityKind::Inherited,
+                tokens: None,
+            },
+            ident: field.variant,
+            data: VariantData::Unit(ast::DUMMY_NODE_ID),
+            disr_expr: None,
+            is_placeholder: false,
+        })
+        .collect();
+    let mut enum_item = cx.item(
+        span,
+        enum_ident,
+        AttrVec::new(),
+        ItemKind::Enum(EnumDef { variants }, ast::Generics::default()),
+    );
+    // The field type of a public container is part of its public interface.
+    enum_item.vis = struct_item.vis.clone();
+    let enum_item = Annotatable::Item(enum_item);
+    bounds::expand_deriving_copy(cx, span, mitem, &enum_item, push);
+    clone::expand_deriving_clone(cx, span, mitem, &enum_item, push);
+
+    let self_ty = cx.ty_path(cx.path_ident(span, Ident::new(kw::SelfUpper, span)));
+    let static_ref = |ty| {
+        let lifetime = cx.lifetime_static(span);
+        cx.ty_rptr(span, ty, Some(lifetime), ast::Mutability::Not)
+    };
+    let self_variant = |field: &MetadataFieldDef| {
+        cx.path(span, vec![Ident::new(kw::SelfUpper, span), field.variant])
+    };
+
+    // const FIELDS: &'static [Self] = &[Self::A, Self::B];
+    let fields_ty = static_ref(cx.ty(span, ast::TyKind::Slice(self_ty)));
+    let all_fields =
+        cx.expr_array_ref(span, fields.iter().map(|f| cx.expr_path(self_variant(f))).collect());
+    let fields_const = assoc_item(
+        span,
+        Ident::new(sym::FIELDS, span),
+        AttrVec::new(),
+        ast::AssocItemKind::Const(ast::Defaultness::Final, fields_ty, Some(all_fields)),
+    );
+
+    // fn name(self) -> &'static str { match self { Self::A => "a", Self::B => "b" } }
+    let arms = fields
+        .iter()
+        .map(|field| {
+            let pat = cx.pat_path(span, self_variant(field));
+            cx.arm(span, pat, cx.expr_str(span, field.name))
+        })
+        .collect();
+    let body = cx.expr_match(span, cx.expr_self(span), arms);
+    let self_param = ast::Param::from_self(
+        AttrVec::new(),
+        respan(span, ast::SelfKind::Value(ast::Mutability::Not)),
+        Ident::new(kw::SelfLower, span),
+    );
+    let str_ty = cx.ty_ident(span, Ident::new(sym::str, span));
+    let decl = cx.fn_decl(vec![self_param], ast::FnRetTy::Ty(static_ref(str_ty)));
+    let name_fn = assoc_item(
+        span,
+        Ident::new(sym::name, span),
+        AttrVec::new(),
+        ast::AssocItemKind::Fn(Box::new(ast::Fn {
+            defaultness: ast::Defaultness::Final,
+            generics: ast::Generics::default(),
+            sig: ast::FnSig { header: ast::FnHeader::default(), decl, span },
+            body: Some(cx.block_expr(body)),
+        })),
+    );
+
+    let trait_path =
+        cx.path_global(span, cx.std_path(&[sym::ptr, sym::metadata_update, sym::MetadataField]));
+    let automatically_derived = cx.attribute(cx.meta_word(span, sym::automatically_derived));
+    push(Annotatable::Item(cx.item(
+        span,
+        Ident::empty(),
+        thin_vec![automatically_derived],
+        ItemKind::Impl(Box::new(ast::Impl {
+            unsafety: ast::Unsafe::No,
+            polarity: ast::ImplPolarity::Positive,
+            defaultness: ast::Defaultness::Final,
+            constness: ast::Const::No,
+            generics: ast::Generics::default(),
+            of_trait: Some(cx.trait_ref(trait_path)),
+            self_ty: cx.ty_ident(span, enum_ident),
+            items: vec![fields_const, name_fn],
+        })),
+    )));
+    push(enum_item);
+}
+
+fn assoc_item(
+    span: Span,
+    ident: Ident,
+    attrs: AttrVec,
+    kind: ast::AssocItemKind,
+) -> P<ast::AssocItem> {
+    P(ast::AssocItem {
+        id: ast::DUMMY_NODE_ID,
+        attrs,
+        span,
+        vis: ast::Visibility {
+            span: span.shrink_to_lo(),
+            kind: ast::VisibilityKind::Inherited,
+            tokens: None,
+        },
+        ident,
+        kind,
+        tokens: None,
+    })
+}
+
+fn synchronize_substructure(
+    cx: &mut ExtCtxt<'_>,
+    trait_span: Span,
+    substr: &Substructure<'_>,
+    enum_ident: Ident,
+    fields: &[MetadataFieldDef],
+) -> BlockOrExpr {
+    let [field, old, new] = substr.nonselflike_args else {
+        cx.span_bug(trait_span, "incorrect number of arguments in `derive(MetaUpdate)`");
+    };
+    let span = trait_span;
+    let arms = fields
+        .iter()
+        .map(|field| {
+            let check = match &field.check {
+                Check::Any | Check::Len(None) => cx.expr_bool(span, true),
+                // new <= (bound)
+                Check::Len(Some(bound)) => {
+                    cx.expr_binary(span, BinOpKind::Le, new.clone(), bound.clone())
+                }
+                // new > old && new - old == 1 || new < old && old - new == 1
+                Check::Count => {
+                    let step = |from: &P<ast::Expr>, to: &P<ast::Expr>| {
+                        let grows = cx.expr_binary(span, BinOpKind::Gt, to.clone(), from.clone());
+                        let diff = cx.expr_binary(span, BinOpKind::Sub, to.clone(), from.clone());
+                        let by_one =
+                            cx.expr_binary(span, BinOpKind::Eq, diff, cx.expr_usize(span, 1));
+                        cx.expr_binary(span, BinOpKind::And, grows, by_one)
+                    };
+                    cx.expr_binary(span, BinOpKind::Or, step(old, new), step(new, old))
+                }
+            };
+            let pat = cx.pat_path(span, cx.path(span, vec![enum_ident, field.variant]));
+            cx.arm(span, pat, check)
+        })
+        .collect();
+    BlockOrExpr::new_expr(cx.expr_match(span, field.clone(), arms))
+}
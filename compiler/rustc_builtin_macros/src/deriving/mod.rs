@@ -26,6 +26,7 @@
 pub mod default;
 pub mod encodable;
 pub mod hash;
+pub mod meta_update;
 
 #[path = "cmp/eq.rs"]
 pub mod eq;
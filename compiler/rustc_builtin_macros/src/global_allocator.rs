@@ -157,7 +157,7 @@ fn arg_ty(
                 self.cx.expr_ident(self.span, ident)
             }
 
-            AllocatorTy::ResultPtr | AllocatorTy::Unit => {
+            AllocatorTy::ResultPtr | AllocatorTy::ResultSize | AllocatorTy::Unit => {
                 panic!("can't convert AllocatorTy to an argument")
             }
         }
@@ -174,6 +174,19 @@ fn ret_ty(&self, ty: &AllocatorTy, expr: P<Expr>) -> (P<Ty>, P<Expr>) {
                 (self.ptr_u8(), expr)
             }
 
+            AllocatorTy::ResultSize => {
+                // We're creating:
+                //
+                //      Option::map_or(#expr, 0, Layout::size)
+
+                let map_or = self.cx.std_path(&[sym::option, sym::Option, sym::map_or]);
+                let map_or = self.cx.expr_path(self.cx.path(self.span, map_or));
+                let size = self.cx.std_path(&[sym::alloc, sym::Layout, sym::size]);
+                let size = self.cx.expr_path(self.cx.path(self.span, size));
+                let zero = self.cx.expr_usize(self.span, 0);
+                (self.usize(), self.cx.expr_call(self.span, map_or, vec![expr, zero, size]))
+            }
+
             AllocatorTy::Unit => (self.cx.ty(self.span, TyKind::Tup(Vec::new())), expr),
 
             AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
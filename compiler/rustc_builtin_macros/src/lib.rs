@@ -112,6 +112,7 @@ pub fn register_builtin_macros(resolver: &mut dyn ResolverExpand) {
         Default: default::expand_deriving_default,
         Eq: eq::expand_deriving_eq,
         Hash: hash::expand_deriving_hash,
+        MetaUpdate: meta_update::expand_deriving_meta_update,
         Ord: ord::expand_deriving_ord,
         PartialEq: partial_eq::expand_deriving_partial_eq,
         PartialOrd: partial_ord::expand_deriving_partial_ord,
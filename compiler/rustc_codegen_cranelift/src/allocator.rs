@@ -53,11 +53,14 @@ fn codegen_inner(
                 AllocatorTy::Ptr => arg_tys.push(usize_ty),
                 AllocatorTy::Usize => arg_tys.push(usize_ty),
 
-                AllocatorTy::ResultPtr | AllocatorTy::Unit => panic!("invalid allocator arg"),
+                AllocatorTy::ResultPtr | AllocatorTy::ResultSize | AllocatorTy::Unit => {
+                    panic!("invalid allocator arg")
+                }
             }
         }
         let output = match method.output {
             AllocatorTy::ResultPtr => Some(usize_ty),
+            AllocatorTy::ResultSize => Some(usize_ty),
             AllocatorTy::Unit => None,
 
             AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
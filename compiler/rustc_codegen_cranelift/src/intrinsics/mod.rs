@@ -779,6 +779,7 @@ fn swap(bcx: &mut FunctionBuilder<'_>, v: Value) -> Value {
         sym::pref_align_of
         | sym::needs_drop
         | sym::type_id
+        | sym::type_family_id
         | sym::type_name
         | sym::variant_count => {
             intrinsic_args!(fx, args => (); intrinsic);
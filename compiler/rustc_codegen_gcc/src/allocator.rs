@@ -31,11 +31,14 @@ pub(crate) unsafe fn codegen(tcx: TyCtxt<'_>, mods: &mut GccContext, _module_nam
                 AllocatorTy::Ptr => types.push(i8p),
                 AllocatorTy::Usize => types.push(usize),
 
-                AllocatorTy::ResultPtr | AllocatorTy::Unit => panic!("invalid allocator arg"),
+                AllocatorTy::ResultPtr | AllocatorTy::ResultSize | AllocatorTy::Unit => {
+                    panic!("invalid allocator arg")
+                }
             }
         }
         let output = match method.output {
             AllocatorTy::ResultPtr => Some(i8p),
+            AllocatorTy::ResultSize => Some(usize),
             AllocatorTy::Unit => None,
 
             AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
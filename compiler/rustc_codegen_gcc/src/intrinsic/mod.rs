@@ -371,6 +371,33 @@ fn type_checked_load(
         self.context.new_rvalue_from_int(self.int_type, 0)
     }
 
+    fn metadata_sanitizer_load(
+        &mut self,
+        _ptr: RValue<'gcc>,
+        _value: RValue<'gcc>,
+        _type_name: &str,
+        _field: &str,
+    ) {
+        // Unsupported.
+    }
+
+    fn metadata_sanitizer_store(&mut self, _ptr: RValue<'gcc>, _value: RValue<'gcc>) {
+        // Unsupported.
+    }
+
+    fn metadata_sanitizer_forget(&mut self, _ptr: RValue<'gcc>, _size: RValue<'gcc>) {
+        // Unsupported.
+    }
+
+    fn metadata_sanitizer_copy(
+        &mut self,
+        _dst: RValue<'gcc>,
+        _src: RValue<'gcc>,
+        _size: RValue<'gcc>,
+    ) {
+        // Unsupported.
+    }
+
     fn va_start(&mut self, _va_list: RValue<'gcc>) -> RValue<'gcc> {
         unimplemented!();
     }
@@ -5,6 +5,7 @@
 use rustc_middle::ty::TyCtxt;
 use rustc_session::config::{DebugInfo, OomStrategy};
 use rustc_span::symbol::sym;
+use rustc_target::spec::SanitizerSet;
 
 use crate::debuginfo;
 use crate::llvm::{self, False, True};
@@ -28,6 +29,7 @@ pub(crate) unsafe fn codegen(
     let i8 = llvm::LLVMInt8TypeInContext(llcx);
     let i8p = llvm::LLVMPointerType(i8, 0);
     let void = llvm::LLVMVoidTypeInContext(llcx);
+    let sanitize_metadata = tcx.sess.opts.unstable_opts.sanitizer.contains(SanitizerSet::METADATA);
 
     for method in ALLOCATOR_METHODS {
         let mut args = Vec::with_capacity(method.inputs.len());
@@ -40,11 +42,14 @@ pub(crate) unsafe fn codegen(
                 AllocatorTy::Ptr => args.push(i8p),
                 AllocatorTy::Usize => args.push(usize),
 
-                AllocatorTy::ResultPtr | AllocatorTy::Unit => panic!("invalid allocator arg"),
+                AllocatorTy::ResultPtr | AllocatorTy::ResultSize | AllocatorTy::Unit => {
+                    panic!("invalid allocator arg")
+                }
             }
         }
         let output = match method.output {
             AllocatorTy::ResultPtr => Some(i8p),
+            AllocatorTy::ResultSize => Some(usize),
             AllocatorTy::Unit => None,
 
             AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
@@ -82,6 +87,34 @@ pub(crate) unsafe fn codegen(
             .enumerate()
             .map(|(i, _)| llvm::LLVMGetParam(llfn, i as c_uint))
             .collect::<Vec<_>>();
+        if sanitize_metadata && (method.name == sym::dealloc || method.name == sym::realloc) {
+            // The block is released or moved, so the shadow copies of the protected fields it
+            // held are dropped before the memory can be handed out again.
+            let forget_args = [usize, usize];
+            let forget_ty = llvm::LLVMFunctionType(
+                void,
+                forget_args.as_ptr(),
+                forget_args.len() as c_uint,
+                False,
+            );
+            let name = "__rust_metadata_sanitizer_forget";
+            let forget = llvm::LLVMRustGetOrInsertFunction(
+                llmod,
+                name.as_ptr().cast(),
+                name.len(),
+                forget_ty,
+            );
+            let addr = llvm::LLVMBuildPtrToInt(llbuilder, args[0], usize, "\0".as_ptr().cast());
+            let forget_args = [addr, args[1]];
+            llvm::LLVMRustBuildCall(
+                llbuilder,
+                forget_ty,
+                forget,
+                forget_args.as_ptr(),
+                forget_args.len() as c_uint,
+                None,
+            );
+        }
         let ret = llvm::LLVMRustBuildCall(
             llbuilder,
             ty,
@@ -418,6 +418,31 @@ fn type_checked_load(
         self.call_intrinsic("llvm.type.checked.load", &[llvtable, vtable_byte_offset, typeid])
     }
 
+    fn metadata_sanitizer_load(
+        &mut self,
+        ptr: &'ll Value,
+        value: &'ll Value,
+        type_name: &str,
+        field: &str,
+    ) {
+        let (type_name, type_len) = self.const_str(type_name);
+        let (field, field_len) = self.const_str(field);
+        let args = [ptr, value, type_name, type_len, field, field_len];
+        self.call_metadata_sanitizer("__rust_metadata_sanitizer_load", &args);
+    }
+
+    fn metadata_sanitizer_store(&mut self, ptr: &'ll Value, value: &'ll Value) {
+        self.call_metadata_sanitizer("__rust_metadata_sanitizer_store", &[ptr, value]);
+    }
+
+    fn metadata_sanitizer_forget(&mut self, ptr: &'ll Value, size: &'ll Value) {
+        self.call_metadata_sanitizer("__rust_metadata_sanitizer_forget", &[ptr, size]);
+    }
+
+    fn metadata_sanitizer_copy(&mut self, dst: &'ll Value, src: &'ll Value, size: &'ll Value) {
+        self.call_metadata_sanitizer("__rust_metadata_sanitizer_copy", &[dst, src, size]);
+    }
+
     fn va_start(&mut self, va_list: &'ll Value) -> &'ll Value {
         self.call_intrinsic("llvm.va_start", &[va_list])
     }
@@ -427,6 +452,26 @@ fn va_end(&mut self, va_list: &'ll Value) -> &'ll Value {
     }
 }
 
+impl<'ll> Builder<'_, 'll, '_> {
+    /// Calls the metadata sanitizer runtime function `name`, which takes all its arguments,
+    /// pointers included, as `usize`.
+    fn call_metadata_sanitizer(&mut self, name: &str, args: &[&'ll Value]) {
+        let isize_ty = self.type_isize();
+        let args: Vec<_> = args
+            .iter()
+            .map(|&arg| match self.type_kind(self.val_ty(arg)) {
+                TypeKind::Pointer => self.ptrtoint(arg, isize_ty),
+                _ => arg,
+            })
+            .collect();
+        let fn_ty = self.type_func(&vec![isize_ty; args.len()], self.type_void());
+        let llfn = self
+            .get_declared_value(name)
+            .unwrap_or_else(|| self.declare_cfn(name, llvm::UnnamedAddr::No, fn_ty));
+        self.call(fn_ty, None, llfn, &args, None);
+    }
+}
+
 fn try_intrinsic<'ll>(
     bx: &mut Builder<'_, 'll, '_>,
     try_func: &'ll Value,
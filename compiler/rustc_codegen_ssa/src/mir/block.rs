@@ -895,6 +895,7 @@ fn codegen_call_terminator(
         let mut copied_constant_arguments = vec![];
         'make_args: for (i, arg) in first_args.iter().enumerate() {
             let mut op = self.codegen_operand(&mut bx, arg);
+            self.sanitize_metadata_call_arg(&mut bx, instance, op);
 
             if let (0, Some(ty::InstanceDef::Virtual(_, idx))) = (i, def) {
                 match op.val {
@@ -1731,7 +1732,11 @@ fn store_return(
 
         match dest {
             Nothing => (),
-            Store(dst) => bx.store_arg(&ret_abi, llval, dst),
+            Store(dst) => {
+                bx.store_arg(&ret_abi, llval, dst);
+                // Call destinations are locals or temporaries, never fields.
+                self.sanitize_metadata_store(bx, None, dst);
+            }
             IndirectOperand(tmp, index) => {
                 let op = bx.load_operand(tmp);
                 tmp.storage_dead(bx);
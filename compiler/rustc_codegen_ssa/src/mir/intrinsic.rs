@@ -1,3 +1,4 @@
+use super::metadata_sanitizer::sanitize_metadata_copy;
 use super::operand::{OperandRef, OperandValue};
 use super::place::PlaceRef;
 use super::FunctionCx;
@@ -33,6 +34,7 @@ fn copy_intrinsic<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
     } else {
         bx.memcpy(dst, align, src, align, size, flags);
     }
+    sanitize_metadata_copy(bx, ty, dst, src, count);
 }
 
 fn memset_intrinsic<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
@@ -119,6 +121,7 @@ pub fn codegen_intrinsic_call(
             sym::pref_align_of
             | sym::needs_drop
             | sym::type_id
+            | sym::type_family_id
             | sym::type_name
             | sym::variant_count => {
                 let value = bx
@@ -0,0 +1,242 @@
+//! Instrumentation of protected metadata for `-Zsanitizer=metadata`.
+//!
+//! The `#[rustc_protected_metadata]` fields of a type, like the length of a `Vec`, may only be
+//! written by the methods of that type. Every typed store of such a field, i.e. an assignment to
+//! the field itself or to a value that contains it, is reported to the sanitizer runtime in `std`
+//! together with the stored value, which the runtime keeps in a shadow table. Every read of the
+//! field is reported together with the loaded value, and the runtime reports a violation if the
+//! two differ: the field was overwritten through a raw pointer, or by an overflow out of a
+//! neighbouring buffer.
+//!
+//! Locals that die are dropped from the shadow table, and `copy` and `copy_nonoverlapping` of
+//! values with protected fields move their shadow entries along, so that memory is not checked
+//! against what it held before it was reused. The allocator shim drops the entries of the blocks
+//! it deallocates or reallocates for the same reason. `#[no_sanitize(metadata)]` only turns off
+//! the checks of the loads: the stores, deaths and copies of a function keep the shadow table in
+//! sync for the functions that do check.
+//!
+//! Code that is not instrumented, like the non-generic functions of `std`, writes protected
+//! fields without reporting it, e.g. when reading into a `Vec<u8>`. Calls to such functions are
+//! treated as writes of everything behind their mutable pointer arguments: the protected fields
+//! there are dropped from the shadow table, and only checked again once instrumented code stored
+//! them.
+
+use super::operand::{OperandRef, OperandValue};
+use super::place::PlaceRef;
+use super::{FunctionCx, LocalRef};
+use crate::common::TypeKind;
+use crate::traits::*;
+
+use rustc_middle::mir;
+use rustc_middle::ty::layout::{HasTyCtxt, LayoutOf, TyAndLayout};
+use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::Symbol;
+use rustc_target::abi::Size;
+use rustc_target::spec::SanitizerSet;
+
+/// Whether the metadata sanitizer is enabled.
+fn enabled<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(bx: &Bx) -> bool {
+    bx.tcx().sess.opts.unstable_opts.sanitizer.contains(SanitizerSet::METADATA)
+}
+
+/// Pushes the offsets of the protected fields in a value of `layout` at `base`, following the
+/// fields of structs and unions, but not enum variants or pointers.
+fn protected_offsets<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
+    bx: &Bx,
+    layout: TyAndLayout<'tcx>,
+    base: Size,
+    offsets: &mut Vec<Size>,
+) {
+    let ty::Adt(adt, _) = layout.ty.kind() else {
+        return;
+    };
+    if adt.is_enum() {
+        return;
+    }
+    let protected = bx.tcx().protected_metadata_fields(adt.did());
+    for index in 0..layout.fields.count() {
+        let offset = base + layout.fields.offset(index);
+        let field = layout.field(bx.cx(), index);
+        if protected.iter().any(|protected| protected.index() == index) {
+            if field.size == bx.tcx().data_layout.pointer_size {
+                offsets.push(offset);
+            }
+        } else {
+            protected_offsets(bx, field, offset, offsets);
+        }
+    }
+}
+
+/// Whether the body of the callee `instance` is codegened into the crate being compiled, and so
+/// instrumented. Upstream crates, like `std`, are not built with the sanitizer, and the target of
+/// a call through a function pointer or a vtable is unknown.
+fn instrumented<'tcx>(tcx: TyCtxt<'tcx>, instance: Option<ty::Instance<'tcx>>) -> bool {
+    let Some(instance) = instance else {
+        return false;
+    };
+    match instance.def {
+        ty::InstanceDef::Virtual(..) => false,
+        _ if instance.def_id().is_local() => true,
+        ty::InstanceDef::Item(_) if instance.substs.non_erasable_generics().next().is_none() => {
+            tcx.codegen_fn_attrs(instance.def_id()).requests_inline()
+        }
+        _ => instance.upstream_monomorphization(tcx).is_none(),
+    }
+}
+
+/// Moves the shadow entries of `count` values of type `ty` from `src` to `dst`, along with the
+/// copy of the values themselves.
+pub(super) fn sanitize_metadata_copy<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
+    bx: &mut Bx,
+    ty: Ty<'tcx>,
+    dst: Bx::Value,
+    src: Bx::Value,
+    count: Bx::Value,
+) {
+    if !enabled(bx) {
+        return;
+    }
+    let layout = bx.layout_of(ty);
+    let mut offsets = Vec::new();
+    protected_offsets(bx, layout, Size::ZERO, &mut offsets);
+    if !offsets.is_empty() {
+        let size = bx.mul(count, bx.const_usize(layout.size.bytes()));
+        bx.metadata_sanitizer_copy(dst, src, size);
+    }
+}
+
+impl<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>> FunctionCx<'a, 'tcx, Bx> {
+    /// The type owning the field `place_ref` ends with, and the name of the field, if that field
+    /// is protected.
+    fn protected_field(&self, place_ref: mir::PlaceRef<'tcx>) -> Option<(Ty<'tcx>, Symbol)> {
+        let (base, mir::ProjectionElem::Field(field, _)) = place_ref.last_projection()? else {
+            return None;
+        };
+        let owner = self.monomorphized_place_ty(base);
+        let ty::Adt(adt, _) = owner.kind() else {
+            return None;
+        };
+        if !adt.is_struct() || !self.cx.tcx().protected_metadata_fields(adt.did()).contains(&field)
+        {
+            return None;
+        }
+        Some((owner, adt.non_enum_variant().fields[field.index()].name))
+    }
+
+    /// Reports the protected fields stored by an assignment to `place_ref`, which lives at
+    /// `dest`, to the runtime.
+    pub(super) fn sanitize_metadata_store(
+        &self,
+        bx: &mut Bx,
+        place_ref: Option<mir::PlaceRef<'tcx>>,
+        dest: PlaceRef<'tcx, Bx::Value>,
+    ) {
+        if !enabled(bx) || dest.layout.is_unsized() {
+            return;
+        }
+        let mut offsets = Vec::new();
+        if place_ref.map_or(false, |place_ref| self.protected_field(place_ref).is_some()) {
+            if dest.layout.size == bx.tcx().data_layout.pointer_size {
+                offsets.push(Size::ZERO);
+            }
+        } else {
+            protected_offsets(bx, dest.layout, Size::ZERO, &mut offsets);
+        }
+        for offset in offsets {
+            let ptr = bx.pointercast(dest.llval, bx.type_i8p());
+            let ptr = bx.inbounds_gep(bx.type_i8(), ptr, &[bx.const_usize(offset.bytes())]);
+            let isize_ptr = bx.pointercast(ptr, bx.type_ptr_to(bx.type_isize()));
+            let value = bx.load(bx.type_isize(), isize_ptr, dest.align.restrict_for_offset(offset));
+            bx.metadata_sanitizer_store(ptr, value);
+        }
+    }
+
+    /// Reports the read of `value` out of the place `place_ref`, which lives at `src`, to the
+    /// runtime, if that place is a protected field.
+    pub(super) fn sanitize_metadata_load(
+        &self,
+        bx: &mut Bx,
+        place_ref: mir::PlaceRef<'tcx>,
+        src: PlaceRef<'tcx, Bx::Value>,
+        value: Bx::Value,
+    ) {
+        if !enabled(bx)
+            || bx
+                .tcx()
+                .codegen_fn_attrs(self.instance.def_id())
+                .no_sanitize
+                .contains(SanitizerSet::METADATA)
+        {
+            return;
+        }
+        let Some((owner, field)) = self.protected_field(place_ref) else {
+            return;
+        };
+        let value = match bx.type_kind(bx.val_ty(value)) {
+            TypeKind::Pointer => bx.ptrtoint(value, bx.type_isize()),
+            TypeKind::Integer => bx.intcast(value, bx.type_isize(), false),
+            _ => return,
+        };
+        let owner = with_no_trimmed_paths!(owner.to_string());
+        bx.metadata_sanitizer_load(src.llval, value, &owner, field.as_str());
+    }
+
+    /// Drops the protected fields of `local`, which is about to die, from the shadow table.
+    pub(super) fn sanitize_metadata_storage_dead(&self, bx: &mut Bx, local: mir::Local) {
+        if !enabled(bx) {
+            return;
+        }
+        let LocalRef::Place(place) = self.locals[local] else {
+            return;
+        };
+        let mut offsets = Vec::new();
+        protected_offsets(bx, place.layout, Size::ZERO, &mut offsets);
+        if !offsets.is_empty() {
+            let size = bx.const_usize(place.layout.size.bytes());
+            bx.metadata_sanitizer_forget(place.llval, size);
+        }
+    }
+
+    /// Drops the protected fields behind `arg` from the shadow table if it is a mutable pointer
+    /// passed to the callee `instance`, and that callee is not instrumented.
+    pub(super) fn sanitize_metadata_call_arg(
+        &self,
+        bx: &mut Bx,
+        instance: Option<ty::Instance<'tcx>>,
+        arg: OperandRef<'tcx, Bx::Value>,
+    ) {
+        if !enabled(bx) {
+            return;
+        }
+        let Some(pointee) = arg.layout.ty.builtin_deref(true) else {
+            return;
+        };
+        let OperandValue::Immediate(ptr) = arg.val else {
+            return;
+        };
+        let layout = bx.layout_of(pointee.ty);
+        if pointee.mutbl == mir::Mutability::Not
+            || layout.is_unsized()
+            || instrumented(bx.tcx(), instance)
+        {
+            return;
+        }
+        let mut offsets = Vec::new();
+        protected_offsets(bx, layout, Size::ZERO, &mut offsets);
+        if !offsets.is_empty() {
+            let size = bx.const_usize(layout.size.bytes());
+            bx.metadata_sanitizer_forget(ptr, size);
+        }
+    }
+
+    /// Reports the protected fields of the arguments that live in memory, which the caller
+    /// stored without an assignment the callee sees.
+    pub(super) fn sanitize_metadata_args(&self, bx: &mut Bx) {
+        for local in self.mir.args_iter() {
+            if let LocalRef::Place(place) = self.locals[local] {
+                self.sanitize_metadata_store(bx, None, place);
+            }
+        }
+    }
+}
@@ -242,6 +242,8 @@ pub fn codegen_mir<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
     // Apply debuginfo to the newly allocated locals.
     fx.debug_introduce_locals(&mut start_bx);
 
+    fx.sanitize_metadata_args(&mut start_bx);
+
     // Codegen the body of each block using reverse postorder
     for (bb, _) in traversal::reverse_postorder(&mir) {
         fx.codegen_block(bb);
@@ -404,6 +406,7 @@ fn arg_local_refs<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
 pub mod coverageinfo;
 pub mod debuginfo;
 mod intrinsic;
+mod metadata_sanitizer;
 pub mod operand;
 pub mod place;
 mod rvalue;
@@ -428,7 +428,11 @@ pub fn codegen_consume(
         // for most places, to consume them we just load them
         // out from their home
         let place = self.codegen_place(bx, place_ref);
-        bx.load_operand(place)
+        let operand = bx.load_operand(place);
+        if let OperandValue::Immediate(value) = operand.val {
+            self.sanitize_metadata_load(bx, place_ref, place, value);
+        }
+        operand
     }
 
     pub fn codegen_operand(
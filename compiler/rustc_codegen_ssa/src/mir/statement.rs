@@ -1,6 +1,8 @@
 use rustc_middle::mir;
 use rustc_middle::mir::NonDivergingIntrinsic;
+use rustc_middle::ty;
 
+use super::metadata_sanitizer::sanitize_metadata_copy;
 use super::FunctionCx;
 use super::LocalRef;
 use crate::traits::BuilderMethods;
@@ -14,7 +16,11 @@ pub fn codegen_statement(&mut self, mut bx: Bx, statement: &mir::Statement<'tcx>
             mir::StatementKind::Assign(box (ref place, ref rvalue)) => {
                 if let Some(index) = place.as_local() {
                     match self.locals[index] {
-                        LocalRef::Place(cg_dest) => self.codegen_rvalue(bx, cg_dest, rvalue),
+                        LocalRef::Place(cg_dest) => {
+                            let mut bx = self.codegen_rvalue(bx, cg_dest, rvalue);
+                            self.sanitize_metadata_store(&mut bx, Some(place.as_ref()), cg_dest);
+                            bx
+                        }
                         LocalRef::UnsizedPlace(cg_indirect_dest) => {
                             self.codegen_rvalue_unsized(bx, cg_indirect_dest, rvalue)
                         }
@@ -40,7 +46,9 @@ pub fn codegen_statement(&mut self, mut bx: Bx, statement: &mir::Statement<'tcx>
                     }
                 } else {
                     let cg_dest = self.codegen_place(&mut bx, place.as_ref());
-                    self.codegen_rvalue(bx, cg_dest, rvalue)
+                    let mut bx = self.codegen_rvalue(bx, cg_dest, rvalue);
+                    self.sanitize_metadata_store(&mut bx, Some(place.as_ref()), cg_dest);
+                    bx
                 }
             }
             mir::StatementKind::SetDiscriminant { box ref place, variant_index } => {
@@ -63,6 +71,7 @@ pub fn codegen_statement(&mut self, mut bx: Bx, statement: &mir::Statement<'tcx>
                 bx
             }
             mir::StatementKind::StorageDead(local) => {
+                self.sanitize_metadata_storage_dead(&mut bx, local);
                 if let LocalRef::Place(cg_place) = self.locals[local] {
                     cg_place.storage_dead(&mut bx);
                 } else if let LocalRef::UnsizedPlace(cg_indirect_place) = self.locals[local] {
@@ -95,6 +104,9 @@ pub fn codegen_statement(&mut self, mut bx: Bx, statement: &mir::Statement<'tcx>
                 let dst = dst_val.immediate();
                 let src = src_val.immediate();
                 bx.memcpy(dst, align, src, align, bytes, crate::MemFlags::empty());
+                if let ty::RawPtr(pointee) = dst_val.layout.ty.kind() {
+                    sanitize_metadata_copy(&mut bx, pointee.ty, dst, src, count);
+                }
                 bx
             }
             mir::StatementKind::FakeRead(..)
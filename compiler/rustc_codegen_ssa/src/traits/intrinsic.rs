@@ -30,6 +30,24 @@ fn type_checked_load(
         vtable_byte_offset: u64,
         typeid: Self::Value,
     ) -> Self::Value;
+    /// Trait method used to report a load of `value` out of the protected metadata at `ptr`,
+    /// the field `field` of a `type_name`, to the metadata sanitizer runtime.
+    fn metadata_sanitizer_load(
+        &mut self,
+        ptr: Self::Value,
+        value: Self::Value,
+        type_name: &str,
+        field: &str,
+    );
+    /// Trait method used to report a store of `value` to the protected metadata at `ptr` to the
+    /// metadata sanitizer runtime.
+    fn metadata_sanitizer_store(&mut self, ptr: Self::Value, value: Self::Value);
+    /// Trait method used to tell the metadata sanitizer runtime that the `size` bytes at `ptr`
+    /// no longer hold protected metadata.
+    fn metadata_sanitizer_forget(&mut self, ptr: Self::Value, size: Self::Value);
+    /// Trait method used to tell the metadata sanitizer runtime that the `size` bytes at `src`
+    /// were copied to `dst`.
+    fn metadata_sanitizer_copy(&mut self, dst: Self::Value, src: Self::Value, size: Self::Value);
     /// Trait method used to inject `va_start` on the "spoofed" `VaListImpl` in
     /// Rust defined C-variadic functions.
     fn va_start(&mut self, val: Self::Value) -> Self::Value;
@@ -79,6 +79,10 @@ pub(crate) fn eval_nullary_intrinsic<'tcx>(
             ensure_monomorphic_enough(tcx, tp_ty)?;
             ConstValue::from_u64(tcx.type_id_hash(tp_ty))
         }
+        sym::type_family_id => {
+            ensure_monomorphic_enough(tcx, tp_ty)?;
+            ConstValue::from_u64(tcx.type_family_id_hash(tp_ty))
+        }
         sym::variant_count => match tp_ty.kind() {
             // Correctly handles non-monomorphic calls, so there is no need for ensure_monomorphic_enough.
             ty::Adt(ref adt, _) => {
@@ -167,13 +171,14 @@ pub fn emulate_intrinsic(
             sym::pref_align_of
             | sym::needs_drop
             | sym::type_id
+            | sym::type_family_id
             | sym::type_name
             | sym::variant_count => {
                 let gid = GlobalId { instance, promoted: None };
                 let ty = match intrinsic_name {
                     sym::pref_align_of | sym::variant_count => self.tcx.types.usize,
                     sym::needs_drop => self.tcx.types.bool,
-                    sym::type_id => self.tcx.types.u64,
+                    sym::type_id | sym::type_family_id => self.tcx.types.u64,
                     sym::type_name => self.tcx.mk_static_str(),
                     _ => bug!(),
                 };
@@ -311,7 +311,14 @@ fn visit_projection_elem(
                             fail_out_of_bounds(self, location);
                             return;
                         };
-                        check_equal(self, location, field.ty(self.tcx, substs));
+                        let mut f_ty = field.ty(self.tcx, substs);
+                        // `MetadataCompartment` runs as part of the lowering to runtime MIR, from
+                        // then on fields relocated into the metadata compartment are projected to
+                        // as the `Compartment` holding them.
+                        if self.mir_phase >= MirPhase::Runtime(RuntimePhase::Initial) {
+                            f_ty = self.tcx.field_storage_ty(*adt_def, f, f_ty);
+                        }
+                        check_equal(self, location, f_ty);
                     }
                     ty::Closure(_, substs) => {
                         let substs = substs.as_closure();
@@ -73,6 +73,18 @@ lint_mixed_script_confusables =
     .includes_note = the usage includes {$includes}
     .note = please recheck to make sure their usages are indeed what you want
 
+lint_metadata_repacking_from_raw_parts =
+    `{$ty}` is rebuilt from raw parts that do not all come from the same source
+    .note = the pointer, length and capacity should come from a single `into_raw_parts` call, or from a single `ManuallyDrop` value
+
+lint_metadata_repacking_overwrite =
+    `{$ty}` is written as a whole, which replaces its metadata
+    .note = the previous value is not dropped, and the new one may alias memory owned elsewhere
+
+lint_metadata_repacking_set_len =
+    `set_len` is not preceded by a capacity check
+    .note = the new length must not exceed the capacity; compare it against `capacity()` or `reserve` space first
+
 lint_non_fmt_panic = panic message is not a string literal
     .note = this usage of `{$name}!()` is deprecated; it will be a hard error in Rust 2021
     .more_info_note = for more information, see <https://doc.rust-lang.org/nightly/edition-guide/rust-2021/panic-macro-consistency.html>
@@ -168,6 +168,44 @@ passes_pass_by_value =
     `pass_by_value` attribute should be applied to a struct, enum or type alias
     .label = is not a struct, enum or type alias
 
+passes_protected_metadata =
+    `rustc_protected_metadata` attribute should be applied to a struct field
+    .label = is not a struct field
+
+passes_protected_metadata_without_meta_update =
+    `rustc_protected_metadata` attribute should be applied to a field of a type implementing `MetaUpdate`
+    .label = `{$ty}` does not implement `MetaUpdate`
+
+passes_synchronizes_metadata =
+    `rustc_synchronizes_metadata` attribute should be applied to a function
+    .label = is not a function
+
+passes_protected_metadata_write =
+    cannot write protected metadata field `{$field}` of `{$ty}` here
+    .label = protected metadata written outside of its owning type
+    .note = protected metadata may only be written from inherent and `MetaUpdate` impls of its type
+
+passes_metadata_compartment =
+    `metadata_compartment` attribute should be applied to a struct or to the crate
+    .label = is not a struct
+
+passes_metadata_compartment_option =
+    invalid `metadata_compartment` option
+    .note = structs accept `#[metadata_compartment(inline)]`, the crate accepts no options
+
+passes_metadata_compartment_repr =
+    `metadata_compartment` attribute conflicts with the `repr` of the struct
+    .label = the layout of the struct is fixed here
+    .note = the fields of a struct with an explicit `repr` are never relocated
+
+passes_metadata_compartment_raw_address =
+    cannot take the raw address of field `{$field}` of `{$ty}`
+    .note = the field is in the metadata compartment, and is only reachable through a pointer that is not set before the field is initialized
+
+passes_metadata_compartment_const =
+    cannot construct `{$ty}` in {$kind}s
+    .note = the struct moves some of its fields into the metadata compartment, which is allocated at runtime
+
 passes_allow_incoherent_impl =
     `rustc_allow_incoherent_impl` attribute should be applied to impl items.
     .label = the only currently supported targets are inherent methods
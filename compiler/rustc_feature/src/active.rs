@@ -316,6 +316,8 @@ pub fn set(&self, features: &mut Features, span: Span) {
     (active, c_variadic, "1.34.0", Some(44930), None),
     /// Allows capturing disjoint fields in a closure/generator (RFC 2229).
     (incomplete, capture_disjoint_fields, "1.49.0", Some(53488), None),
+    /// Allows the use of `#[cfg(metadata_protection = "mode")]`; set by -Zmetadata-protection.
+    (active, cfg_metadata_protection, "CURRENT_RUSTC_VERSION", None, None),
     /// Allows the use of `#[cfg(sanitize = "option")]`; set when -Zsanitizer is used.
     (active, cfg_sanitize, "1.41.0", Some(39699), None),
     /// Allows `cfg(target_abi = "...")`.
@@ -439,6 +441,8 @@ pub fn set(&self, features: &mut Features, span: Span) {
     (active, macro_metavar_expr, "1.61.0", Some(83527), None),
     /// Allows `#[marker]` on certain traits allowing overlapping implementations.
     (active, marker_trait_attr, "1.30.0", Some(29864), None),
+    /// Allows relocating the smart pointers embedded in structs with `#[metadata_compartment]`.
+    (active, metadata_compartment, "CURRENT_RUSTC_VERSION", None, None),
     /// A minimal, sound subset of specialization intended to be used by the
     /// standard library until the soundness issues with specialization
     /// are fixed.
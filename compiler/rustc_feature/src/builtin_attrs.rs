@@ -33,6 +33,7 @@ macro_rules! cfg_fn {
     ),
     (sym::target_has_atomic_load_store, sym::cfg_target_has_atomic, cfg_fn!(cfg_target_has_atomic)),
     (sym::sanitize, sym::cfg_sanitize, cfg_fn!(cfg_sanitize)),
+    (sym::metadata_protection, sym::cfg_metadata_protection, cfg_fn!(cfg_metadata_protection)),
     (sym::version, sym::cfg_version, cfg_fn!(cfg_version)),
 ];
 
@@ -453,6 +454,10 @@ pub struct BuiltinAttribute {
         optimize, Normal, template!(List: "size|speed"), ErrorPreceding, optimize_attribute,
         experimental!(optimize),
     ),
+    gated!(
+        metadata_compartment, Normal, template!(Word, List: "inline"), ErrorFollowing,
+        experimental!(metadata_compartment),
+    ),
 
     gated!(
         ffi_returns_twice, Normal, template!(Word), WarnFollowing, experimental!(ffi_returns_twice)
@@ -683,6 +688,17 @@ pub struct BuiltinAttribute {
         template!(Word), ErrorFollowing,
         "#[rustc_pass_by_value] is used to mark types that must be passed by value instead of reference."
     ),
+    rustc_attr!(
+        rustc_protected_metadata, Normal, template!(Word), WarnFollowing,
+        "#[rustc_protected_metadata] marks a field as `MetaUpdate` metadata, which may \
+        only be written by its own type, and whose writes are synchronized and wrapped in the \
+        protection window by the compiler"
+    ),
+    rustc_attr!(
+        rustc_synchronizes_metadata, Normal, template!(Word), WarnFollowing,
+        "#[rustc_synchronizes_metadata] marks a function that synchronizes its writes to \
+        protected metadata itself, so that the compiler only wraps them in the protection window"
+    ),
     rustc_attr!(
         rustc_coherence_is_core, AttributeType::CrateLevel, template!(Word), ErrorFollowing, @only_local: true,
         "#![rustc_coherence_is_core] allows inherent methods on builtin types, only intended to be used in `core`."
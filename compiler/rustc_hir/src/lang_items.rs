@@ -256,6 +256,17 @@ pub fn extract(attrs: &[ast::Attribute]) -> Option<(Symbol, Span)> {
     /// Align offset for stride != 1; must not panic.
     AlignOffset,             sym::align_offset,        align_offset_fn,            Target::Fn,             GenericRequirement::None;
 
+    MetaUpdate,              sym::meta_update,         meta_update_trait,          Target::Trait,          GenericRequirement::None;
+    // Inserted around writes to `#[rustc_protected_metadata]` fields.
+    ProtectedMetadataCheck,  sym::protected_metadata_check, protected_metadata_check_fn, Target::Fn,   GenericRequirement::Exact(1);
+    ProtectedMetadataOpen,   sym::protected_metadata_open,  protected_metadata_open_fn,  Target::Fn,   GenericRequirement::Exact(1);
+    ProtectedMetadataClose,  sym::protected_metadata_close, protected_metadata_close_fn, Target::Fn,   GenericRequirement::Exact(1);
+    // The storage of the fields relocated out of `#[metadata_compartment]` structs, and the
+    // functions inserted where such a field is initialized or moved out of.
+    MetadataCompartment,     sym::metadata_compartment, metadata_compartment,      Target::Struct,         GenericRequirement::Exact(1);
+    CompartmentNew,          sym::compartment_new,     compartment_new_fn,         Target::Fn,             GenericRequirement::Exact(1);
+    CompartmentTake,         sym::compartment_take,    compartment_take_fn,        Target::Fn,             GenericRequirement::Exact(1);
+
     Termination,             sym::termination,         termination,                Target::Trait,          GenericRequirement::None;
 
     Try,                     sym::Try,                 try_trait,                  Target::Trait,          GenericRequirement::None;
@@ -890,6 +890,18 @@ fn analysis(tcx: TyCtxt<'_>, (): ()) -> Result<()> {
                         tcx.ensure().check_liveness(def_id.to_def_id());
                     });
                 });
+            },
+            {
+                sess.time("protected_metadata_checking", || {
+                    tcx.hir()
+                        .par_body_owners(|def_id| tcx.ensure().check_protected_metadata(def_id))
+                });
+            },
+            {
+                sess.time("metadata_compartment_checking", || {
+                    tcx.hir()
+                        .par_body_owners(|def_id| tcx.ensure().check_metadata_compartment(def_id))
+                });
             }
         );
     });
@@ -58,6 +58,7 @@
 mod late;
 mod let_underscore;
 mod levels;
+mod metadata_repacking;
 mod methods;
 mod non_ascii_idents;
 mod non_fmt_panic;
@@ -91,6 +92,7 @@
 use hidden_unicode_codepoints::*;
 use internal::*;
 use let_underscore::*;
+use metadata_repacking::*;
 use methods::*;
 use non_ascii_idents::*;
 use non_fmt_panic::NonPanicFmt;
@@ -229,6 +231,7 @@ macro_rules! late_lint_mod_passes {
                 InvalidAtomicOrdering: InvalidAtomicOrdering,
                 NamedAsmLabels: NamedAsmLabels,
                 OpaqueHiddenInferredBound: OpaqueHiddenInferredBound,
+                MetadataRepacking: MetadataRepacking,
             ]
         );
     };
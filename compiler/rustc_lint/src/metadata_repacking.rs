@@ -0,0 +1,293 @@
+use crate::{context::LintContext, LateContext, LateLintPass};
+use rustc_errors::fluent;
+use rustc_hir as hir;
+use rustc_hir::def::Res;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, HirId, Node, QPath, StmtKind, UnOp};
+use rustc_middle::ty::Ty;
+use rustc_span::symbol::sym;
+
+declare_lint! {
+    /// The `metadata_repacking` lint detects unsafe code that rebuilds or
+    /// overwrites the metadata of a smart pointer or collection, like the
+    /// length and capacity of a `Vec` or the pointer of an `Rc`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust,compile_fail
+    /// #![deny(metadata_repacking)]
+    /// let mut v = vec![1, 2, 3];
+    /// let w = unsafe { Vec::from_raw_parts(v.as_mut_ptr(), 8, v.capacity()) };
+    /// # std::mem::forget(w);
+    /// ```
+    ///
+    /// {{produces}}
+    ///
+    /// ### Explanation
+    ///
+    /// Such "repacking" is legal unsafe code, but a wrong length or capacity,
+    /// or a pointer that is still owned by another value, leads to
+    /// out-of-bounds accesses, double frees or uses after free. The lint
+    /// flags:
+    ///
+    /// - `Vec::from_raw_parts` and `String::from_raw_parts` whose pointer,
+    ///   length and capacity do not all come from the same `into_raw_parts`
+    ///   call, or from the same `ManuallyDrop` value,
+    /// - `ptr::write` and `transmute` of a whole `Box`, `Vec`, `String`, `Rc`
+    ///   or `Arc`,
+    /// - `Vec::set_len` that is not preceded by a capacity check or a
+    ///   reservation on the same vector.
+    ///
+    /// The checks are syntactic, so correct code can be flagged as well. The
+    /// lint is meant to point unsafe code review at the places to look at.
+    pub METADATA_REPACKING,
+    Allow,
+    "detects unsafe code that rebuilds or overwrites smart pointer metadata"
+}
+
+declare_lint_pass!(MetadataRepacking => [METADATA_REPACKING]);
+
+impl<'tcx> LateLintPass<'tcx> for MetadataRepacking {
+    fn check_expr(&mut self, cx: &LateContext<'tcx>, expr: &'tcx Expr<'tcx>) {
+        match expr.kind {
+            ExprKind::Call(func, args) => {
+                let ExprKind::Path(qpath) = &func.kind else { return };
+                let Some(def_id) = cx.qpath_res(qpath, func.hir_id).opt_def_id() else { return };
+                match cx.tcx.get_diagnostic_name(def_id) {
+                    Some(
+                        sym::vec_from_raw_parts
+                        | sym::vec_from_raw_parts_in
+                        | sym::string_from_raw_parts,
+                    ) => check_from_raw_parts(cx, expr, args),
+                    Some(sym::ptr_write) => {
+                        let ty = cx.typeck_results().node_substs(func.hir_id).type_at(0);
+                        check_overwrite(cx, expr, ty);
+                    }
+                    _ if cx.tcx.is_intrinsic(def_id)
+                        && cx.tcx.item_name(def_id) == sym::transmute =>
+                    {
+                        let sig = cx.typeck_results().node_type(func.hir_id).fn_sig(cx.tcx);
+                        check_overwrite(cx, expr, sig.inputs().skip_binder()[0]);
+                        check_overwrite(cx, expr, sig.output().skip_binder());
+                    }
+                    _ => {}
+                }
+            }
+            ExprKind::MethodCall(_, receiver, [new_len], _) => {
+                let Some(def_id) = cx.typeck_results().type_dependent_def_id(expr.hir_id) else {
+                    return;
+                };
+                if cx.tcx.is_diagnostic_item(sym::vec_set_len, def_id) {
+                    check_set_len(cx, expr, receiver, new_len);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where a raw part passed to `from_raw_parts` comes from.
+#[derive(PartialEq)]
+enum PartsSource {
+    /// A method call like `len()` on the `ManuallyDrop` local with this id.
+    ManuallyDrop(HirId),
+    /// A binding of the `let` with this id, destructuring an `into_raw_parts` call.
+    IntoRawParts(HirId),
+}
+
+fn check_from_raw_parts<'tcx>(
+    cx: &LateContext<'tcx>,
+    expr: &'tcx Expr<'tcx>,
+    args: &'tcx [Expr<'tcx>],
+) {
+    // The pointer, length and capacity, the allocator is not metadata.
+    let sources: Vec<_> = args[..3].iter().map(|arg| parts_source(cx, arg)).collect();
+    if sources[0].is_some() && sources.iter().all(|source| *source == sources[0]) {
+        return;
+    }
+    cx.struct_span_lint(
+        METADATA_REPACKING,
+        expr.span,
+        fluent::lint_metadata_repacking_from_raw_parts,
+        |lint| lint.set_arg("ty", cx.typeck_results().expr_ty(expr)).note(fluent::note),
+    );
+}
+
+fn parts_source<'tcx>(cx: &LateContext<'tcx>, arg: &'tcx Expr<'tcx>) -> Option<PartsSource> {
+    match arg.kind {
+        ExprKind::Cast(inner, _) => parts_source(cx, inner),
+        ExprKind::MethodCall(segment, receiver, [], _)
+            if matches!(
+                segment.ident.name,
+                sym::len | sym::capacity | sym::as_ptr | sym::as_mut_ptr
+            ) =>
+        {
+            let local = path_local(receiver)?;
+            let ty = cx.typeck_results().expr_ty(receiver).peel_refs();
+            let is_manually_drop = ty.ty_adt_def().map_or(false, |adt| adt.is_manually_drop());
+            is_manually_drop.then_some(PartsSource::ManuallyDrop(local))
+        }
+        ExprKind::Path(_) => {
+            let binding = path_local(arg)?;
+            // Skip the patterns around the binding, up to the `let` it is bound by.
+            let (_, node) = cx
+                .tcx
+                .hir()
+                .parent_iter(binding)
+                .find(|(_, node)| !matches!(node, Node::Pat(_)))?;
+            let Node::Local(local) = node else { return None };
+            let init = local.init?;
+            match init.kind {
+                ExprKind::MethodCall(segment, ..)
+                    if matches!(
+                        segment.ident.name,
+                        sym::into_raw_parts | sym::into_raw_parts_with_alloc
+                    ) =>
+                {
+                    Some(PartsSource::IntoRawParts(local.hir_id))
+                }
+                // `let len = parts.len();`
+                _ if let hir::PatKind::Binding(..) = local.pat.kind => parts_source(cx, init),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn check_overwrite<'tcx>(cx: &LateContext<'tcx>, expr: &'tcx Expr<'tcx>, ty: Ty<'tcx>) {
+    let owns_metadata = ty.is_box()
+        || ty.ty_adt_def().map_or(false, |adt| {
+            matches!(
+                cx.tcx.get_diagnostic_name(adt.did()),
+                Some(sym::Vec | sym::String | sym::Rc | sym::Arc)
+            )
+        });
+    if owns_metadata {
+        cx.struct_span_lint(
+            METADATA_REPACKING,
+            expr.span,
+            fluent::lint_metadata_repacking_overwrite,
+            |lint| lint.set_arg("ty", ty).note(fluent::note),
+        );
+    }
+}
+
+fn check_set_len<'tcx>(
+    cx: &LateContext<'tcx>,
+    expr: &'tcx Expr<'tcx>,
+    receiver: &'tcx Expr<'tcx>,
+    new_len: &'tcx Expr<'tcx>,
+) {
+    // Truncating to nothing never needs a check.
+    if let ExprKind::Lit(lit) = &new_len.kind
+        && let rustc_ast::LitKind::Int(0, _) = lit.node
+    {
+        return;
+    }
+    if !capacity_checked(cx, expr, receiver) {
+        cx.struct_span_lint(
+            METADATA_REPACKING,
+            expr.span,
+            fluent::lint_metadata_repacking_set_len,
+            |lint| lint.note(fluent::note),
+        );
+    }
+}
+
+/// Whether the `set_len` call `expr` on `receiver` follows a capacity check on `receiver`: in
+/// the condition of an enclosing `if`, or in an earlier statement of an enclosing block.
+fn capacity_checked<'tcx>(
+    cx: &LateContext<'tcx>,
+    expr: &'tcx Expr<'tcx>,
+    receiver: &'tcx Expr<'tcx>,
+) -> bool {
+    let mut finder = CapacityCheckFinder { receiver, found: false };
+    let mut child = expr.hir_id;
+    for (parent, node) in cx.tcx.hir().parent_iter(expr.hir_id) {
+        match node {
+            Node::Expr(Expr { kind: ExprKind::If(cond, then, _), .. }) if then.hir_id == child => {
+                finder.visit_expr(cond);
+            }
+            Node::Block(block) => {
+                for stmt in block.stmts.iter().take_while(|stmt| stmt.hir_id != child) {
+                    finder.visit_stmt(stmt);
+                }
+            }
+            Node::Expr(Expr { kind: ExprKind::Closure(..), .. })
+            | Node::Item(_)
+            | Node::ImplItem(_)
+            | Node::TraitItem(_) => break,
+            _ => {}
+        }
+        if finder.found {
+            return true;
+        }
+        child = parent;
+    }
+    false
+}
+
+/// Looks for a capacity check or a reservation on `receiver`, or for `receiver` being
+/// created with a capacity.
+struct CapacityCheckFinder<'tcx> {
+    receiver: &'tcx Expr<'tcx>,
+    found: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for CapacityCheckFinder<'tcx> {
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        if let ExprKind::MethodCall(segment, receiver, ..) = expr.kind
+            && matches!(
+                segment.ident.name,
+                sym::capacity
+                    | sym::reserve
+                    | sym::reserve_exact
+                    | sym::try_reserve
+                    | sym::try_reserve_exact
+            )
+            && same_place(receiver, self.receiver)
+        {
+            self.found = true;
+        }
+        intravisit::walk_expr(self, expr);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'tcx hir::Stmt<'tcx>) {
+        if let StmtKind::Local(local) = stmt.kind
+            && let hir::PatKind::Binding(_, binding, ..) = local.pat.kind
+            && path_local(self.receiver) == Some(binding)
+            && let Some(init) = local.init
+            && let ExprKind::Call(func, _) = init.kind
+            && let ExprKind::Path(QPath::TypeRelative(_, segment)) = func.kind
+            && segment.ident.name == sym::with_capacity
+        {
+            self.found = true;
+        }
+        intravisit::walk_stmt(self, stmt);
+    }
+}
+
+fn path_local(expr: &Expr<'_>) -> Option<HirId> {
+    match expr.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Local(id) => Some(id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `a` and `b` name the same place, going through locals, fields and dereferences.
+fn same_place(a: &Expr<'_>, b: &Expr<'_>) -> bool {
+    match (&a.kind, &b.kind) {
+        (ExprKind::Path(QPath::Resolved(None, a)), ExprKind::Path(QPath::Resolved(None, b))) => {
+            a.res == b.res
+        }
+        (ExprKind::Field(a, a_field), ExprKind::Field(b, b_field)) => {
+            a_field.name == b_field.name && same_place(a, b)
+        }
+        (ExprKind::Unary(UnOp::Deref, a), ExprKind::Unary(UnOp::Deref, b)) => same_place(a, b),
+        _ => false,
+    }
+}
@@ -1259,6 +1259,42 @@
     "detects attempts to mutate a `const` item",
 }
 
+declare_lint! {
+    /// The `duplicate_ownership` lint detects drops of values that own an
+    /// allocation, like a `Box`, `Vec` or `Rc`, which may have been freed
+    /// through another owner already.
+    ///
+    /// ### Example
+    ///
+    /// ```rust,compile_fail
+    /// #![deny(duplicate_ownership)]
+    /// let p = Box::into_raw(Box::new(1));
+    /// let a = unsafe { Box::from_raw(p) };
+    /// let b = unsafe { Box::from_raw(p) };
+    /// drop(a);
+    /// drop(b);
+    /// ```
+    ///
+    /// {{produces}}
+    ///
+    /// ### Explanation
+    ///
+    /// Unsafe code can rebuild an owning value from a raw pointer, with
+    /// functions like `Box::from_raw` or `Vec::from_raw_parts`, and duplicate
+    /// one with `ptr::read`. If the allocation still has another owner, both
+    /// of them free it when they are dropped, which is undefined behavior.
+    ///
+    /// The lint follows the raw pointers taken from owning values through a
+    /// single function. Calls are assumed to keep their arguments alive,
+    /// except for `drop` and for functions of the current crate that drop an
+    /// argument on every path. Code that hands the ownership back and forth
+    /// in other ways can be flagged as well, so the lint is meant to point
+    /// unsafe code review at the places to look at.
+    pub DUPLICATE_OWNERSHIP,
+    Allow,
+    "detects allocations that may be freed by more than one owner",
+}
+
 declare_lint! {
     /// The `patterns_in_fns_without_body` lint detects `mut` identifier
     /// patterns as a parameter in functions without a body.
@@ -3201,6 +3237,7 @@
         RENAMED_AND_REMOVED_LINTS,
         UNALIGNED_REFERENCES,
         CONST_ITEM_MUTATION,
+        DUPLICATE_OWNERSHIP,
         PATTERNS_IN_FNS_WITHOUT_BODY,
         MISSING_FRAGMENT_SPECIFIER,
         LATE_BOUND_LIFETIME_ARGUMENTS,
@@ -848,6 +848,30 @@
         desc { |tcx| "checking naked functions in {}", describe_as_module(key, tcx) }
     }
 
+    /// Checks that the body `key` only writes `#[rustc_protected_metadata]` fields of its own
+    /// type, from an inherent or `MetaUpdate` impl of that type.
+    query check_protected_metadata(key: LocalDefId) -> () {
+        desc { |tcx| "checking protected metadata writes in `{}`", tcx.def_path_str(key.to_def_id()) }
+    }
+
+    /// Returns the fields of the struct `key` that are marked `#[rustc_protected_metadata]`, or
+    /// `#[metadata]` if the struct derives `MetaUpdate`.
+    query protected_metadata_fields(key: DefId) -> &'tcx [mir::Field] {
+        desc { |tcx| "computing the protected metadata fields of `{}`", tcx.def_path_str(key) }
+    }
+
+    /// Returns the fields of the struct `key` that are relocated into the metadata compartment,
+    /// i.e. that are stored as a `Compartment` pointing to their value.
+    query metadata_compartment_fields(key: DefId) -> &'tcx [mir::Field] {
+        desc { |tcx| "computing the relocated fields of `{}`", tcx.def_path_str(key) }
+    }
+
+    /// Checks the raw borrows of relocated fields, and the constructions of structs relocating
+    /// fields in const contexts.
+    query check_metadata_compartment(key: LocalDefId) -> () {
+        desc { |tcx| "checking metadata compartment uses in `{}`", tcx.def_path_str(key.to_def_id()) }
+    }
+
     query check_mod_item_types(key: LocalDefId) -> () {
         desc { |tcx| "checking item types in {}", describe_as_module(key, tcx) }
     }
@@ -976,6 +1000,12 @@
         }
     }
 
+    /// Returns the indices of the arguments that the function `key` drops on every path that
+    /// returns normally.
+    query dropped_arguments(key: LocalDefId) -> &'tcx [usize] {
+        desc { |tcx| "computing the dropped arguments of `{}`", tcx.def_path_str(key.to_def_id()) }
+    }
+
     /// Obtain all the calls into other local functions
     query mir_inliner_callees(key: ty::InstanceDef<'tcx>) -> &'tcx [(DefId, SubstsRef<'tcx>)] {
         fatal_cycle
@@ -1426,6 +1456,11 @@
         desc { "getting a crate's configured panic-in-drop strategy" }
         separate_provide_extern
     }
+    query metadata_protection(_: CrateNum) -> MetadataProtection {
+        fatal_cycle
+        desc { "getting a crate's configured metadata protection" }
+        separate_provide_extern
+    }
     query is_no_builtins(_: CrateNum) -> bool {
         fatal_cycle
         desc { "getting whether a crate has `#![no_builtins]`" }
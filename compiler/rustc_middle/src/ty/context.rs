@@ -3002,6 +3002,10 @@ pub fn provide(providers: &mut ty::query::Providers) {
         assert_eq!(cnum, LOCAL_CRATE);
         tcx.sess.contains_name(tcx.hir().krate_attrs(), sym::compiler_builtins)
     };
+    providers.metadata_protection = |tcx, cnum| {
+        assert_eq!(cnum, LOCAL_CRATE);
+        tcx.sess.opts.unstable_opts.metadata_protection
+    };
     providers.has_panic_handler = |tcx, cnum| {
         assert_eq!(cnum, LOCAL_CRATE);
         // We want to check if the panic handler was defined in this crate
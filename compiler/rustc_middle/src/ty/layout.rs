@@ -1,4 +1,5 @@
 use crate::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use crate::mir::Field;
 use crate::ty::normalize_erasing_regions::NormalizationError;
 use crate::ty::{self, ReprOptions, Ty, TyCtxt, TypeVisitable};
 use rustc_ast as ast;
@@ -737,7 +738,9 @@ fn field_ty_or_layout<'tcx>(
                 ty::Adt(def, substs) => {
                     match this.variants {
                         Variants::Single { index } => {
-                            TyMaybeWithLayout::Ty(def.variant(index).fields[i].ty(tcx, substs))
+                            let field_ty = def.variant(index).fields[i].ty(tcx, substs);
+                            let field = Field::new(i);
+                            TyMaybeWithLayout::Ty(tcx.field_storage_ty(def, field, field_ty))
                         }
 
                         // Discriminant field for enums (where applicable).
@@ -1,6 +1,7 @@
 //! Miscellaneous type-system utilities that are too small to deserve their own modules.
 
 use crate::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use crate::mir::Field;
 use crate::ty::layout::IntegerExt;
 use crate::ty::{
     self, DefIdTree, FallibleTypeFolder, Ty, TyCtxt, TypeFoldable, TypeFolder, TypeSuperFoldable,
@@ -16,6 +17,7 @@
 use rustc_hir as hir;
 use rustc_hir::def::{CtorOf, DefKind, Res};
 use rustc_hir::def_id::DefId;
+use rustc_hir::LangItem;
 use rustc_index::bit_set::GrowableBitSet;
 use rustc_macros::HashStable;
 use rustc_span::{sym, DUMMY_SP};
@@ -153,6 +155,39 @@ pub fn type_id_hash(self, ty: Ty<'tcx>) -> u64 {
         })
     }
 
+    /// Creates a hash of the type family of `ty`: the item that defines it, with
+    /// its generic arguments erased, so that `Vec<i32>` and `Vec<f32>` hash the
+    /// same. Types that are not defined by an item, like primitives, slices or
+    /// references, form a family of their own and hash like `type_id_hash`.
+    pub fn type_family_id_hash(self, ty: Ty<'tcx>) -> u64 {
+        let def_id = match *ty.kind() {
+            ty::Adt(adt, _) => adt.did(),
+            ty::Foreign(def_id)
+            | ty::FnDef(def_id, _)
+            | ty::Closure(def_id, _)
+            | ty::Generator(def_id, _, _) => def_id,
+            _ => return self.type_id_hash(ty),
+        };
+        self.def_path_hash(def_id).0.to_smaller_hash()
+    }
+
+    /// Returns the type the field `field` of `adt` is stored as, given its declared type
+    /// `field_ty`: a `Compartment` holding it if the struct relocates the field into the
+    /// metadata compartment, or `field_ty` itself otherwise.
+    pub fn field_storage_ty(
+        self,
+        adt: ty::AdtDef<'tcx>,
+        field: Field,
+        field_ty: Ty<'tcx>,
+    ) -> Ty<'tcx> {
+        if adt.is_struct() && self.metadata_compartment_fields(adt.did()).contains(&field) {
+            let compartment = self.require_lang_item(LangItem::MetadataCompartment, None);
+            self.mk_generic_adt(compartment, field_ty)
+        } else {
+            field_ty
+        }
+    }
+
     pub fn res_generics_def_id(self, res: Res) -> Option<DefId> {
         match res {
             Res::Def(DefKind::Ctor(CtorOf::Variant, _), def_id) => {
@@ -0,0 +1,396 @@
+//! Tracking of allocations that may be owned by more than one value.
+//!
+//! Unsafe code can turn an owning value, like a `Box`, `Vec` or `Rc`, into a raw pointer to its
+//! allocation, and later rebuild an owning value from that pointer. Doing that twice, or while
+//! the original value is still around, gives the allocation two owners, and dropping both of
+//! them frees it twice. `ptr::read` of an owning value has the same effect in a single step.
+//!
+//! The calls that derive a raw pointer from an owning value, or duplicate one, are the *origins*
+//! of this analysis. Their results are followed through copies, casts and reborrows, into the
+//! calls that rebuild owning values from them. Drops of locals that may own the allocation of an
+//! origin are recorded, so that a later drop of another owner of the same allocation can be
+//! recognized.
+//!
+//! The analysis is intraprocedural. Calls are assumed not to drop their arguments, except for
+//! `mem::drop`, and, with [`MaybeDuplicateOwners::with_callee_summaries`], for the arguments
+//! that functions of the local crate drop on every path, as given by the `dropped_arguments`
+//! query.
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_index::bit_set::BitSet;
+use rustc_index::vec::{Idx, IndexVec};
+use rustc_middle::mir::{self, BasicBlock, Body, Local, Location, Operand, Place, Rvalue};
+use rustc_middle::mir::{ProjectionElem, StatementKind, TerminatorKind};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::symbol::sym;
+
+use crate::fmt::DebugWithContext;
+use crate::{Analysis, AnalysisDomain, CallReturnPlaces};
+
+rustc_index::newtype_index! {
+    /// A call that derives a raw pointer from an owning value, or duplicates an owning value.
+    pub struct OriginIndex {
+        DEBUG_FORMAT = "origin{}"
+    }
+}
+
+rustc_index::newtype_index! {
+    /// A fact in the state of [`MaybeDuplicateOwners`]: either that a local may hold a pointer
+    /// to, or an owner of, the allocation of an origin, or that an owner of that allocation may
+    /// have been dropped.
+    pub struct OwnershipFact {
+        DEBUG_FORMAT = "fact{}"
+    }
+}
+
+impl<C> DebugWithContext<C> for OwnershipFact {}
+
+/// The allocation of an owning value, as handed out by a call.
+#[derive(Debug)]
+pub struct Origin {
+    /// The call that hands out the allocation.
+    pub location: Location,
+    /// The local holding the owning value the allocation was taken from, if it still owns it
+    /// after the call, like the receiver of `Vec::as_mut_ptr`.
+    pub owner: Option<Local>,
+}
+
+/// What a call does to the ownership of allocations.
+struct CallEffect {
+    /// The locals whose owning values the call drops.
+    dropped: Vec<Local>,
+    result: CallResult,
+}
+
+enum CallResult {
+    /// The result points to, or owns, the allocation of an origin.
+    Origin(OriginIndex),
+    /// The result owns the allocation of the raw pointer in the given local.
+    Rebuild(Local),
+    /// The result is a raw pointer that may point into the allocations of the given locals.
+    Derived(Vec<Local>),
+    /// The result has nothing to do with the tracked allocations.
+    Unrelated,
+}
+
+/// Finds the drops of owning values whose allocation may have been freed by dropping another
+/// owner before. See the [module-level documentation](self) for details.
+pub struct MaybeDuplicateOwners<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body<'tcx>,
+    origins: IndexVec<OriginIndex, Origin>,
+    calls: FxHashMap<BasicBlock, CallEffect>,
+}
+
+impl<'a, 'tcx> MaybeDuplicateOwners<'a, 'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, body: &'a Body<'tcx>) -> Self {
+        let mut analysis = MaybeDuplicateOwners {
+            tcx,
+            body,
+            origins: IndexVec::new(),
+            calls: FxHashMap::default(),
+        };
+        analysis.collect_calls(false);
+        analysis
+    }
+
+    /// Also treats the arguments that functions of the local crate drop on every path as
+    /// dropped by calls to them.
+    pub fn with_callee_summaries(mut self) -> Self {
+        self.collect_calls(true);
+        self
+    }
+
+    /// Whether there is nothing to track in the body.
+    pub fn is_trivial(&self) -> bool {
+        self.origins.is_empty()
+    }
+
+    pub fn origin(&self, origin: OriginIndex) -> &Origin {
+        &self.origins[origin]
+    }
+
+    /// Returns the locals whose owning values `terminator` drops.
+    pub fn dropped_locals(
+        &self,
+        terminator: &mir::Terminator<'tcx>,
+        location: Location,
+    ) -> Vec<Local> {
+        match &terminator.kind {
+            TerminatorKind::Drop { place, .. } | TerminatorKind::DropAndReplace { place, .. } => {
+                place.as_local().into_iter().collect()
+            }
+            TerminatorKind::Call { .. } => {
+                self.calls.get(&location.block).map_or(vec![], |effect| effect.dropped.clone())
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns an origin whose allocation `local` may own in `state`, and that may have been
+    /// dropped through another owner already.
+    pub fn duplicate_origin(
+        &self,
+        state: &BitSet<OwnershipFact>,
+        local: Local,
+    ) -> Option<OriginIndex> {
+        self.origins.indices().find(|&origin| {
+            state.contains(self.carries(local, origin)) && state.contains(self.dropped(origin))
+        })
+    }
+
+    fn carries(&self, local: Local, origin: OriginIndex) -> OwnershipFact {
+        OwnershipFact::new(local.index() * self.origins.len() + origin.index())
+    }
+
+    fn dropped(&self, origin: OriginIndex) -> OwnershipFact {
+        OwnershipFact::new(self.body.local_decls.len() * self.origins.len() + origin.index())
+    }
+
+    fn collect_calls(&mut self, summaries: bool) {
+        for (block, data) in self.body.basic_blocks.iter_enumerated() {
+            let TerminatorKind::Call { func, args, .. } = &data.terminator().kind else {
+                continue;
+            };
+            let effect = self.call_effect(block, func, args, summaries);
+            self.calls.insert(block, effect);
+        }
+    }
+
+    fn call_effect(
+        &mut self,
+        block: BasicBlock,
+        func: &Operand<'tcx>,
+        args: &[Operand<'tcx>],
+        summaries: bool,
+    ) -> CallEffect {
+        let unrelated = CallEffect { dropped: vec![], result: CallResult::Unrelated };
+        let Some((def_id, substs)) = func.const_fn_def() else {
+            return unrelated;
+        };
+        let location = self.body.terminator_loc(block);
+        let arg_local = |index: usize| args.get(index).and_then(|arg| arg.place()?.as_local());
+        let result = match self.tcx.get_diagnostic_name(def_id) {
+            Some(sym::box_into_raw | sym::rc_into_raw | sym::arc_into_raw) => {
+                CallResult::Origin(self.origin_at(location, None))
+            }
+            Some(sym::vec_as_mut_ptr) => {
+                let owner = arg_local(0).and_then(|arg| self.owner_of(block, arg));
+                CallResult::Origin(self.origin_at(location, owner))
+            }
+            Some(sym::ptr_read) if self.is_owning(substs.type_at(0)) => {
+                let owner = arg_local(0).and_then(|arg| self.owner_of(block, arg));
+                CallResult::Origin(self.origin_at(location, owner))
+            }
+            Some(
+                sym::box_from_raw
+                | sym::rc_from_raw
+                | sym::arc_from_raw
+                | sym::vec_from_raw_parts
+                | sym::vec_from_raw_parts_in
+                | sym::string_from_raw_parts,
+            ) => arg_local(0).map_or(CallResult::Unrelated, CallResult::Rebuild),
+            _ => {
+                let sig = self.tcx.fn_sig(def_id);
+                if sig.output().skip_binder().is_unsafe_ptr() {
+                    CallResult::Derived((0..args.len()).filter_map(arg_local).collect())
+                } else {
+                    CallResult::Unrelated
+                }
+            }
+        };
+        let dropped = match def_id.as_local() {
+            _ if self.tcx.is_diagnostic_item(sym::mem_drop, def_id) => {
+                arg_local(0).into_iter().collect()
+            }
+            Some(local_def_id) if summaries => self
+                .tcx
+                .dropped_arguments(local_def_id)
+                .iter()
+                .filter_map(|&index| arg_local(index))
+                .collect(),
+            _ => vec![],
+        };
+        CallEffect { dropped, result }
+    }
+
+    fn origin_at(&mut self, location: Location, owner: Option<Local>) -> OriginIndex {
+        match self.origins.iter_enumerated().find(|(_, origin)| origin.location == location) {
+            Some((index, _)) => index,
+            None => self.origins.push(Origin { location, owner }),
+        }
+    }
+
+    /// Follows the assignments to `local` back through `block`, through references, raw
+    /// pointers, copies and casts, to a local holding an owning value.
+    fn owner_of(&self, block: BasicBlock, mut local: Local) -> Option<Local> {
+        for statement in self.body.basic_blocks[block].statements.iter().rev() {
+            let StatementKind::Assign(box (dest, rvalue)) = &statement.kind else { continue };
+            if dest.as_local() != Some(local) {
+                continue;
+            }
+            let source = match rvalue {
+                Rvalue::Ref(_, _, place) | Rvalue::AddressOf(_, place) => {
+                    if let Some(source) = place.as_local() {
+                        return self.is_owning(self.body.local_decls[source].ty).then_some(source);
+                    }
+                    match place.as_ref().last_projection() {
+                        Some((base, ProjectionElem::Deref)) if base.projection.is_empty() => {
+                            base.local
+                        }
+                        _ => return None,
+                    }
+                }
+                Rvalue::Use(Operand::Copy(place) | Operand::Move(place))
+                | Rvalue::Cast(_, Operand::Copy(place) | Operand::Move(place), _)
+                | Rvalue::CopyForDeref(place) => place.as_local()?,
+                _ => return None,
+            };
+            local = source;
+        }
+        None
+    }
+
+    fn is_owning(&self, ty: Ty<'tcx>) -> bool {
+        ty.is_box()
+            || matches!(ty.kind(), ty::Adt(adt, _) if matches!(
+                self.tcx.get_diagnostic_name(adt.did()),
+                Some(sym::Vec | sym::String | sym::Rc | sym::Arc)
+            ))
+    }
+
+    fn kill(&self, state: &mut BitSet<OwnershipFact>, local: Local) {
+        for origin in self.origins.indices() {
+            state.remove(self.carries(local, origin));
+        }
+    }
+
+    fn drop_local(&self, state: &mut BitSet<OwnershipFact>, local: Local) {
+        for origin in self.origins.indices() {
+            if state.contains(self.carries(local, origin)) {
+                state.insert(self.dropped(origin));
+            }
+        }
+        self.kill(state, local);
+    }
+
+    /// Assigns to `place` a value that may point into, or own, the allocations of `sources`.
+    fn assign(
+        &self,
+        state: &mut BitSet<OwnershipFact>,
+        place: Place<'tcx>,
+        sources: impl IntoIterator<Item = Local>,
+    ) {
+        let mut origins = BitSet::new_empty(self.origins.len());
+        for source in sources {
+            for origin in self.origins.indices() {
+                if state.contains(self.carries(source, origin)) {
+                    origins.insert(origin);
+                }
+            }
+        }
+        // Writes to a part of a local only add to what the local may hold.
+        if let Some(local) = place.as_local() {
+            self.kill(state, local);
+        }
+        for origin in origins.iter() {
+            state.insert(self.carries(place.local, origin));
+        }
+    }
+}
+
+/// Returns the locals the value of `rvalue` may point into, or take ownership from.
+fn rvalue_sources<'tcx>(rvalue: &Rvalue<'tcx>) -> Vec<Local> {
+    let operand_local = |operand: &Operand<'tcx>| operand.place().map(|place| place.local);
+    match rvalue {
+        Rvalue::Use(operand)
+        | Rvalue::Cast(_, operand, _)
+        | Rvalue::Repeat(operand, _)
+        | Rvalue::ShallowInitBox(operand, _) => operand_local(operand).into_iter().collect(),
+        Rvalue::BinaryOp(mir::BinOp::Offset, box (operand, _)) => {
+            operand_local(operand).into_iter().collect()
+        }
+        Rvalue::CopyForDeref(place) => vec![place.local],
+        // A reborrow through a pointer points into the same allocation.
+        Rvalue::Ref(_, _, place) | Rvalue::AddressOf(_, place)
+            if place.projection.first() == Some(&ProjectionElem::Deref) =>
+        {
+            vec![place.local]
+        }
+        Rvalue::Aggregate(_, operands) => operands.iter().filter_map(operand_local).collect(),
+        _ => vec![],
+    }
+}
+
+impl<'tcx> AnalysisDomain<'tcx> for MaybeDuplicateOwners<'_, 'tcx> {
+    type Domain = BitSet<OwnershipFact>;
+
+    const NAME: &'static str = "maybe_duplicate_owners";
+
+    fn bottom_value(&self, body: &Body<'tcx>) -> Self::Domain {
+        // bottom = no allocation is pointed to, owned or dropped
+        BitSet::new_empty((body.local_decls.len() + 1) * self.origins.len())
+    }
+
+    fn initialize_start_block(&self, _: &Body<'tcx>, _: &mut Self::Domain) {
+        // Nothing is derived from an origin before the first call.
+    }
+}
+
+impl<'tcx> Analysis<'tcx> for MaybeDuplicateOwners<'_, 'tcx> {
+    fn apply_statement_effect(
+        &self,
+        state: &mut Self::Domain,
+        statement: &mir::Statement<'tcx>,
+        _location: Location,
+    ) {
+        match &statement.kind {
+            StatementKind::Assign(box (place, rvalue)) => {
+                self.assign(state, *place, rvalue_sources(rvalue))
+            }
+            StatementKind::StorageDead(local) => self.kill(state, *local),
+            _ => {}
+        }
+    }
+
+    fn apply_terminator_effect(
+        &self,
+        state: &mut Self::Domain,
+        terminator: &mir::Terminator<'tcx>,
+        location: Location,
+    ) {
+        for local in self.dropped_locals(terminator, location) {
+            self.drop_local(state, local);
+        }
+        if let TerminatorKind::DropAndReplace { place, value, .. } = &terminator.kind {
+            self.assign(state, *place, value.place().map(|place| place.local));
+        }
+    }
+
+    fn apply_call_return_effect(
+        &self,
+        state: &mut Self::Domain,
+        block: BasicBlock,
+        return_places: CallReturnPlaces<'_, 'tcx>,
+    ) {
+        let result = self.calls.get(&block).map_or(&CallResult::Unrelated, |effect| &effect.result);
+        return_places.for_each(|place| match result {
+            CallResult::Origin(origin) => {
+                // The call hands out a new allocation each time it runs, e.g. in a loop, so
+                // forget about the previous one.
+                for local in self.body.local_decls.indices() {
+                    state.remove(self.carries(local, *origin));
+                }
+                state.remove(self.dropped(*origin));
+                self.assign(state, place, None);
+                state.insert(self.carries(place.local, *origin));
+                if let Some(owner) = self.origins[*origin].owner {
+                    state.insert(self.carries(owner, *origin));
+                }
+            }
+            CallResult::Rebuild(pointer) => self.assign(state, place, Some(*pointer)),
+            CallResult::Derived(sources) => self.assign(state, place, sources.iter().copied()),
+            CallResult::Unrelated => self.assign(state, place, None),
+        });
+    }
+}
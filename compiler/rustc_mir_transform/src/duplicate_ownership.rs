@@ -0,0 +1,147 @@
+//! Lints drops of values that own an allocation which may have been freed through another
+//! owner already, see `MaybeDuplicateOwners`.
+
+use rustc_data_structures::fx::FxHashSet;
+use rustc_hir::def::DefKind;
+use rustc_index::bit_set::BitSet;
+use rustc_middle::mir::visit::Visitor;
+use rustc_middle::mir::*;
+use rustc_middle::ty::query::Providers;
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_mir_dataflow::impls::{MaybeDuplicateOwners, OriginIndex, OwnershipFact};
+use rustc_mir_dataflow::{Analysis, ResultsVisitor};
+use rustc_session::lint::builtin::DUPLICATE_OWNERSHIP;
+use rustc_session::lint::Level;
+use rustc_span::def_id::LocalDefId;
+
+use crate::MirLint;
+
+pub struct DuplicateOwnership;
+
+impl<'tcx> MirLint<'tcx> for DuplicateOwnership {
+    fn run_lint(&self, tcx: TyCtxt<'tcx>, body: &Body<'tcx>) {
+        if duplicate_ownership_allowed(tcx, body.source.def_id().expect_local()) {
+            return;
+        }
+        let analysis = MaybeDuplicateOwners::new(tcx, body).with_callee_summaries();
+        if analysis.is_trivial() {
+            return;
+        }
+        let results =
+            analysis.into_engine(tcx, body).pass_name("duplicate_ownership").iterate_to_fixpoint();
+        let mut visitor = DuplicateDropVisitor {
+            tcx,
+            body,
+            analysis: &results.analysis,
+            reported: FxHashSet::default(),
+        };
+        results.visit_reachable_with(body, &mut visitor);
+    }
+}
+
+pub(crate) fn provide(providers: &mut Providers) {
+    *providers = Providers { dropped_arguments, ..*providers };
+}
+
+/// Returns the indices of the arguments of `def_id` that it drops on every path that returns
+/// normally, i.e. the arguments that have drops and are never moved out of.
+///
+/// This is computed from the MIR before drop elaboration, where every local that needs dropping
+/// has a drop at the end of its scope, whether it was moved out of before or not.
+///
+/// Functions the lint is allowed in get no summary, which only means that their callers may miss
+/// some duplicates.
+fn dropped_arguments(tcx: TyCtxt<'_>, def_id: LocalDefId) -> &[usize] {
+    if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+        || !tcx.is_mir_available(def_id.to_def_id())
+        || duplicate_ownership_allowed(tcx, def_id)
+    {
+        return &[];
+    }
+    let (body, _) = tcx.mir_promoted(ty::WithOptConstParam::unknown(def_id));
+    let body = body.borrow();
+    let mut dropped = BitSet::new_empty(body.local_decls.len());
+    let mut moved = BitSet::new_empty(body.local_decls.len());
+    let mut collector = DropsAndMoves { dropped: &mut dropped, moved: &mut moved };
+    collector.visit_body(&body);
+    tcx.arena.alloc_from_iter(
+        body.args_iter()
+            .filter(|&arg| dropped.contains(arg) && !moved.contains(arg))
+            .map(|arg| arg.index() - 1),
+    )
+}
+
+fn duplicate_ownership_allowed(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
+    let hir_id = tcx.hir().local_def_id_to_hir_id(def_id);
+    tcx.lint_level_at_node(DUPLICATE_OWNERSHIP, hir_id).0 == Level::Allow
+}
+
+struct DropsAndMoves<'a> {
+    dropped: &'a mut BitSet<Local>,
+    moved: &'a mut BitSet<Local>,
+}
+
+impl<'tcx> Visitor<'tcx> for DropsAndMoves<'_> {
+    fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
+        if let Operand::Move(place) = operand {
+            self.moved.insert(place.local);
+        }
+        self.super_operand(operand, location);
+    }
+
+    fn visit_terminator(&mut self, terminator: &Terminator<'tcx>, location: Location) {
+        if let TerminatorKind::Drop { place, .. } | TerminatorKind::DropAndReplace { place, .. } =
+            &terminator.kind
+            && let Some(local) = place.as_local()
+        {
+            self.dropped.insert(local);
+        }
+        self.super_terminator(terminator, location);
+    }
+}
+
+struct DuplicateDropVisitor<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body<'tcx>,
+    analysis: &'a MaybeDuplicateOwners<'a, 'tcx>,
+    /// The origins reported so far, each origin is only reported at its first duplicate drop.
+    reported: FxHashSet<OriginIndex>,
+}
+
+impl<'a, 'tcx> ResultsVisitor<'a, 'tcx> for DuplicateDropVisitor<'a, 'tcx> {
+    type FlowState = BitSet<OwnershipFact>;
+
+    fn visit_terminator_before_primary_effect(
+        &mut self,
+        state: &Self::FlowState,
+        terminator: &'a Terminator<'tcx>,
+        location: Location,
+    ) {
+        for local in self.analysis.dropped_locals(terminator, location) {
+            let Some(origin) = self.analysis.duplicate_origin(state, local) else { continue };
+            if !self.reported.insert(origin) {
+                continue;
+            }
+            let source_info = terminator.source_info;
+            let lint_root = self.body.source_scopes[source_info.scope]
+                .local_data
+                .as_ref()
+                .assert_crate_local()
+                .lint_root;
+            let origin_span = self.body.source_info(self.analysis.origin(origin).location).span;
+            self.tcx.struct_span_lint_hir(
+                DUPLICATE_OWNERSHIP,
+                lint_root,
+                source_info.span,
+                "dropping a value whose allocation may already have been freed",
+                |lint| {
+                    lint.span_note(
+                        origin_span,
+                        "the allocation is shared by more than one owner from here on",
+                    )
+                    .note("dropping every owner frees the allocation more than once")
+                },
+            );
+        }
+    }
+}
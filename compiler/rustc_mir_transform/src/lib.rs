@@ -61,6 +61,7 @@
 mod deref_separator;
 mod dest_prop;
 pub mod dump_mir;
+mod duplicate_ownership;
 mod early_otherwise_branch;
 mod elaborate_box_derefs;
 mod elaborate_drops;
@@ -72,9 +73,11 @@
 mod lower_intrinsics;
 mod lower_slice_len;
 mod match_branches;
+mod metadata_compartment;
 mod multiple_return_terminators;
 mod normalize_array_len;
 mod nrvo;
+mod protected_metadata;
 // This pass is public to allow external drivers to perform MIR cleanup
 pub mod remove_false_edges;
 mod remove_noop_landing_pads;
@@ -103,6 +106,7 @@ pub fn provide(providers: &mut Providers) {
     check_unsafety::provide(providers);
     check_packed_ref::provide(providers);
     coverage::query::provide(providers);
+    duplicate_ownership::provide(providers);
     ffi_unwind_calls::provide(providers);
     shim::provide(providers);
     *providers = Providers {
@@ -436,6 +440,10 @@ fn mir_drops_elaborated_and_const_checked<'tcx>(
 
     let is_fn_like = tcx.def_kind(def.did).is_fn_like();
     if is_fn_like {
+        // The `duplicate_ownership` lint of the callers needs this, and it reads the MIR that
+        // is stolen below.
+        let _ = tcx.dropped_arguments(def.did);
+
         let did = def.did.to_def_id();
         let def = ty::WithOptConstParam::unknown(did);
 
@@ -509,6 +517,13 @@ fn run_runtime_lowering_passes<'tcx>(tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
         // These next passes must be executed together
         &add_call_guards::CriticalCallEdges,
         &elaborate_drops::ElaborateDrops,
+        // Needs the drops of moved-out values to be gone, so it must run after `ElaborateDrops`.
+        &Lint(duplicate_ownership::DuplicateOwnership),
+        // Inserts calls without cleanup into the elaborated drops, so it must run after
+        // `ElaborateDrops`.
+        &protected_metadata::ProtectedMetadata,
+        // Inserts calls, and needs the drops to be elaborated, like `ProtectedMetadata`.
+        &metadata_compartment::MetadataCompartment,
         // This will remove extraneous landing pads which are no longer
         // necessary as well as well as forcing any call in a non-unwinding
         // function calling a possibly-unwinding function to abort the process.
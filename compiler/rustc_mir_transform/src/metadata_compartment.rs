@@ -0,0 +1,430 @@
+//! This pass lowers the fields that are relocated into the metadata compartment, see the
+//! `metadata_compartment_fields` query. Such a field of declared type `F` is stored as a
+//! `Compartment<F>`, which points to the value, but MIR is built with its declared type. Changes
+//! of ownership of the field are turned into calls:
+//!
+//! ```text
+//! owner.field = value    =>  _t = value;
+//!                            owner.field: Compartment<F> = compartment_new::<F>(move _t)
+//! move owner.field       =>  _v = compartment_take::<F>(move owner.field: Compartment<F>);
+//!                            move _v
+//! drop(owner.field)      =>  drop(owner.field: Compartment<F>)
+//! ```
+//!
+//! and an aggregate initializing the field moves its operand into the compartment first. Every
+//! other use of the field, or of a part of it, goes through the pointer:
+//!
+//! ```text
+//! owner.field.rest       =>  _p = copy owner.field: Compartment<F>.ptr.pointer;
+//!                            (*_p).rest
+//! ```
+//!
+//! This runs after drop elaboration, so a write always initializes a field that holds no value,
+//! and a field that was moved out of is never dropped. Moving out of a part of a relocated value,
+//! rather than the whole of it, leaks the storage of the value.
+//!
+//! The inserted calls only unwind if the compartment can not be allocated, and have no unwind
+//! edges: the values of the frame are leaked then.
+
+use crate::MirPass;
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::patch::MirPatch;
+use rustc_middle::mir::tcx::PlaceTy;
+use rustc_middle::mir::visit::MutVisitor;
+use rustc_middle::mir::*;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use std::{iter, mem};
+
+pub struct MetadataCompartment;
+
+impl<'tcx> MirPass<'tcx> for MetadataCompartment {
+    fn run_pass(&self, tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
+        let lang_items = tcx.lang_items();
+        let (Some(compartment), Some(new), Some(take)) = (
+            lang_items.metadata_compartment(),
+            lang_items.compartment_new_fn(),
+            lang_items.compartment_take_fn(),
+        ) else {
+            return;
+        };
+        let ptr_did = tcx.adt_def(compartment).non_enum_variant().fields[0].did;
+        let relocation = Relocation { tcx, compartment, new, take, ptr_did };
+
+        let mut bb = START_BLOCK;
+        // Inserting a call appends the remainder of the block as a new block, which is visited
+        // later on.
+        while bb.index() < body.basic_blocks.len() {
+            relocation.lower_ownership(body, bb);
+            bb = BasicBlock::from_usize(bb.index() + 1);
+        }
+
+        let patch = MirPatch::new(body);
+        let local_decls = &mut body.local_decls;
+        let mut visitor = AccessVisitor { relocation: &relocation, local_decls, patch };
+        for (block, data) in body.basic_blocks.as_mut_preserves_cfg().iter_enumerated_mut() {
+            visitor.visit_basic_block_data(block, data);
+        }
+        visitor.patch.apply(body);
+
+        for debug_info in body.var_debug_info.iter_mut() {
+            if let VarDebugInfoContents::Place(place) = &mut debug_info.value {
+                let mut new_projections: Option<Vec<_>> = None;
+                let mut last = 0;
+
+                for (i, (base, elem)) in place.iter_projections().enumerate() {
+                    let base_ty = base.ty(&body.local_decls, tcx).ty;
+                    if let ProjectionElem::Field(field, ty) = elem
+                        && let Some(storage_ty) = relocation.storage_ty(base_ty, field, ty)
+                    {
+                        let new_projections = new_projections.get_or_insert_default();
+                        new_projections.extend_from_slice(&base.projection[last..]);
+                        new_projections.push(ProjectionElem::Field(field, storage_ty));
+                        new_projections.extend_from_slice(&relocation.pointer_projection(ty));
+                        new_projections.push(ProjectionElem::Deref);
+                        last = i + 1;
+                    }
+                }
+
+                if let Some(mut new_projections) = new_projections {
+                    new_projections.extend_from_slice(&place.projection[last..]);
+                    place.projection = tcx.intern_place_elems(&new_projections);
+                }
+            }
+        }
+    }
+}
+
+struct Relocation<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    compartment: DefId,
+    new: DefId,
+    take: DefId,
+    /// The `ptr` field of `Compartment`.
+    ptr_did: DefId,
+}
+
+impl<'tcx> Relocation<'tcx> {
+    /// Returns the type the field `field` of type `ty` of `base_ty` is stored as, if it is
+    /// relocated and still projected to with its declared type.
+    fn storage_ty(&self, base_ty: Ty<'tcx>, field: Field, ty: Ty<'tcx>) -> Option<Ty<'tcx>> {
+        let ty::Adt(adt, _) = base_ty.kind() else {
+            return None;
+        };
+        if matches!(ty.kind(), ty::Adt(def, _) if def.did() == self.compartment) {
+            return None;
+        }
+        let storage_ty = self.tcx.field_storage_ty(*adt, field, ty);
+        (storage_ty != ty).then_some(storage_ty)
+    }
+
+    /// If `place` is the whole of a relocated field, returns its declared type and the place of
+    /// the `Compartment` it is stored in.
+    fn relocated_field(
+        &self,
+        local_decls: &LocalDecls<'tcx>,
+        place: Place<'tcx>,
+    ) -> Option<(Ty<'tcx>, Place<'tcx>)> {
+        let (base, ProjectionElem::Field(field, ty)) = place.as_ref().last_projection()? else {
+            return None;
+        };
+        let storage_ty = self.storage_ty(base.ty(local_decls, self.tcx).ty, field, ty)?;
+        let mut projection = base.projection.to_vec();
+        projection.push(ProjectionElem::Field(field, storage_ty));
+        Some((
+            ty,
+            Place { local: base.local, projection: self.tcx.intern_place_elems(&projection) },
+        ))
+    }
+
+    /// The projection from a `Compartment<ty>` to the pointer to its value.
+    fn pointer_projection(&self, ty: Ty<'tcx>) -> [PlaceElem<'tcx>; 2] {
+        let substs = self.tcx.intern_substs(&[ty.into()]);
+        let nonnull_ty = self.tcx.bound_type_of(self.ptr_did).subst(self.tcx, substs);
+        [
+            PlaceElem::Field(Field::new(0), nonnull_ty),
+            PlaceElem::Field(Field::new(0), self.tcx.mk_imm_ptr(ty)),
+        ]
+    }
+
+    /// Finds the first operand that moves out of a whole relocated field.
+    fn find_move(
+        &self,
+        local_decls: &LocalDecls<'tcx>,
+        operands: Vec<&mut Operand<'tcx>>,
+    ) -> Option<(usize, Ty<'tcx>, Place<'tcx>)> {
+        operands.into_iter().enumerate().find_map(|(i, operand)| {
+            let Operand::Move(place) = operand else {
+                return None;
+            };
+            let (ty, storage) = self.relocated_field(local_decls, *place)?;
+            Some((i, ty, storage))
+        })
+    }
+
+    /// Lowers the first change of ownership of a relocated field in `bb` that needs a call, and
+    /// the drops of relocated fields.
+    fn lower_ownership(&self, body: &mut Body<'tcx>, bb: BasicBlock) {
+        let tcx = self.tcx;
+        for index in 0..body.basic_blocks[bb].statements.len() {
+            let (blocks, local_decls) =
+                (body.basic_blocks.as_mut_preserves_cfg(), &body.local_decls);
+            let statement = &mut blocks[bb].statements[index];
+            let span = statement.source_info.span;
+            let StatementKind::Assign(box (place, rvalue)) = &mut statement.kind else {
+                continue;
+            };
+
+            if let Some((i, ty, storage)) = self.find_move(local_decls, operands_mut(rvalue)) {
+                let value = body.local_decls.push(LocalDecl::new(ty, span).internal());
+                let StatementKind::Assign(box (_, rvalue)) =
+                    &mut body.basic_blocks.as_mut_preserves_cfg()[bb].statements[index].kind
+                else {
+                    bug!()
+                };
+                *operands_mut(rvalue)[i] = Operand::Move(value.into());
+                self.insert_call(body, bb, index, self.take, ty, Operand::Move(storage), value);
+                return;
+            }
+
+            if let Rvalue::Aggregate(box AggregateKind::Adt(did, _, substs, _, _), operands) =
+                rvalue
+                && tcx.adt_def(*did).is_struct()
+            {
+                let adt = tcx.adt_def(*did);
+                let fields = &adt.non_enum_variant().fields;
+                let unwrapped = operands.iter().enumerate().find_map(|(i, operand)| {
+                    let ty = fields[i].ty(tcx, *substs);
+                    let storage_ty = tcx.field_storage_ty(adt, Field::new(i), ty);
+                    (storage_ty != ty && operand.ty(local_decls, tcx) != storage_ty)
+                        .then_some((i, ty, storage_ty))
+                });
+                if let Some((i, ty, storage_ty)) = unwrapped {
+                    let storage =
+                        body.local_decls.push(LocalDecl::new(storage_ty, span).internal());
+                    let StatementKind::Assign(box (_, Rvalue::Aggregate(_, operands))) =
+                        &mut body.basic_blocks.as_mut_preserves_cfg()[bb].statements[index].kind
+                    else {
+                        bug!()
+                    };
+                    let value = mem::replace(&mut operands[i], Operand::Move(storage.into()));
+                    self.insert_call(body, bb, index, self.new, ty, value, storage);
+                    return;
+                }
+            }
+
+            if let Some((ty, storage)) = self.relocated_field(local_decls, *place) {
+                let value = body.local_decls.push(LocalDecl::new(ty, span).internal());
+                let StatementKind::Assign(box (place, _)) =
+                    &mut body.basic_blocks.as_mut_preserves_cfg()[bb].statements[index].kind
+                else {
+                    bug!()
+                };
+                *place = value.into();
+                self.insert_call(
+                    body,
+                    bb,
+                    index + 1,
+                    self.new,
+                    ty,
+                    Operand::Move(value.into()),
+                    storage,
+                );
+                return;
+            }
+        }
+
+        let (blocks, local_decls) = (body.basic_blocks.as_mut_preserves_cfg(), &body.local_decls);
+        let terminator = blocks[bb].terminator_mut();
+        let span = terminator.source_info.span;
+        match &mut terminator.kind {
+            TerminatorKind::Drop { place, .. } => {
+                if let Some((_, storage)) = self.relocated_field(local_decls, *place) {
+                    *place = storage;
+                }
+            }
+            TerminatorKind::Call { .. } | TerminatorKind::Yield { .. } => {
+                if let Some((i, ty, storage)) =
+                    self.find_move(local_decls, terminator_operands_mut(&mut terminator.kind))
+                {
+                    let value = body.local_decls.push(LocalDecl::new(ty, span).internal());
+                    let kind =
+                        &mut body.basic_blocks.as_mut_preserves_cfg()[bb].terminator_mut().kind;
+                    *terminator_operands_mut(kind)[i] = Operand::Move(value.into());
+                    let index = body.basic_blocks[bb].statements.len();
+                    self.insert_call(body, bb, index, self.take, ty, Operand::Move(storage), value);
+                    return;
+                }
+
+                let TerminatorKind::Call { destination, target: Some(target), .. } =
+                    &mut terminator.kind
+                else {
+                    return;
+                };
+                let Some((ty, storage)) = self.relocated_field(local_decls, *destination) else {
+                    return;
+                };
+                let value = body.local_decls.push(LocalDecl::new(ty, span).internal());
+                let call = self.call(
+                    self.new,
+                    ty,
+                    Operand::Move(value.into()),
+                    storage,
+                    *target,
+                    terminator.source_info,
+                );
+                let is_cleanup = body.basic_blocks[bb].is_cleanup;
+                let blocks = body.basic_blocks_mut();
+                let new_bb = blocks.push(BasicBlockData {
+                    statements: vec![],
+                    terminator: Some(call),
+                    is_cleanup,
+                });
+                let TerminatorKind::Call { destination, target, .. } =
+                    &mut blocks[bb].terminator_mut().kind
+                else {
+                    bug!()
+                };
+                *destination = value.into();
+                *target = Some(new_bb);
+            }
+            _ => {}
+        }
+    }
+
+    /// Inserts a call to `def_id::<ty>(arg)`, returning into `destination`, in front of the
+    /// statement `index` of `bb`, or in front of its terminator if `index` is past its
+    /// statements. The statements from there on move into a new block, which the call returns
+    /// to.
+    fn insert_call(
+        &self,
+        body: &mut Body<'tcx>,
+        bb: BasicBlock,
+        index: usize,
+        def_id: DefId,
+        ty: Ty<'tcx>,
+        arg: Operand<'tcx>,
+        destination: impl Into<Place<'tcx>>,
+    ) {
+        let blocks = body.basic_blocks_mut();
+        let data = &mut blocks[bb];
+        let source_info =
+            data.statements.get(index).map_or(data.terminator().source_info, |s| s.source_info);
+        let rest = BasicBlockData {
+            statements: data.statements.split_off(index),
+            terminator: data.terminator.take(),
+            is_cleanup: data.is_cleanup,
+        };
+        let rest = blocks.push(rest);
+        let call = self.call(def_id, ty, arg, destination.into(), rest, source_info);
+        blocks[bb].terminator = Some(call);
+    }
+
+    fn call(
+        &self,
+        def_id: DefId,
+        ty: Ty<'tcx>,
+        arg: Operand<'tcx>,
+        destination: Place<'tcx>,
+        target: BasicBlock,
+        source_info: SourceInfo,
+    ) -> Terminator<'tcx> {
+        let substs = self.tcx.intern_substs(&[ty.into()]);
+        Terminator {
+            source_info,
+            kind: TerminatorKind::Call {
+                func: Operand::function_handle(self.tcx, def_id, substs, source_info.span),
+                args: vec![arg],
+                destination,
+                target: Some(target),
+                cleanup: None,
+                from_hir_call: false,
+                fn_span: source_info.span,
+            },
+        }
+    }
+}
+
+fn operands_mut<'a, 'tcx>(rvalue: &'a mut Rvalue<'tcx>) -> Vec<&'a mut Operand<'tcx>> {
+    match rvalue {
+        Rvalue::Use(operand)
+        | Rvalue::Repeat(operand, _)
+        | Rvalue::Cast(_, operand, _)
+        | Rvalue::UnaryOp(_, operand)
+        | Rvalue::ShallowInitBox(operand, _) => vec![operand],
+        Rvalue::BinaryOp(_, box (lhs, rhs)) | Rvalue::CheckedBinaryOp(_, box (lhs, rhs)) => {
+            vec![lhs, rhs]
+        }
+        Rvalue::Aggregate(_, operands) => operands.iter_mut().collect(),
+        Rvalue::Ref(..)
+        | Rvalue::ThreadLocalRef(_)
+        | Rvalue::AddressOf(..)
+        | Rvalue::Len(_)
+        | Rvalue::NullaryOp(..)
+        | Rvalue::Discriminant(_)
+        | Rvalue::CopyForDeref(_) => vec![],
+    }
+}
+
+fn terminator_operands_mut<'a, 'tcx>(
+    kind: &'a mut TerminatorKind<'tcx>,
+) -> Vec<&'a mut Operand<'tcx>> {
+    match kind {
+        TerminatorKind::Call { func, args, .. } => iter::once(func).chain(args).collect(),
+        TerminatorKind::Yield { value, .. } => vec![value],
+        _ => vec![],
+    }
+}
+
+/// Makes every use of a relocated field go through the pointer to its value.
+struct AccessVisitor<'tcx, 'a> {
+    relocation: &'a Relocation<'tcx>,
+    local_decls: &'a mut LocalDecls<'tcx>,
+    patch: MirPatch<'tcx>,
+}
+
+impl<'tcx, 'a> MutVisitor<'tcx> for AccessVisitor<'tcx, 'a> {
+    fn tcx(&self) -> TyCtxt<'tcx> {
+        self.relocation.tcx
+    }
+
+    fn visit_place(
+        &mut self,
+        place: &mut Place<'tcx>,
+        context: visit::PlaceContext,
+        location: Location,
+    ) {
+        let tcx = self.relocation.tcx;
+        let source_info = self.local_decls[place.local].source_info;
+        let mut local = place.local;
+        let mut projection = Vec::new();
+        let mut place_ty = PlaceTy::from_ty(self.local_decls[place.local].ty);
+        let mut changed = false;
+
+        for elem in place.projection {
+            if let ProjectionElem::Field(field, ty) = elem
+                && let Some(storage_ty) = self.relocation.storage_ty(place_ty.ty, field, ty)
+            {
+                projection.push(ProjectionElem::Field(field, storage_ty));
+                projection.extend_from_slice(&self.relocation.pointer_projection(ty));
+                let ptr = self.patch.new_internal(tcx.mk_imm_ptr(ty), source_info.span);
+                let pointer = Place { local, projection: tcx.intern_place_elems(&projection) };
+                self.patch.add_assign(location, ptr.into(), Rvalue::Use(Operand::Copy(pointer)));
+
+                // A relocated field nested in this one is reached through its own pointer.
+                local = ptr;
+                projection = vec![ProjectionElem::Deref];
+                place_ty = PlaceTy::from_ty(ty);
+                changed = true;
+            } else {
+                projection.push(elem);
+                place_ty = place_ty.projection_ty(tcx, elem);
+            }
+        }
+
+        if changed {
+            *place = Place { local, projection: tcx.intern_place_elems(&projection) };
+        }
+
+        self.super_place(place, context, location);
+    }
+}
@@ -0,0 +1,293 @@
+//! This pass wraps writes to `#[rustc_protected_metadata]` fields in the `MetaUpdate`
+//! protection window, so that library code can write them as plain fields.
+//!
+//! A write `owner.field = value` is turned into
+//!
+//! ```text
+//! bb0: { ...; _t = value; _r = &owner; }
+//!      _ok = call protected_metadata_check::<Owner>(move _r, "field", copy owner.field, copy _t)
+//! bb1: switchInt(move _ok) -> [false: bb2, otherwise: bb3]
+//! bb2: { _t = copy owner.field; } goto bb3
+//! bb3: call protected_metadata_open::<Owner>()
+//! bb4: { owner.field = move _t; }
+//!      call protected_metadata_close::<Owner>()
+//! bb5: { rest of bb0 }
+//! ```
+//!
+//! A write the check rejects, and the violation handler does not let through, stores the old
+//! value back. `MetaUpdate::synchronize` works on `usize` values, so only writes to `usize`
+//! fields are checked; writes to other protected fields are only wrapped in the window.
+//! Adjacent writes to distinct fields of the same owner share a single window, with all their
+//! checks in front of it. Only statements run inside the window.
+//!
+//! The lang items are `#[rustc_nounwind]`, and the calls to them have no cleanup. The cleanup of
+//! the surrounding code can not be reused: after drop elaboration, it may drop locals that are
+//! only initialized after the write. A violation handler that panics therefore aborts.
+//!
+//! Functions marked `#[rustc_synchronizes_metadata]` call `MetaUpdate::synchronize` themselves
+//! before they write, so their writes are only wrapped in the window, without a check.
+//!
+//! The calls to the lang items are made on the owner type, so only writes to owners that
+//! implement `MetaUpdate` are instrumented. `check_attr` rejects the attribute on the fields of
+//! other structs, and the pass leaves them alone rather than emit calls that do not type check.
+//! Whether writes are checked at all is decided by `METADATA_CHECKS` in `core`, so the pass follows
+//! the `-Z metadata-protection` mode the crate defining the lang items was built with, and leaves
+//! writes alone if that is `none`.
+
+use crate::MirPass;
+use rustc_data_structures::fx::FxHashSet;
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::interpret::{Allocation, ConstValue};
+use rustc_middle::mir::*;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_session::config::MetadataProtection;
+use rustc_span::{sym, Span, Symbol};
+
+pub struct ProtectedMetadata;
+
+struct LangItems {
+    meta_update: DefId,
+    check: DefId,
+    open: DefId,
+    close: DefId,
+}
+
+/// A write to a protected field.
+struct ProtectedWrite<'tcx> {
+    place: Place<'tcx>,
+    owner: Place<'tcx>,
+    owner_ty: Ty<'tcx>,
+    field: Field,
+    field_ty: Ty<'tcx>,
+    name: Symbol,
+    rvalue: Rvalue<'tcx>,
+}
+
+impl<'tcx> MirPass<'tcx> for ProtectedMetadata {
+    fn run_pass(&self, tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
+        let lang_items = tcx.lang_items();
+        let (Some(meta_update), Some(check), Some(open), Some(close)) = (
+            lang_items.meta_update_trait(),
+            lang_items.protected_metadata_check_fn(),
+            lang_items.protected_metadata_open_fn(),
+            lang_items.protected_metadata_close_fn(),
+        ) else {
+            return;
+        };
+        if tcx.metadata_protection(check.krate) == MetadataProtection::None {
+            return;
+        }
+        let lang_items = LangItems { meta_update, check, open, close };
+        // Writes of functions that synchronize them themselves would be checked twice.
+        let checked = !tcx.has_attr(body.source.def_id(), sym::rustc_synchronizes_metadata);
+
+        // The blocks holding the instrumented writes, which must not be instrumented again.
+        let mut windows = FxHashSet::default();
+        let mut bb = START_BLOCK;
+        // Instrumenting a block appends its remainder as a new block, which is visited later on.
+        while bb.index() < body.basic_blocks.len() {
+            if !windows.contains(&bb) {
+                if let Some((start, len)) = find_run(tcx, body, bb, &lang_items) {
+                    let window = instrument_run(tcx, body, bb, start, len, &lang_items, checked);
+                    windows.insert(window);
+                }
+            }
+            bb = BasicBlock::from_usize(bb.index() + 1);
+        }
+    }
+}
+
+fn protected_write<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+    statement: &Statement<'tcx>,
+    lang_items: &LangItems,
+) -> Option<ProtectedWrite<'tcx>> {
+    let StatementKind::Assign(box (place, rvalue)) = &statement.kind else {
+        return None;
+    };
+    let (owner, ProjectionElem::Field(field, field_ty)) = place.as_ref().last_projection()? else {
+        return None;
+    };
+    let owner_ty = owner.ty(&body.local_decls, tcx).ty;
+    let ty::Adt(adt, _) = owner_ty.kind() else {
+        return None;
+    };
+    if !tcx.protected_metadata_fields(adt.did()).contains(&field)
+        || !implements_meta_update(tcx, adt.did(), lang_items)
+    {
+        return None;
+    }
+    Some(ProtectedWrite {
+        place: *place,
+        owner: Place { local: owner.local, projection: tcx.intern_place_elems(owner.projection) },
+        owner_ty,
+        field,
+        field_ty,
+        name: adt.non_enum_variant().fields[field.index()].name,
+        rvalue: rvalue.clone(),
+    })
+}
+
+/// Whether the struct `owner` has a `MetaUpdate` impl, which the lang items are called on.
+fn implements_meta_update(tcx: TyCtxt<'_>, owner: DefId, lang_items: &LangItems) -> bool {
+    tcx.all_impls(lang_items.meta_update).any(|impl_def_id| {
+        tcx.type_of(impl_def_id).ty_adt_def().map_or(false, |adt| adt.did() == owner)
+    })
+}
+
+/// Finds the first run of adjacent protected writes in `bb` that can share a window, and
+/// returns its start and length.
+///
+/// The values of all writes in a run are evaluated before the window opens. That is only
+/// fine for the later writes if their values can not observe the earlier ones, so they must
+/// be plain locals or constants.
+fn find_run<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+    bb: BasicBlock,
+    lang_items: &LangItems,
+) -> Option<(usize, usize)> {
+    let statements = &body.basic_blocks[bb].statements;
+    let (start, first) = statements
+        .iter()
+        .enumerate()
+        .find_map(|(i, statement)| Some((i, protected_write(tcx, body, statement, lang_items)?)))?;
+    let mut fields = vec![first.field];
+    let mut len = 1;
+    for statement in &statements[start + 1..] {
+        let Some(write) = protected_write(tcx, body, statement, lang_items) else { break };
+        let value_is_local = match &write.rvalue {
+            Rvalue::Use(Operand::Constant(_)) => true,
+            Rvalue::Use(Operand::Copy(place) | Operand::Move(place)) => place.as_local().is_some(),
+            _ => false,
+        };
+        if write.owner != first.owner || fields.contains(&write.field) || !value_is_local {
+            break;
+        }
+        fields.push(write.field);
+        len += 1;
+    }
+    Some((start, len))
+}
+
+/// Instruments the writes `start..start + len` of `bb`, and returns the block that now holds
+/// them. The writes are only checked if `checked` is set.
+fn instrument_run<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &mut Body<'tcx>,
+    bb: BasicBlock,
+    start: usize,
+    len: usize,
+    lang_items: &LangItems,
+    checked: bool,
+) -> BasicBlock {
+    let data = &mut body.basic_blocks_mut()[bb];
+    let source_info = data.statements[start].source_info;
+    let span = source_info.span;
+    let is_cleanup = data.is_cleanup;
+    let tail = data.statements.split_off(start + len);
+    let run = data.statements.split_off(start);
+    let terminator = data.terminator.take();
+
+    let writes: Vec<_> = run
+        .iter()
+        .map(|statement| protected_write(tcx, body, statement, lang_items).unwrap())
+        .collect();
+    let owner_ty = writes[0].owner_ty;
+    let substs = tcx.intern_substs(&[owner_ty.into()]);
+    let unit = Place::from(body.local_decls.push(LocalDecl::new(tcx.mk_unit(), span).internal()));
+    // The lang items never unwind, see the module documentation.
+    let call = |def_id, args, destination, target| Terminator {
+        source_info,
+        kind: TerminatorKind::Call {
+            func: Operand::function_handle(tcx, def_id, substs, span),
+            args,
+            destination,
+            target: Some(target),
+            cleanup: None,
+            from_hir_call: false,
+            fn_span: span,
+        },
+    };
+
+    let mut prelude = Vec::new();
+    let mut window = Vec::new();
+    let mut checks = Vec::new();
+    for write in writes {
+        let value = body.local_decls.push(LocalDecl::new(write.field_ty, span).internal());
+        prelude.push(assign(source_info, Place::from(value), write.rvalue));
+        window.push(assign(source_info, write.place, Rvalue::Use(Operand::Move(value.into()))));
+        if checked && write.field_ty == tcx.types.usize {
+            let owner_ref_ty = tcx.mk_imm_ref(tcx.lifetimes.re_erased, owner_ty);
+            let owner_ref = body.local_decls.push(LocalDecl::new(owner_ref_ty, span).internal());
+            let borrow = Rvalue::Ref(tcx.lifetimes.re_erased, BorrowKind::Shared, write.owner);
+            prelude.push(assign(source_info, Place::from(owner_ref), borrow));
+            let accepted = body.local_decls.push(LocalDecl::new(tcx.types.bool, span).internal());
+            let args = vec![
+                Operand::Move(owner_ref.into()),
+                str_operand(tcx, write.name, span),
+                Operand::Copy(write.place),
+                Operand::Copy(value.into()),
+            ];
+            let keep = assign(source_info, value.into(), Rvalue::Use(Operand::Copy(write.place)));
+            checks.push((args, Place::from(accepted), keep));
+        }
+    }
+
+    let blocks = body.basic_blocks_mut();
+    let rest = blocks.push(BasicBlockData { statements: tail, terminator, is_cleanup });
+    let window_bb = blocks.push(BasicBlockData {
+        statements: window,
+        terminator: Some(call(lang_items.close, vec![], unit, rest)),
+        is_cleanup,
+    });
+    let mut next = call(lang_items.open, vec![], unit, window_bb);
+    for (args, accepted, keep) in checks.into_iter().rev() {
+        let target =
+            blocks.push(BasicBlockData { statements: vec![], terminator: Some(next), is_cleanup });
+        let goto = Terminator { source_info, kind: TerminatorKind::Goto { target } };
+        let rejected = blocks.push(BasicBlockData {
+            statements: vec![keep],
+            terminator: Some(goto),
+            is_cleanup,
+        });
+        let switch = Terminator {
+            source_info,
+            kind: TerminatorKind::if_(tcx, Operand::Move(accepted), target, rejected),
+        };
+        let decide = blocks.push(BasicBlockData {
+            statements: vec![],
+            terminator: Some(switch),
+            is_cleanup,
+        });
+        next = call(lang_items.check, args, accepted, decide);
+    }
+
+    let data = &mut blocks[bb];
+    data.statements.extend(prelude);
+    data.terminator = Some(next);
+    window_bb
+}
+
+fn assign<'tcx>(
+    source_info: SourceInfo,
+    place: Place<'tcx>,
+    rvalue: Rvalue<'tcx>,
+) -> Statement<'tcx> {
+    Statement { source_info, kind: StatementKind::Assign(Box::new((place, rvalue))) }
+}
+
+fn str_operand<'tcx>(tcx: TyCtxt<'tcx>, s: Symbol, span: Span) -> Operand<'tcx> {
+    let s = s.as_str();
+    let allocation =
+        tcx.intern_const_alloc(Allocation::from_bytes_byte_aligned_immutable(s.as_bytes()));
+    Operand::Constant(Box::new(Constant {
+        span,
+        user_ty: None,
+        literal: ConstantKind::Val(
+            ConstValue::Slice { data: allocation, start: 0, end: s.len() },
+            tcx.mk_static_str(),
+        ),
+    }))
+}
@@ -18,7 +18,7 @@
 use crate::util::expand_aggregate;
 use crate::{
     abort_unwinding_calls, add_call_guards, add_moves_for_packed_drops, deref_separator,
-    pass_manager as pm, remove_noop_landing_pads, simplify,
+    metadata_compartment, pass_manager as pm, remove_noop_landing_pads, simplify,
 };
 use rustc_middle::mir::patch::MirPatch;
 use rustc_mir_dataflow::elaborate_drops::{self, DropElaborator, DropFlagMode, DropStyle};
@@ -87,6 +87,14 @@ fn make_shim<'tcx>(tcx: TyCtxt<'tcx>, instance: ty::InstanceDef<'tcx>) -> Body<'
     };
     debug!("make_shim({:?}) = untransformed {:?}", instance, result);
 
+    // Relocated fields are projected to with their declared type until the lowering to runtime
+    // MIR, which `MetadataCompartment` is part of.
+    pm::run_passes_no_validate(
+        tcx,
+        &mut result,
+        &[&metadata_compartment::MetadataCompartment],
+        Some(MirPhase::Runtime(RuntimePhase::Initial)),
+    );
     pm::run_passes(
         tcx,
         &mut result,
@@ -833,13 +841,21 @@ pub fn build_adt_ctor(tcx: TyCtxt<'_>, ctor_id: DefId) -> Body<'_> {
     };
 
     let source = MirSource::item(ctor_id);
-    let body = new_body(
+    let mut body = new_body(
         source,
         IndexVec::from_elem_n(start_block, 1),
         local_decls,
         sig.inputs().len(),
         span,
     );
+    // The constructor moves the relocated fields into the metadata compartment, which makes it
+    // runtime MIR.
+    pm::run_passes_no_validate(
+        tcx,
+        &mut body,
+        &[&metadata_compartment::MetadataCompartment],
+        Some(MirPhase::Runtime(RuntimePhase::Initial)),
+    );
 
     crate::pass_manager::dump_mir_for_phase_change(tcx, &body);
 
@@ -14,6 +14,7 @@
 use rustc_expand::base::resolve_path;
 use rustc_feature::{AttributeDuplicates, AttributeType, BuiltinAttribute, BUILTIN_ATTRIBUTE_MAP};
 use rustc_hir as hir;
+use rustc_hir::def::DefKind;
 use rustc_hir::def_id::LocalDefId;
 use rustc_hir::intravisit::{self, Visitor};
 use rustc_hir::{
@@ -141,6 +142,15 @@ fn check_attributes(
                 sym::must_not_suspend => self.check_must_not_suspend(&attr, span, target),
                 sym::must_use => self.check_must_use(hir_id, &attr, span, target),
                 sym::rustc_pass_by_value => self.check_pass_by_value(&attr, span, target),
+                sym::rustc_protected_metadata => {
+                    self.check_protected_metadata(hir_id, &attr, span, target)
+                }
+                sym::rustc_synchronizes_metadata => {
+                    self.check_synchronizes_metadata(&attr, span, target)
+                }
+                sym::metadata_compartment => {
+                    self.check_metadata_compartment(hir_id, &attr, span, target)
+                }
                 sym::rustc_allow_incoherent_impl => {
                     self.check_allow_incoherent_impl(&attr, span, target)
                 }
@@ -1133,6 +1143,89 @@ fn check_pass_by_value(&self, attr: &Attribute, span: Span, target: Target) -> b
         }
     }
 
+    /// Checks that `#[rustc_protected_metadata]` is applied to a field of a struct that
+    /// implements `MetaUpdate`, which its writes are synchronized and wrapped in a window with.
+    fn check_protected_metadata(
+        &self,
+        hir_id: HirId,
+        attr: &Attribute,
+        span: Span,
+        target: Target,
+    ) -> bool {
+        let parent = self.tcx.hir().get_parent_item(hir_id).def_id;
+        if target != Target::Field || self.tcx.def_kind(parent) != DefKind::Struct {
+            self.tcx.sess.emit_err(errors::ProtectedMetadata { attr_span: attr.span, span });
+            return false;
+        }
+        // Crates without the lang item can not implement it.
+        let Some(meta_update) = self.tcx.lang_items().meta_update_trait() else {
+            return true;
+        };
+        let implemented = self.tcx.all_impls(meta_update).any(|impl_def_id| {
+            self.tcx
+                .type_of(impl_def_id)
+                .ty_adt_def()
+                .map_or(false, |adt| adt.did() == parent.to_def_id())
+        });
+        if !implemented {
+            self.tcx.sess.emit_err(errors::ProtectedMetadataWithoutMetaUpdate {
+                attr_span: attr.span,
+                span: self.tcx.def_span(parent),
+                ty: self.tcx.def_path_str(parent.to_def_id()),
+            });
+        }
+        implemented
+    }
+
+    /// Checks that `#[rustc_synchronizes_metadata]` is applied to a function.
+    fn check_synchronizes_metadata(&self, attr: &Attribute, span: Span, target: Target) -> bool {
+        match target {
+            Target::Fn | Target::Method(..) => true,
+            _ => {
+                self.tcx.sess.emit_err(errors::SynchronizesMetadata { attr_span: attr.span, span });
+                false
+            }
+        }
+    }
+
+    /// Checks that `#[metadata_compartment]` is applied to a struct, with no option or with
+    /// `inline`, or without options to the crate. A struct with an explicit `repr` has its
+    /// layout fixed, so it can not relocate its fields.
+    fn check_metadata_compartment(
+        &self,
+        hir_id: HirId,
+        attr: &Attribute,
+        span: Span,
+        target: Target,
+    ) -> bool {
+        if hir_id != CRATE_HIR_ID && target != Target::Struct {
+            self.tcx.sess.emit_err(errors::MetadataCompartment { attr_span: attr.span, span });
+            return false;
+        }
+        let valid = match attr.meta_item_list().as_deref() {
+            None => true,
+            Some([option]) => {
+                hir_id != CRATE_HIR_ID && option.is_word() && option.has_name(sym::inline)
+            }
+            Some(_) => false,
+        };
+        if !valid {
+            self.tcx.sess.emit_err(errors::MetadataCompartmentOption { span: attr.span });
+            return false;
+        }
+        if hir_id != CRATE_HIR_ID
+            && attr.meta_item_list().is_none()
+            && let Some(repr) = self.tcx.hir().attrs(hir_id).iter().find(|a| a.has_name(sym::repr))
+        {
+            self.tcx.sess.emit_err(errors::MetadataCompartmentRepr {
+                attr_span: attr.span,
+                repr_span: repr.span,
+            });
+            return false;
+        }
+        true
+    }
+
     fn check_allow_incoherent_impl(&self, attr: &Attribute, span: Span, target: Target) -> bool {
         match target {
             Target::Method(MethodKind::Inherent) => true,
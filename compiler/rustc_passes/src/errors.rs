@@ -320,6 +320,92 @@ pub struct PassByValue {
     pub span: Span,
 }
 
+#[derive(Diagnostic)]
+#[diag(passes_protected_metadata)]
+pub struct ProtectedMetadata {
+    #[primary_span]
+    pub attr_span: Span,
+    #[label]
+    pub span: Span,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_protected_metadata_without_meta_update)]
+pub struct ProtectedMetadataWithoutMetaUpdate {
+    #[primary_span]
+    pub attr_span: Span,
+    #[label]
+    pub span: Span,
+    pub ty: String,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_synchronizes_metadata)]
+pub struct SynchronizesMetadata {
+    #[primary_span]
+    pub attr_span: Span,
+    #[label]
+    pub span: Span,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_protected_metadata_write)]
+#[note]
+pub struct ProtectedMetadataWrite {
+    #[primary_span]
+    #[label]
+    pub span: Span,
+    pub field: Symbol,
+    pub ty: String,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_metadata_compartment)]
+pub struct MetadataCompartment {
+    #[primary_span]
+    pub attr_span: Span,
+    #[label]
+    pub span: Span,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_metadata_compartment_option)]
+#[note]
+pub struct MetadataCompartmentOption {
+    #[primary_span]
+    pub span: Span,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_metadata_compartment_repr)]
+#[note]
+pub struct MetadataCompartmentRepr {
+    #[primary_span]
+    pub attr_span: Span,
+    #[label]
+    pub repr_span: Span,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_metadata_compartment_raw_address)]
+#[note]
+pub struct MetadataCompartmentRawAddress {
+    #[primary_span]
+    pub span: Span,
+    pub field: Symbol,
+    pub ty: String,
+}
+
+#[derive(Diagnostic)]
+#[diag(passes_metadata_compartment_const, code = "E0015")]
+#[note]
+pub struct MetadataCompartmentConst {
+    #[primary_span]
+    pub span: Span,
+    pub ty: String,
+    pub kind: hir::ConstContext,
+}
+
 #[derive(Diagnostic)]
 #[diag(passes_allow_incoherent_impl)]
 pub struct AllowIncoherentImpl {
@@ -34,7 +34,9 @@
 mod lib_features;
 mod liveness;
 pub mod loops;
+mod metadata_compartment;
 mod naked_functions;
+mod protected_metadata;
 mod reachable;
 pub mod stability;
 mod upvars;
@@ -50,7 +52,9 @@ pub fn provide(providers: &mut Providers) {
     lang_items::provide(providers);
     lib_features::provide(providers);
     loops::provide(providers);
+    metadata_compartment::provide(providers);
     naked_functions::provide(providers);
+    protected_metadata::provide(providers);
     liveness::provide(providers);
     reachable::provide(providers);
     stability::provide(providers);
@@ -0,0 +1,160 @@
+//! Decides which fields of a struct are relocated into the metadata compartment, and checks
+//! the uses of structs relocating fields.
+//!
+//! A struct relocates its fields if it is marked `#[metadata_compartment]`, or if its crate is
+//! and the struct is not marked `#[metadata_compartment(inline)]`. It then relocates every field
+//! whose declared type is a sized smart pointer or collection, i.e. a type implementing
+//! `MetaUpdate` and never `Copy`, unless the struct implements `MetaUpdate` itself and so
+//! protects its own metadata, or has an explicit `repr`, which fixes its layout. Values of
+//! relocated fields are owned by the compartment, so a field that could be overwritten without
+//! being dropped first is never relocated. The choice only depends on the definition of the
+//! struct, so every crate computes the same layout for it.
+//!
+//! A relocated field is reached through a pointer that is only set when the field is
+//! initialized, so taking its raw address, which is the way to reach a field that is not
+//! initialized yet, is rejected. The compartment is allocated at runtime, so structs relocating
+//! fields can not be constructed in const contexts either.
+
+use rustc_hir as hir;
+use rustc_hir::def::{CtorOf, DefKind, Res};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_middle::mir::Field;
+use rustc_middle::ty::query::Providers;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::sym;
+
+use crate::errors::{MetadataCompartmentConst, MetadataCompartmentRawAddress};
+
+pub(crate) fn provide(providers: &mut Providers) {
+    *providers =
+        Providers { check_metadata_compartment, metadata_compartment_fields, ..*providers };
+}
+
+fn metadata_compartment_fields(tcx: TyCtxt<'_>, def_id: DefId) -> &[Field] {
+    if tcx.def_kind(def_id) != DefKind::Struct
+        || tcx.lang_items().metadata_compartment().is_none()
+        || tcx.has_attr(def_id, sym::repr)
+        || !relocates_fields(tcx, def_id)
+        || has_impl(tcx, tcx.lang_items().meta_update_trait(), def_id)
+    {
+        return &[];
+    }
+    let param_env = tcx.param_env(def_id);
+    let fields = &tcx.adt_def(def_id).non_enum_variant().fields;
+    tcx.arena.alloc_from_iter(
+        fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                let ty = tcx.type_of(field.did);
+                ty.ty_adt_def().map_or(false, |adt| {
+                    has_impl(tcx, tcx.lang_items().meta_update_trait(), adt.did())
+                        && !has_impl(tcx, tcx.lang_items().copy_trait(), adt.did())
+                }) && ty.is_sized(tcx, param_env)
+            })
+            .map(|(index, _)| Field::new(index)),
+    )
+}
+
+/// Whether the struct `def_id` relocates its fields, as chosen with `#[metadata_compartment]`
+/// on the struct, or else on its crate.
+fn relocates_fields(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    match tcx.get_attr(def_id, sym::metadata_compartment) {
+        Some(attr) => !attr
+            .meta_item_list()
+            .map_or(false, |options| options.iter().any(|option| option.has_name(sym::inline))),
+        None => tcx.has_attr(def_id.krate.as_def_id(), sym::metadata_compartment),
+    }
+}
+
+/// Whether the type `def_id` has an impl of `trait_def_id`, for any of its parameters.
+fn has_impl(tcx: TyCtxt<'_>, trait_def_id: Option<DefId>, def_id: DefId) -> bool {
+    let Some(trait_def_id) = trait_def_id else {
+        return false;
+    };
+    tcx.all_impls(trait_def_id).any(|impl_def_id| {
+        tcx.type_of(impl_def_id).ty_adt_def().map_or(false, |adt| adt.did() == def_id)
+    })
+}
+
+fn check_metadata_compartment(tcx: TyCtxt<'_>, def_id: LocalDefId) {
+    if tcx.lang_items().metadata_compartment().is_none() {
+        return;
+    }
+    let Some(body_id) = tcx.hir().maybe_body_owned_by(def_id) else {
+        return;
+    };
+    let mut checker = CompartmentChecker {
+        tcx,
+        typeck_results: tcx.typeck(def_id),
+        const_context: tcx.hir().body_const_context(def_id),
+    };
+    checker.visit_body(tcx.hir().body(body_id));
+}
+
+struct CompartmentChecker<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx ty::TypeckResults<'tcx>,
+    const_context: Option<hir::ConstContext>,
+}
+
+impl<'tcx> CompartmentChecker<'tcx> {
+    /// Rejects a raw borrow of `place` if it projects through a relocated field.
+    fn check_raw_borrow(&self, mut place: &'tcx hir::Expr<'tcx>) {
+        while let hir::ExprKind::Field(base, _) = place.kind {
+            if let ty::Adt(adt, _) = self.typeck_results.expr_ty_adjusted(base).kind()
+                // Bodies with type errors may lack the field index.
+                && let Some(&index) = self.typeck_results.field_indices().get(place.hir_id)
+                && self.tcx.metadata_compartment_fields(adt.did()).contains(&Field::new(index))
+            {
+                self.tcx.sess.emit_err(MetadataCompartmentRawAddress {
+                    span: place.span,
+                    field: adt.non_enum_variant().fields[index].name,
+                    ty: self.tcx.def_path_str(adt.did()),
+                });
+                return;
+            }
+            place = base;
+        }
+    }
+
+    /// Rejects the construction of a value of type `ty` in a const context if it relocates
+    /// fields.
+    fn check_construction(&self, expr: &'tcx hir::Expr<'tcx>, ty: Ty<'tcx>) {
+        let Some(kind) = self.const_context else {
+            return;
+        };
+        if let ty::Adt(adt, _) = ty.kind()
+            && adt.is_struct()
+            && !self.tcx.metadata_compartment_fields(adt.did()).is_empty()
+        {
+            self.tcx.sess.emit_err(MetadataCompartmentConst {
+                span: expr.span,
+                ty: self.tcx.def_path_str(adt.did()),
+                kind,
+            });
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for CompartmentChecker<'tcx> {
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        match expr.kind {
+            hir::ExprKind::AddrOf(hir::BorrowKind::Raw, _, place) => self.check_raw_borrow(place),
+            hir::ExprKind::Struct(..) => {
+                self.check_construction(expr, self.typeck_results.expr_ty(expr))
+            }
+            hir::ExprKind::Call(callee, _) => {
+                if let hir::ExprKind::Path(ref qpath) = callee.kind
+                    && let Res::Def(DefKind::Ctor(CtorOf::Struct, _), _) =
+                        self.typeck_results.qpath_res(qpath, callee.hir_id)
+                {
+                    self.check_construction(expr, self.typeck_results.expr_ty(expr));
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
@@ -0,0 +1,124 @@
+//! Checks writes to `#[rustc_protected_metadata]` fields, and to the `#[metadata]` fields of
+//! types deriving `MetaUpdate`.
+//!
+//! Protected metadata, like the length of a `Vec`, may only be assigned by the type that owns
+//! it: from the methods of its inherent impls, or of its `MetaUpdate` impl. Those are the
+//! places that synchronize their writes, so any other assignment to such a field is rejected,
+//! even from within the crate defining the type. Mutable borrows of the field are not writes
+//! in this sense, as they are needed to initialize the metadata in place.
+
+use rustc_hir as hir;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_middle::mir::Field;
+use rustc_middle::ty::query::Providers;
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_span::sym;
+
+use crate::errors::ProtectedMetadataWrite;
+
+pub(crate) fn provide(providers: &mut Providers) {
+    *providers = Providers { check_protected_metadata, protected_metadata_fields, ..*providers };
+}
+
+fn protected_metadata_fields(tcx: TyCtxt<'_>, def_id: DefId) -> &[Field] {
+    if tcx.def_kind(def_id) != DefKind::Struct {
+        return &[];
+    }
+    // `#[derive(MetaUpdate)]` protects the fields it synchronizes, those marked `#[metadata]`.
+    let derived = derives_meta_update(tcx, def_id);
+    let fields = &tcx.adt_def(def_id).non_enum_variant().fields;
+    tcx.arena.alloc_from_iter(
+        fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                tcx.has_attr(field.did, sym::rustc_protected_metadata)
+                    || derived && tcx.has_attr(field.did, sym::metadata)
+            })
+            .map(|(index, _)| Field::new(index)),
+    )
+}
+
+/// Whether the struct `def_id` has a derived `MetaUpdate` impl.
+fn derives_meta_update(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    let Some(meta_update) = tcx.lang_items().meta_update_trait() else {
+        return false;
+    };
+    tcx.all_impls(meta_update).any(|impl_def_id| {
+        tcx.has_attr(impl_def_id, sym::automatically_derived)
+            && tcx.type_of(impl_def_id).ty_adt_def().map_or(false, |adt| adt.did() == def_id)
+    })
+}
+
+fn check_protected_metadata(tcx: TyCtxt<'_>, def_id: LocalDefId) {
+    let Some(body_id) = tcx.hir().maybe_body_owned_by(def_id) else {
+        return;
+    };
+    let mut checker =
+        ProtectedMetadataChecker { tcx, typeck_results: tcx.typeck(def_id), body_owner: def_id };
+    checker.visit_body(tcx.hir().body(body_id));
+}
+
+struct ProtectedMetadataChecker<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx ty::TypeckResults<'tcx>,
+    body_owner: LocalDefId,
+}
+
+impl<'tcx> ProtectedMetadataChecker<'tcx> {
+    fn check_write(&self, place: &'tcx hir::Expr<'tcx>) {
+        let hir::ExprKind::Field(base, _) = place.kind else {
+            return;
+        };
+        let ty::Adt(adt, _) = self.typeck_results.expr_ty_adjusted(base).kind() else {
+            return;
+        };
+        // Bodies with type errors may lack the field index.
+        let Some(&index) = self.typeck_results.field_indices().get(place.hir_id) else {
+            return;
+        };
+        if !self.tcx.protected_metadata_fields(adt.did()).contains(&Field::new(index)) {
+            return;
+        }
+        if !self.may_write(adt.did()) {
+            self.tcx.sess.emit_err(ProtectedMetadataWrite {
+                span: place.span,
+                field: adt.non_enum_variant().fields[index].name,
+                ty: self.tcx.def_path_str(adt.did()),
+            });
+        }
+    }
+
+    /// Whether the body may write the protected metadata of `owner`, i.e. whether it is a
+    /// method of an inherent impl or of the `MetaUpdate` impl of `owner`, or a closure in one.
+    fn may_write(&self, owner: DefId) -> bool {
+        let method = self.tcx.typeck_root_def_id(self.body_owner.to_def_id());
+        if self.tcx.def_kind(method) != DefKind::AssocFn {
+            return false;
+        }
+        let Some(impl_def_id) = self.tcx.impl_of_method(method) else {
+            return false;
+        };
+        let ty::Adt(self_adt, _) = self.tcx.type_of(impl_def_id).kind() else {
+            return false;
+        };
+        self_adt.did() == owner
+            && match self.tcx.trait_id_of_impl(impl_def_id) {
+                None => true,
+                Some(trait_def_id) => {
+                    self.tcx.lang_items().meta_update_trait() == Some(trait_def_id)
+                }
+            }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for ProtectedMetadataChecker<'tcx> {
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        if let hir::ExprKind::Assign(place, ..) | hir::ExprKind::AssignOp(_, place, _) = expr.kind {
+            self.check_write(place);
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
@@ -957,6 +957,9 @@ fn default_configuration(sess: &Session) -> CrateConfig {
         ret.insert((sym::sanitize, Some(symbol)));
     }
 
+    let metadata_protection = sess.opts.unstable_opts.metadata_protection;
+    ret.insert((sym::metadata_protection, Some(Symbol::intern(metadata_protection.as_str()))));
+
     if sess.opts.debug_assertions {
         ret.insert((sym::debug_assertions, None));
     }
@@ -1043,6 +1046,7 @@ fn fill_well_known_names(&mut self) {
             sym::target_feature,
             sym::panic,
             sym::sanitize,
+            sym::metadata_protection,
             sym::debug_assertions,
             sym::proc_macro,
             sym::test,
@@ -1084,6 +1088,9 @@ fn fill_well_known_values(&mut self) {
             .into_iter()
             .map(|sanitizer| Symbol::intern(sanitizer.as_str().unwrap()));
 
+        let metadata_protection_values =
+            MetadataProtection::all().map(|protection| Symbol::intern(protection.as_str()));
+
         // Unknown possible values:
         //  - `feature`
         //  - `target_feature`
@@ -1106,6 +1113,10 @@ fn fill_well_known_values(&mut self) {
         // Pre-defined values
         self.values_valid.entry(sym::panic).or_default().extend(panic_values);
         self.values_valid.entry(sym::sanitize).or_default().extend(sanitize_values);
+        self.values_valid
+            .entry(sym::metadata_protection)
+            .or_default()
+            .extend(metadata_protection_values);
         self.values_valid.entry(sym::target_has_atomic).or_default().extend(atomic_values);
         self.values_valid
             .entry(sym::target_has_atomic_load_store)
@@ -2757,9 +2768,9 @@ pub fn needs_analysis(&self) -> bool {
 pub(crate) mod dep_tracking {
     use super::{
         BranchProtection, CFGuard, CFProtection, CrateType, DebugInfo, ErrorOutputType,
-        InstrumentCoverage, LdImpl, LinkerPluginLto, LocationDetail, LtoCli, OomStrategy, OptLevel,
-        OutputType, OutputTypes, Passes, SourceFileHashAlgorithm, SplitDwarfKind,
-        SwitchWithOptPath, SymbolManglingVersion, TrimmedDefPaths,
+        InstrumentCoverage, LdImpl, LinkerPluginLto, LocationDetail, LtoCli, MetadataProtection,
+        OomStrategy, OptLevel, OutputType, OutputTypes, Passes, SourceFileHashAlgorithm,
+        SplitDwarfKind, SwitchWithOptPath, SymbolManglingVersion, TrimmedDefPaths,
     };
     use crate::lint;
     use crate::options::WasiExecModel;
@@ -2858,6 +2869,7 @@ fn hash(
         LocationDetail,
         BranchProtection,
         OomStrategy,
+        MetadataProtection,
         LanguageIdentifier,
     );
 
@@ -2970,6 +2982,47 @@ pub fn should_panic(self) -> u8 {
     }
 }
 
+/// How the metadata of smart pointers and collections is protected, selected with
+/// `-Z metadata-protection` and exposed to the library as `cfg(metadata_protection = "..")`.
+#[derive(Clone, Copy, PartialEq, Hash, Debug, Encodable, Decodable, HashStable_Generic)]
+pub enum MetadataProtection {
+    /// Metadata updates are neither checked nor write protected.
+    None,
+    /// Metadata updates are checked through `MetaUpdate::synchronize`.
+    Check,
+    /// Metadata updates are checked, and the records of the reference counts of `Rc` and `Arc`
+    /// are write protected with memory protection keys where the platform supports them.
+    Mpk,
+    /// Metadata updates are checked, and the allocations of `Rc` and `Arc`, counts and value
+    /// alike, are placed on pages of their own, surrounded by guard pages.
+    Guard,
+    /// Metadata updates are checked, and the headers of `Vec`, `String` and `VecDeque` are
+    /// mirrored in a shadow table, which their reads are compared with.
+    Shadow,
+}
+
+impl MetadataProtection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetadataProtection::None => "none",
+            MetadataProtection::Check => "check",
+            MetadataProtection::Mpk => "mpk",
+            MetadataProtection::Guard => "guard",
+            MetadataProtection::Shadow => "shadow",
+        }
+    }
+
+    pub fn all() -> [MetadataProtection; 5] {
+        [
+            MetadataProtection::None,
+            MetadataProtection::Check,
+            MetadataProtection::Mpk,
+            MetadataProtection::Guard,
+            MetadataProtection::Shadow,
+        ]
+    }
+}
+
 /// How to run proc-macro code when building this crate
 #[derive(Clone, Copy, PartialEq, Hash, Debug)]
 pub enum ProcMacroExecutionStrategy {
@@ -367,8 +367,10 @@ mod desc {
     pub const parse_panic_strategy: &str = "either `unwind` or `abort`";
     pub const parse_opt_panic_strategy: &str = parse_panic_strategy;
     pub const parse_oom_strategy: &str = "either `panic` or `abort`";
+    pub const parse_metadata_protection: &str =
+        "one of: `none`, `check`, `mpk`, `guard`, or `shadow`";
     pub const parse_relro_level: &str = "one of: `full`, `partial`, or `off`";
-    pub const parse_sanitizers: &str = "comma separated list of sanitizers: `address`, `cfi`, `hwaddress`, `leak`, `memory`, `memtag`, `shadow-call-stack`, or `thread`";
+    pub const parse_sanitizers: &str = "comma separated list of sanitizers: `address`, `cfi`, `hwaddress`, `leak`, `memory`, `memtag`, `metadata`, `shadow-call-stack`, or `thread`";
     pub const parse_sanitizer_memory_track_origins: &str = "0, 1, or 2";
     pub const parse_cfguard: &str =
         "either a boolean (`yes`, `no`, `on`, `off`, etc), `checks`, or `nochecks`";
@@ -658,6 +660,21 @@ pub(crate) fn parse_oom_strategy(slot: &mut OomStrategy, v: Option<&str>) -> boo
         true
     }
 
+    pub(crate) fn parse_metadata_protection(
+        slot: &mut MetadataProtection,
+        v: Option<&str>,
+    ) -> bool {
+        match v {
+            Some("none") => *slot = MetadataProtection::None,
+            Some("check") => *slot = MetadataProtection::Check,
+            Some("mpk") => *slot = MetadataProtection::Mpk,
+            Some("guard") => *slot = MetadataProtection::Guard,
+            Some("shadow") => *slot = MetadataProtection::Shadow,
+            _ => return false,
+        }
+        true
+    }
+
     pub(crate) fn parse_relro_level(slot: &mut Option<RelroLevel>, v: Option<&str>) -> bool {
         match v {
             Some(s) => match s.parse::<RelroLevel>() {
@@ -678,6 +695,7 @@ pub(crate) fn parse_sanitizers(slot: &mut SanitizerSet, v: Option<&str>) -> bool
                     "leak" => SanitizerSet::LEAK,
                     "memory" => SanitizerSet::MEMORY,
                     "memtag" => SanitizerSet::MEMTAG,
+                    "metadata" => SanitizerSet::METADATA,
                     "shadow-call-stack" => SanitizerSet::SHADOWCALLSTACK,
                     "thread" => SanitizerSet::THREAD,
                     "hwaddress" => SanitizerSet::HWADDRESS,
@@ -1388,6 +1406,10 @@ pub(crate) fn parse_proc_macro_execution_strategy(
     #[rustc_lint_opt_deny_field_access("use `Session::meta_stats` instead of this field")]
     meta_stats: bool = (false, parse_bool, [UNTRACKED],
         "gather metadata statistics (default: no)"),
+    metadata_protection: MetadataProtection = (MetadataProtection::Check,
+        parse_metadata_protection, [TRACKED],
+        "how the metadata of smart pointers and collections is protected \
+        (`none`, `check`, `mpk`, `guard` or `shadow`; default: `check`)"),
     mir_emit_retag: bool = (false, parse_bool, [TRACKED],
         "emit Retagging MIR statements, interpreted e.g., by miri; implies -Zmir-opt-level=0 \
         (default: no)"),
@@ -910,7 +910,8 @@ pub fn emit_lifetime_markers(&self) -> bool {
         // AddressSanitizer uses lifetimes to detect use after scope bugs.
         // MemorySanitizer uses lifetimes to detect use of uninitialized stack variables.
         // HWAddressSanitizer will use lifetimes to detect use after scope bugs in the future.
-        || self.opts.unstable_opts.sanitizer.intersects(SanitizerSet::ADDRESS | SanitizerSet::MEMORY | SanitizerSet::HWADDRESS)
+        // The metadata sanitizer drops dead locals from its shadow table at their storage markers.
+        || self.opts.unstable_opts.sanitizer.intersects(SanitizerSet::ADDRESS | SanitizerSet::MEMORY | SanitizerSet::HWADDRESS | SanitizerSet::METADATA)
     }
 
     pub fn is_proc_macro_attr(&self, attr: &Attribute) -> bool {
@@ -1503,8 +1504,10 @@ fn validate_commandline_args_with_session_available(sess: &Session) {
             sess.emit_err(SanitizersNotSupported { us: unsupported_sanitizers.to_string() });
         }
     }
-    // Cannot mix and match sanitizers.
-    let mut sanitizer_iter = sess.opts.unstable_opts.sanitizer.into_iter();
+    // Cannot mix and match sanitizers. The metadata sanitizer only instruments protected fields,
+    // and its runtime is part of `std`, so it composes with any of them.
+    let mut sanitizer_iter =
+        (sess.opts.unstable_opts.sanitizer - SanitizerSet::METADATA).into_iter();
     if let (Some(first), Some(second)) = (sanitizer_iter.next(), sanitizer_iter.next()) {
         sess.emit_err(CannotMixAndMatchSanitizers {
             first: first.to_string(),
@@ -1513,7 +1516,9 @@ fn validate_commandline_args_with_session_available(sess: &Session) {
     }
 
     // Cannot enable crt-static with sanitizers on Linux
-    if sess.crt_static(None) && !sess.opts.unstable_opts.sanitizer.is_empty() {
+    if sess.crt_static(None)
+        && !(sess.opts.unstable_opts.sanitizer - SanitizerSet::METADATA).is_empty()
+    {
         sess.emit_err(CannotEnableCrtStaticLinux);
     }
 
@@ -188,6 +188,8 @@
         Equal,
         Err,
         Error,
+        FIELDS,
+        Field,
         File,
         FileType,
         Fn,
@@ -225,6 +227,8 @@
         LinkedList,
         LintPass,
         LocalKey,
+        MetaUpdate,
+        MetadataField,
         Mutex,
         MutexGuard,
         N,
@@ -350,6 +354,7 @@
         alloc_error_handler,
         alloc_layout,
         alloc_zeroed,
+        allocation_layout,
         allocator,
         allocator_api,
         allocator_internals,
@@ -367,12 +372,15 @@
         append_const_msg,
         arbitrary_enum_discriminant,
         arbitrary_self_types,
+        arc_from_raw,
+        arc_into_raw,
         args,
         arith_offset,
         arm,
         arm_target_feature,
         array,
         arrays,
+        as_mut_ptr,
         as_ptr,
         as_ref,
         as_str,
@@ -434,7 +442,10 @@
         borrowck_graphviz_format,
         borrowck_graphviz_postflow,
         borrowck_graphviz_preflow,
+        bound,
         box_free,
+        box_from_raw,
+        box_into_raw,
         box_patterns,
         box_syntax,
         bpf_target_feature,
@@ -450,6 +461,7 @@
         call_mut,
         call_once,
         caller_location,
+        capacity,
         capture_disjoint_fields,
         cause,
         cdylib,
@@ -463,6 +475,7 @@
         cfg_eval,
         cfg_hide,
         cfg_macro,
+        cfg_metadata_protection,
         cfg_panic,
         cfg_sanitize,
         cfg_target_abi,
@@ -499,6 +512,8 @@
         compare_and_swap,
         compare_exchange,
         compare_exchange_weak,
+        compartment_new,
+        compartment_take,
         compile_error,
         compile_error_macro,
         compiler,
@@ -845,6 +860,8 @@
         intel,
         into_future,
         into_iter,
+        into_raw_parts,
+        into_raw_parts_with_alloc,
         intra_doc_pointers,
         intrinsics,
         irrefutable_let_patterns,
@@ -919,6 +936,7 @@
         managed_boxes,
         manually_drop,
         map,
+        map_or,
         marker,
         marker_trait_attr,
         masked,
@@ -946,7 +964,12 @@
         memtag,
         message,
         meta,
+        meta_update,
+        metadata,
+        metadata_compartment,
+        metadata_protection,
         metadata_type,
+        metadata_update,
         min_align_of,
         min_align_of_val,
         min_const_fn,
@@ -1046,6 +1069,7 @@
         object_safe_for_dispatch,
         of,
         offset,
+        old,
         omit_gdb_pretty_printer_section,
         on,
         on_unimplemented,
@@ -1139,6 +1163,9 @@
         proc_macro_path_invoc,
         profiler_builtins,
         profiler_runtime,
+        protected_metadata_check,
+        protected_metadata_close,
+        protected_metadata_open,
         ptr,
         ptr_guaranteed_cmp,
         ptr_mask,
@@ -1146,6 +1173,8 @@
         ptr_null_mut,
         ptr_offset_from,
         ptr_offset_from_unsigned,
+        ptr_read,
+        ptr_write,
         pub_macro_rules,
         pub_restricted,
         public,
@@ -1162,6 +1191,8 @@
         raw_eq,
         raw_identifiers,
         raw_ref_op,
+        rc_from_raw,
+        rc_into_raw,
         re_rebalance_coherence,
         read_enum,
         read_enum_variant,
@@ -1202,6 +1233,8 @@
         repr_simd,
         repr_transparent,
         require,
+        reserve,
+        reserve_exact,
         residual,
         result,
         return_position_impl_trait_in_trait,
@@ -1297,6 +1330,7 @@
         rustc_private,
         rustc_proc_macro_decls,
         rustc_promotable,
+        rustc_protected_metadata,
         rustc_reallocator,
         rustc_regions,
         rustc_reservation_impl,
@@ -1308,6 +1342,7 @@
         rustc_std_internal_symbol,
         rustc_strict_coherence,
         rustc_symbol_name,
+        rustc_synchronizes_metadata,
         rustc_test_marker,
         rustc_then_this_would_need,
         rustc_trivial_field_reads,
@@ -1446,6 +1481,7 @@
         str_trim_end,
         str_trim_start,
         strict_provenance,
+        string_from_raw_parts,
         stringify,
         stringify_macro,
         struct_field_attributes,
@@ -1461,6 +1497,7 @@
         suggestion,
         sym,
         sync,
+        synchronize,
         t32,
         target,
         target_abi,
@@ -1519,6 +1556,8 @@
         try_capture,
         try_from,
         try_into,
+        try_reserve,
+        try_reserve_exact,
         try_trait_v2,
         tt,
         tuple,
@@ -1531,6 +1570,7 @@
         type_alias_impl_trait,
         type_ascription,
         type_changing_struct_update,
+        type_family_id,
         type_id,
         type_length_limit,
         type_macros,
@@ -1609,7 +1649,11 @@
         var,
         variant_count,
         vec,
+        vec_as_mut_ptr,
+        vec_from_raw_parts,
+        vec_from_raw_parts_in,
         vec_macro,
+        vec_set_len,
         version,
         vfp2,
         vis,
@@ -1632,6 +1676,7 @@
         width,
         windows,
         windows_subsystem,
+        with_capacity,
         with_negative_coherence,
         wrapping_add,
         wrapping_mul,
@@ -1,7 +1,7 @@
 use rustc_hir as hir;
 use rustc_index::bit_set::BitSet;
 use rustc_index::vec::{Idx, IndexVec};
-use rustc_middle::mir::{GeneratorLayout, GeneratorSavedLocal};
+use rustc_middle::mir::{Field, GeneratorLayout, GeneratorSavedLocal};
 use rustc_middle::ty::layout::{
     IntegerExt, LayoutCx, LayoutError, LayoutOf, TyAndLayout, MAX_SIMD_LANES,
 };
@@ -635,14 +635,19 @@ fn layout_of_uncached<'tcx>(
 
         // ADTs.
         ty::Adt(def, substs) => {
-            // Cache the field layouts.
+            // Cache the field layouts. Fields relocated into the metadata compartment are laid
+            // out as the pointer to their value.
             let variants = def
                 .variants()
                 .iter()
                 .map(|v| {
                     v.fields
                         .iter()
-                        .map(|field| cx.layout_of(field.ty(tcx, substs)))
+                        .enumerate()
+                        .map(|(i, field)| {
+                            let field_ty = field.ty(tcx, substs);
+                            cx.layout_of(tcx.field_storage_ty(def, Field::new(i), field_ty))
+                        })
                         .collect::<Result<Vec<_>, _>>()
                 })
                 .collect::<Result<IndexVec<VariantIdx, _>, _>>()?;
@@ -16,6 +16,8 @@
 
 use core::marker::Destruct;
 
+use crate::metadata_ownership;
+
 #[cfg(test)]
 mod tests;
 
@@ -43,6 +45,10 @@
     #[cfg_attr(not(bootstrap), rustc_nounwind)]
     #[cfg_attr(bootstrap, rustc_allocator_nounwind)]
     fn __rust_alloc_zeroed(size: usize, align: usize) -> *mut u8;
+    // The usable size of the block at `ptr`, or 0 if the allocator cannot tell.
+    #[cfg(not(bootstrap))]
+    #[rustc_nounwind]
+    fn __rust_allocation_layout(ptr: *mut u8) -> usize;
 }
 
 /// The global memory allocator.
@@ -114,6 +120,7 @@ pub unsafe fn alloc(layout: Layout) -> *mut u8 {
 #[stable(feature = "global_alloc", since = "1.28.0")]
 #[inline]
 pub unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
+    metadata_ownership::forget(ptr);
     unsafe { __rust_dealloc(ptr, layout.size(), layout.align()) }
 }
 
@@ -133,6 +140,7 @@ pub unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
 #[must_use = "losing the pointer will leak memory"]
 #[inline]
 pub unsafe fn realloc(ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    metadata_ownership::forget(ptr);
     unsafe { __rust_realloc(ptr, layout.size(), layout.align(), new_size) }
 }
 
@@ -319,6 +327,19 @@ unsafe fn shrink(
             },
         }
     }
+
+    #[cfg(not(bootstrap))]
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        let size = unsafe { __rust_allocation_layout(ptr.as_ptr()) };
+        if size == 0 {
+            return None;
+        }
+        // Only the size is passed through the allocator shim, but the block is
+        // aligned to at least the alignment of its address.
+        Layout::from_size_align(size, 1 << ptr.addr().trailing_zeros()).ok()
+    }
 }
 
 /// The allocator for unique pointers.
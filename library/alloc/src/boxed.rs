@@ -166,7 +166,8 @@
     CoerceUnsized, Deref, DerefMut, DispatchFromDyn, Generator, GeneratorState, Receiver,
 };
 use core::pin::Pin;
-use core::ptr::{self, Unique};
+use core::ptr::metadata_update::{metadata_violation, METADATA_CHECKS};
+use core::ptr::{self, NonNull, Unique};
 use core::task::{Context, Poll};
 
 #[cfg(not(no_global_oom_handling))]
@@ -174,6 +175,7 @@
 use crate::alloc::{AllocError, Allocator, Global, Layout};
 #[cfg(not(no_global_oom_handling))]
 use crate::borrow::Cow;
+use crate::metadata_ownership::{self, Owner};
 use crate::raw_vec::RawVec;
 #[cfg(not(no_global_oom_handling))]
 use crate::str::from_boxed_utf8_unchecked;
@@ -490,7 +492,7 @@ pub const fn try_new_uninit_in(alloc: A) -> Result<Box<mem::MaybeUninit<T>, A>,
         A: ~const Allocator + ~const Destruct,
     {
         let layout = Layout::new::<mem::MaybeUninit<T>>();
-        let ptr = alloc.allocate(layout)?.cast();
+        let ptr = alloc.allocate_for::<T>(layout)?.cast();
         unsafe { Ok(Box::from_raw_in(ptr.as_ptr(), alloc)) }
     }
 
@@ -562,7 +564,7 @@ pub const fn try_new_zeroed_in(alloc: A) -> Result<Box<mem::MaybeUninit<T>, A>,
         A: ~const Allocator + ~const Destruct,
     {
         let layout = Layout::new::<mem::MaybeUninit<T>>();
-        let ptr = alloc.allocate_zeroed(layout)?.cast();
+        let ptr = alloc.allocate_zeroed_for::<T>(layout)?.cast();
         unsafe { Ok(Box::from_raw_in(ptr.as_ptr(), alloc)) }
     }
 
@@ -955,7 +957,22 @@ impl<T: ?Sized> Box<T> {
     #[stable(feature = "box_raw", since = "1.4.0")]
     #[inline]
     #[must_use = "call `drop(from_raw(ptr))` if you intend to drop the `Box`"]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "box_from_raw")]
     pub unsafe fn from_raw(raw: *mut T) -> Self {
+        // SAFETY: the caller guarantees that `raw` points to a value of `T`.
+        let layout = unsafe { Layout::for_value_raw(raw) };
+        if METADATA_CHECKS && layout.size() != 0 {
+            // SAFETY: the caller guarantees that `raw` was allocated by the global allocator.
+            let block = unsafe { Global.allocation_layout(NonNull::new_unchecked(raw.cast())) };
+            if let Some(block) = block.filter(|block| block.size() < layout.size()) {
+                metadata_violation("Box", "size", block.size(), layout.size());
+                panic!("cannot rebuild a `Box` larger than its allocation");
+            }
+        }
+        if !metadata_ownership::claim("Box", raw.cast(), layout, Owner::Unique) {
+            // Unlike a `Vec`, there is no empty box to return instead.
+            panic!("cannot rebuild a `Box` from an allocation it may not own");
+        }
         unsafe { Self::from_raw_in(raw, Global) }
     }
 }
@@ -1056,8 +1073,13 @@ impl<T: ?Sized, A: Allocator> Box<T, A> {
     /// [memory layout]: self#memory-layout
     #[stable(feature = "box_raw", since = "1.4.0")]
     #[inline]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "box_into_raw")]
     pub fn into_raw(b: Self) -> *mut T {
-        Self::into_raw_with_allocator(b).0
+        let raw = Self::into_raw_with_allocator(b).0;
+        // SAFETY: `raw` points to the value the box owned, which is still allocated.
+        let layout = unsafe { Layout::for_value_raw(raw) };
+        metadata_ownership::release::<A>(raw.cast(), layout, Owner::Unique);
+        raw
     }
 
     /// Consumes the `Box`, returning a wrapped raw pointer and the allocator.
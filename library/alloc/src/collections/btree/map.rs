@@ -9,6 +9,9 @@
 use core::ops::{Index, RangeBounds};
 use core::ptr;
 
+/*SOR-MetaUpdate@kayondomartin*/
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
+
 use crate::alloc::{Allocator, Global};
 
 use super::borrow::DormantMutRef;
@@ -174,7 +177,9 @@ pub struct BTreeMap<
     V,
     #[unstable(feature = "allocator_api", issue = "32838")] A: Allocator + Clone = Global,
 > {
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     root: Option<Root<K, V>>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     length: usize,
     /// `ManuallyDrop` to control drop order (needs to be dropped after all the nodes).
     pub(super) alloc: ManuallyDrop<A>,
@@ -222,21 +227,21 @@ fn clone_subtree<'a, K: Clone, V: Clone, A: Allocator + Clone>(
                         _marker: PhantomData,
                     };
 
-                    {
+                    let mut in_edge = leaf.first_edge();
+                    while let Ok(kv) = in_edge.right_kv() {
+                        let (k, v) = kv.into_kv();
+                        in_edge = kv.right_edge();
+
+                        // The node is borrowed anew for every element, so that
+                        // the length can be updated in between.
                         let root = out_tree.root.as_mut().unwrap(); // unwrap succeeds because we just wrapped
-                        let mut out_node = match root.borrow_mut().force() {
-                            Leaf(leaf) => leaf,
+                        match root.borrow_mut().force() {
+                            Leaf(mut out_node) => {
+                                out_node.push(k.clone(), v.clone());
+                            }
                             Internal(_) => unreachable!(),
-                        };
-
-                        let mut in_edge = leaf.first_edge();
-                        while let Ok(kv) = in_edge.right_kv() {
-                            let (k, v) = kv.into_kv();
-                            in_edge = kv.right_edge();
-
-                            out_node.push(k.clone(), v.clone());
-                            out_tree.length += 1;
                         }
+                        out_tree.set_length(out_tree.length + 1);
                     }
 
                     out_tree
@@ -245,34 +250,36 @@ fn clone_subtree<'a, K: Clone, V: Clone, A: Allocator + Clone>(
                     let mut out_tree =
                         clone_subtree(internal.first_edge().descend(), alloc.clone());
 
-                    {
+                    out_tree.root.as_mut().unwrap().push_internal_level(alloc.clone());
+                    let mut in_edge = internal.first_edge();
+                    while let Ok(kv) = in_edge.right_kv() {
+                        let (k, v) = kv.into_kv();
+                        in_edge = kv.right_edge();
+
+                        let k = (*k).clone();
+                        let v = (*v).clone();
+                        let subtree = clone_subtree(in_edge.descend(), alloc.clone());
+
+                        // We can't destructure subtree directly
+                        // because BTreeMap implements Drop
+                        let (subroot, sublength) = unsafe {
+                            let subtree = ManuallyDrop::new(subtree);
+                            let root = ptr::read(&subtree.root);
+                            let length = subtree.length;
+                            (root, length)
+                        };
+
+                        // As above, the node is borrowed anew for every element.
                         let out_root = out_tree.root.as_mut().unwrap();
-                        let mut out_node = out_root.push_internal_level(alloc.clone());
-                        let mut in_edge = internal.first_edge();
-                        while let Ok(kv) = in_edge.right_kv() {
-                            let (k, v) = kv.into_kv();
-                            in_edge = kv.right_edge();
-
-                            let k = (*k).clone();
-                            let v = (*v).clone();
-                            let subtree = clone_subtree(in_edge.descend(), alloc.clone());
-
-                            // We can't destructure subtree directly
-                            // because BTreeMap implements Drop
-                            let (subroot, sublength) = unsafe {
-                                let subtree = ManuallyDrop::new(subtree);
-                                let root = ptr::read(&subtree.root);
-                                let length = subtree.length;
-                                (root, length)
-                            };
-
-                            out_node.push(
+                        match out_root.borrow_mut().force() {
+                            Internal(mut out_node) => out_node.push(
                                 k,
                                 v,
                                 subroot.unwrap_or_else(|| Root::new(alloc.clone())),
-                            );
-                            out_tree.length += 1 + sublength;
+                            ),
+                            Leaf(_) => unreachable!(),
                         }
+                        out_tree.set_length(out_tree.length + 1 + sublength);
                     }
 
                     out_tree
@@ -588,6 +595,44 @@ pub const fn new() -> BTreeMap<K, V> {
 }
 
 impl<K, V, A: Allocator + Clone> BTreeMap<K, V, A> {
+    /// Sets the number of elements in the map. Every write of `length` on the
+    /// map itself goes through here, so that it is synchronized through
+    /// `MetaUpdate`. Bulk building and `drain_filter` count through a borrowed
+    /// `&mut usize`, and are not synchronized.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_length(&mut self, length: usize) {
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(
+                self,
+                BTreeMapField::Length,
+                self.length,
+                length,
+            )
+        {
+            self.length = length;
+        } else {
+            self.metadata_violation(BTreeMapField::Length, self.length, length);
+        }
+    }
+
+    /// Plants a new root. The root is otherwise only replaced in place, by the
+    /// node operations.
+    #[inline]
+    fn set_root(&mut self, root: Root<K, V>) {
+        self.root = Some(root);
+    }
+
+    // Reports a rejected update, and leaks the tree: its elements can no
+    // longer be counted, so the map is left empty instead.
+    #[cold]
+    #[inline(never)]
+    fn metadata_violation(&mut self, field: BTreeMapField, old: usize, new: usize) {
+        metadata_update::metadata_violation("BTreeMap", field.name(), old, new);
+        self.root = None;
+        self.length = 0;
+    }
+
     /// Clears the map, removing all elements.
     ///
     /// # Examples
@@ -1332,7 +1377,7 @@ pub fn split_off<Q: ?Sized + Ord>(&mut self, key: &Q) -> Self
         let right_root = left_root.split_off(key, (*self.alloc).clone());
 
         let (new_left_len, right_len) = Root::calc_split_length(total_num, &left_root, &right_root);
-        self.length = new_left_len;
+        self.set_length(new_left_len);
 
         BTreeMap {
             root: Some(right_root),
@@ -2424,5 +2469,40 @@ pub const fn is_empty(&self) -> bool {
     }
 }
 
+/// The protected metadata of a `BTreeMap`.
+/// The root is replaced in place by the node operations, and is not a value
+/// that can be synchronized on its own.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BTreeMapField {
+    /// The number of elements in the map.
+    Length,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for BTreeMapField {
+    const FIELDS: &'static [Self] = &[BTreeMapField::Length];
+
+    fn name(self) -> &'static str {
+        match self {
+            BTreeMapField::Length => "length",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<K, V, A: Allocator + Clone> MetaUpdate for BTreeMap<K, V, A> {
+    type Field = BTreeMapField;
+
+    /// Synchronize a metadata update with the map.
+    /// `old` must be the value the field currently holds. A non-empty map
+    /// always has a root.
+    fn synchronize(&self, field: BTreeMapField, old: usize, new: usize) -> bool {
+        match field {
+            BTreeMapField::Length => old == self.length && (new == 0 || self.root.is_some()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests;
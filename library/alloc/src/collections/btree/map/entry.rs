@@ -354,15 +354,15 @@ pub fn insert(self, value: V) -> &'a mut V {
                 let map = unsafe { self.dormant_map.awaken() };
                 let mut root = NodeRef::new_leaf(self.alloc.clone());
                 let val_ptr = root.borrow_mut().push(self.key, value) as *mut V;
-                map.root = Some(root.forget_type());
-                map.length = 1;
+                map.set_root(root.forget_type());
+                map.set_length(1);
                 val_ptr
             }
             Some(handle) => match handle.insert_recursing(self.key, value, self.alloc.clone()) {
                 (None, val_ptr) => {
                     // SAFETY: We have consumed self.handle.
                     let map = unsafe { self.dormant_map.awaken() };
-                    map.length += 1;
+                    map.set_length(map.length + 1);
                     val_ptr
                 }
                 (Some(ins), val_ptr) => {
@@ -372,7 +372,7 @@ pub fn insert(self, value: V) -> &'a mut V {
                     let map = unsafe { self.dormant_map.awaken() };
                     let root = map.root.as_mut().unwrap(); // same as ins.left
                     root.push_internal_level(self.alloc).push(ins.kv.0, ins.kv.1, ins.right);
-                    map.length += 1;
+                    map.set_length(map.length + 1);
                     val_ptr
                 }
             },
@@ -555,7 +555,7 @@ pub(super) fn remove_kv(self) -> (K, V) {
             self.handle.remove_kv_tracking(|| emptied_internal_root = true, self.alloc.clone());
         // SAFETY: we consumed the intermediate root borrow, `self.handle`.
         let map = unsafe { self.dormant_map.awaken() };
-        map.length -= 1;
+        map.set_length(map.length - 1);
         if emptied_internal_root {
             let root = map.root.as_mut().unwrap();
             root.pop_internal_level(self.alloc);
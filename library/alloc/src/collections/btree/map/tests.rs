@@ -2336,3 +2336,20 @@ fn from_array() {
     let unordered_duplicates = BTreeMap::from([(3, 4), (1, 2), (1, 2)]);
     assert_eq!(map, unordered_duplicates);
 }
+
+#[test]
+fn test_metadata_synchronize() {
+    let mut map = BTreeMap::new();
+    assert!(map.synchronize(BTreeMapField::Length, 0, 0));
+    assert!(!map.synchronize(BTreeMapField::Length, 0, 1));
+    map.insert(1, 2);
+    assert!(map.synchronize(BTreeMapField::Length, 1, 2));
+    assert!(!map.synchronize(BTreeMapField::Length, 2, 3));
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `BTreeMap::length`")]
+fn test_metadata_violation() {
+    let mut map: BTreeMap<i32, i32> = BTreeMap::new();
+    map.set_length(1);
+}
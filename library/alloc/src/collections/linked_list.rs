@@ -20,6 +20,9 @@
 use core::mem;
 use core::ptr::NonNull;
 
+/*SOR-MetaUpdate@kayondomartin*/
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
+
 use super::SpecExtend;
 use crate::boxed::Box;
 
@@ -48,8 +51,11 @@
 #[cfg_attr(not(test), rustc_diagnostic_item = "LinkedList")]
 #[rustc_insignificant_dtor]
 pub struct LinkedList<T> {
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     head: Option<NonNull<Node<T>>>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     tail: Option<NonNull<Node<T>>>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     len: usize,
     marker: PhantomData<Box<Node<T>>>,
 }
@@ -156,6 +162,65 @@ fn into_element(self: Box<Self>) -> T {
 
 // private methods
 impl<T> LinkedList<T> {
+    /// Sets the first node of the list. Every write of `head` goes through
+    /// here, so that it is synchronized through `MetaUpdate`.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_head(&mut self, head: Option<NonNull<Node<T>>>) {
+        let (old, new) = (Self::addr(self.head), Self::addr(head));
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, LinkedListField::Head, old, new)
+        {
+            self.head = head;
+        } else {
+            self.metadata_violation(LinkedListField::Head, old, new);
+        }
+    }
+
+    /// Sets the last node of the list, see `set_head`.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_tail(&mut self, tail: Option<NonNull<Node<T>>>) {
+        let (old, new) = (Self::addr(self.tail), Self::addr(tail));
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, LinkedListField::Tail, old, new)
+        {
+            self.tail = tail;
+        } else {
+            self.metadata_violation(LinkedListField::Tail, old, new);
+        }
+    }
+
+    /// Sets the number of nodes in the list, see `set_head`.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_len(&mut self, len: usize) {
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, LinkedListField::Len, self.len, len)
+        {
+            self.len = len;
+        } else {
+            self.metadata_violation(LinkedListField::Len, self.len, len);
+        }
+    }
+
+    /// The address a node is synchronized as, 0 for no node.
+    #[inline]
+    fn addr(node: Option<NonNull<Node<T>>>) -> usize {
+        node.map_or(0, |node| node.as_ptr().addr())
+    }
+
+    // Reports a rejected update, and leaks the nodes: the list can no longer
+    // be walked safely, so it is left empty instead.
+    #[cold]
+    #[inline(never)]
+    fn metadata_violation(&mut self, field: LinkedListField, old: usize, new: usize) {
+        metadata_update::metadata_violation("LinkedList", field.name(), old, new);
+        self.head = None;
+        self.tail = None;
+        self.len = 0;
+    }
+
     /// Adds the given node to the front of the list.
     #[inline]
     fn push_front_node(&mut self, mut node: Box<Node<T>>) {
@@ -167,13 +232,13 @@ fn push_front_node(&mut self, mut node: Box<Node<T>>) {
             let node = Some(Box::leak(node).into());
 
             match self.head {
-                None => self.tail = node,
+                None => self.set_tail(node),
                 // Not creating new mutable (unique!) references overlapping `element`.
                 Some(head) => (*head.as_ptr()).prev = node,
             }
 
-            self.head = node;
-            self.len += 1;
+            self.set_head(node);
+            self.set_len(self.len + 1);
         }
     }
 
@@ -184,15 +249,15 @@ fn pop_front_node(&mut self) -> Option<Box<Node<T>>> {
         // to maintain validity of aliasing pointers into `element`.
         self.head.map(|node| unsafe {
             let node = Box::from_raw(node.as_ptr());
-            self.head = node.next;
+            self.set_head(node.next);
 
             match self.head {
-                None => self.tail = None,
+                None => self.set_tail(None),
                 // Not creating new mutable (unique!) references overlapping `element`.
                 Some(head) => (*head.as_ptr()).prev = None,
             }
 
-            self.len -= 1;
+            self.set_len(self.len - 1);
             node
         })
     }
@@ -208,13 +273,13 @@ fn push_back_node(&mut self, mut node: Box<Node<T>>) {
             let node = Some(Box::leak(node).into());
 
             match self.tail {
-                None => self.head = node,
+                None => self.set_head(node),
                 // Not creating new mutable (unique!) references overlapping `element`.
                 Some(tail) => (*tail.as_ptr()).next = node,
             }
 
-            self.tail = node;
-            self.len += 1;
+            self.set_tail(node);
+            self.set_len(self.len + 1);
         }
     }
 
@@ -225,15 +290,15 @@ fn pop_back_node(&mut self) -> Option<Box<Node<T>>> {
         // to maintain validity of aliasing pointers into `element`.
         self.tail.map(|node| unsafe {
             let node = Box::from_raw(node.as_ptr());
-            self.tail = node.prev;
+            self.set_tail(node.prev);
 
             match self.tail {
-                None => self.head = None,
+                None => self.set_head(None),
                 // Not creating new mutable (unique!) references overlapping `element`.
                 Some(tail) => (*tail.as_ptr()).next = None,
             }
 
-            self.len -= 1;
+            self.set_len(self.len - 1);
             node
         })
     }
@@ -252,16 +317,16 @@ unsafe fn unlink_node(&mut self, mut node: NonNull<Node<T>>) {
         match node.prev {
             Some(prev) => unsafe { (*prev.as_ptr()).next = node.next },
             // this node is the head node
-            None => self.head = node.next,
+            None => self.set_head(node.next),
         };
 
         match node.next {
             Some(next) => unsafe { (*next.as_ptr()).prev = node.prev },
             // this node is the tail node
-            None => self.tail = node.prev,
+            None => self.set_tail(node.prev),
         };
 
-        self.len -= 1;
+        self.set_len(self.len - 1);
     }
 
     /// Splices a series of nodes between two existing nodes.
@@ -283,21 +348,21 @@ unsafe fn splice_nodes(
                 existing_prev.as_mut().next = Some(splice_start);
             }
         } else {
-            self.head = Some(splice_start);
+            self.set_head(Some(splice_start));
         }
         if let Some(mut existing_next) = existing_next {
             unsafe {
                 existing_next.as_mut().prev = Some(splice_end);
             }
         } else {
-            self.tail = Some(splice_end);
+            self.set_tail(Some(splice_end));
         }
         unsafe {
             splice_start.as_mut().prev = existing_prev;
             splice_end.as_mut().next = existing_next;
         }
 
-        self.len += splice_length;
+        self.set_len(self.len + splice_length);
     }
 
     /// Detaches all nodes from a linked list as a series of nodes.
@@ -347,8 +412,8 @@ unsafe fn split_off_before_node(
             };
 
             // Fix the head ptr of the second part
-            self.head = Some(split_node);
-            self.len = self.len - at;
+            self.set_head(Some(split_node));
+            self.set_len(self.len - at);
 
             first_part
         } else {
@@ -387,8 +452,8 @@ unsafe fn split_off_after_node(
             };
 
             // Fix the tail ptr of the first part
-            self.tail = Some(split_node);
-            self.len = at;
+            self.set_tail(Some(split_node));
+            self.set_len(at);
 
             second_part
         } else {
@@ -460,14 +525,17 @@ pub fn append(&mut self, other: &mut Self) {
             Some(mut tail) => {
                 // `as_mut` is okay here because we have exclusive access to the entirety
                 // of both lists.
-                if let Some(mut other_head) = other.head.take() {
+                if let Some(mut other_head) = other.head {
                     unsafe {
                         tail.as_mut().next = Some(other_head);
                         other_head.as_mut().prev = Some(tail);
                     }
 
-                    self.tail = other.tail.take();
-                    self.len += mem::replace(&mut other.len, 0);
+                    self.set_tail(other.tail);
+                    self.set_len(self.len + other.len);
+                    other.set_head(None);
+                    other.set_tail(None);
+                    other.set_len(0);
                 }
             }
         }
@@ -2010,3 +2078,52 @@ unsafe impl<T: Send> Send for CursorMut<'_, T> {}
 
 #[unstable(feature = "linked_list_cursors", issue = "58533")]
 unsafe impl<T: Sync> Sync for CursorMut<'_, T> {}
+
+/// The protected metadata of a `LinkedList`.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkedListField {
+    /// The address of the first node, or 0 if the list is empty.
+    Head,
+    /// The address of the last node, or 0 if the list is empty.
+    Tail,
+    /// The number of nodes in the list.
+    Len,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for LinkedListField {
+    const FIELDS: &'static [Self] =
+        &[LinkedListField::Head, LinkedListField::Tail, LinkedListField::Len];
+
+    fn name(self) -> &'static str {
+        match self {
+            LinkedListField::Head => "head",
+            LinkedListField::Tail => "tail",
+            LinkedListField::Len => "len",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T> MetaUpdate for LinkedList<T> {
+    type Field = LinkedListField;
+
+    /// Synchronize a metadata update with the list.
+    /// `old` must be the value the field currently holds. A new head or tail
+    /// must be null or aligned for a node, and a new length must not exceed the
+    /// number of nodes that fit in the address space.
+    fn synchronize(&self, field: LinkedListField, old: usize, new: usize) -> bool {
+        match field {
+            LinkedListField::Head => {
+                old == Self::addr(self.head) && new % mem::align_of::<Node<T>>() == 0
+            }
+            LinkedListField::Tail => {
+                old == Self::addr(self.tail) && new % mem::align_of::<Node<T>>() == 0
+            }
+            LinkedListField::Len => {
+                old == self.len && new <= isize::MAX as usize / mem::size_of::<Node<T>>()
+            }
+        }
+    }
+}
@@ -1154,3 +1154,25 @@ fn drop(&mut self) {
 
     assert_eq!(unsafe { DROPS }, 8);
 }
+
+#[test]
+fn test_metadata_synchronize() {
+    let mut list = LinkedList::new();
+    list.push_back(1u32);
+    list.push_back(2u32);
+    let head = list.head.unwrap().as_ptr().addr();
+    assert!(list.synchronize(LinkedListField::Len, 2, 3));
+    assert!(!list.synchronize(LinkedListField::Len, 3, 4));
+    assert!(!list.synchronize(LinkedListField::Len, 2, usize::MAX));
+    assert!(list.synchronize(LinkedListField::Head, head, 0));
+    assert!(!list.synchronize(LinkedListField::Tail, head, 0));
+    assert!(!list.synchronize(LinkedListField::Head, head, head + 1));
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `LinkedList::len`")]
+fn test_metadata_violation() {
+    let mut list = LinkedList::new();
+    list.push_back(1u32);
+    list.set_len(usize::MAX);
+}
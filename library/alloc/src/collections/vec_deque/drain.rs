@@ -2,12 +2,16 @@
 use core::iter::FusedIterator;
 use core::marker::PhantomData;
 use core::mem::{self, MaybeUninit};
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
 use core::ptr::{self, NonNull};
 
 use crate::alloc::{Allocator, Global};
 
 use super::{count, wrap_index, VecDeque};
 
+#[cfg(test)]
+mod tests;
+
 /// A draining iterator over the elements of a `VecDeque`.
 ///
 /// This `struct` is created by the [`drain`] method on [`VecDeque`]. See its
@@ -39,7 +43,83 @@ pub(super) unsafe fn new(
         deque: NonNull<VecDeque<T, A>>,
     ) -> Self {
         let ring = unsafe { NonNull::new_unchecked(ring as *const [MaybeUninit<T>] as *mut _) };
-        Drain { after_tail, after_head, ring, tail, head, deque, _phantom: PhantomData }
+        let mut drain =
+            Drain { after_tail, after_head, ring, tail, head, deque, _phantom: PhantomData };
+        drain.check(true);
+        drain
+    }
+
+    /// Checks that the remaining elements are within the ring buffer, and that
+    /// the ring buffer is the one of the deque, before one of them is read.
+    /// Only when the iterator is created and before the remaining elements are
+    /// dropped, `allocation` is set and the ring buffer is also compared with
+    /// the block the allocator handed out for the deque.
+    ///
+    /// If they are not, the violation is reported, the remaining elements are
+    /// leaked, and `false` is returned.
+    #[inline]
+    fn check(&mut self, allocation: bool) -> bool {
+        if !METADATA_CHECKS {
+            return true;
+        }
+        let len = self.ring.len();
+        let deque = unsafe { self.deque.as_ref() };
+        let in_deque = self.ring.as_mut_ptr() == deque.ptr()
+            && len == deque.cap()
+            && (!allocation || deque.buf.fits_allocation(len));
+        let indices = [(DrainField::Tail, self.tail), (DrainField::Head, self.head)];
+        let Some(&(field, index)) = indices
+            .iter()
+            .find(|&&(field, index)| !(in_deque && self.synchronize(field, index, index)))
+        else {
+            return true;
+        };
+        self.leak_remaining(field, index);
+        false
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn leak_remaining(&mut self, field: DrainField, index: usize) {
+        self.tail = 0;
+        self.head = 0;
+        metadata_update::metadata_violation("vec_deque::Drain", field.name(), index, index);
+    }
+}
+
+/// The metadata fields of a deque's [`Drain`].
+///
+/// They locate the remaining elements in the ring buffer of the deque.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrainField {
+    /// The index of the first remaining element.
+    Tail,
+    /// The index right after the last remaining element.
+    Head,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for DrainField {
+    const FIELDS: &'static [Self] = &[DrainField::Tail, DrainField::Head];
+
+    fn name(self) -> &'static str {
+        match self {
+            DrainField::Tail => "tail",
+            DrainField::Head => "head",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, A: Allocator> MetaUpdate for Drain<'_, T, A> {
+    type Field = DrainField;
+
+    /// Synchronize an update of the remaining elements with the deque.
+    /// Both indices must be within the ring buffer, which `check` compares
+    /// with the allocation of the deque.
+    fn synchronize(&self, _field: DrainField, _old: usize, new: usize) -> bool {
+        new < self.ring.len()
     }
 }
 
@@ -86,25 +166,25 @@ fn drop(&mut self) {
                 let head_len = count(drain_head, orig_head, source_deque.cap());
 
                 // Restore the original head value
-                source_deque.head = orig_head;
+                source_deque.set_head(orig_head);
 
                 match (tail_len, head_len) {
                     (0, 0) => {
-                        source_deque.head = 0;
-                        source_deque.tail = 0;
+                        source_deque.set_head(0);
+                        source_deque.set_tail(0);
                     }
                     (0, _) => {
-                        source_deque.tail = drain_head;
+                        source_deque.set_tail(drain_head);
                     }
                     (_, 0) => {
-                        source_deque.head = drain_tail;
+                        source_deque.set_head(drain_tail);
                     }
                     _ => unsafe {
                         if tail_len <= head_len {
-                            source_deque.tail = source_deque.wrap_sub(drain_head, tail_len);
+                            source_deque.set_tail(source_deque.wrap_sub(drain_head, tail_len));
                             source_deque.wrap_copy(source_deque.tail, orig_tail, tail_len);
                         } else {
-                            source_deque.head = source_deque.wrap_add(drain_tail, head_len);
+                            source_deque.set_head(source_deque.wrap_add(drain_tail, head_len));
                             source_deque.wrap_copy(drain_tail, drain_head, head_len);
                         }
                     },
@@ -112,6 +192,9 @@ fn drop(&mut self) {
             }
         }
 
+        // The remaining elements are only dropped once they are known to be in
+        // the allocation.
+        self.check(true);
         while let Some(item) = self.next() {
             let guard = DropGuard(self);
             drop(item);
@@ -128,7 +211,7 @@ impl<T, A: Allocator> Iterator for Drain<'_, T, A> {
 
     #[inline]
     fn next(&mut self) -> Option<T> {
-        if self.tail == self.head {
+        if self.tail == self.head || !self.check(false) {
             return None;
         }
         let tail = self.tail;
@@ -150,7 +233,7 @@ fn size_hint(&self) -> (usize, Option<usize>) {
 impl<T, A: Allocator> DoubleEndedIterator for Drain<'_, T, A> {
     #[inline]
     fn next_back(&mut self) -> Option<T> {
-        if self.tail == self.head {
+        if self.tail == self.head || !self.check(false) {
             return None;
         }
         self.head = wrap_index(self.head.wrapping_sub(1), self.ring.len());
@@ -0,0 +1,49 @@
+use crate::collections::VecDeque;
+use crate::rc::Rc;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec_deque::Drain::head`")]
+fn drop_reports_indices_outside_the_ring() {
+    let mut deque: VecDeque<u8> = (0..5).collect();
+    let mut drain = deque.drain(1..4);
+    drain.next();
+    drain.head = drain.ring.len();
+}
+
+#[test]
+fn drop_leaks_remaining_after_a_violation() {
+    let rc = Rc::new(());
+    let mut deque: VecDeque<_> = (0..4).map(|_| Rc::clone(&rc)).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut drain = deque.drain(..);
+        drain.tail = drain.ring.len();
+    }));
+    assert!(result.is_err());
+    // None of the drained elements are dropped.
+    assert_eq!(Rc::strong_count(&rc), 5);
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec_deque::Drain::tail`")]
+fn next_reports_indices_outside_the_ring() {
+    let mut deque: VecDeque<u8> = (0..5).collect();
+    let mut drain = deque.drain(1..4);
+    drain.tail = drain.ring.len();
+    drain.next();
+}
+
+#[test]
+fn next_back_leaks_remaining_after_a_violation() {
+    let rc = Rc::new(());
+    let mut deque: VecDeque<_> = (0..4).map(|_| Rc::clone(&rc)).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut drain = deque.drain(..);
+        drain.next();
+        drain.head = drain.ring.len() + 1;
+        drain.next_back()
+    }));
+    assert!(result.is_err());
+    // Only the element yielded before the violation is dropped.
+    assert_eq!(Rc::strong_count(&rc), 4);
+}
@@ -17,6 +17,9 @@
 use core::ptr::{self, NonNull};
 use core::slice;
 
+/*SOR-MetaUpdate@kayondomartin*/
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
+
 // This is used in a bunch of intra-doc links.
 // FIXME: For some reason, `#[cfg(doc)]` wasn't sufficient, resulting in
 // failures in linkchecker even though rustdoc built the docs just fine.
@@ -26,6 +29,7 @@
 use crate::alloc::{Allocator, Global};
 use crate::collections::TryReserveError;
 use crate::collections::TryReserveErrorKind;
+use crate::metadata_shadow::Indices;
 use crate::raw_vec::RawVec;
 use crate::vec::Vec;
 
@@ -34,6 +38,8 @@
 
 #[stable(feature = "drain", since = "1.6.0")]
 pub use self::drain::Drain;
+#[unstable(feature = "metadata_update", issue = "none")]
+pub use self::drain::DrainField;
 
 mod drain;
 
@@ -110,7 +116,9 @@ pub struct VecDeque<
     // to where data should be written.
     // If tail == head the buffer is empty. The length of the ringbuffer
     // is defined as the distance between the two.
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     tail: usize,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     head: usize,
     buf: RawVec<T, A>,
 }
@@ -191,6 +199,56 @@ fn cap(&self) -> usize {
         }
     }
 
+    /// Moves the tail of the ring buffer to `tail`. Every write of `tail` goes
+    /// through here, so that it is synchronized through `MetaUpdate`.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_tail(&mut self, tail: usize) {
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, VecDequeField::Tail, self.tail, tail)
+        {
+            self.tail = tail;
+            self.buf.set_shadow(Indices::Ring { tail, head: self.head });
+        } else {
+            self.metadata_violation(VecDequeField::Tail, self.tail, tail);
+        }
+    }
+
+    /// Moves the head of the ring buffer to `head`. Every write of `head` goes
+    /// through here, so that it is synchronized through `MetaUpdate`.
+    #[inline]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
+    fn set_head(&mut self, head: usize) {
+        if !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, VecDequeField::Head, self.head, head)
+        {
+            self.head = head;
+            self.buf.set_shadow(Indices::Ring { tail: self.tail, head });
+        } else {
+            self.metadata_violation(VecDequeField::Head, self.head, head);
+        }
+    }
+
+    // Reports an index outside of the ring buffer, and leaks the elements: a
+    // single index cannot be repaired without knowing which slots still hold
+    // elements, so the deque is left empty instead.
+    #[cold]
+    #[inline(never)]
+    fn metadata_violation(&mut self, field: VecDequeField, old: usize, new: usize) {
+        metadata_update::metadata_violation("VecDeque", field.name(), old, new);
+        self.tail = 0;
+        self.head = 0;
+        self.buf.set_shadow(Indices::Ring { tail: 0, head: 0 });
+    }
+
+    /// Compares the header with its shadow copy, see `RawVec::check_shadow`.
+    #[inline]
+    #[track_caller]
+    fn check_shadow(&self) {
+        let indices = Indices::Ring { tail: self.tail, head: self.head };
+        self.buf.check_shadow("VecDeque", (self as *const Self).cast(), indices);
+    }
+
     /// Turn ptr into a slice, since the elements of the backing buffer may be uninitialized,
     /// we will return a slice of [`MaybeUninit<T>`].
     ///
@@ -506,7 +564,7 @@ unsafe fn handle_capacity_increase(&mut self, old_capacity: usize) {
             unsafe {
                 self.copy_nonoverlapping(old_capacity, 0, self.head);
             }
-            self.head += old_capacity;
+            self.set_head(self.head + old_capacity);
             debug_assert!(self.head > self.tail);
         } else {
             // C
@@ -514,7 +572,7 @@ unsafe fn handle_capacity_increase(&mut self, old_capacity: usize) {
             unsafe {
                 self.copy_nonoverlapping(new_tail, self.tail, old_capacity - self.tail);
             }
-            self.tail = new_tail;
+            self.set_tail(new_tail);
             debug_assert!(self.head < self.tail);
         }
         debug_assert!(self.head < self.cap());
@@ -915,8 +973,8 @@ pub fn shrink_to(&mut self, min_capacity: usize) {
                 unsafe {
                     self.copy_nonoverlapping(0, self.tail, self.len());
                 }
-                self.head = self.len();
-                self.tail = 0;
+                self.set_head(self.len());
+                self.set_tail(0);
             } else if self.tail != 0 && self.tail < target_cap && head_outside {
                 //          T             H
                 //   [. . . o o o o o o o . . . . . . ]
@@ -926,7 +984,7 @@ pub fn shrink_to(&mut self, min_capacity: usize) {
                 unsafe {
                     self.copy_nonoverlapping(0, target_cap, len);
                 }
-                self.head = len;
+                self.set_head(len);
                 debug_assert!(self.head < self.tail);
             } else if self.tail >= target_cap {
                 //              H                 T
@@ -939,7 +997,7 @@ pub fn shrink_to(&mut self, min_capacity: usize) {
                 unsafe {
                     self.copy_nonoverlapping(new_tail, self.tail, len);
                 }
-                self.tail = new_tail;
+                self.set_tail(new_tail);
                 debug_assert!(self.head < self.tail);
             }
 
@@ -1000,12 +1058,12 @@ fn drop(&mut self) {
             if len > front.len() {
                 let begin = len - front.len();
                 let drop_back = back.get_unchecked_mut(begin..) as *mut _;
-                self.head = self.wrap_sub(self.head, num_dropped);
+                self.set_head(self.wrap_sub(self.head, num_dropped));
                 ptr::drop_in_place(drop_back);
             } else {
                 let drop_back = back as *mut _;
                 let drop_front = front.get_unchecked_mut(len..) as *mut _;
-                self.head = self.wrap_sub(self.head, num_dropped);
+                self.set_head(self.wrap_sub(self.head, num_dropped));
 
                 // Make sure the second half is dropped even when a destructor
                 // in the first one panics.
@@ -1097,6 +1155,7 @@ pub fn iter_mut(&mut self) -> IterMut<'_, T> {
     #[inline]
     #[stable(feature = "deque_extras_15", since = "1.5.0")]
     pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.check_shadow();
         // Safety:
         // - `self.head` and `self.tail` in a ring buffer are always valid indices.
         // - `RingSlices::ring_slices` guarantees that the slices split according to `self.head` and `self.tail` are initialized.
@@ -1135,6 +1194,7 @@ pub fn as_slices(&self) -> (&[T], &[T]) {
     #[inline]
     #[stable(feature = "deque_extras_15", since = "1.5.0")]
     pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        self.check_shadow();
         // Safety:
         // - `self.head` and `self.tail` in a ring buffer are always valid indices.
         // - `RingSlices::ring_slices` guarantees that the slices split according to `self.head` and `self.tail` are initialized.
@@ -1332,7 +1392,7 @@ pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, A>
 
         // "forget" about the values after the start of the drain until after
         // the drain is complete and the Drain destructor is run.
-        self.head = drain_tail;
+        self.set_head(drain_tail);
 
         let deque = NonNull::from(&mut *self);
         unsafe {
@@ -1504,7 +1564,7 @@ pub fn pop_front(&mut self) -> Option<T> {
             None
         } else {
             let tail = self.tail;
-            self.tail = self.wrap_add(self.tail, 1);
+            self.set_tail(self.wrap_add(self.tail, 1));
             unsafe { Some(self.buffer_read(tail)) }
         }
     }
@@ -1528,7 +1588,7 @@ pub fn pop_back(&mut self) -> Option<T> {
         if self.is_empty() {
             None
         } else {
-            self.head = self.wrap_sub(self.head, 1);
+            self.set_head(self.wrap_sub(self.head, 1));
             let head = self.head;
             unsafe { Some(self.buffer_read(head)) }
         }
@@ -1552,7 +1612,7 @@ pub fn push_front(&mut self, value: T) {
             self.grow();
         }
 
-        self.tail = self.wrap_sub(self.tail, 1);
+        self.set_tail(self.wrap_sub(self.tail, 1));
         let tail = self.tail;
         unsafe {
             self.buffer_write(tail, value);
@@ -1578,7 +1638,7 @@ pub fn push_back(&mut self, value: T) {
         }
 
         let head = self.head;
-        self.head = self.wrap_add(self.head, 1);
+        self.set_head(self.wrap_add(self.head, 1));
         unsafe { self.buffer_write(head, value) }
     }
 
@@ -1730,7 +1790,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                 //      [A o o o o o o o . . . . . I]
                 //
 
-                self.tail = self.wrap_sub(self.tail, 1);
+                self.set_tail(self.wrap_sub(self.tail, 1));
             }
             (true, true, _) => {
                 unsafe {
@@ -1759,7 +1819,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     // Already moved the tail, so we only copy `index - 1` elements.
                     self.copy(self.tail, self.tail + 1, index - 1);
 
-                    self.tail = new_tail;
+                    self.set_tail(new_tail);
                 }
             }
             (true, false, _) => {
@@ -1774,7 +1834,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     //                       M M M
 
                     self.copy(idx + 1, idx, self.head - idx);
-                    self.head = self.wrap_add(self.head, 1);
+                    self.set_head(self.wrap_add(self.head, 1));
                 }
             }
             (false, true, true) => {
@@ -1789,7 +1849,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     //                           M M
 
                     self.copy(self.tail - 1, self.tail, index);
-                    self.tail -= 1;
+                    self.set_tail(self.tail - 1);
                 }
             }
             (false, false, true) => {
@@ -1812,7 +1872,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     // move elements from idx to end forward not including ^ element
                     self.copy(idx + 1, idx, self.cap() - 1 - idx);
 
-                    self.head += 1;
+                    self.set_head(self.head + 1);
                 }
             }
             (false, true, false) if idx == 0 => {
@@ -1833,7 +1893,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     // copy last element into empty spot at bottom of buffer
                     self.copy(self.cap() - 1, 0, 1);
 
-                    self.tail -= 1;
+                    self.set_tail(self.tail - 1);
                 }
             }
             (false, true, false) => {
@@ -1856,7 +1916,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     // move elements from idx-1 to end forward not including ^ element
                     self.copy(0, 1, idx - 1);
 
-                    self.tail -= 1;
+                    self.set_tail(self.tail - 1);
                 }
             }
             (false, false, false) => {
@@ -1871,7 +1931,7 @@ pub fn insert(&mut self, index: usize, value: T) {
                     //                 M M M
 
                     self.copy(idx + 1, idx, self.head - idx);
-                    self.head += 1;
+                    self.set_head(self.head + 1);
                 }
             }
         }
@@ -1950,7 +2010,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                     //               M M
 
                     self.copy(self.tail + 1, self.tail, index);
-                    self.tail += 1;
+                    self.set_tail(self.tail + 1);
                 }
             }
             (true, false, _) => {
@@ -1965,7 +2025,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                     //                     M M
 
                     self.copy(idx, idx + 1, self.head - idx - 1);
-                    self.head -= 1;
+                    self.set_head(self.head - 1);
                 }
             }
             (false, true, true) => {
@@ -1980,7 +2040,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                     //                               M M
 
                     self.copy(self.tail + 1, self.tail, index);
-                    self.tail = self.wrap_add(self.tail, 1);
+                    self.set_tail(self.wrap_add(self.tail, 1));
                 }
             }
             (false, false, false) => {
@@ -1995,7 +2055,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                     //               M M
 
                     self.copy(idx, idx + 1, self.head - idx - 1);
-                    self.head -= 1;
+                    self.set_head(self.head - 1);
                 }
             }
             (false, false, true) => {
@@ -2030,7 +2090,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                         self.copy(0, 1, self.head - 1);
                     }
 
-                    self.head = self.wrap_sub(self.head, 1);
+                    self.set_head(self.wrap_sub(self.head, 1));
                 }
             }
             (false, true, false) => {
@@ -2053,7 +2113,7 @@ pub fn remove(&mut self, index: usize) -> Option<T> {
                     // move elements from tail to end forward, excluding the last one
                     self.copy(self.tail + 1, self.tail, self.cap() - self.tail - 1);
 
-                    self.tail = self.wrap_add(self.tail, 1);
+                    self.set_tail(self.wrap_add(self.tail, 1));
                 }
             }
         }
@@ -2128,8 +2188,8 @@ pub fn split_off(&mut self, at: usize) -> Self
         }
 
         // Cleanup where the ends of the buffers are
-        self.head = self.wrap_sub(self.head, other_len);
-        other.head = other.wrap_index(other_len);
+        self.set_head(self.wrap_sub(self.head, other_len));
+        other.set_head(other.wrap_index(other_len));
 
         other
     }
@@ -2162,9 +2222,9 @@ pub fn append(&mut self, other: &mut Self) {
         }
         // SAFETY: Update pointers after copying to avoid leaving doppelganger
         // in case of panics.
-        self.head = self.wrap_add(self.head, other.len());
+        self.set_head(self.wrap_add(self.head, other.len()));
         // Silently drop values in `other`.
-        other.tail = other.head;
+        other.set_tail(other.head);
     }
 
     /// Retains only the elements specified by the predicate.
@@ -2405,8 +2465,8 @@ pub fn make_contiguous(&mut self) -> &mut [T] {
                 ptr::copy_nonoverlapping(buf.add(self.tail), buf, tail_len);
                 // ABCDEFGH....
 
-                self.tail = 0;
-                self.head = len;
+                self.set_tail(0);
+                self.set_head(len);
             }
         } else if free > self.head {
             // FIXME: We currently do not consider ....ABCDEFGH
@@ -2427,8 +2487,8 @@ pub fn make_contiguous(&mut self) -> &mut [T] {
                 ptr::copy_nonoverlapping(buf, buf.add(self.head + tail_len), self.head);
                 // ...ABCDEFGH.
 
-                self.tail = self.head;
-                self.head = self.wrap_add(self.tail, len);
+                self.set_tail(self.head);
+                self.set_head(self.wrap_add(self.tail, len));
             }
         } else {
             // free is smaller than both head and tail,
@@ -2461,8 +2521,8 @@ pub fn make_contiguous(&mut self) -> &mut [T] {
                     right_edge += right_offset + 1;
                 }
 
-                self.tail = 0;
-                self.head = len;
+                self.set_tail(0);
+                self.set_head(len);
             }
         }
 
@@ -2577,14 +2637,14 @@ unsafe fn rotate_left_inner(&mut self, mid: usize) {
         unsafe {
             self.wrap_copy(self.head, self.tail, mid);
         }
-        self.head = self.wrap_add(self.head, mid);
-        self.tail = self.wrap_add(self.tail, mid);
+        self.set_head(self.wrap_add(self.head, mid));
+        self.set_tail(self.wrap_add(self.tail, mid));
     }
 
     unsafe fn rotate_right_inner(&mut self, k: usize) {
         debug_assert!(k * 2 <= self.len());
-        self.head = self.wrap_sub(self.head, k);
-        self.tail = self.wrap_sub(self.tail, k);
+        self.set_head(self.wrap_sub(self.head, k));
+        self.set_tail(self.wrap_sub(self.tail, k));
         unsafe {
             self.wrap_copy(self.tail, self.head, k);
         }
@@ -2936,6 +2996,7 @@ impl<T, A: Allocator> Index<usize> for VecDeque<T, A> {
 
     #[inline]
     fn index(&self, index: usize) -> &T {
+        self.check_shadow();
         self.get(index).expect("Out of bounds access")
     }
 }
@@ -2944,6 +3005,7 @@ fn index(&self, index: usize) -> &T {
 impl<T, A: Allocator> IndexMut<usize> for VecDeque<T, A> {
     #[inline]
     fn index_mut(&mut self, index: usize) -> &mut T {
+        self.check_shadow();
         self.get_mut(index).expect("Out of bounds access")
     }
 }
@@ -3062,6 +3124,7 @@ fn from(mut other: Vec<T, A>) -> Self {
         unsafe {
             let (other_buf, len, capacity, alloc) = other.into_raw_parts_with_alloc();
             let buf = RawVec::from_raw_parts_in(other_buf, capacity, alloc);
+            buf.set_shadow(Indices::Ring { tail: 0, head: len });
             VecDeque { tail: 0, head: len, buf }
         }
     }
@@ -3136,8 +3199,48 @@ fn from(mut other: VecDeque<T, A>) -> Self {
                 ptr::copy_nonoverlapping(arr.as_ptr(), deq.ptr(), N);
             }
         }
-        deq.tail = 0;
-        deq.head = N;
+        deq.set_tail(0);
+        deq.set_head(N);
         deq
     }
 }
+
+/// The protected metadata of a `VecDeque`.
+/// The capacity lives in the buffer backing the deque, which validates it
+/// against the allocation itself.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VecDequeField {
+    /// The index of the first element that could be read.
+    Tail,
+    /// The index where the next element will be written.
+    Head,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for VecDequeField {
+    const FIELDS: &'static [Self] = &[VecDequeField::Tail, VecDequeField::Head];
+
+    fn name(self) -> &'static str {
+        match self {
+            VecDequeField::Tail => "tail",
+            VecDequeField::Head => "head",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, A: Allocator> MetaUpdate for VecDeque<T, A> {
+    type Field = VecDequeField;
+
+    /// Synchronize a metadata update with the ring buffer.
+    /// `old` must be the index the field currently holds. `tail` and `head`
+    /// are indices into the ring buffer and must stay below the capacity.
+    fn synchronize(&self, field: VecDequeField, old: usize, new: usize) -> bool {
+        let current = match field {
+            VecDequeField::Tail => self.tail,
+            VecDequeField::Head => self.head,
+        };
+        old == current && new < self.cap()
+    }
+}
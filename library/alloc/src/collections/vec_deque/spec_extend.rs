@@ -27,7 +27,7 @@ impl<T, I, A: Allocator> SpecExtend<T, I> for VecDeque<T, A>
             }
 
             let head = self.head;
-            self.head = self.wrap_add(self.head, 1);
+            self.set_head(self.wrap_add(self.head, 1));
             unsafe {
                 self.buffer_write(head, element);
             }
@@ -100,7 +100,7 @@ fn spec_extend(&mut self, mut iterator: vec::IntoIter<T>) {
 
         unsafe {
             self.copy_slice(self.head, slice);
-            self.head = self.wrap_add(self.head, slice.len());
+            self.set_head(self.wrap_add(self.head, slice.len()));
         }
         iterator.forget_remaining_elements();
     }
@@ -126,7 +126,7 @@ fn spec_extend(&mut self, iterator: slice::Iter<'a, T>) {
 
         unsafe {
             self.copy_slice(self.head, slice);
-            self.head = self.wrap_add(self.head, slice.len());
+            self.set_head(self.wrap_add(self.head, slice.len()));
         }
     }
 }
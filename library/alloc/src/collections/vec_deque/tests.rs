@@ -10,8 +10,8 @@ fn bench_push_back_100(b: &mut test::Bencher) {
         for i in 0..100 {
             deq.push_back(i);
         }
-        deq.head = 0;
-        deq.tail = 0;
+        deq.set_head(0);
+        deq.set_tail(0);
     })
 }
 
@@ -23,8 +23,8 @@ fn bench_push_front_100(b: &mut test::Bencher) {
         for i in 0..100 {
             deq.push_front(i);
         }
-        deq.head = 0;
-        deq.tail = 0;
+        deq.set_head(0);
+        deq.set_tail(0);
     })
 }
 
@@ -34,8 +34,8 @@ fn bench_pop_back_100(b: &mut test::Bencher) {
     let mut deq = VecDeque::<i32>::with_capacity(101);
 
     b.iter(|| {
-        deq.head = 100;
-        deq.tail = 0;
+        deq.set_head(100);
+        deq.set_tail(0);
         while !deq.is_empty() {
             test::black_box(deq.pop_back());
         }
@@ -81,8 +81,8 @@ fn bench_pop_front_100(b: &mut test::Bencher) {
     let mut deq = VecDeque::<i32>::with_capacity(101);
 
     b.iter(|| {
-        deq.head = 100;
-        deq.tail = 0;
+        deq.set_head(100);
+        deq.set_tail(0);
         while !deq.is_empty() {
             test::black_box(deq.pop_front());
         }
@@ -102,8 +102,8 @@ fn test(back: bool) {
             let expected: VecDeque<_> =
                 if back { (0..len).collect() } else { (0..len).rev().collect() };
             for tail_pos in 0..usable_cap {
-                tester.tail = tail_pos;
-                tester.head = tail_pos;
+                tester.set_tail(tail_pos);
+                tester.set_head(tail_pos);
                 if back {
                     for i in 0..len * 2 {
                         tester.push_front(i);
@@ -148,8 +148,8 @@ fn test_insert() {
         let expected = (0..).take(len).collect::<VecDeque<_>>();
         for tail_pos in 0..cap {
             for to_insert in 0..len {
-                tester.tail = tail_pos;
-                tester.head = tail_pos;
+                tester.set_tail(tail_pos);
+                tester.set_head(tail_pos);
                 for i in 0..len {
                     if i != to_insert {
                         tester.push_back(i);
@@ -586,8 +586,8 @@ fn test_remove() {
         let expected = (0..).take(len).collect::<VecDeque<_>>();
         for tail_pos in 0..cap {
             for to_remove in 0..=len {
-                tester.tail = tail_pos;
-                tester.head = tail_pos;
+                tester.set_tail(tail_pos);
+                tester.set_head(tail_pos);
                 for i in 0..len {
                     if i == to_remove {
                         tester.push_back(1234);
@@ -616,8 +616,8 @@ fn test_range() {
         for tail in 0..=cap {
             for start in 0..=len {
                 for end in start..=len {
-                    tester.tail = tail;
-                    tester.head = tail;
+                    tester.set_tail(tail);
+                    tester.set_head(tail);
                     for i in 0..len {
                         tester.push_back(i);
                     }
@@ -641,8 +641,8 @@ fn test_range_mut() {
         for tail in 0..=cap {
             for start in 0..=len {
                 for end in start..=len {
-                    tester.tail = tail;
-                    tester.head = tail;
+                    tester.set_tail(tail);
+                    tester.set_head(tail);
                     for i in 0..len {
                         tester.push_back(i);
                     }
@@ -675,8 +675,8 @@ fn test_drain() {
         for tail in 0..=cap {
             for drain_start in 0..=len {
                 for drain_end in drain_start..=len {
-                    tester.tail = tail;
-                    tester.head = tail;
+                    tester.set_tail(tail);
+                    tester.set_head(tail);
                     for i in 0..len {
                         tester.push_back(i);
                     }
@@ -718,8 +718,8 @@ fn test_shrink_to_fit() {
         // 0, 1, 2, .., len - 1
         let expected = (0..).take(len).collect::<VecDeque<_>>();
         for tail_pos in 0..=max_cap {
-            tester.tail = tail_pos;
-            tester.head = tail_pos;
+            tester.set_tail(tail_pos);
+            tester.set_head(tail_pos);
             tester.reserve(63);
             for i in 0..len {
                 tester.push_back(i);
@@ -755,8 +755,8 @@ fn test_split_off() {
             let expected_other = (at..).take(len - at).collect::<VecDeque<_>>();
 
             for tail_pos in 0..cap {
-                tester.tail = tail_pos;
-                tester.head = tail_pos;
+                tester.set_tail(tail_pos);
+                tester.set_head(tail_pos);
                 for i in 0..len {
                     tester.push_back(i);
                 }
@@ -1108,3 +1108,20 @@ fn hash_code(value: impl Hash) -> u64 {
     assert_eq!(vda, vdb);
     assert_eq!(hash_code(vda), hash_code(vdb));
 }
+
+#[test]
+fn test_metadata_synchronize() {
+    let deque: VecDeque<u8> = VecDeque::with_capacity(7);
+    let cap = deque.cap();
+    assert!(deque.synchronize(VecDequeField::Head, 0, cap - 1));
+    assert!(!deque.synchronize(VecDequeField::Tail, 0, cap));
+    assert!(!deque.synchronize(VecDequeField::Tail, 1, 2));
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `VecDeque::head`")]
+fn test_metadata_violation() {
+    let mut deque: VecDeque<u8> = VecDeque::with_capacity(7);
+    let cap = deque.cap();
+    deque.set_head(cap);
+}
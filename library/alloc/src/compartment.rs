@@ -0,0 +1,315 @@
+//! Out-of-line storage for smart pointer headers embedded in other types.
+//!
+//! A struct marked `#[metadata_compartment]`, or any struct of a crate marked
+//! `#![metadata_compartment]`, does not hold the headers of the smart pointers
+//! and collections among its fields inline. Every field whose type implements
+//! [`MetaUpdate`], like a `Vec` or an `Rc`, is stored as a [`Compartment`]
+//! instead: a pointer to the header, which lives in the metadata compartment,
+//! away from the ordinary heap. A linear overflow out of a neighbouring buffer
+//! can then no longer reach the length or the counters of the header.
+//!
+//! The relocation is transparent: the field keeps its declared type, and the
+//! compiler allocates the header when the field is initialized, reaches it
+//! through the pointer whenever the field is accessed, and frees it when the
+//! field is moved out of or dropped. What it costs is one allocation per field
+//! and one extra load per access, so types on hot paths can opt out with
+//! `#[metadata_compartment(inline)]` and keep their headers inline, where they
+//! are only protected by [`MetaUpdate::synchronize`].
+//!
+//! ```
+//! #![feature(metadata_compartment)]
+//!
+//! use std::mem::size_of;
+//!
+//! #[metadata_compartment]
+//! struct Relocated {
+//!     ids: Vec<u32>,
+//!     generation: u64,
+//! }
+//!
+//! #[metadata_compartment(inline)]
+//! struct Inline {
+//!     ids: Vec<u32>,
+//!     generation: u64,
+//! }
+//!
+//! assert!(size_of::<Relocated>() < size_of::<Inline>());
+//!
+//! let mut relocated = Relocated { ids: vec![1], generation: 0 };
+//! relocated.ids.push(2);
+//! assert_eq!(relocated.ids, [1, 2]);
+//! ```
+//!
+//! Values of such structs can not be built in const contexts, as the headers
+//! are allocated at runtime, and the raw address of a relocated field can not
+//! be taken, as the pointer to its header is only set once the field is
+//! initialized. Fields whose type may be unsized are never relocated, and
+//! neither are the fields of structs with an explicit `repr`, whose layout is
+//! fixed, or of types that implement `MetaUpdate` themselves, which protect
+//! their own headers.
+//!
+//! The runtime of `std` allocates the compartment from the guarded arenas of
+//! [`GuardedArena`]. Without it, the global allocator is used.
+//!
+//! With `-Z metadata-protection=guard`, `Rc` and `Arc` allocate from the
+//! compartment as well. The counters are not split from the value, so the
+//! whole `RcBox` or `ArcInner`, value included, lands there: every `Rc` and
+//! `Arc` then costs at least a page of its own and a guard page, and one whose
+//! value does not fit in a page costs a mapping of its own.
+//!
+//! [`MetaUpdate`]: core::ptr::metadata_update::MetaUpdate
+//! [`MetaUpdate::synchronize`]: core::ptr::metadata_update::MetaUpdate::synchronize
+//! [`GuardedArena`]: ../../std/alloc/struct.GuardedArena.html
+
+#![unstable(feature = "metadata_compartment", issue = "none")]
+
+use core::fmt;
+use core::marker::PhantomData;
+#[cfg(target_has_atomic = "ptr")]
+use core::mem;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+#[cfg(target_has_atomic = "ptr")]
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use crate::alloc::{self, handle_alloc_error, AllocError, Allocator, Layout};
+use crate::metadata_ownership;
+
+#[cfg(test)]
+mod tests;
+
+/// A value stored in the metadata compartment.
+///
+/// This is the type the compiler stores a relocated field as, see the
+/// [module documentation](self). It owns its value like a `Box`.
+#[cfg_attr(all(not(bootstrap), not(test)), lang = "metadata_compartment")]
+pub struct Compartment<T> {
+    ptr: NonNull<T>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for Compartment<T> {}
+unsafe impl<T: Sync> Sync for Compartment<T> {}
+
+impl<T> Compartment<T> {
+    /// Moves `value` into the metadata compartment.
+    #[inline]
+    pub fn new(value: T) -> Compartment<T> {
+        let layout = Layout::new::<T>();
+        let ptr = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            // SAFETY: `layout` is non-zero in size.
+            let ptr = unsafe { allocate(layout) };
+            match NonNull::new(ptr.cast::<T>()) {
+                Some(ptr) => ptr,
+                None => handle_alloc_error(layout),
+            }
+        };
+        // SAFETY: `ptr` is valid for writes of a `T`.
+        unsafe { ptr.as_ptr().write(value) };
+        Compartment { ptr, _marker: PhantomData }
+    }
+
+    /// Moves the value out of the metadata compartment, and frees its storage.
+    pub fn into_inner(this: Compartment<T>) -> T {
+        let this = ManuallyDrop::new(this);
+        // SAFETY: the value is initialized, and `this` is never used again.
+        unsafe {
+            let value = ptr::read(this.ptr.as_ptr());
+            release(this.ptr);
+            value
+        }
+    }
+}
+
+impl<T> Deref for Compartment<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the value is initialized for as long as `self` lives.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for Compartment<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the value is initialized for as long as `self` lives, and
+        // `self` owns it.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+unsafe impl<#[may_dangle] T> Drop for Compartment<T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is initialized, and dropped only here.
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            release(self.ptr);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Compartment<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// Moves `value` into the metadata compartment. Calls to this are inserted by
+/// the compiler where a relocated field is initialized.
+#[cfg_attr(all(not(bootstrap), not(test)), lang = "compartment_new")]
+#[cfg_attr(test, allow(dead_code))]
+#[inline]
+fn compartment_new<T>(value: T) -> Compartment<T> {
+    Compartment::new(value)
+}
+
+/// Moves a value out of the metadata compartment. Calls to this are inserted
+/// by the compiler where a relocated field is moved out of.
+#[cfg_attr(all(not(bootstrap), not(test)), lang = "compartment_take")]
+#[cfg_attr(test, allow(dead_code))]
+#[inline]
+fn compartment_take<T>(compartment: Compartment<T>) -> T {
+    Compartment::into_inner(compartment)
+}
+
+// Frees the storage of a value moved into the compartment by `Compartment::new`.
+unsafe fn release<T>(ptr: NonNull<T>) {
+    let layout = Layout::new::<T>();
+    if layout.size() != 0 {
+        // SAFETY: `ptr` was allocated by `allocate` with the same layout.
+        unsafe { deallocate(ptr.as_ptr().cast(), layout) }
+    }
+}
+
+// The allocator of the runtime. Which allocator the compartment uses is
+// decided once, by whichever comes first: the installation of the hooks, or
+// the first allocation, which settles on the global allocator for good. The
+// storage of a value is then always freed by the allocator that handed it out.
+#[cfg(target_has_atomic = "ptr")]
+static ALLOC_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+#[cfg(target_has_atomic = "ptr")]
+static DEALLOC_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+#[cfg(target_has_atomic = "ptr")]
+static STATE: AtomicU8 = AtomicU8::new(UNDECIDED);
+
+#[cfg(target_has_atomic = "ptr")]
+const UNDECIDED: u8 = 0;
+#[cfg(target_has_atomic = "ptr")]
+const INSTALLING: u8 = 1;
+#[cfg(target_has_atomic = "ptr")]
+const HOOKED: u8 = 2;
+#[cfg(target_has_atomic = "ptr")]
+const GLOBAL: u8 = 3;
+
+/// Installs the functions that allocate and free the storage of the metadata
+/// compartment.
+///
+/// This is an implementation detail of the runtime of `std`, which calls it
+/// once at startup. It has no effect if the compartment has already been
+/// allocated from, or if it is called again, and then the compartment keeps
+/// using the global allocator, or the functions installed first. Targets
+/// without atomic pointers ignore the hooks.
+///
+/// # Safety
+///
+/// `alloc` must behave like [`GlobalAlloc::alloc`] for layouts that are non-zero
+/// in size, returning null on failure, and `dealloc` like
+/// [`GlobalAlloc::dealloc`] for the blocks `alloc` returned. The storage of
+/// `Rc`, `Arc` and relocated fields is handed out and freed by them, so they
+/// must stay usable for the rest of the program.
+///
+/// [`GlobalAlloc::alloc`]: core::alloc::GlobalAlloc::alloc
+/// [`GlobalAlloc::dealloc`]: core::alloc::GlobalAlloc::dealloc
+#[doc(hidden)]
+#[unstable(feature = "liballoc_internals", issue = "none", reason = "implementation detail")]
+pub unsafe fn set_compartment_allocator(
+    alloc: unsafe fn(Layout) -> *mut u8,
+    dealloc: unsafe fn(*mut u8, Layout),
+) {
+    #[cfg(target_has_atomic = "ptr")]
+    if STATE.compare_exchange(UNDECIDED, INSTALLING, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+        ALLOC_HOOK.store(alloc as *mut (), Ordering::Relaxed);
+        DEALLOC_HOOK.store(dealloc as *mut (), Ordering::Relaxed);
+        STATE.store(HOOKED, Ordering::Release);
+    }
+    #[cfg(not(target_has_atomic = "ptr"))]
+    let _ = (alloc, dealloc);
+}
+
+// Returns whether the hooks are installed, settling on the global allocator
+// if nothing has been decided yet.
+#[cfg(target_has_atomic = "ptr")]
+fn hooked() -> bool {
+    let mut state = STATE.load(Ordering::Acquire);
+    if state == UNDECIDED {
+        state = STATE
+            .compare_exchange(UNDECIDED, GLOBAL, Ordering::Acquire, Ordering::Acquire)
+            .map_or_else(|state| state, |_| GLOBAL);
+    }
+    while state == INSTALLING {
+        core::hint::spin_loop();
+        state = STATE.load(Ordering::Acquire);
+    }
+    state == HOOKED
+}
+
+// `layout` must be non-zero in size.
+unsafe fn allocate(layout: Layout) -> *mut u8 {
+    #[cfg(target_has_atomic = "ptr")]
+    if hooked() {
+        let hook = ALLOC_HOOK.load(Ordering::Relaxed);
+        // SAFETY: only `set_compartment_allocator` stores to the hook, and it stores an
+        // `unsafe fn(Layout) -> *mut u8`.
+        let hook = unsafe { mem::transmute::<*mut (), unsafe fn(Layout) -> *mut u8>(hook) };
+        return unsafe { hook(layout) };
+    }
+    unsafe { alloc::alloc(layout) }
+}
+
+// `ptr` must have been allocated by `allocate` with `layout`.
+unsafe fn deallocate(ptr: *mut u8, layout: Layout) {
+    #[cfg(target_has_atomic = "ptr")]
+    if hooked() {
+        let hook = DEALLOC_HOOK.load(Ordering::Relaxed);
+        // SAFETY: only `set_compartment_allocator` stores to the hook, and it stores an
+        // `unsafe fn(*mut u8, Layout)`.
+        let hook = unsafe { mem::transmute::<*mut (), unsafe fn(*mut u8, Layout)>(hook) };
+        // `alloc::dealloc` forgets the ownership of what it frees, the hook
+        // does not.
+        metadata_ownership::forget(ptr);
+        return unsafe { hook(ptr, layout) };
+    }
+    unsafe { alloc::dealloc(ptr, layout) }
+}
+
+/// The metadata compartment as an [`Allocator`], for the headers of the types
+/// of this crate that are not stored in a [`Compartment`]. With guard page
+/// metadata protection, `Rc` and `Arc` allocate their whole `RcBox` and
+/// `ArcInner` with it, the value along with the counters.
+#[derive(Copy, Clone, Default, Debug)]
+pub(crate) struct CompartmentAlloc;
+
+unsafe impl Allocator for CompartmentAlloc {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match layout.size() {
+            0 => Ok(NonNull::slice_from_raw_parts(layout.dangling(), 0)),
+            // SAFETY: `layout` is non-zero in size.
+            size => unsafe {
+                let ptr = NonNull::new(allocate(layout)).ok_or(AllocError)?;
+                Ok(NonNull::slice_from_raw_parts(ptr, size))
+            },
+        }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            // SAFETY: `ptr` was allocated by `allocate`, with the same layout.
+            unsafe { deallocate(ptr.as_ptr(), layout) }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+struct DropCounter<'a>(&'a Cell<usize>);
+
+impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+fn new_and_deref() {
+    let mut compartment = Compartment::new(vec![1, 2]);
+    compartment.push(3);
+    assert_eq!(*compartment, [1, 2, 3]);
+    assert_eq!(format!("{compartment:?}"), "[1, 2, 3]");
+}
+
+#[test]
+fn drops_its_value_once() {
+    let drops = Cell::new(0);
+    drop(Compartment::new(DropCounter(&drops)));
+    assert_eq!(drops.get(), 1);
+
+    let value = Compartment::into_inner(Compartment::new(DropCounter(&drops)));
+    assert_eq!(drops.get(), 1);
+    drop(value);
+    assert_eq!(drops.get(), 2);
+}
+
+#[test]
+fn zero_sized() {
+    let compartment = Compartment::new(());
+    let () = Compartment::into_inner(compartment);
+}
+
+#[test]
+fn shares_counters_with_clones() {
+    let rc = Rc::new(5);
+    let compartment = Compartment::new(Rc::clone(&rc));
+    assert_eq!(Rc::strong_count(&rc), 2);
+    drop(compartment);
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
@@ -89,6 +89,7 @@
 //
 // Library features:
 #![feature(alloc_layout_extra)]
+#![feature(allocation_layout)]
 #![feature(allocator_api)]
 #![feature(array_chunks)]
 #![feature(array_into_iter_constructors)]
@@ -150,6 +151,7 @@
 #![feature(trusted_len)]
 #![feature(trusted_random_access)]
 #![feature(try_trait_v2)]
+#![feature(typed_allocation)]
 #![cfg_attr(not(bootstrap), feature(tuple_trait))]
 #![feature(unchecked_math)]
 #![feature(unicode_internals)]
@@ -158,11 +160,13 @@
 #![feature(std_internals)]
 /*SOR-MetaUpdate@kayondomartin*/
 #![feature(metadata_update)]
+#![feature(metadata_update_internals)]
 //
 // Language features:
 #![feature(allocator_internals)]
 #![feature(allow_internal_unstable)]
 #![feature(associated_type_bounds)]
+#![cfg_attr(not(bootstrap), feature(cfg_metadata_protection))]
 #![feature(cfg_sanitize)]
 #![feature(const_deref)]
 #![feature(const_mut_refs)]
@@ -211,6 +215,12 @@
 #[macro_use]
 mod macros;
 
+mod metadata_counts;
+mod metadata_ownership;
+mod metadata_shadow;
+#[doc(hidden)]
+#[unstable(feature = "liballoc_internals", issue = "none", reason = "implementation detail")]
+pub mod metadata_table;
 mod raw_vec;
 
 // Heaps provided for low-level allocation strategies
@@ -230,6 +240,8 @@ mod boxed {
 }
 pub mod borrow;
 pub mod collections;
+#[cfg(not(no_global_oom_handling))]
+pub mod compartment;
 #[cfg(all(not(no_rc), not(no_sync), not(no_global_oom_handling)))]
 pub mod ffi;
 pub mod fmt;
@@ -0,0 +1,190 @@
+//! Shadow records of the reference counters of `Rc` and `Arc`.
+//!
+//! Unless metadata protection is disabled, every allocation of an `RcBox` or
+//! an `ArcInner` gets a record of its strong and weak counts in a side table,
+//! keyed by the address of the allocation, and every update of a counter is
+//! validated against, and mirrored into, its record. The record is dropped
+//! when the allocation is deallocated.
+//!
+//! Keeping the records out of the allocations has two benefits: the pointers
+//! do not grow, and a record outlives the allocation it describes. Dropping
+//! the last strong pointer twice, or cloning it after it was dropped, finds no
+//! record for an allocation that was freed, and is reported without reading or
+//! writing the freed counters. A write through a dangling or forged pointer, or
+//! a linear overflow out of a neighbouring allocation, changes a counter but
+//! not its record, and is reported on the next update.
+//!
+//! The table is split into shards, each an [`AddrTable`] behind a lock of its
+//! own, picked by the address of the allocation, so that threads counting
+//! different allocations rarely wait on each other. The shards have a fixed
+//! size, as they are updated in the middle of allocations and deallocations.
+//! Allocations that no longer fit in their shard are not recorded, and their
+//! updates are only validated against the counters themselves. While any of
+//! them is alive, an allocation of that shard without a record is not taken
+//! for a freed one. As the records are kept by default, this is not reported.
+//!
+//! The table fills pages of its own, which the runtime tags with its memory
+//! protection key in `mpk` mode, and it is only written with the metadata
+//! write window open. A stray write that hits the records faults.
+
+// Targets without atomics never record counts.
+#![cfg_attr(not(target_has_atomic = "8"), allow(dead_code))]
+
+#[cfg(target_has_atomic = "8")]
+use core::mem;
+use core::ptr::metadata_update::METADATA_CHECKS;
+#[cfg(target_has_atomic = "8")]
+use core::ptr::metadata_update::with_metadata_writes;
+
+#[cfg(target_has_atomic = "8")]
+use crate::metadata_table::{Locked, Protected};
+use crate::metadata_table::{AddrEntry, AddrTable};
+
+#[cfg(test)]
+mod tests;
+
+// The number of shards, and of entries of each, powers of two. Only one entry
+// is reserved when counts are not recorded.
+const SHARDS: usize = if METADATA_CHECKS { 64 } else { 1 };
+const SHARD_CAPACITY: usize = if METADATA_CHECKS { 1 << 10 } else { 1 };
+
+/// A reference counter of an `Rc` or `Arc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Count {
+    Strong,
+    Weak,
+}
+
+/// The outcome of looking up the record of a counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Shadow<R> {
+    /// The allocation has a record, and this is what was made of it.
+    Found(R),
+    /// The allocation may be alive, but has no record: it did not fit in its
+    /// shard, or counts are not recorded at all.
+    Untracked,
+    /// The allocation has no record although every allocation of its shard
+    /// does: it was freed.
+    Missing,
+}
+
+/// Records the counts of a new allocation at `addr`.
+#[inline]
+pub(crate) fn insert(addr: *const u8, strong: usize, weak: usize) {
+    let addr = addr.addr();
+    with_shard(addr, |table| table.insert(addr, strong, weak));
+}
+
+/// Drops the record of the allocation at `addr`, which is being deallocated.
+#[inline]
+pub(crate) fn remove(addr: *const u8) {
+    let addr = addr.addr();
+    with_shard(addr, |table| table.remove(addr));
+}
+
+/// Runs `f` on the shadow of the counter `count` of the allocation at `addr`,
+/// with the lock of its shard held. `f` must not update counts itself.
+#[inline]
+pub(crate) fn update<R>(
+    addr: *const u8,
+    count: Count,
+    f: impl FnOnce(&mut usize) -> R,
+) -> Shadow<R> {
+    let addr = addr.addr();
+    with_shard(addr, |table| table.update(addr, count, f)).unwrap_or(Shadow::Untracked)
+}
+
+// Picks the shard of an allocation. The low bits are skipped, as alignment
+// keeps them at 0, and the shard tables hash all bits again.
+fn shard(addr: usize) -> usize {
+    (addr >> 4) % SHARDS
+}
+
+/// Runs `f` on the shard of `addr` if counts are recorded, with its lock held.
+/// The lock is in the table too, so the window is open before it is taken,
+/// until after it was released.
+#[cfg(target_has_atomic = "8")]
+#[inline]
+fn with_shard<R>(addr: usize, f: impl FnOnce(&mut Table<SHARD_CAPACITY>) -> R) -> Option<R> {
+    if !METADATA_CHECKS {
+        return None;
+    }
+    Some(with_metadata_writes(|| COUNTS.0[shard(addr)].with(f)))
+}
+
+#[cfg(not(target_has_atomic = "8"))]
+#[inline]
+fn with_shard<R>(_addr: usize, _f: impl FnOnce(&mut Table<SHARD_CAPACITY>) -> R) -> Option<R> {
+    None
+}
+
+#[cfg(target_has_atomic = "8")]
+const EMPTY_SHARD: Locked<Table<SHARD_CAPACITY>> = Locked::new(Table::new());
+
+#[cfg(target_has_atomic = "8")]
+static COUNTS: Protected<[Locked<Table<SHARD_CAPACITY>>; SHARDS]> =
+    Protected([EMPTY_SHARD; SHARDS]);
+
+/// Returns the pages of the table, see `metadata_table::protected_region`.
+#[cfg(target_has_atomic = "8")]
+pub(crate) fn region() -> (*mut u8, usize) {
+    (&COUNTS as *const _ as *mut u8, mem::size_of_val(&COUNTS))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    // The address of the allocation, 0 if the slot is free.
+    addr: usize,
+    strong: usize,
+    weak: usize,
+}
+
+impl AddrEntry for Entry {
+    const FREE: Self = Entry { addr: 0, strong: 0, weak: 0 };
+
+    fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+struct Table<const N: usize> {
+    entries: AddrTable<Entry, N>,
+    // The number of live allocations that did not fit. Allocations without a
+    // record are not taken for freed ones while there are any.
+    untracked: usize,
+}
+
+impl<const N: usize> Table<N> {
+    const fn new() -> Self {
+        Table { entries: AddrTable::new(), untracked: 0 }
+    }
+
+    fn insert(&mut self, addr: usize, strong: usize, weak: usize) {
+        if self.entries.insert(Entry { addr, strong, weak }).is_err() {
+            self.untracked += 1;
+        }
+    }
+
+    // An allocation without a record is one that did not fit.
+    fn remove(&mut self, addr: usize) {
+        if self.entries.remove(addr).is_none() {
+            self.untracked = self.untracked.saturating_sub(1);
+        }
+    }
+
+    fn update<R>(
+        &mut self,
+        addr: usize,
+        count: Count,
+        f: impl FnOnce(&mut usize) -> R,
+    ) -> Shadow<R> {
+        match self.entries.get_mut(addr) {
+            Some(entry) => Shadow::Found(f(match count {
+                Count::Strong => &mut entry.strong,
+                Count::Weak => &mut entry.weak,
+            })),
+            None if self.untracked > 0 => Shadow::Untracked,
+            None => Shadow::Missing,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use super::*;
+
+#[test]
+fn insert_update_and_remove() {
+    let mut table = Table::<16>::new();
+    table.insert(0x1000, 1, 1);
+    assert_eq!(table.update(0x1000, Count::Strong, |strong| *strong += 1), Shadow::Found(()));
+    assert_eq!(table.update(0x1000, Count::Strong, |strong| *strong), Shadow::Found(2));
+    assert_eq!(table.update(0x1000, Count::Weak, |weak| *weak), Shadow::Found(1));
+
+    table.remove(0x1000);
+    assert!(table.entries.is_empty());
+}
+
+#[test]
+fn freed_allocations_are_missing() {
+    let mut table = Table::<16>::new();
+    table.insert(0x1000, 1, 1);
+    table.remove(0x1000);
+    // Like a second drop of the last `Rc`: the counter is never handed out.
+    assert_eq!(table.update(0x1000, Count::Strong, |_| unreachable!()), Shadow::<()>::Missing);
+}
+
+#[test]
+fn untracked_allocations_are_not_missing() {
+    let mut table = Table::<4>::new();
+    for addr in 1..=3 {
+        table.insert(addr * 0x1000, 1, 1);
+    }
+    // The table is full: the fourth allocation is not recorded.
+    table.insert(0x4000, 1, 1);
+    assert_eq!(table.untracked, 1);
+    assert_eq!(table.update(0x4000, Count::Strong, |strong| *strong), Shadow::Untracked);
+    assert_eq!(table.update(0x5000, Count::Strong, |strong| *strong), Shadow::Untracked);
+
+    table.remove(0x4000);
+    assert_eq!(table.untracked, 0);
+    assert_eq!(table.update(0x5000, Count::Strong, |strong| *strong), Shadow::Missing);
+}
@@ -0,0 +1,241 @@
+//! A registry of the owners of allocations that went through raw pointers.
+//!
+//! With `--cfg metadata_ownership_checks`, every allocation of the global
+//! allocator that is turned into a raw pointer (`Box::into_raw`,
+//! `Vec::into_raw_parts`, `Vec::leak`, `Rc::into_raw`, ...) is recorded in a side
+//! table, along with its layout and the kind of owner that released it: a
+//! unique owner (a `Box` or a `Vec`, and with it a `String`), or shared owners
+//! (`Rc` or `Arc`), together with the number of raw pointers they handed out.
+//! Rebuilding an owner from such an allocation (`Box::from_raw`,
+//! `Vec::from_raw_parts`, `Rc::from_raw`, ...) claims the release: a unique owner
+//! takes the allocation out of the table, a shared owner takes back one of the
+//! raw pointers. Rebuilding a shared owner from more raw pointers than were
+//! handed out, rebuilding an owner of the other kind, or rebuilding one with a
+//! layout other than the one the allocation was released with, sets up a double
+//! free or a deallocation with the wrong layout. These are reported through
+//! [`metadata_violation`], and the owner is not rebuilt: the function rebuilding
+//! it panics once the violation handler returns, whatever it answers. There is
+//! nothing it could return instead, as opposed to, e.g., a length that can be
+//! clamped, and [`ViolationAction::Continue`] would set up the very double free
+//! or mismatched deallocation that was reported.
+//!
+//! Only releases are recorded, as owners also give up allocations in ways the
+//! registry never sees: a `Vec` in a `ManuallyDrop` may be rebuilt from its raw
+//! parts any number of times. Allocations without an entry are trusted.
+//!
+//! Entries are dropped when their allocation is deallocated or reallocated
+//! through the global allocator, so an address is never checked against what
+//! was allocated there before. Allocations of other allocators are not tracked,
+//! as their deallocations are never seen.
+//!
+//! The table is an [`AddrTable`], of a fixed size, as it is updated in the
+//! middle of deallocations. Allocations that no longer fit in it are not
+//! tracked.
+//!
+//! [`metadata_violation`]: core::ptr::metadata_update::metadata_violation
+//! [`ViolationAction::Continue`]: core::ptr::metadata_update::ViolationAction::Continue
+
+// Targets without atomics never track allocations.
+#![cfg_attr(not(target_has_atomic = "8"), allow(dead_code))]
+
+use core::ptr::metadata_update::{METADATA_OWNERSHIP, metadata_violation};
+
+use crate::alloc::{Allocator, Global, Layout};
+use crate::compartment::CompartmentAlloc;
+#[cfg(target_has_atomic = "8")]
+use crate::metadata_table::Locked;
+use crate::metadata_table::{AddrEntry, AddrTable};
+
+#[cfg(test)]
+mod tests;
+
+// The number of entries of the table, a power of two. Only one entry is
+// reserved when ownership is not tracked.
+const CAPACITY: usize = if METADATA_OWNERSHIP { 1 << 16 } else { 1 };
+
+/// How an allocation is owned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Owner {
+    /// By exactly one `Box` or `Vec`.
+    Unique,
+    /// By any number of `Rc`s or `Arc`s, and raw pointers to them.
+    Shared,
+}
+
+/// Records that the allocation at `ptr`, of `layout`, was turned into a raw
+/// pointer by an owner of kind `owner` that used the allocator `A`.
+#[inline]
+pub(crate) fn release<A: Allocator>(ptr: *const u8, layout: Layout, owner: Owner) {
+    if !<A as Tracked>::tracked() || layout.size() == 0 {
+        return;
+    }
+    with_table(|table| table.release(ptr.addr(), layout, owner));
+}
+
+/// Records that a `type_name`, an owner of kind `owner` in the global
+/// allocator, is about to be rebuilt from the allocation at `ptr`, of `layout`.
+///
+/// Returns whether it may be. If it may not, the violation was reported, and
+/// the caller must not take ownership of the allocation, which is left to the
+/// owner it already has. The callers panic then, see the module documentation.
+#[inline]
+#[track_caller]
+pub(crate) fn claim(type_name: &'static str, ptr: *const u8, layout: Layout, owner: Owner) -> bool {
+    if layout.size() == 0 {
+        return true;
+    }
+    // The lock is released before reporting, so that the violation handler can
+    // still deallocate.
+    match with_table(|table| table.claim(ptr.addr(), layout, owner)) {
+        Some(Err(violation)) => {
+            metadata_violation(type_name, violation.field, violation.old, violation.new);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Drops the allocation at `ptr` from the table, as it is being deallocated or
+/// reallocated.
+#[inline]
+pub(crate) fn forget(ptr: *const u8) {
+    with_table(|table| table.remove(ptr.addr()));
+}
+
+/// Whether the allocations of an allocator are tracked. Only those of `Global`
+/// are, as only its deallocations go through [`forget`].
+trait Tracked {
+    fn tracked() -> bool;
+}
+
+impl<A: Allocator> Tracked for A {
+    default fn tracked() -> bool {
+        false
+    }
+}
+
+impl Tracked for Global {
+    fn tracked() -> bool {
+        METADATA_OWNERSHIP
+    }
+}
+
+impl Tracked for CompartmentAlloc {
+    fn tracked() -> bool {
+        METADATA_OWNERSHIP
+    }
+}
+
+/// Runs `f` on the table if ownership is tracked, with the lock held.
+#[cfg(target_has_atomic = "8")]
+#[inline]
+fn with_table<R>(f: impl FnOnce(&mut Table<CAPACITY>) -> R) -> Option<R> {
+    if !METADATA_OWNERSHIP {
+        return None;
+    }
+    Some(REGISTRY.with(f))
+}
+
+#[cfg(not(target_has_atomic = "8"))]
+#[inline]
+fn with_table<R>(_f: impl FnOnce(&mut Table<CAPACITY>) -> R) -> Option<R> {
+    None
+}
+
+#[cfg(target_has_atomic = "8")]
+static REGISTRY: Locked<Table<CAPACITY>> = Locked::new(Table::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Released into a raw pointer by its unique owner, and owned by nobody.
+    Released,
+    /// Owned by `Rc`s or `Arc`s, and by this many raw pointers they handed out.
+    Shared(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    // The address of the allocation, 0 if the slot is free.
+    addr: usize,
+    size: usize,
+    align: usize,
+    state: State,
+}
+
+impl AddrEntry for Entry {
+    const FREE: Self = Entry { addr: 0, size: 0, align: 0, state: State::Released };
+
+    fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+/// An owner that may not be rebuilt, as reported to the violation handler.
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    // `ptr` if the allocation is already owned, `size` or `align` if that part
+    // of the layout does not match.
+    field: &'static str,
+    // What the allocation was released with, and what the owner asked for.
+    old: usize,
+    new: usize,
+}
+
+struct Table<const N: usize> {
+    entries: AddrTable<Entry, N>,
+}
+
+impl<const N: usize> Table<N> {
+    const fn new() -> Self {
+        Table { entries: AddrTable::new() }
+    }
+
+    fn remove(&mut self, addr: usize) -> Option<Entry> {
+        self.entries.remove(addr)
+    }
+
+    fn release(&mut self, addr: usize, layout: Layout, owner: Owner) {
+        let state = match owner {
+            Owner::Unique => State::Released,
+            Owner::Shared => {
+                // The raw pointers handed out before are still out there.
+                let raw = match self.entries.get(addr) {
+                    Some(&Entry { state: State::Shared(raw), .. }) => raw,
+                    _ => 0,
+                };
+                State::Shared(raw + 1)
+            }
+        };
+        let entry = Entry { addr, size: layout.size(), align: layout.align(), state };
+        // Allocations that do not fit are not tracked.
+        let _ = self.entries.insert(entry);
+    }
+
+    fn claim(&mut self, addr: usize, layout: Layout, owner: Owner) -> Result<(), Violation> {
+        let Some(entry) = self.entries.get_mut(addr) else {
+            return Ok(());
+        };
+        // A unique owner may only be rebuilt from an allocation a unique owner
+        // released, a shared one as long as raw pointers are left.
+        let claimable = match (owner, entry.state) {
+            (Owner::Unique, State::Released) => true,
+            (Owner::Shared, State::Shared(raw)) => raw > 0,
+            _ => false,
+        };
+        if !claimable {
+            return Err(Violation { field: "ptr", old: addr, new: addr });
+        }
+        if layout.size() != entry.size {
+            return Err(Violation { field: "size", old: entry.size, new: layout.size() });
+        }
+        if layout.align() != entry.align {
+            return Err(Violation { field: "align", old: entry.align, new: layout.align() });
+        }
+        if let State::Shared(raw) = &mut entry.state {
+            *raw -= 1;
+        } else {
+            self.entries.remove(addr);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use super::*;
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+#[test]
+fn unique_releases_are_claimed_once() {
+    let mut table = Table::<16>::new();
+    table.release(0x1000, layout(32, 8), Owner::Unique);
+    assert_eq!(table.claim(0x1000, layout(32, 8), Owner::Unique), Ok(()));
+    assert_eq!(table.entries.get(0x1000), None);
+
+    // Released again, e.g. by a second `into_raw`.
+    table.release(0x1000, layout(32, 8), Owner::Unique);
+    assert_eq!(table.claim(0x1000, layout(32, 8), Owner::Unique), Ok(()));
+}
+
+#[test]
+fn unknown_allocations_are_trusted() {
+    let mut table = Table::<16>::new();
+    // Like `Vec::from_raw_parts` on the buffer of a `Vec` in `ManuallyDrop`,
+    // again and again.
+    for _ in 0..3 {
+        assert_eq!(table.claim(0x2000, layout(16, 4), Owner::Unique), Ok(()));
+    }
+    assert!(table.entries.is_empty());
+}
+
+#[test]
+fn shared_claims_are_counted_against_releases() {
+    let mut table = Table::<16>::new();
+    table.release(0x3000, layout(24, 8), Owner::Shared);
+    table.release(0x3000, layout(24, 8), Owner::Shared);
+    assert_eq!(table.claim(0x3000, layout(24, 8), Owner::Shared), Ok(()));
+    assert_eq!(table.claim(0x3000, layout(24, 8), Owner::Shared), Ok(()));
+    // A third `from_raw` would take a strong reference nobody handed out.
+    assert_eq!(
+        table.claim(0x3000, layout(24, 8), Owner::Shared),
+        Err(Violation { field: "ptr", old: 0x3000, new: 0x3000 })
+    );
+
+    table.release(0x3000, layout(24, 8), Owner::Shared);
+    assert_eq!(table.claim(0x3000, layout(24, 8), Owner::Shared), Ok(()));
+}
+
+#[test]
+fn owners_are_rebuilt_as_the_kind_that_released() {
+    let mut table = Table::<16>::new();
+    table.release(0x3000, layout(24, 8), Owner::Shared);
+    assert_eq!(
+        table.claim(0x3000, layout(24, 8), Owner::Unique),
+        Err(Violation { field: "ptr", old: 0x3000, new: 0x3000 })
+    );
+
+    table.release(0x4000, layout(24, 8), Owner::Unique);
+    assert_eq!(
+        table.claim(0x4000, layout(24, 8), Owner::Shared),
+        Err(Violation { field: "ptr", old: 0x4000, new: 0x4000 })
+    );
+}
+
+#[test]
+fn reports_mismatched_layouts() {
+    let mut table = Table::<16>::new();
+    table.release(0x1000, layout(48, 8), Owner::Unique);
+    assert_eq!(
+        table.claim(0x1000, layout(64, 8), Owner::Unique),
+        Err(Violation { field: "size", old: 48, new: 64 })
+    );
+    assert_eq!(
+        table.claim(0x1000, layout(48, 16), Owner::Unique),
+        Err(Violation { field: "align", old: 8, new: 16 })
+    );
+    // A refused owner leaves the allocation released.
+    assert_eq!(table.claim(0x1000, layout(48, 8), Owner::Unique), Ok(()));
+}
+
+#[test]
+fn trusts_allocations_that_did_not_fit() {
+    let mut table = Table::<4>::new();
+    for addr in 1..=4 {
+        table.release(addr * 0x40, layout(8, 8), Owner::Shared);
+    }
+    assert_eq!(table.entries.len(), 3);
+    assert_eq!(table.entries.get(4 * 0x40), None);
+    // Allocations that did not fit are trusted.
+    assert_eq!(table.claim(4 * 0x40, layout(8, 8), Owner::Shared), Ok(()));
+    assert_eq!(table.claim(4 * 0x40, layout(8, 8), Owner::Shared), Ok(()));
+}
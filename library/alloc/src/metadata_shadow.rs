@@ -0,0 +1,253 @@
+//! Shadow copies of the headers of `Vec`, `String` and `VecDeque`.
+//!
+//! With `-Z metadata-protection=shadow`, every time a `RawVec` allocates,
+//! reallocates or releases its buffer, every time a `Vec` sets its length, and
+//! every time a `VecDeque` moves its tail or head, the new header is mirrored in
+//! a side table. `Vec` (and with it `String`) and `VecDeque` compare their
+//! inline header with that copy on the paths that read it: `Deref`, and so
+//! `as_slice` and `Index`, `as_slices` and `drop`. A header that was
+//! overwritten without going through its setters or the reallocation paths,
+//! e.g. by a linear overflow out of a neighbouring buffer, no longer matches,
+//! and the program aborts through [`metadata_corruption`].
+//!
+//! Collections are moved by plain copies the table never hears of, so entries
+//! are not keyed by the address of the collection but by the address of its
+//! buffer, which exactly one collection owns at any time. The address the
+//! collection was last checked at is kept alongside, for debugging. A header
+//! whose buffer is not in the table has a corrupted pointer. Collections that
+//! have not allocated have no entry, and their header is only valid if it is
+//! empty.
+//!
+//! The table is split into shards, each an [`AddrTable`] behind a lock of its
+//! own, and the shard of a buffer is picked by its address: threads updating
+//! different collections rarely wait on each other, and a header update costs
+//! an uncontended lock and a probe of a small table. The shards have a fixed
+//! size, as they are updated in the middle of allocations. Buffers that no
+//! longer fit in their shard are not mirrored, and while any of them is alive,
+//! unknown buffers of that shard are not reported. As that leaves headers
+//! unchecked, the first buffer that does not fit in a shard is reported as a
+//! [`metadata_violation`] of `metadata_shadow::untracked`, from 0 to 1. Once
+//! all untracked buffers of the shard are released, the next one is reported
+//! again. Whatever the violation hook answers, the buffer stays unmirrored, so
+//! the default `panic` policy stops programs that keep more buffers alive than
+//! the table holds, and a `log-and-*` policy lets them run on with less
+//! checking.
+//!
+//! [`metadata_corruption`]: core::ptr::metadata_update::metadata_corruption
+//! [`metadata_violation`]: core::ptr::metadata_update::metadata_violation
+
+// Targets without atomics never mirror headers.
+#![cfg_attr(not(target_has_atomic = "8"), allow(dead_code))]
+
+use core::ptr::metadata_update::{METADATA_SHADOW, metadata_corruption, metadata_violation};
+
+#[cfg(target_has_atomic = "8")]
+use crate::metadata_table::Locked;
+use crate::metadata_table::{AddrEntry, AddrTable};
+
+#[cfg(test)]
+mod tests;
+
+// The number of shards, and of entries of each, powers of two. Only one entry
+// is reserved when headers are not mirrored.
+const SHARDS: usize = if METADATA_SHADOW { 64 } else { 1 };
+const SHARD_CAPACITY: usize = if METADATA_SHADOW { 1 << 10 } else { 1 };
+
+/// The indices of a collection into its buffer, as far as they are mirrored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Indices {
+    /// The length of a `Vec`.
+    Len(usize),
+    /// The tail and head of the ring buffer of a `VecDeque`.
+    Ring { tail: usize, head: usize },
+}
+
+impl Indices {
+    fn values(self) -> [usize; 2] {
+        match self {
+            Indices::Len(len) => [len, 0],
+            Indices::Ring { tail, head } => [tail, head],
+        }
+    }
+
+    // Compares the indices with their mirrored `values`, which are all 0 for a
+    // new buffer, as for an empty collection of either kind.
+    fn compare(self, values: [usize; 2]) -> Result<(), Mismatch> {
+        let mismatch =
+            |field, inline, shadow| Err(Mismatch { field, inline, shadow: Some(shadow) });
+        match self {
+            Indices::Len(len) if len != values[0] => mismatch("len", len, values[0]),
+            Indices::Ring { tail, .. } if tail != values[0] => mismatch("tail", tail, values[0]),
+            Indices::Ring { head, .. } if head != values[1] => mismatch("head", head, values[1]),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Mirrors a change of the buffer of a `RawVec` from `old` to `new`, where
+/// `None` stands for no allocation, and `cap` is the new capacity. The indices
+/// are kept when a buffer is reallocated, and start out at 0 otherwise.
+#[inline]
+pub(crate) fn update_buffer(old: Option<usize>, new: Option<usize>, cap: usize) {
+    // The buffers may be in different shards, whose locks are taken one after
+    // the other.
+    let indices = old.and_then(|old| with_shard(old, |table| table.release(old)).flatten());
+    if let Some(new) = new {
+        let indices = indices.unwrap_or([0; 2]);
+        // The lock is released before reporting, so that the hook can still
+        // use collections.
+        if with_shard(new, |table| table.insert(new, cap, indices)) == Some(true) {
+            report_untracked();
+        }
+    }
+}
+
+// Reports that a shard is full, and that its unknown buffers go unreported
+// from now on. The buffer is not mirrored whatever the hook answers.
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn report_untracked() {
+    let _ = metadata_violation("metadata_shadow", "untracked", 0, 1);
+}
+
+/// Mirrors the indices of the collection owning the buffer `buf`.
+#[inline]
+pub(crate) fn set_indices(buf: usize, indices: Indices) {
+    with_shard(buf, |table| table.set_indices(buf, indices));
+}
+
+/// Compares the header of the collection of type `type_name` at `object` with
+/// its shadow copy, and aborts if they do not match.
+#[inline]
+#[track_caller]
+pub(crate) fn check(
+    type_name: &'static str,
+    object: usize,
+    buf: usize,
+    cap: usize,
+    indices: Indices,
+) {
+    // The lock is released before aborting, so that the panic hook can still
+    // use collections.
+    if let Some(Err(mismatch)) = with_shard(buf, |table| table.check(object, buf, cap, indices)) {
+        metadata_corruption(type_name, mismatch.field, mismatch.inline, mismatch.shadow);
+    }
+}
+
+// Picks the shard of a buffer. The low bits are skipped, as alignment keeps
+// them at 0, and the shard tables hash all bits again.
+fn shard(buf: usize) -> usize {
+    (buf >> 4) % SHARDS
+}
+
+/// Runs `f` on the shard of `buf` if headers are mirrored, with its lock held.
+#[cfg(target_has_atomic = "8")]
+#[inline]
+fn with_shard<R>(buf: usize, f: impl FnOnce(&mut Table<SHARD_CAPACITY>) -> R) -> Option<R> {
+    if !METADATA_SHADOW {
+        return None;
+    }
+    Some(SHADOW[shard(buf)].with(f))
+}
+
+#[cfg(not(target_has_atomic = "8"))]
+#[inline]
+fn with_shard<R>(_buf: usize, _f: impl FnOnce(&mut Table<SHARD_CAPACITY>) -> R) -> Option<R> {
+    None
+}
+
+#[cfg(target_has_atomic = "8")]
+const EMPTY_SHARD: Locked<Table<SHARD_CAPACITY>> = Locked::new(Table::new());
+
+#[cfg(target_has_atomic = "8")]
+static SHADOW: [Locked<Table<SHARD_CAPACITY>>; SHARDS] = [EMPTY_SHARD; SHARDS];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    // The address of the buffer, 0 if the slot is free.
+    buf: usize,
+    // The address the collection was last checked at, 0 if it never was.
+    object: usize,
+    cap: usize,
+    // The values of the `Indices`.
+    indices: [usize; 2],
+}
+
+impl AddrEntry for Entry {
+    const FREE: Self = Entry { buf: 0, object: 0, cap: 0, indices: [0; 2] };
+
+    fn addr(&self) -> usize {
+        self.buf
+    }
+}
+
+/// A field of a header that does not match its shadow copy.
+#[derive(Debug, PartialEq, Eq)]
+struct Mismatch {
+    field: &'static str,
+    inline: usize,
+    // `None` if the buffer has no shadow copy at all.
+    shadow: Option<usize>,
+}
+
+struct Table<const N: usize> {
+    entries: AddrTable<Entry, N>,
+    // The number of live buffers that did not fit. Unknown buffers are not
+    // reported while there are any.
+    untracked: usize,
+}
+
+impl<const N: usize> Table<N> {
+    const fn new() -> Self {
+        Table { entries: AddrTable::new(), untracked: 0 }
+    }
+
+    // Returns whether the buffer is the first of the shard that does not fit,
+    // and is to be reported.
+    fn insert(&mut self, buf: usize, cap: usize, indices: [usize; 2]) -> bool {
+        if self.entries.insert(Entry { buf, object: 0, cap, indices }).is_ok() {
+            return false;
+        }
+        self.untracked += 1;
+        self.untracked == 1
+    }
+
+    // Drops the entry of a buffer that is being released, and returns its
+    // indices. A buffer without an entry is one that did not fit.
+    fn release(&mut self, buf: usize) -> Option<[usize; 2]> {
+        let released = self.entries.remove(buf);
+        if released.is_none() {
+            self.untracked = self.untracked.saturating_sub(1);
+        }
+        released.map(|entry| entry.indices)
+    }
+
+    fn set_indices(&mut self, buf: usize, indices: Indices) {
+        if let Some(entry) = self.entries.get_mut(buf) {
+            entry.indices = indices.values();
+        }
+    }
+
+    fn check(
+        &mut self,
+        object: usize,
+        buf: usize,
+        cap: usize,
+        indices: Indices,
+    ) -> Result<(), Mismatch> {
+        let Some(entry) = self.entries.get_mut(buf) else {
+            return match cap {
+                0 => indices.compare([0; 2]),
+                _ if self.untracked > 0 => Ok(()),
+                _ => Err(Mismatch { field: "ptr", inline: buf, shadow: None }),
+            };
+        };
+        if cap != entry.cap {
+            return Err(Mismatch { field: "cap", inline: cap, shadow: Some(entry.cap) });
+        }
+        indices.compare(entry.indices)?;
+        entry.object = object;
+        Ok(())
+    }
+}
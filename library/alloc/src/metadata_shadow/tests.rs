@@ -0,0 +1,100 @@
+use super::*;
+
+#[test]
+fn insert_check_and_release() {
+    let mut table = Table::<16>::new();
+    table.insert(0x1000, 4, [0; 2]);
+    table.set_indices(0x1000, Indices::Len(3));
+    assert_eq!(table.check(0x10, 0x1000, 4, Indices::Len(3)), Ok(()));
+    assert_eq!(table.check(0x20, 0x1000, 4, Indices::Len(3)), Ok(()));
+    assert_eq!(table.entries.get(0x1000).map(|entry| entry.object), Some(0x20));
+
+    assert_eq!(table.release(0x1000), Some([3, 0]));
+    assert_eq!(table.release(0x1000), None);
+    assert!(table.entries.is_empty());
+}
+
+#[test]
+fn reports_the_corrupted_field() {
+    let mut table = Table::<16>::new();
+    table.insert(0x1000, 4, [2, 0]);
+    assert_eq!(
+        table.check(0, 0x1000, 5, Indices::Len(2)),
+        Err(Mismatch { field: "cap", inline: 5, shadow: Some(4) })
+    );
+    assert_eq!(
+        table.check(0, 0x1000, 4, Indices::Len(7)),
+        Err(Mismatch { field: "len", inline: 7, shadow: Some(2) })
+    );
+    assert_eq!(
+        table.check(0, 0x2000, 4, Indices::Len(2)),
+        Err(Mismatch { field: "ptr", inline: 0x2000, shadow: None })
+    );
+}
+
+#[test]
+fn mirrors_ring_indices() {
+    let mut table = Table::<16>::new();
+    // Like `VecDeque::push_front` into a new buffer of 8 elements.
+    table.insert(0x1000, 8, [0; 2]);
+    assert_eq!(table.check(0, 0x1000, 8, Indices::Ring { tail: 0, head: 0 }), Ok(()));
+    table.set_indices(0x1000, Indices::Ring { tail: 7, head: 0 });
+    assert_eq!(table.check(0, 0x1000, 8, Indices::Ring { tail: 7, head: 0 }), Ok(()));
+    assert_eq!(
+        table.check(0, 0x1000, 8, Indices::Ring { tail: 7, head: 5 }),
+        Err(Mismatch { field: "head", inline: 5, shadow: Some(0) })
+    );
+    assert_eq!(
+        table.check(0, 0x1000, 8, Indices::Ring { tail: 3, head: 0 }),
+        Err(Mismatch { field: "tail", inline: 3, shadow: Some(7) })
+    );
+}
+
+#[test]
+fn unallocated_headers_are_empty() {
+    let mut table = Table::<16>::new();
+    assert_eq!(table.check(0, 0x8, 0, Indices::Len(0)), Ok(()));
+    assert_eq!(
+        table.check(0, 0x8, 0, Indices::Len(1)),
+        Err(Mismatch { field: "len", inline: 1, shadow: Some(0) })
+    );
+    assert_eq!(
+        table.check(0, 0x8, 0, Indices::Ring { tail: 0, head: 2 }),
+        Err(Mismatch { field: "head", inline: 2, shadow: Some(0) })
+    );
+}
+
+#[test]
+fn reports_again_once_untracked_buffers_are_released() {
+    let mut table = Table::<4>::new();
+    for buf in 1..=3 {
+        table.insert(buf * 0x40, 1, [0; 2]);
+    }
+    assert!(table.check(0, 0x1000, 1, Indices::Len(0)).is_err());
+
+    table.insert(0x400, 1, [0; 2]);
+    assert_eq!(table.untracked, 1);
+    assert_eq!(table.entries.get(0x400), None);
+    assert_eq!(table.check(0, 0x400, 1, Indices::Len(0)), Ok(()));
+    assert_eq!(table.check(0, 0x1000, 1, Indices::Len(0)), Ok(()));
+
+    assert_eq!(table.release(0x400), None);
+    assert_eq!(table.untracked, 0);
+    assert!(table.check(0, 0x1000, 1, Indices::Len(0)).is_err());
+}
+
+#[test]
+fn reports_the_first_buffer_that_does_not_fit() {
+    let mut table = Table::<4>::new();
+    for buf in 1..=3 {
+        assert!(!table.insert(buf * 0x40, 1, [0; 2]));
+    }
+    assert!(table.insert(0x400, 1, [0; 2]));
+    assert!(!table.insert(0x440, 1, [0; 2]));
+
+    table.release(0x400);
+    assert!(!table.insert(0x400, 1, [0; 2]));
+    table.release(0x400);
+    table.release(0x440);
+    assert!(table.insert(0x440, 1, [0; 2]));
+}
@@ -0,0 +1,203 @@
+//! The side tables of the metadata checks.
+//!
+//! The shadow copies of collection headers, the registry of the owners of raw
+//! allocations, and the shadow memory of the metadata sanitizer in `std` all
+//! map addresses to a few words. They are updated in the middle of allocations
+//! and deallocations, so they cannot allocate themselves: each is an
+//! [`AddrTable`] of a fixed number of entries behind a [`Locked`] spin lock.
+
+#[cfg(target_has_atomic = "8")]
+use core::cell::UnsafeCell;
+#[cfg(target_has_atomic = "8")]
+use core::hint;
+use core::mem;
+#[cfg(target_has_atomic = "8")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod tests;
+
+/// An entry of an [`AddrTable`].
+pub trait AddrEntry: Copy {
+    /// The entry of a free slot. Its address is 0.
+    const FREE: Self;
+
+    /// The address the entry is keyed by.
+    fn addr(&self) -> usize;
+}
+
+/// An open-addressing hash table of `N` entries keyed by non-zero addresses,
+/// `N` a power of two, with linear probing.
+///
+/// Probe sequences get long in a full table, so a quarter of the entries is
+/// kept free: once `N - N / 4` addresses are in the table, inserting another
+/// one fails. A table whose bytes are all zero is empty if the bytes of
+/// `E::FREE` are, so that it can be allocated zeroed.
+pub struct AddrTable<E, const N: usize> {
+    entries: [E; N],
+    used: usize,
+}
+
+impl<E: AddrEntry, const N: usize> AddrTable<E, N> {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        AddrTable { entries: [E::FREE; N], used: 0 }
+    }
+
+    /// Returns the number of addresses in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.used
+    }
+
+    /// Returns whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+
+    // Fibonacci hashing: the upper half of the product depends on all the bits
+    // of the address, not just on the low ones, which alignment keeps at 0.
+    fn home(addr: usize) -> usize {
+        (addr.wrapping_mul(0x9e37_79b9_7f4a_7c15_u64 as usize) >> (usize::BITS / 2)) % N
+    }
+
+    fn find(&self, addr: usize) -> Option<usize> {
+        let mut slot = Self::home(addr);
+        for _ in 0..N {
+            match self.entries[slot].addr() {
+                0 => return None,
+                found if found == addr => return Some(slot),
+                _ => slot = (slot + 1) % N,
+            }
+        }
+        None
+    }
+
+    /// Returns the entry of `addr`.
+    #[inline]
+    pub fn get(&self, addr: usize) -> Option<&E> {
+        self.find(addr).map(|slot| &self.entries[slot])
+    }
+
+    /// Returns the entry of `addr`, mutably. Its address must not be changed.
+    #[inline]
+    pub fn get_mut(&mut self, addr: usize) -> Option<&mut E> {
+        self.find(addr).map(|slot| &mut self.entries[slot])
+    }
+
+    /// Inserts `entry`, replacing the entry of its address if there is one.
+    /// Gives the entry back if the table is full.
+    pub fn insert(&mut self, entry: E) -> Result<(), E> {
+        if let Some(slot) = self.find(entry.addr()) {
+            self.entries[slot] = entry;
+            return Ok(());
+        }
+        if self.used >= N - N / 4 {
+            return Err(entry);
+        }
+        let mut slot = Self::home(entry.addr());
+        while self.entries[slot].addr() != 0 {
+            slot = (slot + 1) % N;
+        }
+        self.entries[slot] = entry;
+        self.used += 1;
+        Ok(())
+    }
+
+    /// Removes the entry of `addr` and returns it.
+    pub fn remove(&mut self, addr: usize) -> Option<E> {
+        let mut hole = self.find(addr)?;
+        let removed = mem::replace(&mut self.entries[hole], E::FREE);
+        self.used -= 1;
+        // Moves the entries following the hole back into it, unless that would
+        // move them before their home slot, so that no probe sequence stops
+        // early at the hole.
+        let mut slot = hole;
+        loop {
+            slot = (slot + 1) % N;
+            let entry = self.entries[slot];
+            if entry.addr() == 0 {
+                return Some(removed);
+            }
+            let home = Self::home(entry.addr());
+            if slot.wrapping_sub(home) % N >= slot.wrapping_sub(hole) % N {
+                self.entries[hole] = entry;
+                self.entries[slot] = E::FREE;
+                hole = slot;
+            }
+        }
+    }
+
+    /// Removes the entries `f` returns `false` for.
+    pub fn retain(&mut self, mut f: impl FnMut(&E) -> bool) {
+        let mut slot = 0;
+        while slot < N {
+            let entry = self.entries[slot];
+            if entry.addr() != 0 && !f(&entry) {
+                self.remove(entry.addr());
+                // An entry that was not visited yet may have moved into the slot.
+                continue;
+            }
+            slot += 1;
+        }
+    }
+}
+
+/// A value behind a spin lock. The tables are used from within the global
+/// allocator, where blocking on a `Mutex` of `std` is not an option.
+#[cfg(target_has_atomic = "8")]
+pub struct Locked<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: the value is only accessed with the lock held.
+#[cfg(target_has_atomic = "8")]
+unsafe impl<T: Send> Sync for Locked<T> {}
+
+#[cfg(target_has_atomic = "8")]
+impl<T> Locked<T> {
+    /// Creates an unlocked `value`.
+    pub const fn new(value: T) -> Self {
+        Locked { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    /// Runs `f` on the value with the lock held. `f` must not take the lock
+    /// again.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        // Releases the lock even if `f` unwinds.
+        struct Unlock<'a>(&'a AtomicBool);
+        impl Drop for Unlock<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+        let _unlock = Unlock(&self.locked);
+        // SAFETY: the lock is held, so nothing else accesses the value.
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+/// A side table the runtime may write protect. It is aligned to, and fills,
+/// whole pages, so that its pages can be protected without its neighbours.
+#[cfg(target_has_atomic = "8")]
+#[repr(C, align(4096))]
+pub(crate) struct Protected<T>(pub(crate) T);
+
+/// Returns the side tables the runtime may write protect, as a page aligned
+/// region and its length, a multiple of 4096 bytes. They are only written
+/// with the metadata write window open, see
+/// [`with_metadata_writes`](core::ptr::metadata_update::with_metadata_writes).
+#[cfg(target_has_atomic = "8")]
+pub fn protected_region() -> (*mut u8, usize) {
+    crate::metadata_counts::region()
+}
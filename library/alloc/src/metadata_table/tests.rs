@@ -0,0 +1,82 @@
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    addr: usize,
+    value: usize,
+}
+
+impl AddrEntry for Entry {
+    const FREE: Self = Entry { addr: 0, value: 0 };
+
+    fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+#[test]
+fn insert_get_and_remove() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    assert_eq!(table.insert(Entry { addr: 0x1000, value: 1 }), Ok(()));
+    assert_eq!(table.insert(Entry { addr: 0x1000, value: 2 }), Ok(()));
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.get(0x1000), Some(&Entry { addr: 0x1000, value: 2 }));
+    assert_eq!(table.get(0x2000), None);
+
+    table.get_mut(0x1000).unwrap().value = 3;
+    assert_eq!(table.remove(0x1000), Some(Entry { addr: 0x1000, value: 3 }));
+    assert_eq!(table.remove(0x1000), None);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn removal_keeps_colliding_entries() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    let addrs: Vec<usize> = (1..=12).map(|i| i * 0x40).collect();
+    for &addr in &addrs {
+        assert_eq!(table.insert(Entry { addr, value: addr }), Ok(()));
+    }
+    for &addr in addrs.iter().step_by(2) {
+        assert!(table.remove(addr).is_some());
+    }
+    for (i, &addr) in addrs.iter().enumerate() {
+        assert_eq!(table.get(addr).is_some(), i % 2 == 1);
+    }
+}
+
+#[test]
+fn retain_visits_moved_entries() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    for addr in 1..=12 {
+        assert_eq!(table.insert(Entry { addr: addr * 0x40, value: addr }), Ok(()));
+    }
+    table.retain(|entry| entry.value % 3 != 0);
+    assert_eq!(table.len(), 8);
+    for addr in 1..=12 {
+        assert_eq!(table.get(addr * 0x40).is_some(), addr % 3 != 0);
+    }
+}
+
+#[test]
+fn keeps_a_quarter_free() {
+    let mut table = AddrTable::<Entry, 4>::new();
+    for addr in 1..=3 {
+        assert_eq!(table.insert(Entry { addr: addr * 0x40, value: 0 }), Ok(()));
+    }
+    let entry = Entry { addr: 4 * 0x40, value: 0 };
+    assert_eq!(table.insert(entry), Err(entry));
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.get(4 * 0x40), None);
+    // Entries already in the table can still be replaced.
+    assert_eq!(table.insert(Entry { addr: 0x40, value: 1 }), Ok(()));
+}
+
+#[test]
+fn unlocks_on_unwind() {
+    let locked = Locked::new(0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        locked.with(|_| panic!());
+    }));
+    assert!(result.is_err());
+    assert_eq!(locked.with(|value| mem::replace(value, 1)), 0);
+}
@@ -8,12 +8,17 @@
 use core::ptr::{self, NonNull, Unique};
 use core::slice;
 
+/*SOR-MetaUpdate@kayondomartin*/
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
+
 #[cfg(not(no_global_oom_handling))]
 use crate::alloc::handle_alloc_error;
 use crate::alloc::{Allocator, Global, Layout};
 use crate::boxed::Box;
 use crate::collections::TryReserveError;
 use crate::collections::TryReserveErrorKind::*;
+use crate::metadata_shadow::{self, Indices};
+use crate::vec::VecField;
 
 #[cfg(test)]
 mod tests;
@@ -50,7 +55,9 @@ enum AllocInit {
 /// `Box<[T]>`, since `capacity()` won't yield the length.
 #[allow(missing_debug_implementations)]
 pub(crate) struct RawVec<T, A: Allocator = Global> {
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     ptr: Unique<T>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     cap: usize,
     alloc: A,
 }
@@ -159,6 +166,7 @@ pub unsafe fn into_box(self, len: usize) -> Box<[MaybeUninit<T>], A> {
         );
 
         let me = ManuallyDrop::new(self);
+        metadata_shadow::update_buffer(me.buffer(), None, 0);
         unsafe {
             let slice = slice::from_raw_parts_mut(me.ptr() as *mut MaybeUninit<T>, len);
             Box::from_raw_in(slice, ptr::read(&me.alloc))
@@ -182,8 +190,8 @@ fn allocate_in(capacity: usize, init: AllocInit, alloc: A) -> Self {
                 Err(_) => capacity_overflow(),
             }
             let result = match init {
-                AllocInit::Uninitialized => alloc.allocate(layout),
-                AllocInit::Zeroed => alloc.allocate_zeroed(layout),
+                AllocInit::Uninitialized => alloc.allocate_for::<T>(layout),
+                AllocInit::Zeroed => alloc.allocate_zeroed_for::<T>(layout),
             };
             let ptr = match result {
                 Ok(ptr) => ptr,
@@ -193,11 +201,26 @@ fn allocate_in(capacity: usize, init: AllocInit, alloc: A) -> Self {
             // Allocators currently return a `NonNull<[u8]>` whose length
             // matches the size requested. If that ever changes, the capacity
             // here should change to `ptr.len() / mem::size_of::<T>()`.
-            Self {
+            let this = Self {
                 ptr: unsafe { Unique::new_unchecked(ptr.cast().as_ptr()) },
                 cap: capacity,
                 alloc,
+            };
+            // The block is checked like the ones `set_ptr_and_cap` installs.
+            // Nothing else owns it yet, so it is handed back before reporting.
+            if METADATA_CHECKS {
+                let new_ptr = ptr.as_mut_ptr().addr();
+                if !this.synchronize(VecField::Ptr, 0, new_ptr) {
+                    drop(this);
+                    metadata_violation(VecField::Ptr, 0, new_ptr);
+                }
+                if capacity > Self::block_cap(ptr) {
+                    drop(this);
+                    metadata_violation(VecField::Cap, 0, capacity);
+                }
             }
+            metadata_shadow::update_buffer(None, this.buffer(), capacity);
+            this
         }
     }
 
@@ -211,9 +234,26 @@ fn allocate_in(capacity: usize, init: AllocInit, alloc: A) -> Self {
     /// systems). ZST vectors may have a capacity up to `usize::MAX`.
     /// If the `ptr` and `capacity` come from a `RawVec` created via `alloc`, then this is
     /// guaranteed.
+    ///
+    /// The pair is only accepted if it describes an allocation `RawVec` could have requested
+    /// itself, and if the capacity fits in the block the allocator handed out for `ptr`, as far as
+    /// the allocator can tell. Anything else is reported as a metadata violation.
     #[inline]
     pub unsafe fn from_raw_parts_in(ptr: *mut T, capacity: usize, alloc: A) -> Self {
-        Self { ptr: unsafe { Unique::new_unchecked(ptr) }, cap: capacity, alloc }
+        let this = Self { ptr: unsafe { Unique::new_unchecked(ptr) }, cap: capacity, alloc };
+        // Dropping `this` on a violation would hand the bogus pair back to the allocator.
+        if METADATA_CHECKS && !this.synchronize(VecField::Ptr, 0, ptr.addr()) {
+            mem::forget(this);
+            metadata_violation(VecField::Ptr, 0, ptr.addr());
+        }
+        if METADATA_CHECKS
+            && !(this.synchronize(VecField::Cap, 0, capacity) && this.fits_allocation(capacity))
+        {
+            mem::forget(this);
+            metadata_violation(VecField::Cap, 0, capacity);
+        }
+        metadata_shadow::update_buffer(None, this.buffer(), capacity);
+        this
     }
 
     /// Gets a raw pointer to the start of the allocation. Note that this is
@@ -237,6 +277,51 @@ pub fn allocator(&self) -> &A {
         &self.alloc
     }
 
+    /// Compares the header with its shadow copy under
+    /// `-Z metadata-protection=shadow`, together with the `indices` of the
+    /// collection of type `type_name` at `object` owning the buffer. Aborts if
+    /// they do not match.
+    #[inline]
+    #[track_caller]
+    pub fn check_shadow(&self, type_name: &'static str, object: *const (), indices: Indices) {
+        if !T::IS_ZST {
+            let buf = self.ptr.as_ptr().addr();
+            metadata_shadow::check(type_name, object.addr(), buf, self.cap, indices);
+        }
+    }
+
+    /// Mirrors the `indices` of the collection owning the buffer, see
+    /// `check_shadow`.
+    #[inline]
+    pub fn set_shadow(&self, indices: Indices) {
+        if let Some(buf) = self.buffer() {
+            metadata_shadow::set_indices(buf, indices);
+        }
+    }
+
+    /// Whether `cap` elements fit in the block the allocator handed out for the
+    /// buffer, as far as the allocator can tell. This does not trust `self.cap`,
+    /// which is what a corrupted header would get wrong.
+    pub fn fits_allocation(&self, cap: usize) -> bool {
+        let Some((ptr, _)) = self.current_memory() else {
+            // Nothing was allocated, so there is no block to check against.
+            return true;
+        };
+        // SAFETY: the buffer is currently allocated by `self.alloc`.
+        match unsafe { self.alloc.allocation_layout(ptr) } {
+            Some(block) => {
+                Layout::array::<T>(cap).map_or(false, |layout| layout.size() <= block.size())
+            }
+            None => true,
+        }
+    }
+
+    // The address of the buffer, if one is allocated.
+    #[inline]
+    fn buffer(&self) -> Option<usize> {
+        self.current_memory().map(|(ptr, _)| ptr.as_ptr().addr())
+    }
+
     fn current_memory(&self) -> Option<(NonNull<u8>, Layout)> {
         if T::IS_ZST || self.cap == 0 {
             None
@@ -361,12 +446,52 @@ fn needs_to_grow(&self, len: usize, additional: usize) -> bool {
         additional > self.capacity().wrapping_sub(len)
     }
 
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
     fn set_ptr_and_cap(&mut self, ptr: NonNull<[u8]>, cap: usize) {
         // Allocators currently return a `NonNull<[u8]>` whose length matches
         // the size requested. If that ever changes, the capacity here should
         // change to `ptr.len() / mem::size_of::<T>()`.
+        //
+        // Both fields are checked against the block the allocator actually
+        // handed out: the pointer must be aligned for `T`, and the capacity
+        // must fit in the block. The old block may already be gone, so the
+        // fields are written first, with the capacity clamped to what the
+        // block can hold, and a violation is only reported once they describe
+        // the new block, which unwinding out of the report then deallocates.
+        let old_buffer = self.buffer();
+        let (old_ptr, old_cap) = (self.ptr.as_ptr().addr(), self.cap);
+        let new_ptr = ptr.as_mut_ptr().addr();
+        let ptr_ok = !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, VecField::Ptr, old_ptr, new_ptr);
+        let block_cap = Self::block_cap(ptr);
+        let cap_ok = !METADATA_CHECKS
+            || metadata_update::synchronize_metadata(self, VecField::Cap, old_cap, cap)
+                && cap <= block_cap;
+        let requested_cap = cap;
+        let cap = if cap_ok { cap } else { block_cap };
+
+        // Both fields are `#[rustc_protected_metadata]`, so the compiler wraps the
+        // writes themselves in the protection window, without checking them again.
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
         self.ptr = unsafe { Unique::new_unchecked(ptr.cast().as_ptr()) };
         self.cap = cap;
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
+        metadata_shadow::update_buffer(old_buffer, self.buffer(), cap);
+
+        if !ptr_ok {
+            metadata_violation(VecField::Ptr, old_ptr, new_ptr);
+        }
+        if !cap_ok {
+            metadata_violation(VecField::Cap, old_cap, requested_cap);
+        }
+    }
+
+    // The number of elements a block handed out by the allocator can hold.
+    #[inline]
+    fn block_cap(block: NonNull<[u8]>) -> usize {
+        if T::IS_ZST { usize::MAX } else { block.len() / mem::size_of::<T>() }
     }
 
     // This method is usually instantiated many times. So we want it to be as
@@ -397,7 +522,8 @@ fn grow_amortized(&mut self, len: usize, additional: usize) -> Result<(), TryRes
         let new_layout = Layout::array::<T>(cap);
 
         // `finish_grow` is non-generic over `T`.
-        let ptr = finish_grow(new_layout, self.current_memory(), &mut self.alloc)?;
+        let ptr =
+            finish_grow(new_layout, self.current_memory(), &mut self.alloc, A::allocate_for::<T>)?;
         self.set_ptr_and_cap(ptr, cap);
         Ok(())
     }
@@ -416,7 +542,8 @@ fn grow_exact(&mut self, len: usize, additional: usize) -> Result<(), TryReserve
         let new_layout = Layout::array::<T>(cap);
 
         // `finish_grow` is non-generic over `T`.
-        let ptr = finish_grow(new_layout, self.current_memory(), &mut self.alloc)?;
+        let ptr =
+            finish_grow(new_layout, self.current_memory(), &mut self.alloc, A::allocate_for::<T>)?;
         self.set_ptr_and_cap(ptr, cap);
         Ok(())
     }
@@ -443,12 +570,14 @@ fn shrink(&mut self, cap: usize) -> Result<(), TryReserveError> {
 // This function is outside `RawVec` to minimize compile times. See the comment
 // above `RawVec::grow_amortized` for details. (The `A` parameter isn't
 // significant, because the number of different `A` types seen in practice is
-// much smaller than the number of `T` types.)
+// much smaller than the number of `T` types.) A first allocation goes through
+// `allocate`, which is `Allocator::allocate_for` for the element type.
 #[inline(never)]
 fn finish_grow<A>(
     new_layout: Result<Layout, LayoutError>,
     current_memory: Option<(NonNull<u8>, Layout)>,
     alloc: &mut A,
+    allocate: fn(&A, Layout) -> Result<NonNull<[u8]>, core::alloc::AllocError>,
 ) -> Result<NonNull<[u8]>, TryReserveError>
 where
     A: Allocator,
@@ -466,7 +595,7 @@ fn finish_grow<A>(
             alloc.grow(ptr, old_layout, new_layout)
         }
     } else {
-        alloc.allocate(new_layout)
+        allocate(alloc, new_layout)
     };
 
     memory.map_err(|_| AllocError { layout: new_layout, non_exhaustive: () }.into())
@@ -476,11 +605,36 @@ unsafe impl<#[may_dangle] T, A: Allocator> Drop for RawVec<T, A> {
     /// Frees the memory owned by the `RawVec` *without* trying to drop its contents.
     fn drop(&mut self) {
         if let Some((ptr, layout)) = self.current_memory() {
+            metadata_shadow::update_buffer(Some(ptr.as_ptr().addr()), None, 0);
             unsafe { self.alloc.deallocate(ptr, layout) }
         }
     }
 }
 
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, A: Allocator> MetaUpdate for RawVec<T, A> {
+    type Field = VecField;
+
+    /// Synchronize a new capacity or pointer with the allocator.
+    /// A capacity is only acceptable if an array of that many `T`s could have
+    /// been requested from the allocator in the first place, and a pointer only
+    /// if it is non-null and aligned for `T`. The check against the block the
+    /// allocator actually handed out is done in `set_ptr_and_cap`, where that
+    /// block is known, and in `from_raw_parts_in` through `fits_allocation`.
+    /// `RawVec` has no length.
+    fn synchronize(&self, field: VecField, _old: usize, new: usize) -> bool {
+        match field {
+            VecField::Cap => {
+                T::IS_ZST
+                    || Layout::array::<T>(new)
+                        .map_or(false, |layout| alloc_guard(layout.size()).is_ok())
+            }
+            VecField::Ptr => new != 0 && new % mem::align_of::<T>() == 0,
+            VecField::Len => false,
+        }
+    }
+}
+
 // Central function for reserve error handling.
 #[cfg(not(no_global_oom_handling))]
 #[inline]
@@ -510,6 +664,17 @@ fn alloc_guard(alloc_size: usize) -> Result<(), TryReserveError> {
     }
 }
 
+// Reports a pointer or capacity that does not match what the allocator
+// handed out. Kept out of line, like `capacity_overflow`, so that the checks
+// in the hot paths stay small.
+#[cold]
+#[inline(never)]
+fn metadata_violation(field: VecField, old: usize, new: usize) -> ! {
+    metadata_update::metadata_violation("RawVec", field.name(), old, new);
+    // Unlike a length, there is no safe value to fall back to here.
+    panic!("RawVec metadata does not match the allocation");
+}
+
 // One central function responsible for reporting capacity overflows. This'll
 // ensure that the code generation related to these panics is minimal as there's
 // only one location which panics rather than a bunch throughout the module.
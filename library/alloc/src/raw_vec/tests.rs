@@ -161,3 +161,45 @@ fn zst_reserve_exact_panic() {
 
     v.reserve_exact(101, usize::MAX - 100);
 }
+
+#[test]
+#[should_panic(expected = "metadata violation in `RawVec::cap`")]
+fn from_raw_parts_rejects_impossible_capacity() {
+    let ptr = NonNull::<u64>::dangling().as_ptr();
+    let _v = unsafe { RawVec::from_raw_parts_in(ptr, usize::MAX, Global) };
+}
+
+// An allocator that hands out blocks one `u32` shorter than requested.
+struct ShortAlloc {
+    // The size of the live block, as requested, to deallocate it with.
+    size: Cell<usize>,
+}
+
+unsafe impl Allocator for ShortAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, crate::alloc::AllocError> {
+        let block = Global.allocate(layout)?;
+        self.size.set(layout.size());
+        Ok(NonNull::slice_from_raw_parts(block.cast(), block.len() - 4))
+    }
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let layout = Layout::from_size_align(self.size.get(), layout.align()).unwrap();
+        unsafe { Global.deallocate(ptr, layout) }
+    }
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `RawVec::cap`")]
+fn allocate_in_rejects_short_block() {
+    let _v: RawVec<u32, _> = RawVec::with_capacity_in(4, ShortAlloc { size: Cell::new(0) });
+}
+
+#[test]
+fn grow_reports_short_block() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut v: RawVec<u32, _> = RawVec::new_in(ShortAlloc { size: Cell::new(0) });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| v.reserve_exact(0, 4)));
+    assert!(result.is_err());
+    // The capacity was clamped to the block before reporting.
+    assert_eq!(v.capacity(), 3);
+}
@@ -273,15 +273,23 @@
 use crate::alloc::handle_alloc_error;
 #[cfg(not(no_global_oom_handling))]
 use crate::alloc::{box_free, WriteCloneIntoRaw};
-use crate::alloc::{AllocError, Allocator, Global, Layout};
+#[cfg(not(metadata_protection = "guard"))]
+use crate::alloc::Global as RcAlloc;
+use crate::alloc::{AllocError, Allocator, Layout};
 use crate::borrow::{Cow, ToOwned};
+#[cfg(metadata_protection = "guard")]
+use crate::compartment::CompartmentAlloc as RcAlloc;
+use crate::metadata_counts::{self, Count, Shadow};
+use crate::metadata_ownership::{self, Owner};
 #[cfg(not(no_global_oom_handling))]
 use crate::string::String;
 #[cfg(not(no_global_oom_handling))]
 use crate::vec::Vec;
 
 /*SOR-MetaUpdate@kayondomartin */
-use core::ptr::metadata_update::MetaUpdate;
+use core::ptr::metadata_update::{
+    metadata_violation, with_metadata_writes, MetaUpdate, MetadataField, METADATA_CHECKS,
+};
 
 #[cfg(test)]
 mod tests;
@@ -289,13 +297,69 @@
 // This is repr(C) to future-proof against possible field-reordering, which
 // would interfere with otherwise safe [into|from]_raw() of transmutable
 // inner types.
+//
+// The counters are not `#[rustc_protected_metadata]`: they are `Cell`s, updated
+// through shared references with `Cell::set`, which the attribute does not see.
+// Their updates are validated against a shadow record kept outside of the
+// allocation instead, see `metadata_counts`, and made with the metadata write
+// window open, which is how Miri's `-Zmiri-protected-metadata`, knowing the
+// counters through the diagnostic item, tells them from stray writes.
 #[repr(C)]
+#[cfg_attr(not(test), rustc_diagnostic_item = "RcBox")]
 struct RcBox<T: ?Sized> {
     strong: Cell<usize>,
     weak: Cell<usize>,
     value: T,
 }
 
+/// Returns `true` if a counter may be updated from `old` to `new`, where
+/// `shadow` is its record, if it has one. `old` must agree with the record and
+/// must not be zero, as nothing may be counted (or dropped) again once the
+/// counter reached zero. `new` must be one step away.
+#[inline(always)]
+fn accepts(shadow: Option<usize>, old: usize, new: usize) -> bool {
+    old != 0
+        && shadow.map_or(true, |shadow| shadow == old)
+        && (new == old.wrapping_add(1) || new == old - 1)
+}
+
+/// The protected metadata fields of an [`Rc`], i.e. its reference counters.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RcField {
+    /// The number of [`Rc`] pointers to the allocation.
+    Strong,
+    /// The number of [`Weak`] pointers to the allocation, plus one for all
+    /// [`Rc`] pointers together.
+    Weak,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for RcField {
+    const FIELDS: &'static [Self] = &[RcField::Strong, RcField::Weak];
+
+    fn name(self) -> &'static str {
+        match self {
+            RcField::Strong => "strong",
+            RcField::Weak => "weak",
+        }
+    }
+}
+
+impl RcField {
+    #[inline(always)]
+    fn count(self) -> Count {
+        match self {
+            RcField::Strong => Count::Strong,
+            RcField::Weak => Count::Weak,
+        }
+    }
+}
+
+/// Value both a counter and its record are pinned to once a violation was
+/// reported, so that the allocation is never released based on the count.
+const RC_POISONED: usize = usize::MAX;
+
 /// A single-threaded reference-counting pointer. 'Rc' stands for 'Reference
 /// Counted'.
 ///
@@ -371,10 +435,13 @@ pub fn new(value: T) -> Rc<T> {
         // the allocation while the strong destructor is running, even
         // if the weak pointer is stored inside the strong one.
         unsafe {
-            Self::from_inner(
-                Box::leak(Box::new(RcBox { strong: Cell::new(1), weak: Cell::new(1), value }))
-                    .into(),
-            )
+            let inner: NonNull<_> = Box::leak(Box::new_in(
+                RcBox { strong: Cell::new(1), weak: Cell::new(1), value },
+                RcAlloc,
+            ))
+            .into();
+            metadata_counts::insert(inner.as_ptr().cast(), 1, 1);
+            Self::from_inner(inner)
         }
     }
 
@@ -437,12 +504,16 @@ pub fn new_cyclic<F>(data_fn: F) -> Rc<T>
     {
         // Construct the inner in the "uninitialized" state with a single
         // weak reference.
-        let uninit_ptr: NonNull<_> = Box::leak(Box::new(RcBox {
-            strong: Cell::new(0),
-            weak: Cell::new(1),
-            value: mem::MaybeUninit::<T>::uninit(),
-        }))
+        let uninit_ptr: NonNull<_> = Box::leak(Box::new_in(
+            RcBox {
+                strong: Cell::new(0),
+                weak: Cell::new(1),
+                value: mem::MaybeUninit::<T>::uninit(),
+            },
+            RcAlloc,
+        ))
         .into();
+        metadata_counts::insert(uninit_ptr.as_ptr().cast(), 0, 1);
 
         let init_ptr: NonNull<RcBox<T>> = uninit_ptr.cast();
 
@@ -462,7 +533,8 @@ pub fn new_cyclic<F>(data_fn: F) -> Rc<T>
 
             let prev_value = (*inner).strong.get();
             debug_assert_eq!(prev_value, 0, "No prior strong references should exist");
-            (*inner).strong.set(1);
+            with_metadata_writes(|| (*inner).strong.set(1));
+            metadata_counts::update(inner.cast(), Count::Strong, |strong| *strong = 1);
 
             Rc::from_inner(init_ptr)
         };
@@ -499,7 +571,7 @@ pub fn new_uninit() -> Rc<mem::MaybeUninit<T>> {
         unsafe {
             Rc::from_ptr(Rc::allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate(layout),
+                |layout| RcAlloc.allocate(layout),
                 |mem| mem as *mut RcBox<mem::MaybeUninit<T>>,
             ))
         }
@@ -532,7 +604,7 @@ pub fn new_zeroed() -> Rc<mem::MaybeUninit<T>> {
         unsafe {
             Rc::from_ptr(Rc::allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| RcAlloc.allocate_zeroed(layout),
                 |mem| mem as *mut RcBox<mem::MaybeUninit<T>>,
             ))
         }
@@ -556,10 +628,13 @@ pub fn try_new(value: T) -> Result<Rc<T>, AllocError> {
         // the allocation while the strong destructor is running, even
         // if the weak pointer is stored inside the strong one.
         unsafe {
-            Ok(Self::from_inner(
-                Box::leak(Box::try_new(RcBox { strong: Cell::new(1), weak: Cell::new(1), value })?)
-                    .into(),
-            ))
+            let inner: NonNull<_> = Box::leak(Box::try_new_in(
+                RcBox { strong: Cell::new(1), weak: Cell::new(1), value },
+                RcAlloc,
+            )?)
+            .into();
+            metadata_counts::insert(inner.as_ptr().cast(), 1, 1);
+            Ok(Self::from_inner(inner))
         }
     }
 
@@ -589,7 +664,7 @@ pub fn try_new_uninit() -> Result<Rc<mem::MaybeUninit<T>>, AllocError> {
         unsafe {
             Ok(Rc::from_ptr(Rc::try_allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate(layout),
+                |layout| RcAlloc.allocate(layout),
                 |mem| mem as *mut RcBox<mem::MaybeUninit<T>>,
             )?))
         }
@@ -622,7 +697,7 @@ pub fn try_new_zeroed() -> Result<Rc<mem::MaybeUninit<T>>, AllocError> {
         unsafe {
             Ok(Rc::from_ptr(Rc::try_allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| RcAlloc.allocate_zeroed(layout),
                 |mem| mem as *mut RcBox<mem::MaybeUninit<T>>,
             )?))
         }
@@ -734,7 +809,7 @@ pub fn new_zeroed_slice(len: usize) -> Rc<[mem::MaybeUninit<T>]> {
         unsafe {
             Rc::from_ptr(Rc::allocate_for_layout(
                 Layout::array::<T>(len).unwrap(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| RcAlloc.allocate_zeroed(layout),
                 |mem| {
                     ptr::slice_from_raw_parts_mut(mem as *mut T, len)
                         as *mut RcBox<[mem::MaybeUninit<T>]>
@@ -837,7 +912,11 @@ impl<T: ?Sized> Rc<T> {
     /// assert_eq!(unsafe { &*x_ptr }, "hello");
     /// ```
     #[stable(feature = "rc_raw", since = "1.17.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "rc_into_raw")]
     pub fn into_raw(this: Self) -> *const T {
+        // SAFETY: `this` owns a strong count, so the `RcBox` is still allocated.
+        let layout = unsafe { Layout::for_value_raw(this.ptr.as_ptr()) };
+        metadata_ownership::release::<RcAlloc>(this.ptr.as_ptr().cast(), layout, Owner::Shared);
         let ptr = Self::as_ptr(&this);
         mem::forget(this);
         ptr
@@ -906,13 +985,33 @@ pub fn as_ptr(this: &Self) -> *const T {
     /// // The memory was freed when `x` went out of scope above, so `x_ptr` is now dangling!
     /// ```
     #[stable(feature = "rc_raw", since = "1.17.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "rc_from_raw")]
     pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let rc = unsafe { Self::from_raw_borrowed(ptr) };
+
+        // SAFETY: the caller guarantees that `ptr` came from `into_raw`, so the
+        // `RcBox` is still allocated.
+        let layout = unsafe { Layout::for_value_raw(rc.ptr.as_ptr()) };
+        if !metadata_ownership::claim("Rc", rc.ptr.as_ptr().cast(), layout, Owner::Shared) {
+            panic!("cannot rebuild an `Rc` from an allocation it may not own");
+        }
+
+        mem::ManuallyDrop::into_inner(rc)
+    }
+
+    /// Rebuilds the `Rc` behind `ptr` without taking its strong reference
+    /// back, so that it is not claimed from the ownership registry.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Rc::from_raw`].
+    unsafe fn from_raw_borrowed(ptr: *const T) -> mem::ManuallyDrop<Self> {
         let offset = unsafe { data_offset(ptr) };
 
         // Reverse the offset to find the original RcBox.
         let rc_ptr = unsafe { ptr.byte_sub(offset) as *mut RcBox<T> };
 
-        unsafe { Self::from_ptr(rc_ptr) }
+        mem::ManuallyDrop::new(unsafe { Self::from_ptr(rc_ptr) })
     }
 
     /// Creates a new [`Weak`] pointer to this allocation.
@@ -1000,9 +1099,9 @@ pub fn strong_count(this: &Self) -> usize {
     #[stable(feature = "rc_mutate_strong_count", since = "1.53.0")]
     pub unsafe fn increment_strong_count(ptr: *const T) {
         // Retain Rc, but don't touch refcount by wrapping in ManuallyDrop
-        let rc = unsafe { mem::ManuallyDrop::new(Rc::<T>::from_raw(ptr)) };
-        // Now increase refcount, but don't drop new refcount either
-        let _rc_clone: mem::ManuallyDrop<_> = rc.clone();
+        let rc = unsafe { Rc::<T>::from_raw_borrowed(ptr) };
+        // Now increase refcount, and hand it out as another raw pointer
+        let _ = Rc::into_raw(Rc::clone(&rc));
     }
 
     /// Decrements the strong reference count on the `Rc<T>` associated with the
@@ -1377,6 +1476,7 @@ unsafe fn try_allocate_for_layout(
             ptr::write(&mut (*inner).strong, Cell::new(1));
             ptr::write(&mut (*inner).weak, Cell::new(1));
         }
+        metadata_counts::insert(inner.cast(), 1, 1);
 
         Ok(inner)
     }
@@ -1388,7 +1488,7 @@ unsafe fn allocate_for_ptr(ptr: *const T) -> *mut RcBox<T> {
         unsafe {
             Self::allocate_for_layout(
                 Layout::for_value(&*ptr),
-                |layout| Global.allocate(layout),
+                |layout| RcAlloc.allocate(layout),
                 |mem| mem.with_metadata_of(ptr as *const RcBox<T>),
             )
         }
@@ -1425,7 +1525,7 @@ unsafe fn allocate_for_slice(len: usize) -> *mut RcBox<[T]> {
         unsafe {
             Self::allocate_for_layout(
                 Layout::array::<T>(len).unwrap(),
-                |layout| Global.allocate(layout),
+                |layout| RcAlloc.allocate(layout),
                 |mem| ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut RcBox<[T]>,
             )
         }
@@ -1464,7 +1564,8 @@ fn drop(&mut self) {
                     let slice = from_raw_parts_mut(self.elems, self.n_elems);
                     ptr::drop_in_place(slice);
 
-                    Global.deallocate(self.mem, self.layout);
+                    metadata_counts::remove(self.mem.as_ptr());
+                    RcAlloc.deallocate(self.mem, self.layout);
                 }
             }
         }
@@ -1525,16 +1626,21 @@ fn deref(&self) -> &T {
 }
 
 #[unstable(feature = "metadata_update", issue = "none")]
-impl<T> MetaUpdate for Rc<T> {
-
-    /// Synchronize metadata changes for Rc. In Rc, the only metadata available is
-    /// the reference counter. changing it doesn't seem to require synchronization:
-    /// Rather, we will ensure no double free/drop is reaches the allocator side to 
-    /// ensure temporal safety. This will be done through the analysis stage of
-    /// the MetaUpdate project. 
-    /// TODO: ensure analysis is done to prevent double free/drop
-    fn synchronize(&self, new: usize) -> bool {
-        true
+impl<T: ?Sized> MetaUpdate for Rc<T> {
+    type Field = RcField;
+
+    /// Synchronize a counter update with the shadow record of the allocation.
+    /// `old` must be the current count, agree with its record and must not be
+    /// zero: decrementing it again would be a double drop, incrementing it
+    /// would resurrect a dropped value. `new` must be one step away from it.
+    fn synchronize(&self, field: RcField, old: usize, new: usize) -> bool {
+        let inner = self.inner();
+        let counter = inner.counter(field);
+        match metadata_counts::update(inner.addr(), field.count(), |shadow| *shadow) {
+            Shadow::Found(shadow) => old == counter.get() && accepts(Some(shadow), old, new),
+            Shadow::Untracked => old == counter.get() && accepts(None, old, new),
+            Shadow::Missing => false,
+        }
     }
 }
 
@@ -1570,17 +1676,15 @@ unsafe impl<#[may_dangle] T: ?Sized> Drop for Rc<T> {
     /// ```
     fn drop(&mut self) {
         unsafe {
-            self.inner().dec_strong();
-            if self.inner().strong() == 0 {
+            if self.inner().dec_strong() == 0 {
                 // destroy the contained object
                 ptr::drop_in_place(Self::get_mut_unchecked(self));
 
                 // remove the implicit "strong weak" pointer now that we've
                 // destroyed the contents.
-                self.inner().dec_weak();
-
-                if self.inner().weak() == 0 {
-                    Global.deallocate(self.ptr.cast(), Layout::for_value(self.ptr.as_ref()));
+                if self.inner().dec_weak() == 0 {
+                    metadata_counts::remove(self.ptr.as_ptr().cast());
+                    RcAlloc.deallocate(self.ptr.cast(), Layout::for_value(self.ptr.as_ref()));
                 }
             }
         }
@@ -2512,12 +2616,12 @@ unsafe impl<#[may_dangle] T: ?Sized> Drop for Weak<T> {
     fn drop(&mut self) {
         let inner = if let Some(inner) = self.inner() { inner } else { return };
 
-        inner.dec_weak();
         // the weak count starts at 1, and will only go to zero if all
         // the strong pointers have disappeared.
-        if inner.weak() == 0 {
+        if inner.dec_weak() == 0 {
             unsafe {
-                Global.deallocate(self.ptr.cast(), Layout::for_value_raw(self.ptr.as_ptr()));
+                metadata_counts::remove(self.ptr.as_ptr().cast());
+                RcAlloc.deallocate(self.ptr.cast(), Layout::for_value_raw(self.ptr.as_ptr()));
             }
         }
     }
@@ -2586,6 +2690,76 @@ trait RcInnerPtr {
     fn weak_ref(&self) -> &Cell<usize>;
     fn strong_ref(&self) -> &Cell<usize>;
 
+    /// Returns the address of the `RcBox`, which its counts are recorded by.
+    /// `strong` is its first field.
+    #[inline(always)]
+    fn addr(&self) -> *const u8 {
+        (self.strong_ref() as *const Cell<usize>).cast()
+    }
+
+    /// Returns the counter behind `field`.
+    #[inline(always)]
+    fn counter(&self, field: RcField) -> &Cell<usize> {
+        match field {
+            RcField::Strong => self.strong_ref(),
+            RcField::Weak => self.weak_ref(),
+        }
+    }
+
+    /// Increments or decrements the counter behind `field`, mirrors the update
+    /// into its record, and returns the new count.
+    ///
+    /// The record is looked up first. If there is none although the
+    /// allocation should have one, it was freed, along with the counter: the
+    /// update is reported as a metadata violation, without reading or writing
+    /// the counter. If the record does not accept the update, the violation is
+    /// reported and both the counter and its record are pinned to
+    /// `RC_POISONED` instead: the count is never trusted again, so nothing gets
+    /// dropped or freed based on it. Either way, `RC_POISONED` is returned.
+    #[inline(always)]
+    fn sync_count(&self, field: RcField, inc: bool) -> usize {
+        let counter = self.counter(field);
+        let step = |old: usize| if inc { old.wrapping_add(1) } else { old.wrapping_sub(1) };
+        if !METADATA_CHECKS {
+            let new = step(counter.get());
+            with_metadata_writes(|| counter.set(new));
+            return new;
+        }
+        // The record is updated with the window open, which covers the counter.
+        let synced = metadata_counts::update(self.addr(), field.count(), |shadow| {
+            let old = counter.get();
+            let new = step(old);
+            if core::intrinsics::likely(accepts(Some(*shadow), old, new)) {
+                counter.set(new);
+                *shadow = new;
+                Ok(new)
+            } else {
+                // Pin first: reporting the violation may unwind through
+                // further drops.
+                counter.set(RC_POISONED);
+                *shadow = RC_POISONED;
+                Err((old, new))
+            }
+        });
+        let (old, new) = match synced {
+            Shadow::Found(Ok(new)) => return new,
+            Shadow::Found(Err(update)) => update,
+            Shadow::Untracked => {
+                let old = counter.get();
+                let new = step(old);
+                if core::intrinsics::likely(accepts(None, old, new)) {
+                    with_metadata_writes(|| counter.set(new));
+                    return new;
+                }
+                with_metadata_writes(|| counter.set(RC_POISONED));
+                (old, new)
+            }
+            // The count reached zero when the allocation was freed.
+            Shadow::Missing => (0, step(0)),
+        };
+        count_violation(field, old, new)
+    }
+
     #[inline]
     fn strong(&self) -> usize {
         self.strong_ref().get()
@@ -2593,30 +2767,20 @@ fn strong(&self) -> usize {
 
     #[inline]
     fn inc_strong(&self) {
-        let strong = self.strong();
-
-        // We insert an `assume` here to hint LLVM at an otherwise
-        // missed optimization.
-        // SAFETY: The reference count will never be zero when this is
-        // called.
-        unsafe {
-            core::intrinsics::assume(strong != 0);
-        }
-
-        let strong = strong.wrapping_add(1);
-        self.strong_ref().set(strong);
+        let new = self.sync_count(RcField::Strong, true);
 
         // We want to abort on overflow instead of dropping the value.
         // Checking for overflow after the store instead of before
         // allows for slightly better code generation.
-        if core::intrinsics::unlikely(strong == 0) {
+        if core::intrinsics::unlikely(new == 0) {
             abort();
         }
     }
 
+    /// Decrements the strong count, and returns the new one.
     #[inline]
-    fn dec_strong(&self) {
-        self.strong_ref().set(self.strong() - 1);
+    fn dec_strong(&self) -> usize {
+        self.sync_count(RcField::Strong, false)
     }
 
     #[inline]
@@ -2626,33 +2790,31 @@ fn weak(&self) -> usize {
 
     #[inline]
     fn inc_weak(&self) {
-        let weak = self.weak();
-
-        // We insert an `assume` here to hint LLVM at an otherwise
-        // missed optimization.
-        // SAFETY: The reference count will never be zero when this is
-        // called.
-        unsafe {
-            core::intrinsics::assume(weak != 0);
-        }
-
-        let weak = weak.wrapping_add(1);
-        self.weak_ref().set(weak);
+        let new = self.sync_count(RcField::Weak, true);
 
         // We want to abort on overflow instead of dropping the value.
         // Checking for overflow after the store instead of before
         // allows for slightly better code generation.
-        if core::intrinsics::unlikely(weak == 0) {
+        if core::intrinsics::unlikely(new == 0) {
             abort();
         }
     }
 
+    /// Decrements the weak count, and returns the new one.
     #[inline]
-    fn dec_weak(&self) {
-        self.weak_ref().set(self.weak() - 1);
+    fn dec_weak(&self) -> usize {
+        self.sync_count(RcField::Weak, false)
     }
 }
 
+// Out of line, so that the validation in `RcInnerPtr::sync_count` stays small.
+#[cold]
+#[inline(never)]
+fn count_violation(field: RcField, old: usize, new: usize) -> usize {
+    metadata_violation("Rc", field.name(), old, new);
+    RC_POISONED
+}
+
 impl<T: ?Sized> RcInnerPtr for RcBox<T> {
     #[inline(always)]
     fn weak_ref(&self) -> &Cell<usize> {
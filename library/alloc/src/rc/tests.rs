@@ -559,3 +559,43 @@ struct TwoRefs {
     assert_eq!(Rc::strong_count(&two_refs), 3);
     assert_eq!(Rc::weak_count(&two_refs), 2);
 }
+
+#[test]
+fn test_rcbox_size() {
+    // The counts are recorded outside of the allocation.
+    assert_eq!(mem::size_of::<RcBox<()>>(), 2 * mem::size_of::<usize>());
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Rc::strong`")]
+fn test_corrupted_strong_count() {
+    let x = Rc::new(5);
+    // Overwrite the counter behind its record's back.
+    x.inner().strong.set(7);
+    let _y = x.clone();
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Rc::strong`")]
+fn test_double_drop() {
+    let x = Rc::new(5);
+    // Keep the allocation alive, so that the second drop can be caught.
+    let _weak = Rc::downgrade(&x);
+    let raw = Rc::into_raw(x);
+    unsafe {
+        Rc::decrement_strong_count(raw);
+        Rc::decrement_strong_count(raw);
+    }
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Rc::strong`: 0 ->")]
+fn test_double_drop_after_free() {
+    let raw = Rc::into_raw(Rc::new(5));
+    unsafe {
+        Rc::decrement_strong_count(raw);
+        // The allocation is gone, and its record with it: the freed counter
+        // is not touched.
+        Rc::decrement_strong_count(raw);
+    }
+}
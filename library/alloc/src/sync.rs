@@ -35,15 +35,24 @@
 use crate::alloc::handle_alloc_error;
 #[cfg(not(no_global_oom_handling))]
 use crate::alloc::{box_free, WriteCloneIntoRaw};
-use crate::alloc::{AllocError, Allocator, Global, Layout};
+#[cfg(not(metadata_protection = "guard"))]
+use crate::alloc::Global as ArcAlloc;
+use crate::alloc::{AllocError, Allocator, Layout};
 use crate::borrow::{Cow, ToOwned};
 use crate::boxed::Box;
+#[cfg(metadata_protection = "guard")]
+use crate::compartment::CompartmentAlloc as ArcAlloc;
+use crate::metadata_counts::{self, Count, Shadow};
+use crate::metadata_ownership::{self, Owner};
 use crate::rc::is_dangling;
 #[cfg(not(no_global_oom_handling))]
 use crate::string::String;
 #[cfg(not(no_global_oom_handling))]
 use crate::vec::Vec;
 
+/*SOR-MetaUpdate@kayondomartin*/
+use core::ptr::metadata_update::{metadata_violation, MetaUpdate, MetadataField};
+
 #[cfg(test)]
 mod tests;
 
@@ -336,6 +345,202 @@ struct ArcInner<T: ?Sized> {
 unsafe impl<T: ?Sized + Sync + Send> Send for ArcInner<T> {}
 unsafe impl<T: ?Sized + Sync + Send> Sync for ArcInner<T> {}
 
+impl<T: ?Sized> ArcInner<T> {
+    #[inline(always)]
+    fn shadowed(&self, field: ArcField) -> ShadowedCount<'_> {
+        ShadowedCount::new(field, &self.strong, &self.weak)
+    }
+}
+
+/// Value both a counter and its record are pinned to once a violation was
+/// reported. It is above `MAX_REFCOUNT`, so that further clones abort, and far
+/// enough from zero that the allocation is never released.
+const ARC_POISONED: usize = MAX_REFCOUNT + 1;
+
+/// One reference counter of an `ArcInner`, together with its shadow record in
+/// `metadata_counts`.
+///
+/// Unlike `Rc`'s, the record cannot be compared with the counter on every
+/// update: other threads update the counter concurrently, and keeping the two
+/// in lockstep would mean updating the counter with the lock of the record
+/// held. Instead, an increment is mirrored into the record *after* the counter
+/// is updated, and a decrement *before* it. The record thus never counts a
+/// reference the counter does not, which gives two checks that hold under any
+/// interleaving:
+///
+/// * The holder of a reference always finds the record non-zero. A zero record
+///   on `clone`, `drop` or `from_raw` means a double drop, or a reference that
+///   was forged after the last one had been released. No record at all means
+///   the allocation was freed, and its counter is then neither read nor
+///   written.
+/// * Once a counter is observed to reach zero, the acquire fence makes every
+///   other update visible, so the record must be zero too. Otherwise the
+///   counter was tampered with, and the allocation is not released.
+///
+/// `Weak::upgrade` may legitimately mirror its increment into a zero record,
+/// and the `usize::MAX` lock of the weak counter is not mirrored at all, since
+/// neither changes the number of outstanding references.
+#[derive(Clone, Copy)]
+struct ShadowedCount<'a> {
+    field: ArcField,
+    count: &'a atomic::AtomicUsize,
+    // The address of the `ArcInner`, which its counts are recorded by.
+    addr: *const u8,
+}
+
+impl<'a> ShadowedCount<'a> {
+    #[inline(always)]
+    fn new(
+        field: ArcField,
+        strong: &'a atomic::AtomicUsize,
+        weak: &'a atomic::AtomicUsize,
+    ) -> Self {
+        // `strong` is the first field of the `ArcInner`.
+        let addr = (strong as *const atomic::AtomicUsize).cast();
+        match field {
+            ArcField::Strong => ShadowedCount { field, count: strong, addr },
+            ArcField::Weak => ShadowedCount { field, count: weak, addr },
+        }
+    }
+
+    #[inline(always)]
+    fn shadow<R>(self, f: impl FnOnce(&mut usize) -> R) -> Shadow<R> {
+        metadata_counts::update(self.addr, self.field.count(), f)
+    }
+
+    /// Returns the number of references the record counts, 0 if the
+    /// allocation was freed, or `None` if it has no record.
+    #[inline]
+    fn recorded(self) -> Option<usize> {
+        match self.shadow(|shadow| *shadow) {
+            Shadow::Found(shadow) => Some(shadow),
+            Shadow::Untracked => None,
+            Shadow::Missing => Some(0),
+        }
+    }
+
+    /// Returns `true` while the record counts outstanding references, and
+    /// `new` stays within `MAX_REFCOUNT`. The `usize::MAX` lock taken on the
+    /// weak count by `get_mut`/`make_mut` is not a count, and is accepted.
+    #[inline]
+    fn accepts(self, new: usize) -> bool {
+        (self.field == ArcField::Weak && new == usize::MAX)
+            || (self.recorded() != Some(0) && new <= MAX_REFCOUNT)
+    }
+
+    /// Mirrors an increment the caller already made to the count, on behalf
+    /// of a reference it holds.
+    #[inline]
+    fn inc(self) {
+        let inc = |shadow: &mut usize| {
+            let old = *shadow;
+            *shadow = old.wrapping_add(1);
+            old
+        };
+        match self.shadow(inc) {
+            Shadow::Found(0) | Shadow::Missing => self.violation(0, 1),
+            _ => {}
+        }
+    }
+
+    /// Mirrors an increment `Weak::upgrade` already made to the count. The
+    /// record may legitimately be zero, as no strong reference was held.
+    #[inline]
+    fn inc_upgraded(self) {
+        self.shadow(|shadow| *shadow += 1);
+    }
+
+    /// Mirrors a decrement the caller is about to make to the count. Returns
+    /// `false` if the decrement must not be made.
+    #[inline]
+    fn dec(self) -> bool {
+        let dec = |shadow: &mut usize| match *shadow {
+            0 => false,
+            _ => {
+                *shadow -= 1;
+                true
+            }
+        };
+        match self.shadow(dec) {
+            Shadow::Found(true) | Shadow::Untracked => true,
+            Shadow::Found(false) | Shadow::Missing => {
+                self.violation(0, usize::MAX);
+                false
+            }
+        }
+    }
+
+    /// Checks that the record agrees once the count was observed to reach
+    /// zero. Must be called after the acquire fence. Returns `false` if the
+    /// allocation must not be released.
+    #[inline]
+    fn released(self) -> bool {
+        match self.shadow(|shadow| *shadow) {
+            Shadow::Found(0) | Shadow::Untracked => true,
+            Shadow::Found(old) => {
+                self.violation(old, 0);
+                false
+            }
+            Shadow::Missing => {
+                self.violation(0, 0);
+                false
+            }
+        }
+    }
+
+    /// Sets the record of a count the caller sets to `value`, with no other
+    /// reference around to update it concurrently.
+    #[inline]
+    fn reset(self, value: usize) {
+        self.shadow(|shadow| *shadow = value);
+    }
+
+    // Out of line, so that the checks above stay small.
+    #[cold]
+    #[inline(never)]
+    fn violation(self, old: usize, new: usize) {
+        // Pin first: reporting the violation may unwind through further drops.
+        // Without a record, the allocation was freed along with the counter.
+        if self.shadow(|shadow| *shadow = ARC_POISONED) != Shadow::Missing {
+            self.count.store(ARC_POISONED, Relaxed);
+        }
+        metadata_violation("Arc", self.field.name(), old, new);
+    }
+}
+
+/// The protected metadata fields of an [`Arc`], i.e. its reference counters.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArcField {
+    /// The number of [`Arc`] pointers to the allocation.
+    Strong,
+    /// The number of [`Weak`] pointers to the allocation, plus one for all
+    /// [`Arc`] pointers together.
+    Weak,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for ArcField {
+    const FIELDS: &'static [Self] = &[ArcField::Strong, ArcField::Weak];
+
+    fn name(self) -> &'static str {
+        match self {
+            ArcField::Strong => "strong",
+            ArcField::Weak => "weak",
+        }
+    }
+}
+
+impl ArcField {
+    #[inline(always)]
+    fn count(self) -> Count {
+        match self {
+            ArcField::Strong => Count::Strong,
+            ArcField::Weak => Count::Weak,
+        }
+    }
+}
+
 impl<T> Arc<T> {
     /// Constructs a new `Arc<T>`.
     ///
@@ -352,12 +557,17 @@ impl<T> Arc<T> {
     pub fn new(data: T) -> Arc<T> {
         // Start the weak pointer count as 1 which is the weak pointer that's
         // held by all the strong pointers (kinda), see std/rc.rs for more info
-        let x: Box<_> = Box::new(ArcInner {
-            strong: atomic::AtomicUsize::new(1),
-            weak: atomic::AtomicUsize::new(1),
-            data,
-        });
-        unsafe { Self::from_inner(Box::leak(x).into()) }
+        let x: Box<_, ArcAlloc> = Box::new_in(
+            ArcInner {
+                strong: atomic::AtomicUsize::new(1),
+                weak: atomic::AtomicUsize::new(1),
+                data,
+            },
+            ArcAlloc,
+        );
+        let inner: NonNull<_> = Box::leak(x).into();
+        metadata_counts::insert(inner.as_ptr().cast(), 1, 1);
+        unsafe { Self::from_inner(inner) }
     }
 
     /// Constructs a new `Arc<T>` while giving you a `Weak<T>` to the allocation,
@@ -420,12 +630,16 @@ pub fn new_cyclic<F>(data_fn: F) -> Arc<T>
     {
         // Construct the inner in the "uninitialized" state with a single
         // weak reference.
-        let uninit_ptr: NonNull<_> = Box::leak(Box::new(ArcInner {
-            strong: atomic::AtomicUsize::new(0),
-            weak: atomic::AtomicUsize::new(1),
-            data: mem::MaybeUninit::<T>::uninit(),
-        }))
+        let uninit_ptr: NonNull<_> = Box::leak(Box::new_in(
+            ArcInner {
+                strong: atomic::AtomicUsize::new(0),
+                weak: atomic::AtomicUsize::new(1),
+                data: mem::MaybeUninit::<T>::uninit(),
+            },
+            ArcAlloc,
+        ))
         .into();
+        metadata_counts::insert(uninit_ptr.as_ptr().cast(), 0, 1);
         let init_ptr: NonNull<ArcInner<T>> = uninit_ptr.cast();
 
         let weak = Weak { ptr: init_ptr };
@@ -456,6 +670,10 @@ pub fn new_cyclic<F>(data_fn: F) -> Arc<T>
             //
             // These side effects do not impact us in any way, and no other side effects are
             // possible with safe code alone.
+            //
+            // The record is set up first, so that it is visible to any thread that manages
+            // to upgrade, and then drop, a `Weak` right after the `fetch_add` below.
+            (*inner).shadowed(ArcField::Strong).reset(1);
             let prev_value = (*inner).strong.fetch_add(1, Release);
             debug_assert_eq!(prev_value, 0, "No prior strong references should exist");
 
@@ -494,7 +712,7 @@ pub fn new_uninit() -> Arc<mem::MaybeUninit<T>> {
         unsafe {
             Arc::from_ptr(Arc::allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate(layout),
+                |layout| ArcAlloc.allocate(layout),
                 |mem| mem as *mut ArcInner<mem::MaybeUninit<T>>,
             ))
         }
@@ -527,7 +745,7 @@ pub fn new_zeroed() -> Arc<mem::MaybeUninit<T>> {
         unsafe {
             Arc::from_ptr(Arc::allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| ArcAlloc.allocate_zeroed(layout),
                 |mem| mem as *mut ArcInner<mem::MaybeUninit<T>>,
             ))
         }
@@ -565,12 +783,17 @@ pub fn try_pin(data: T) -> Result<Pin<Arc<T>>, AllocError> {
     pub fn try_new(data: T) -> Result<Arc<T>, AllocError> {
         // Start the weak pointer count as 1 which is the weak pointer that's
         // held by all the strong pointers (kinda), see std/rc.rs for more info
-        let x: Box<_> = Box::try_new(ArcInner {
-            strong: atomic::AtomicUsize::new(1),
-            weak: atomic::AtomicUsize::new(1),
-            data,
-        })?;
-        unsafe { Ok(Self::from_inner(Box::leak(x).into())) }
+        let x: Box<_, ArcAlloc> = Box::try_new_in(
+            ArcInner {
+                strong: atomic::AtomicUsize::new(1),
+                weak: atomic::AtomicUsize::new(1),
+                data,
+            },
+            ArcAlloc,
+        )?;
+        let inner: NonNull<_> = Box::leak(x).into();
+        metadata_counts::insert(inner.as_ptr().cast(), 1, 1);
+        unsafe { Ok(Self::from_inner(inner)) }
     }
 
     /// Constructs a new `Arc` with uninitialized contents, returning an error
@@ -600,7 +823,7 @@ pub fn try_new_uninit() -> Result<Arc<mem::MaybeUninit<T>>, AllocError> {
         unsafe {
             Ok(Arc::from_ptr(Arc::try_allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate(layout),
+                |layout| ArcAlloc.allocate(layout),
                 |mem| mem as *mut ArcInner<mem::MaybeUninit<T>>,
             )?))
         }
@@ -633,7 +856,7 @@ pub fn try_new_zeroed() -> Result<Arc<mem::MaybeUninit<T>>, AllocError> {
         unsafe {
             Ok(Arc::from_ptr(Arc::try_allocate_for_layout(
                 Layout::new::<T>(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| ArcAlloc.allocate_zeroed(layout),
                 |mem| mem as *mut ArcInner<mem::MaybeUninit<T>>,
             )?))
         }
@@ -666,6 +889,11 @@ pub fn try_unwrap(this: Self) -> Result<T, Self> {
 
         acquire!(this.inner().strong);
 
+        let strong = this.inner().shadowed(ArcField::Strong);
+        if !(strong.dec() && strong.released()) {
+            return Err(this);
+        }
+
         unsafe {
             let elem = ptr::read(&this.ptr.as_ref().data);
 
@@ -735,7 +963,7 @@ pub fn new_zeroed_slice(len: usize) -> Arc<[mem::MaybeUninit<T>]> {
         unsafe {
             Arc::from_ptr(Arc::allocate_for_layout(
                 Layout::array::<T>(len).unwrap(),
-                |layout| Global.allocate_zeroed(layout),
+                |layout| ArcAlloc.allocate_zeroed(layout),
                 |mem| {
                     ptr::slice_from_raw_parts_mut(mem as *mut T, len)
                         as *mut ArcInner<[mem::MaybeUninit<T>]>
@@ -841,7 +1069,11 @@ impl<T: ?Sized> Arc<T> {
     /// ```
     #[must_use = "losing the pointer will leak memory"]
     #[stable(feature = "rc_raw", since = "1.17.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "arc_into_raw")]
     pub fn into_raw(this: Self) -> *const T {
+        // SAFETY: `this` owns a strong count, so the `ArcInner` is still allocated.
+        let layout = unsafe { Layout::for_value_raw(this.ptr.as_ptr()) };
+        metadata_ownership::release::<ArcAlloc>(this.ptr.as_ptr().cast(), layout, Owner::Shared);
         let ptr = Self::as_ptr(&this);
         mem::forget(this);
         ptr
@@ -912,14 +1144,44 @@ pub fn as_ptr(this: &Self) -> *const T {
     /// // The memory was freed when `x` went out of scope above, so `x_ptr` is now dangling!
     /// ```
     #[stable(feature = "rc_raw", since = "1.17.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "arc_from_raw")]
     pub unsafe fn from_raw(ptr: *const T) -> Self {
+        unsafe {
+            let arc = Self::from_raw_borrowed(ptr);
+
+            // The caller guarantees that `ptr` came from `into_raw`, so the
+            // `ArcInner` is still allocated.
+            let arc_ptr = arc.ptr.as_ptr();
+            let layout = Layout::for_value_raw(arc_ptr);
+            if !metadata_ownership::claim("Arc", arc_ptr.cast(), layout, Owner::Shared) {
+                panic!("cannot rebuild an `Arc` from an allocation it may not own");
+            }
+
+            mem::ManuallyDrop::into_inner(arc)
+        }
+    }
+
+    /// Rebuilds the `Arc` behind `ptr` without taking its strong reference
+    /// back, so that it is not claimed from the ownership registry.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Arc::from_raw`].
+    pub(crate) unsafe fn from_raw_borrowed(ptr: *const T) -> mem::ManuallyDrop<Self> {
         unsafe {
             let offset = data_offset(ptr);
 
             // Reverse the offset to find the original ArcInner.
             let arc_ptr = ptr.byte_sub(offset) as *mut ArcInner<T>;
 
-            Self::from_ptr(arc_ptr)
+            // The pointer must still own a strong reference. See `ShadowedCount`
+            // for why a zero record cannot be observed if it does.
+            let strong = (*arc_ptr).shadowed(ArcField::Strong);
+            if core::intrinsics::unlikely(strong.recorded() == Some(0)) {
+                strong.violation(0, 0);
+            }
+
+            mem::ManuallyDrop::new(Self::from_ptr(arc_ptr))
         }
     }
 
@@ -959,6 +1221,7 @@ pub fn downgrade(this: &Self) -> Weak<T> {
             // events prior to that write happen before this read.
             match this.inner().weak.compare_exchange_weak(cur, cur + 1, Acquire, Relaxed) {
                 Ok(_) => {
+                    this.inner().shadowed(ArcField::Weak).inc();
                     // Make sure we do not create a dangling Weak
                     debug_assert!(!is_dangling(this.ptr.as_ptr()));
                     return Weak { ptr: this.ptr };
@@ -1055,9 +1318,9 @@ pub fn strong_count(this: &Self) -> usize {
     #[stable(feature = "arc_mutate_strong_count", since = "1.51.0")]
     pub unsafe fn increment_strong_count(ptr: *const T) {
         // Retain Arc, but don't touch refcount by wrapping in ManuallyDrop
-        let arc = unsafe { mem::ManuallyDrop::new(Arc::<T>::from_raw(ptr)) };
-        // Now increase refcount, but don't drop new refcount either
-        let _arc_clone: mem::ManuallyDrop<_> = arc.clone();
+        let arc = unsafe { Arc::<T>::from_raw_borrowed(ptr) };
+        // Now increase refcount, and hand it out as another raw pointer
+        let _ = Arc::into_raw(Arc::clone(&arc));
     }
 
     /// Decrements the strong reference count on the `Arc<T>` associated with the
@@ -1192,6 +1455,7 @@ unsafe fn try_allocate_for_layout(
             ptr::write(&mut (*inner).strong, atomic::AtomicUsize::new(1));
             ptr::write(&mut (*inner).weak, atomic::AtomicUsize::new(1));
         }
+        metadata_counts::insert(inner.cast(), 1, 1);
 
         Ok(inner)
     }
@@ -1203,7 +1467,7 @@ unsafe fn allocate_for_ptr(ptr: *const T) -> *mut ArcInner<T> {
         unsafe {
             Self::allocate_for_layout(
                 Layout::for_value(&*ptr),
-                |layout| Global.allocate(layout),
+                |layout| ArcAlloc.allocate(layout),
                 |mem| mem.with_metadata_of(ptr as *const ArcInner<T>),
             )
         }
@@ -1240,7 +1504,7 @@ unsafe fn allocate_for_slice(len: usize) -> *mut ArcInner<[T]> {
         unsafe {
             Self::allocate_for_layout(
                 Layout::array::<T>(len).unwrap(),
-                |layout| Global.allocate(layout),
+                |layout| ArcAlloc.allocate(layout),
                 |mem| ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut ArcInner<[T]>,
             )
         }
@@ -1281,7 +1545,8 @@ fn drop(&mut self) {
                     let slice = from_raw_parts_mut(self.elems, self.n_elems);
                     ptr::drop_in_place(slice);
 
-                    Global.deallocate(self.mem, self.layout);
+                    metadata_counts::remove(self.mem.as_ptr());
+                    ArcAlloc.deallocate(self.mem, self.layout);
                 }
             }
         }
@@ -1377,6 +1642,8 @@ fn clone(&self) -> Arc<T> {
             abort();
         }
 
+        self.inner().shadowed(ArcField::Strong).inc();
+
         unsafe { Self::from_inner(self.ptr) }
     }
 }
@@ -1391,6 +1658,21 @@ fn deref(&self) -> &T {
     }
 }
 
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T: ?Sized> MetaUpdate for Arc<T> {
+    type Field = ArcField;
+
+    /// Synchronize a counter update with the shadow record of the allocation.
+    /// The counters are updated concurrently, so `old` cannot be compared with
+    /// the current count (see `ShadowedCount`). The update is accepted as long
+    /// as the record still counts outstanding references of that kind, and `new`
+    /// stays within `MAX_REFCOUNT`. The `usize::MAX` lock taken on the weak
+    /// count by `get_mut`/`make_mut` is not a count, and is always accepted.
+    fn synchronize(&self, field: ArcField, _old: usize, new: usize) -> bool {
+        self.inner().shadowed(field).accepts(new)
+    }
+}
+
 #[unstable(feature = "receiver_trait", issue = "none")]
 impl<T: ?Sized> Receiver for Arc<T> {}
 
@@ -1457,7 +1739,13 @@ pub fn make_mut(this: &mut Self) -> &mut T {
         // before release writes (i.e., decrements) to `strong`. Since we hold a
         // weak count, there's no chance the ArcInner itself could be
         // deallocated.
-        if this.inner().strong.compare_exchange(1, 0, Acquire, Relaxed).is_err() {
+        //
+        // A record that disagrees is treated like another strong pointer:
+        // the contents are cloned, and the old allocation is never released.
+        if this.inner().strong.compare_exchange(1, 0, Acquire, Relaxed).is_err()
+            || !(this.inner().shadowed(ArcField::Strong).dec()
+                && this.inner().shadowed(ArcField::Strong).released())
+        {
             // Another strong pointer exists, so we must clone.
             // Pre-allocate memory to allow writing the cloned value directly.
             let mut arc = Self::new_uninit();
@@ -1493,6 +1781,7 @@ pub fn make_mut(this: &mut Self) -> &mut T {
         } else {
             // We were the sole reference of either kind; bump back up the
             // strong ref count.
+            this.inner().shadowed(ArcField::Strong).reset(1);
             this.inner().strong.store(1, Release);
         }
 
@@ -1630,6 +1919,18 @@ fn is_unique(&mut self) -> bool {
             // is being dropped.
             let unique = self.inner().strong.load(Acquire) == 1;
 
+            // With the weak count locked and a single strong reference left,
+            // nothing can update the strong count concurrently, so its record
+            // must agree exactly.
+            let strong = self.inner().shadowed(ArcField::Strong);
+            let unique = match strong.recorded() {
+                Some(shadow) if unique && shadow != 1 => {
+                    strong.violation(shadow, 1);
+                    false
+                }
+                _ => unique,
+            };
+
             // The release write here synchronizes with a read in `downgrade`,
             // effectively preventing the above read of `strong` from happening
             // after the write.
@@ -1673,6 +1974,11 @@ fn drop(&mut self) {
         // Because `fetch_sub` is already atomic, we do not need to synchronize
         // with other threads unless we are going to delete the object. This
         // same logic applies to the below `fetch_sub` to the `weak` count.
+        //
+        // The record is decremented first, see `ShadowedCount`.
+        if !self.inner().shadowed(ArcField::Strong).dec() {
+            return;
+        }
         if self.inner().strong.fetch_sub(1, Release) != 1 {
             return;
         }
@@ -1707,6 +2013,10 @@ fn drop(&mut self) {
         // [2]: (https://github.com/rust-lang/rust/pull/41714)
         acquire!(self.inner().strong);
 
+        if !self.inner().shadowed(ArcField::Strong).released() {
+            return;
+        }
+
         unsafe {
             self.drop_slow();
         }
@@ -1818,6 +2128,13 @@ struct WeakInner<'a> {
     strong: &'a atomic::AtomicUsize,
 }
 
+impl<'a> WeakInner<'a> {
+    #[inline(always)]
+    fn shadowed(&self, field: ArcField) -> ShadowedCount<'a> {
+        ShadowedCount::new(field, self.strong, self.weak)
+    }
+}
+
 impl<T: ?Sized> Weak<T> {
     /// Returns a raw pointer to the object `T` pointed to by this `Weak<T>`.
     ///
@@ -1989,7 +2306,8 @@ pub fn upgrade(&self) -> Option<Arc<T>> {
         // We use a CAS loop to increment the strong count instead of a
         // fetch_add as this function should never take the reference count
         // from zero to one.
-        self.inner()?
+        let inner = self.inner()?;
+        inner
             .strong
             // Relaxed is fine for the failure case because we don't have any expectations about the new state.
             // Acquire is necessary for the success case to synchronise with `Arc::new_cyclic`, when the inner
@@ -2008,7 +2326,12 @@ pub fn upgrade(&self) -> Option<Arc<T>> {
             })
             .ok()
             // null checked above
-            .map(|_| unsafe { Arc::from_inner(self.ptr) })
+            .map(|_| {
+                // We do not hold a strong reference yet, so the record may
+                // legitimately be zero here (see `ShadowedCount`).
+                inner.shadowed(ArcField::Strong).inc_upgraded();
+                unsafe { Arc::from_inner(self.ptr) }
+            })
     }
 
     /// Gets the number of strong (`Arc`) pointers pointing to this allocation.
@@ -2149,6 +2472,8 @@ fn clone(&self) -> Weak<T> {
             abort();
         }
 
+        inner.shadowed(ArcField::Weak).inc();
+
         Weak { ptr: self.ptr }
     }
 }
@@ -2211,9 +2536,32 @@ fn drop(&mut self) {
         // ref, which can only happen after the lock is released.
         let inner = if let Some(inner) = self.inner() { inner } else { return };
 
+        if !inner.shadowed(ArcField::Weak).dec() {
+            return;
+        }
         if inner.weak.fetch_sub(1, Release) == 1 {
             acquire!(inner.weak);
-            unsafe { Global.deallocate(self.ptr.cast(), Layout::for_value_raw(self.ptr.as_ptr())) }
+            if !inner.shadowed(ArcField::Weak).released() {
+                return;
+            }
+            metadata_counts::remove(self.ptr.as_ptr().cast());
+            unsafe {
+                ArcAlloc.deallocate(self.ptr.cast(), Layout::for_value_raw(self.ptr.as_ptr()))
+            }
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T: ?Sized> MetaUpdate for Weak<T> {
+    type Field = ArcField;
+
+    /// Synchronize a counter update with the shadow record of the allocation,
+    /// as for `Arc`. A `Weak` created by `Weak::new` has no counts at all.
+    fn synchronize(&self, field: ArcField, _old: usize, new: usize) -> bool {
+        match self.inner() {
+            Some(inner) => inner.shadowed(field).accepts(new),
+            None => new == 0,
         }
     }
 }
@@ -637,3 +637,65 @@ fn arc_drop_dereferenceable_race() {
         thread.join().unwrap();
     }
 }
+
+#[test]
+fn arc_inner_size() {
+    // The counts are recorded outside of the allocation.
+    assert_eq!(mem::size_of::<ArcInner<()>>(), 2 * mem::size_of::<usize>());
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Arc::strong`")]
+fn arc_corrupted_strong_count() {
+    let x = Arc::new(5);
+    let _y = x.clone();
+    // Pretend there is a single owner left, behind its record's back.
+    x.inner().strong.store(1, SeqCst);
+    drop(x);
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Arc::strong`")]
+fn arc_double_drop() {
+    let x = Arc::new(5);
+    // Keep the allocation alive, so that the second drop can be caught.
+    let _weak = Arc::downgrade(&x);
+    let raw = Arc::into_raw(x);
+    unsafe {
+        Arc::decrement_strong_count(raw);
+        Arc::decrement_strong_count(raw);
+    }
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `Arc::strong`")]
+fn arc_double_drop_after_free() {
+    let raw = Arc::into_raw(Arc::new(5));
+    unsafe {
+        Arc::decrement_strong_count(raw);
+        // The allocation is gone, and its record with it: the freed counter
+        // is not touched.
+        Arc::decrement_strong_count(raw);
+    }
+}
+
+#[test]
+fn arc_shadow_concurrent_clone_drop() {
+    let x = Arc::new(5);
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let x = x.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    let weak = Arc::downgrade(&x);
+                    drop(weak.upgrade().unwrap());
+                    drop(x.clone());
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(Arc::try_unwrap(x), Ok(5));
+}
@@ -6,7 +6,6 @@
 //! loads and stores of pointers. This may be detected at compile time using
 //! `#[cfg(target_has_atomic = "ptr")]`.
 
-use core::mem::ManuallyDrop;
 use core::task::{RawWaker, RawWakerVTable, Waker};
 
 use crate::sync::Arc;
@@ -135,9 +134,9 @@ unsafe fn wake<W: Wake + Send + Sync + 'static>(waker: *const ()) {
         <W as Wake>::wake(waker);
     }
 
-    // Wake by reference, wrap the waker in ManuallyDrop to avoid dropping it
+    // Wake by reference, borrowing the waker in a ManuallyDrop to avoid dropping it
     unsafe fn wake_by_ref<W: Wake + Send + Sync + 'static>(waker: *const ()) {
-        let waker = unsafe { ManuallyDrop::new(Arc::from_raw(waker as *const W)) };
+        let waker = unsafe { Arc::from_raw_borrowed(waker as *const W) };
         <W as Wake>::wake_by_ref(&waker);
     }
 
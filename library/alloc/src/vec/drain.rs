@@ -2,11 +2,15 @@
 use core::fmt;
 use core::iter::{FusedIterator, TrustedLen};
 use core::mem::{self, ManuallyDrop, SizedTypeProperties};
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
 use core::ptr::{self, NonNull};
 use core::slice::{self};
 
 use super::Vec;
 
+#[cfg(test)]
+mod tests;
+
 /// A draining iterator for `Vec<T>`.
 ///
 /// This `struct` is created by [`Vec::drain`].
@@ -25,8 +29,10 @@ pub struct Drain<
     #[unstable(feature = "allocator_api", issue = "32838")] A: Allocator + 'a = Global,
 > {
     /// Index of tail to preserve
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     pub(super) tail_start: usize,
     /// Length of tail
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     pub(super) tail_len: usize,
     /// Current remaining range to remove
     pub(super) iter: slice::Iter<'a, T>,
@@ -101,6 +107,8 @@ pub fn keep_rest(self) {
         //    a. In case of ZST, this is the only thing we want to do
         // 4. Do *not* drop self, as everything is put in a consistent state already, there is nothing to do
         let mut this = ManuallyDrop::new(self);
+        this.check_tail();
+        this.check_remaining();
 
         unsafe {
             let source_vec = this.vec.as_mut();
@@ -136,6 +144,117 @@ pub fn keep_rest(self) {
             source_vec.set_len(start + unyielded_len + this.tail_len);
         }
     }
+
+    /// Checks that the tail is within the allocation of the source vector
+    /// before it is moved. If it is not, the violation is reported and the
+    /// tail is leaked.
+    pub(super) fn check_tail(&mut self) {
+        let tail_len = self.tail_len;
+        if METADATA_CHECKS && !self.synchronize(DrainField::TailLen, tail_len, tail_len) {
+            self.metadata_violation(DrainField::TailLen, tail_len, tail_len);
+        }
+    }
+
+    // Reports a tail that does not fit in the allocation, and leaks it: it is
+    // left out of the vector, which keeps its current length, instead of being
+    // moved from or to memory the vector does not own.
+    #[cold]
+    #[inline(never)]
+    pub(super) fn metadata_violation(&mut self, field: DrainField, old: usize, new: usize) {
+        metadata_update::metadata_violation("vec::Drain", field.name(), old, new);
+        // Both writes are checked by the compiler, and accepted in this order.
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
+        self.tail_len = 0;
+        self.tail_start = unsafe { self.vec.as_ref().len() };
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
+    }
+
+    /// Checks that the remaining elements are in the drained range, between
+    /// the current length of the source vector and the tail, before one of
+    /// them is read. If they are not, the violation is reported and they are
+    /// leaked: the iterator is left empty.
+    #[inline]
+    fn check_remaining(&mut self) -> bool {
+        // Zero-sized elements are made up, not read.
+        if !METADATA_CHECKS || T::IS_ZST || self.iter.len() == 0 {
+            return true;
+        }
+        let vec = unsafe { self.vec.as_ref() };
+        let size = mem::size_of::<T>();
+        let start = self.iter.as_slice().as_ptr().addr().wrapping_sub(vec.as_ptr().addr());
+        let end = start.saturating_add(self.iter.len().saturating_mul(size));
+        // The bounds of the drained range, in bytes from the start of the
+        // allocation.
+        let (min, max) = (vec.len() * size, self.tail_start.min(vec.capacity()) * size);
+        if start % size != 0 || start < min {
+            self.leak_remaining(min, start);
+        } else if end > max {
+            self.leak_remaining(max, end);
+        } else {
+            return true;
+        }
+        false
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn leak_remaining(&mut self, old: usize, new: usize) {
+        self.iter = (&mut []).iter();
+        metadata_update::metadata_violation("vec::Drain", "iter", old, new);
+    }
+
+    // Whether `end` elements fit in the source vector, both in its capacity
+    // and in the block the allocator handed out for it.
+    fn fits(&self, end: Option<usize>) -> bool {
+        let vec = unsafe { self.vec.as_ref() };
+        end.map_or(false, |end| end <= vec.capacity() && vec.buf.fits_allocation(end))
+    }
+}
+
+/// The protected metadata fields of a vector's [`Drain`].
+///
+/// They locate the elements after the drained range, which are moved back
+/// into place when the iterator is dropped.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrainField {
+    /// The index of the first element after the drained range.
+    TailStart,
+    /// The number of elements after the drained range.
+    TailLen,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for DrainField {
+    const FIELDS: &'static [Self] = &[DrainField::TailStart, DrainField::TailLen];
+
+    fn name(self) -> &'static str {
+        match self {
+            DrainField::TailStart => "tail_start",
+            DrainField::TailLen => "tail_len",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, A: Allocator> MetaUpdate for Drain<'_, T, A> {
+    type Field = DrainField;
+
+    /// Synchronize an update of the tail with the source vector.
+    /// The tail must start at or after the current length of the vector, and
+    /// end within its capacity and the block the allocator handed out for it.
+    /// An empty tail is always accepted, as it is never moved.
+    fn synchronize(&self, field: DrainField, _old: usize, new: usize) -> bool {
+        match field {
+            DrainField::TailStart => {
+                unsafe { self.vec.as_ref().len() } <= new
+                    && self.fits(new.checked_add(self.tail_len))
+            }
+            DrainField::TailLen => new == 0 || self.fits(self.tail_start.checked_add(new)),
+        }
+    }
 }
 
 #[stable(feature = "vec_drain_as_slice", since = "1.46.0")]
@@ -156,6 +275,9 @@ impl<T, A: Allocator> Iterator for Drain<'_, T, A> {
 
     #[inline]
     fn next(&mut self) -> Option<T> {
+        if !self.check_remaining() {
+            return None;
+        }
         self.iter.next().map(|elt| unsafe { ptr::read(elt as *const _) })
     }
 
@@ -168,6 +290,9 @@ fn size_hint(&self) -> (usize, Option<usize>) {
 impl<T, A: Allocator> DoubleEndedIterator for Drain<'_, T, A> {
     #[inline]
     fn next_back(&mut self) -> Option<T> {
+        if !self.check_remaining() {
+            return None;
+        }
         self.iter.next_back().map(|elt| unsafe { ptr::read(elt as *const _) })
     }
 }
@@ -180,6 +305,7 @@ fn drop(&mut self) {
 
         impl<'r, 'a, T, A: Allocator> Drop for DropGuard<'r, 'a, T, A> {
             fn drop(&mut self) {
+                self.0.check_tail();
                 if self.0.tail_len > 0 {
                     unsafe {
                         let source_vec = self.0.vec.as_mut();
@@ -197,6 +323,9 @@ fn drop(&mut self) {
             }
         }
 
+        // The remaining elements are only dropped once they are known to be in
+        // the drained range.
+        self.check_remaining();
         let iter = mem::replace(&mut self.iter, (&mut []).iter());
         let drop_len = iter.len();
 
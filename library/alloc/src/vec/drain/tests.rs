@@ -0,0 +1,29 @@
+use crate::rc::Rc;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec::Drain::iter`")]
+fn next_reports_remaining_outside_the_drained_range() {
+    let other = [1u8, 2];
+    let mut v = vec![1u8, 2, 3, 4];
+    let mut drain = v.drain(1..3);
+    drain.iter = other.iter();
+    drain.next();
+}
+
+#[test]
+fn next_leaks_remaining_after_a_violation() {
+    let rc = Rc::new(());
+    let other = [Rc::clone(&rc)];
+    let mut v = vec![Rc::clone(&rc), Rc::clone(&rc)];
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut drain = v.drain(..);
+        drain.iter = other.iter();
+        drain.next_back()
+    }));
+    assert!(result.is_err());
+    // Nothing is read from outside the vector, and the drained elements are
+    // not dropped.
+    assert!(v.is_empty());
+    assert_eq!(Rc::strong_count(&rc), 4);
+}
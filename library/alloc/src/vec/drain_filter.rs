@@ -1,10 +1,14 @@
 use crate::alloc::{Allocator, Global};
 use core::mem::{self, ManuallyDrop};
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
 use core::ptr;
 use core::slice;
 
 use super::Vec;
 
+#[cfg(test)]
+mod tests;
+
 /// An iterator which uses a closure to determine if an element should be removed.
 ///
 /// This struct is created by [`Vec::drain_filter`].
@@ -34,6 +38,7 @@ pub struct DrainFilter<
     /// The number of items that have been drained (removed) thus far.
     pub(super) del: usize,
     /// The original length of `vec` prior to draining.
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     pub(super) old_len: usize,
     /// The filter test predicate.
     pub(super) pred: F,
@@ -93,6 +98,7 @@ pub fn keep_rest(self) {
         //    a. In case of ZST, this is the only thing we want to do
         // 3. Do *not* drop self, as everything is put in a consistent state already, there is nothing to do
         let mut this = ManuallyDrop::new(self);
+        this.check(DrainFilterField::FIELDS);
 
         unsafe {
             // ZSTs have no identity, so we don't need to move them around.
@@ -110,6 +116,106 @@ pub fn keep_rest(self) {
             this.vec.set_len(new_len);
         }
     }
+
+    /// Checks `fields` before the items still to inspect and the gap left by
+    /// the drained ones are accessed. The index and the count are checked
+    /// before every item is inspected. The original length, which is compared
+    /// with the allocation of the vector, is protected, and only checked again
+    /// before the items are moved back into place.
+    ///
+    /// If they are not within the vector, the violation is reported, all the
+    /// items are leaked, leaving the vector empty, and `false` is returned.
+    #[inline]
+    fn check(&mut self, fields: &[DrainFilterField]) -> bool {
+        if !METADATA_CHECKS {
+            return true;
+        }
+        let Some(&field) = fields.iter().find(|&&field| {
+            let value = self.field(field);
+            !self.synchronize(field, value, value)
+        }) else {
+            return true;
+        };
+        self.leak_all(field);
+        false
+    }
+
+    fn field(&self, field: DrainFilterField) -> usize {
+        match field {
+            DrainFilterField::Idx => self.idx,
+            DrainFilterField::Del => self.del,
+            DrainFilterField::OldLen => self.old_len,
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn leak_all(&mut self, field: DrainFilterField) {
+        let value = self.field(field);
+        self.del = 0;
+        self.idx = 0;
+        // The write is checked by the compiler, and accepted now that
+        // nothing is left to inspect.
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
+        self.old_len = 0;
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
+        metadata_update::metadata_violation("vec::DrainFilter", field.name(), value, value);
+    }
+}
+
+/// The metadata fields of a vector's [`DrainFilter`].
+///
+/// They locate the items still to inspect, and the gap the drained ones left
+/// before them, in the allocation of the vector. The original length is
+/// protected, the index and the count are checked before every item is
+/// inspected.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrainFilterField {
+    /// The index of the next item to inspect.
+    Idx,
+    /// The number of items drained so far.
+    Del,
+    /// The length of the vector before draining.
+    OldLen,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for DrainFilterField {
+    const FIELDS: &'static [Self] =
+        &[DrainFilterField::Idx, DrainFilterField::Del, DrainFilterField::OldLen];
+
+    fn name(self) -> &'static str {
+        match self {
+            DrainFilterField::Idx => "idx",
+            DrainFilterField::Del => "del",
+            DrainFilterField::OldLen => "old_len",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, F, A: Allocator> MetaUpdate for DrainFilter<'_, T, F, A>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    type Field = DrainFilterField;
+
+    /// Synchronize an update of the draining state with the vector.
+    /// The original length must fit in the capacity of the vector and in the
+    /// block the allocator handed out for it, the index of the next item must
+    /// not exceed it, and no more items than were inspected can be drained.
+    fn synchronize(&self, field: DrainFilterField, _old: usize, new: usize) -> bool {
+        match field {
+            DrainFilterField::Idx => new <= self.old_len,
+            DrainFilterField::Del => new <= self.idx,
+            DrainFilterField::OldLen => {
+                new <= self.vec.capacity() && self.vec.buf.fits_allocation(new)
+            }
+        }
+    }
 }
 
 #[unstable(feature = "drain_filter", reason = "recently added", issue = "43244")]
@@ -122,6 +228,9 @@ impl<T, F, A: Allocator> Iterator for DrainFilter<'_, T, F, A>
     fn next(&mut self) -> Option<T> {
         unsafe {
             while self.idx < self.old_len {
+                if !self.check(&[DrainFilterField::Idx, DrainFilterField::Del]) {
+                    return None;
+                }
                 let i = self.idx;
                 let v = slice::from_raw_parts_mut(self.vec.as_mut_ptr(), self.old_len);
                 self.panic_flag = true;
@@ -187,6 +296,9 @@ fn drop(&mut self) {
             }
         }
 
+        // The remaining items are only consumed and moved back into place once
+        // they are known to be within the allocation.
+        self.check(DrainFilterField::FIELDS);
         let backshift = BackshiftOnDrop { drain: self };
 
         // Attempt to consume any remaining elements if the filter predicate
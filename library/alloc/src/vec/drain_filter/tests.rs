@@ -0,0 +1,45 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec::DrainFilter::del`")]
+fn drop_reports_more_drained_than_inspected() {
+    let mut v = vec![1u8, 2, 3, 4];
+    let mut drain = v.drain_filter(|x| *x % 2 == 0);
+    drain.next();
+    drain.del = drain.idx + 1;
+}
+
+#[test]
+fn drop_leaves_the_vector_empty_after_a_violation() {
+    let mut v = vec![1u8, 2, 3, 4];
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut drain = v.drain_filter(|x| *x % 2 == 0);
+        drain.next();
+        drain.idx = drain.old_len + 1;
+    }));
+    assert!(result.is_err());
+    assert!(v.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec::DrainFilter::del`")]
+fn next_reports_more_drained_than_inspected() {
+    let mut v = vec![1u8, 2, 3, 4];
+    let mut drain = v.drain_filter(|x| *x % 2 == 0);
+    drain.next();
+    drain.del = drain.idx + 1;
+    drain.next();
+}
+
+#[test]
+fn next_leaves_the_vector_empty_after_a_violation() {
+    let mut v = vec![1u8, 2, 3, 4];
+    let mut drain = v.drain_filter(|x| *x % 2 == 0);
+    drain.next();
+    drain.del = drain.idx + 1;
+    assert!(catch_unwind(AssertUnwindSafe(|| drain.next())).is_err());
+    // Nothing is left to inspect or to move back into place.
+    assert_eq!((drain.idx, drain.del, drain.old_len), (0, 0, 0));
+    drop(drain);
+    assert!(v.is_empty());
+}
@@ -11,9 +11,13 @@
 use core::mem::{self, ManuallyDrop, MaybeUninit, SizedTypeProperties};
 #[cfg(not(no_global_oom_handling))]
 use core::ops::Deref;
+use core::ptr::metadata_update::{self, MetaUpdate, MetadataField, METADATA_CHECKS};
 use core::ptr::{self, NonNull};
 use core::slice::{self};
 
+#[cfg(test)]
+mod tests;
+
 /// An iterator that moves out of a vector.
 ///
 /// This `struct` is created by the `into_iter` method on [`Vec`](super::Vec)
@@ -31,8 +35,10 @@ pub struct IntoIter<
     T,
     #[unstable(feature = "allocator_api", issue = "32838")] A: Allocator = Global,
 > {
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     pub(super) buf: NonNull<T>,
     pub(super) phantom: PhantomData<T>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     pub(super) cap: usize,
     // the drop impl reconstructs a RawVec from buf, cap and alloc
     // to avoid dropping the allocator twice we need to wrap it into ManuallyDrop
@@ -111,20 +117,27 @@ fn as_raw_mut_slice(&mut self) -> *mut [T] {
     /// documentation for an overview.
     #[cfg(not(no_global_oom_handling))]
     pub(super) fn forget_allocation_drop_remaining(&mut self) {
+        let checked = self.check_remaining(self.allocated_cap());
         let remaining = self.as_raw_mut_slice();
 
         // overwrite the individual fields instead of creating a new
         // struct and then overwriting &mut self.
         // this creates less assembly
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
         self.cap = 0;
         self.buf = unsafe { NonNull::new_unchecked(RawVec::NEW.ptr()) };
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
         self.ptr = self.buf.as_ptr();
         self.end = self.buf.as_ptr();
 
-        // Dropping the remaining elements can panic, so this needs to be
-        // done only after updating the other fields.
-        unsafe {
-            ptr::drop_in_place(remaining);
+        // Dropping the remaining elements can panic, and so can reporting that
+        // they are not in the allocation, so this needs to be done only after
+        // updating the other fields.
+        match checked {
+            Ok(()) => unsafe { ptr::drop_in_place(remaining) },
+            Err(violation) => violation.report(),
         }
     }
 
@@ -132,6 +145,153 @@ pub(super) fn forget_allocation_drop_remaining(&mut self) {
     pub(crate) fn forget_remaining_elements(&mut self) {
         self.ptr = self.end;
     }
+
+    /// Checks that the remaining elements are within the first `cap` elements
+    /// of the allocation of the source vector, where `cap` is what the
+    /// allocation is known to hold, before they are all dropped at once.
+    ///
+    /// A violation found here is returned to be reported once the allocation
+    /// is taken care of, and the remaining elements are leaked.
+    fn check_remaining(&self, cap: usize) -> Result<(), Violation> {
+        if !METADATA_CHECKS {
+            return Ok(());
+        }
+        if self.cap > cap {
+            return Err(Violation { field: IntoIterField::Cap, old: cap, new: self.cap });
+        }
+        let size = mem::size_of::<T>();
+        let bytes = if T::IS_ZST { usize::MAX } else { self.cap.saturating_mul(size) };
+        let aligned = |offset| T::IS_ZST || offset % size == 0;
+        let (ptr, end) = (self.offset(self.ptr.addr()), self.offset(self.end.addr()));
+        if !(end <= bytes && aligned(end)) {
+            return Err(Violation { field: IntoIterField::End, old: bytes, new: end });
+        }
+        if !(ptr <= end && aligned(ptr)) {
+            return Err(Violation { field: IntoIterField::Ptr, old: end, new: ptr });
+        }
+        Ok(())
+    }
+
+    /// Checks the remaining elements against the capacity before the iterator
+    /// reads or drops some of them. This only compares the pointers with each
+    /// other and with `buf` and `cap`, which are protected; the allocation
+    /// itself is only asked for its size before the remaining elements are
+    /// all dropped.
+    ///
+    /// If they are not within the capacity, the violation is reported, the
+    /// remaining elements are leaked, and `false` is returned.
+    #[inline]
+    fn check_step(&mut self) -> bool {
+        // Zero-sized elements are made up, not read.
+        if T::IS_ZST {
+            return true;
+        }
+        match self.check_remaining(self.cap) {
+            Ok(()) => true,
+            Err(violation) => {
+                // Both pointers are reset, so that dropping the iterator does
+                // not report the violation again.
+                self.ptr = self.buf.as_ptr();
+                self.end = self.buf.as_ptr();
+                violation.report();
+                false
+            }
+        }
+    }
+
+    // The number of elements the allocation of the source vector can hold, as
+    // far as its allocator can tell, and `self.cap` if it cannot. This does not
+    // trust `self.cap`, which a corrupted iterator gets wrong along with its
+    // pointers.
+    fn allocated_cap(&self) -> usize {
+        if !METADATA_CHECKS || T::IS_ZST || self.cap == 0 {
+            return self.cap;
+        }
+        // SAFETY: the buffer is allocated by `self.alloc`, as the capacity is
+        // not 0.
+        match unsafe { self.alloc.allocation_layout(self.buf.cast()) } {
+            Some(block) => block.size() / mem::size_of::<T>(),
+            None => self.cap,
+        }
+    }
+
+    // The offset of `addr` from the start of the allocation, in bytes. The
+    // pointers of an iterator over zero-sized elements are counters offset from
+    // `buf`, which may wrap around.
+    #[inline]
+    fn offset(&self, addr: usize) -> usize {
+        addr.wrapping_sub(self.buf.as_ptr().addr())
+    }
+}
+
+/// Remaining elements that are not within the allocation of the source
+/// vector. `old` is the bound they exceed, `new` the offending value, in
+/// bytes from the start of the allocation for the pointers.
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    field: IntoIterField,
+    old: usize,
+    new: usize,
+}
+
+impl Violation {
+    #[cold]
+    #[inline(never)]
+    fn report(self) {
+        metadata_update::metadata_violation("vec::IntoIter", self.field.name(), self.old, self.new);
+    }
+}
+
+/// The metadata fields of a vector's [`IntoIter`].
+///
+/// The buffer and the capacity describe the allocation of the source vector,
+/// and are protected: they are only written when the allocation is handed over
+/// to in-place iteration. The pointers to the remaining elements move with
+/// every step, and are checked against the capacity before every step and
+/// against the allocation before the remaining elements are dropped.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntoIterField {
+    /// The address of the allocation.
+    Buf,
+    /// The number of elements the allocation can hold.
+    Cap,
+    /// The address of the first remaining element.
+    Ptr,
+    /// The address right after the last remaining element.
+    End,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for IntoIterField {
+    const FIELDS: &'static [Self] =
+        &[IntoIterField::Buf, IntoIterField::Cap, IntoIterField::Ptr, IntoIterField::End];
+
+    fn name(self) -> &'static str {
+        match self {
+            IntoIterField::Buf => "buf",
+            IntoIterField::Cap => "cap",
+            IntoIterField::Ptr => "ptr",
+            IntoIterField::End => "end",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T, A: Allocator> MetaUpdate for IntoIter<T, A> {
+    type Field = IntoIterField;
+
+    /// Synchronize a metadata update with the allocation of the source vector.
+    /// The capacity may only be given up, together with the buffer, when the
+    /// allocation is handed over to in-place iteration. The pointers are
+    /// checked as a whole, see `check_remaining`.
+    fn synchronize(&self, field: IntoIterField, _old: usize, new: usize) -> bool {
+        match field {
+            IntoIterField::Cap => new == 0,
+            IntoIterField::Buf => new != 0 && new % mem::align_of::<T>() == 0,
+            IntoIterField::Ptr | IntoIterField::End => self.check_remaining(self.cap).is_ok(),
+        }
+    }
 }
 
 #[stable(feature = "vec_intoiter_as_ref", since = "1.46.0")]
@@ -152,7 +312,7 @@ impl<T, A: Allocator> Iterator for IntoIter<T, A> {
 
     #[inline]
     fn next(&mut self) -> Option<T> {
-        if self.ptr == self.end {
+        if self.ptr == self.end || !self.check_step() {
             None
         } else if T::IS_ZST {
             // purposefully don't use 'ptr.offset' because for
@@ -182,6 +342,8 @@ fn size_hint(&self) -> (usize, Option<usize>) {
 
     #[inline]
     fn advance_by(&mut self, n: usize) -> Result<(), usize> {
+        // After a violation, nothing is left to advance over.
+        self.check_step();
         let step_size = self.len().min(n);
         let to_drop = ptr::slice_from_raw_parts_mut(self.ptr as *mut T, step_size);
         if T::IS_ZST {
@@ -212,6 +374,7 @@ fn count(self) -> usize {
     fn next_chunk<const N: usize>(&mut self) -> Result<[T; N], core::array::IntoIter<T, N>> {
         let mut raw_ary = MaybeUninit::uninit_array();
 
+        self.check_step();
         let len = self.len();
 
         if T::IS_ZST {
@@ -267,7 +430,7 @@ unsafe fn __iterator_get_unchecked(&mut self, i: usize) -> Self::Item
 impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
     #[inline]
     fn next_back(&mut self) -> Option<T> {
-        if self.end == self.ptr {
+        if self.end == self.ptr || !self.check_step() {
             None
         } else if T::IS_ZST {
             // See above for why 'ptr.offset' isn't used
@@ -284,6 +447,7 @@ fn next_back(&mut self) -> Option<T> {
 
     #[inline]
     fn advance_back_by(&mut self, n: usize) -> Result<(), usize> {
+        self.check_step();
         let step_size = self.len().min(n);
         if T::IS_ZST {
             // SAFETY: same as for advance_by()
@@ -354,25 +518,19 @@ fn clone(&self) -> Self {
 #[stable(feature = "rust1", since = "1.0.0")]
 unsafe impl<#[may_dangle] T, A: Allocator> Drop for IntoIter<T, A> {
     fn drop(&mut self) {
-        struct DropGuard<'a, T, A: Allocator>(&'a mut IntoIter<T, A>);
-
-        impl<T, A: Allocator> Drop for DropGuard<'_, T, A> {
-            fn drop(&mut self) {
-                unsafe {
-                    // `IntoIter::alloc` is not used anymore after this and will be dropped by RawVec
-                    let alloc = ManuallyDrop::take(&mut self.0.alloc);
-                    // RawVec handles deallocation
-                    let _ = RawVec::from_raw_parts_in(self.0.buf.as_ptr(), self.0.cap, alloc);
-                }
-            }
-        }
-
-        let guard = DropGuard(self);
+        // `IntoIter::alloc` is not used anymore after this and will be dropped by RawVec.
+        // Rebuilding the RawVec first checks the buffer against the allocation before the
+        // remaining elements are trusted to be in it.
+        let buf = unsafe {
+            let alloc = ManuallyDrop::take(&mut self.alloc);
+            RawVec::from_raw_parts_in(self.buf.as_ptr(), self.cap, alloc)
+        };
         // destroy the remaining elements
-        unsafe {
-            ptr::drop_in_place(guard.0.as_raw_mut_slice());
+        match self.check_remaining(buf.capacity()) {
+            Ok(()) => unsafe { ptr::drop_in_place(self.as_raw_mut_slice()) },
+            Err(violation) => violation.report(),
         }
-        // now `guard` will be dropped and do the rest
+        // now `buf` will be dropped and handle deallocation, even if dropping an element panicked
     }
 }
 
@@ -0,0 +1,71 @@
+use super::*;
+use crate::rc::Rc;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+fn remaining_elements_are_checked_against_the_allocation() {
+    let mut it = vec![1u32, 2, 3, 4].into_iter();
+    it.next();
+    assert_eq!(it.check_remaining(4), Ok(()));
+    // A capacity beyond what the allocation holds.
+    assert_eq!(it.check_remaining(2), Err(Violation { field: IntoIterField::Cap, old: 2, new: 4 }));
+
+    it.end = it.end.wrapping_add(1);
+    assert_eq!(
+        it.check_remaining(4),
+        Err(Violation { field: IntoIterField::End, old: 16, new: 20 })
+    );
+
+    it.end = it.end.wrapping_sub(1);
+    it.ptr = it.ptr.wrapping_byte_add(1);
+    assert_eq!(
+        it.check_remaining(4),
+        Err(Violation { field: IntoIterField::Ptr, old: 16, new: 5 })
+    );
+
+    it.ptr = it.end.wrapping_add(1);
+    assert_eq!(
+        it.check_remaining(4),
+        Err(Violation { field: IntoIterField::Ptr, old: 16, new: 20 })
+    );
+    it.ptr = it.end;
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec::IntoIter::end`")]
+fn drop_reports_remaining_past_the_allocation() {
+    let mut it = vec![1u32, 2, 3].into_iter();
+    it.end = it.end.wrapping_add(it.cap);
+}
+
+#[test]
+fn drop_leaks_remaining_after_a_violation() {
+    let rc = Rc::new(());
+    let mut it = vec![Rc::clone(&rc), Rc::clone(&rc)].into_iter();
+    it.ptr = it.ptr.wrapping_byte_add(1);
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(it))).is_err());
+    // The allocation is freed, the elements are not dropped.
+    assert_eq!(Rc::strong_count(&rc), 3);
+}
+
+#[test]
+#[should_panic(expected = "metadata violation in `vec::IntoIter::end`")]
+fn next_reports_remaining_past_the_capacity() {
+    let mut it = vec![1u32, 2, 3].into_iter();
+    it.end = it.end.wrapping_add(it.cap);
+    it.next();
+}
+
+#[test]
+fn steps_leak_remaining_after_a_violation() {
+    let rc = Rc::new(());
+    let mut it = vec![Rc::clone(&rc), Rc::clone(&rc)].into_iter();
+    it.ptr = it.ptr.wrapping_sub(1);
+    assert!(catch_unwind(AssertUnwindSafe(|| it.next_back())).is_err());
+    // Nothing is read, and nothing is left to read or to drop.
+    assert_eq!(it.len(), 0);
+    assert!(it.next().is_none());
+    assert_eq!(it.advance_by(1), Err(0));
+    drop(it);
+    assert_eq!(Rc::strong_count(&rc), 3);
+}
@@ -69,17 +69,23 @@
 use core::ptr::{self, NonNull};
 use core::slice::{self, SliceIndex};
 
-use crate::alloc::{Allocator, Global};
+use crate::alloc::{Allocator, Global, Layout};
 use crate::borrow::{Cow, ToOwned};
 use crate::boxed::Box;
 use crate::collections::TryReserveError;
+use crate::metadata_ownership::{self, Owner};
+use crate::metadata_shadow::Indices;
 use crate::raw_vec::RawVec;
 
 /*SOR-MetaUpdate@kayondomartin*/
-use core::ptr::metadata_update::MetaUpdate;
+use core::ptr::metadata_update::{
+    metadata_violation, synchronize_metadata, MetaUpdate, MetadataField, METADATA_CHECKS,
+};
 
 #[unstable(feature = "drain_filter", reason = "recently added", issue = "43244")]
 pub use self::drain_filter::DrainFilter;
+#[unstable(feature = "metadata_update", issue = "none")]
+pub use self::drain_filter::DrainFilterField;
 
 mod drain_filter;
 
@@ -92,6 +98,8 @@
 
 #[stable(feature = "drain", since = "1.6.0")]
 pub use self::drain::Drain;
+#[unstable(feature = "metadata_update", issue = "none")]
+pub use self::drain::DrainField;
 
 mod drain;
 
@@ -102,6 +110,8 @@
 pub(crate) use self::in_place_collect::AsVecIntoIter;
 #[stable(feature = "rust1", since = "1.0.0")]
 pub use self::into_iter::IntoIter;
+#[unstable(feature = "metadata_update", issue = "none")]
+pub use self::into_iter::IntoIterField;
 
 mod into_iter;
 
@@ -402,6 +412,7 @@
 #[rustc_insignificant_dtor]
 pub struct Vec<T, #[unstable(feature = "allocator_api", issue = "32838")] A: Allocator = Global> {
     buf: RawVec<T, A>,
+    #[cfg_attr(not(bootstrap), rustc_protected_metadata)]
     len: usize,
 }
 
@@ -588,7 +599,13 @@ pub fn with_capacity(capacity: usize) -> Self {
     /// ```
     #[inline]
     #[stable(feature = "rust1", since = "1.0.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "vec_from_raw_parts")]
     pub unsafe fn from_raw_parts(ptr: *mut T, length: usize, capacity: usize) -> Self {
+        if let Ok(layout) = Layout::array::<T>(capacity) {
+            if !metadata_ownership::claim("Vec", ptr.cast(), layout, Owner::Unique) {
+                panic!("cannot rebuild a `Vec` from an allocation it may not own");
+            }
+        }
         unsafe { Self::from_raw_parts_in(ptr, length, capacity, Global) }
     }
 }
@@ -781,8 +798,11 @@ pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
     /// ```
     #[inline]
     #[unstable(feature = "allocator_api", issue = "32838")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "vec_from_raw_parts_in")]
     pub unsafe fn from_raw_parts_in(ptr: *mut T, length: usize, capacity: usize, alloc: A) -> Self {
-        unsafe { Vec { buf: RawVec::from_raw_parts_in(ptr, capacity, alloc), len: length } }
+        let buf = unsafe { RawVec::from_raw_parts_in(ptr, capacity, alloc) };
+        buf.set_shadow(Indices::Len(length));
+        Vec { buf, len: length }
     }
 
     /// Decomposes a `Vec<T>` into its raw components.
@@ -820,6 +840,7 @@ pub unsafe fn from_raw_parts_in(ptr: *mut T, length: usize, capacity: usize, all
     #[unstable(feature = "vec_into_raw_parts", reason = "new API", issue = "65816")]
     pub fn into_raw_parts(self) -> (*mut T, usize, usize) {
         let mut me = ManuallyDrop::new(self);
+        me.release_buffer();
         (me.as_mut_ptr(), me.len(), me.capacity())
     }
 
@@ -864,6 +885,7 @@ pub fn into_raw_parts(self) -> (*mut T, usize, usize) {
     // #[unstable(feature = "vec_into_raw_parts", reason = "new API", issue = "65816")]
     pub fn into_raw_parts_with_alloc(self) -> (*mut T, usize, usize, A) {
         let mut me = ManuallyDrop::new(self);
+        me.release_buffer();
         let len = me.len();
         let capacity = me.capacity();
         let ptr = me.as_mut_ptr();
@@ -871,6 +893,14 @@ pub fn into_raw_parts_with_alloc(self) -> (*mut T, usize, usize, A) {
         (ptr, len, capacity, alloc)
     }
 
+    /// Records that the buffer is about to be handed out as a raw pointer, so
+    /// that only one `Vec` is rebuilt from it.
+    fn release_buffer(&mut self) {
+        if let Ok(layout) = Layout::array::<T>(self.capacity()) {
+            metadata_ownership::release::<A>(self.as_ptr().cast(), layout, Owner::Unique);
+        }
+    }
+
     /// Returns the total number of elements the vector can hold without
     /// reallocating.
     ///
@@ -1167,7 +1197,7 @@ pub fn truncate(&mut self, len: usize) {
             }
             let remaining_len = self.len - len;
             let s = ptr::slice_from_raw_parts_mut(self.as_mut_ptr().add(len), remaining_len);
-            self.len = len;
+            self.set_len(len);
             ptr::drop_in_place(s);
         }
     }
@@ -1271,6 +1301,7 @@ pub fn as_ptr(&self) -> *const T {
     /// ```
     #[stable(feature = "vec_as_ptr", since = "1.37.0")]
     #[inline]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "vec_as_mut_ptr")]
     pub fn as_mut_ptr(&mut self) -> *mut T {
         // We shadow the slice method of the same name to avoid going through
         // `deref_mut`, which creates an intermediate reference.
@@ -1367,14 +1398,43 @@ pub fn allocator(&self) -> &A {
     /// Normally, here, one would use [`clear`] instead to correctly drop
     /// the contents and thus not leak memory.
     #[inline]
+    // Only changes the location a metadata violation is reported at, which is
+    // the caller of `set_len` rather than `set_len` itself.
+    #[track_caller]
     #[stable(feature = "rust1", since = "1.0.0")]
+    #[cfg_attr(not(test), rustc_diagnostic_item = "vec_set_len")]
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
     pub unsafe fn set_len(&mut self, new_len: usize) {
-        debug_assert!(new_len <= self.capacity());
-
-	    let actual_len = if self.synchronize(new_len) { new_len} else { self.capacity()};
-	    MetaUpdate::enable_metadata_update();
+        // This is the only place in `alloc::vec` that writes `self.len`: every
+        // other length mutation goes through here, so that the `MetaUpdate`
+        // protection window and synchronization cover the whole `Vec` API.
+        // `len` is `#[rustc_protected_metadata]`, so the compiler wraps the write
+        // itself in the protection window, and leaves the check to this method,
+        // which is `#[rustc_synchronizes_metadata]`. A rejected length is reported, and
+        // clamped to the capacity whatever the violation policy says: a length
+        // past the capacity would let safe code read and write out of bounds.
+        let actual_len = if !METADATA_CHECKS {
+            debug_assert!(new_len <= self.capacity());
+            new_len
+        } else if synchronize_metadata(self, VecField::Len, self.len, new_len) {
+            new_len
+        } else {
+            let _ = metadata_violation("Vec", VecField::Len.name(), self.len, new_len);
+            self.capacity()
+        };
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
         self.len = actual_len;
-	    MetaUpdate::disable_metadata_update();
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
+        self.buf.set_shadow(Indices::Len(actual_len));
+    }
+
+    /// Compares the header with its shadow copy, see `RawVec::check_shadow`.
+    #[inline]
+    #[track_caller]
+    fn check_shadow(&self) {
+        self.buf.check_shadow("Vec", (self as *const Self).cast(), Indices::Len(self.len));
     }
 
     /// Removes an element from the vector and returns it.
@@ -1842,7 +1902,7 @@ pub fn push(&mut self, value: T) {
         unsafe {
             let end = self.as_mut_ptr().add(self.len);
             ptr::write(end, value);
-            self.len += 1;
+            self.set_len(self.len + 1);
         }
     }
 
@@ -1886,7 +1946,7 @@ pub fn push_within_capacity(&mut self, value: T) -> Result<(), T> {
         unsafe {
             let end = self.as_mut_ptr().add(self.len);
             ptr::write(end, value);
-            self.len += 1;
+            self.set_len(self.len + 1);
         }
         Ok(())
     }
@@ -1913,7 +1973,7 @@ pub fn pop(&mut self) -> Option<T> {
             None
         } else {
             unsafe {
-                self.len -= 1;
+                self.set_len(self.len - 1);
                 Some(ptr::read(self.as_ptr().add(self.len())))
             }
         }
@@ -1952,7 +2012,7 @@ unsafe fn append_elements(&mut self, other: *const [T]) {
         self.reserve(count);
         let len = self.len();
         unsafe { ptr::copy_nonoverlapping(other as *const T, self.as_mut_ptr().add(len), count) };
-        self.len += count;
+        unsafe { self.set_len(len + count) };
     }
 
     /// Removes the specified range from the vector in bulk, returning all
@@ -2042,7 +2102,7 @@ pub fn clear(&mut self) {
         //   do nothing (leaking the rest of the elements) instead of dropping
         //   some twice.
         unsafe {
-            self.len = 0;
+            self.set_len(0);
             ptr::drop_in_place(elems);
         }
     }
@@ -2205,6 +2265,7 @@ pub fn leak<'a>(self) -> &'a mut [T]
         A: 'a,
     {
         let mut me = ManuallyDrop::new(self);
+        me.release_buffer();
         unsafe { slice::from_raw_parts_mut(me.as_mut_ptr(), me.len) }
     }
 
@@ -2306,16 +2367,19 @@ pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
     pub fn split_at_spare_mut(&mut self) -> (&mut [T], &mut [MaybeUninit<T>]) {
         // SAFETY:
         // - len is ignored and so never changed
-        let (init, spare, _) = unsafe { self.split_at_spare_mut_with_len() };
+        let (init, spare, len) = unsafe { self.split_at_spare_mut_with_len() };
+        // The length guard would only write back the unchanged length.
+        mem::forget(len);
         (init, spare)
     }
 
-    /// Safety: changing returned .2 (&mut usize) is considered the same as calling `.set_len(_)`.
+    /// Safety: incrementing the returned .2 (`SetLenOnDrop`) is considered the same as
+    /// calling `.set_len(_)`, which it does once dropped.
     ///
     /// This method provides unique access to all vec parts at once in `extend_from_within`.
     unsafe fn split_at_spare_mut_with_len(
         &mut self,
-    ) -> (&mut [T], &mut [MaybeUninit<T>], &mut usize) {
+    ) -> (&mut [T], &mut [MaybeUninit<T>], SetLenOnDrop<'_, T, A>) {
         let ptr = self.as_mut_ptr();
         // SAFETY:
         // - `ptr` is guaranteed to be valid for `self.len` elements
@@ -2332,7 +2396,7 @@ unsafe fn split_at_spare_mut_with_len(
             let initialized = slice::from_raw_parts_mut(ptr, self.len);
             let spare = slice::from_raw_parts_mut(spare_ptr, spare_len);
 
-            (initialized, spare, &mut self.len)
+            (initialized, spare, SetLenOnDrop::new(self))
         }
     }
 }
@@ -2518,7 +2582,7 @@ fn extend_with<E: ExtendWith<T>>(&mut self, n: usize, mut value: E) {
             // Use SetLenOnDrop to work around bug where compiler
             // might not realize the store through `ptr` through self.set_len()
             // don't alias.
-            let mut local_len = SetLenOnDrop::new(&mut self.len);
+            let mut local_len = SetLenOnDrop::new(self);
 
             // Write all elements except the last one
             for _ in 1..n {
@@ -2591,7 +2655,7 @@ impl<T: Clone, A: Allocator> ExtendFromWithinSpec for Vec<T, A> {
     default unsafe fn spec_extend_from_within(&mut self, src: Range<usize>) {
         // SAFETY:
         // - len is increased only after initializing elements
-        let (this, spare, len) = unsafe { self.split_at_spare_mut_with_len() };
+        let (this, spare, mut len) = unsafe { self.split_at_spare_mut_with_len() };
 
         // SAFETY:
         // - caller guarantees that src is a valid index
@@ -2602,7 +2666,7 @@ impl<T: Clone, A: Allocator> ExtendFromWithinSpec for Vec<T, A> {
             // Note:
             // - Element was just initialized with `MaybeUninit::write`, so it's ok to increase len
             // - len is increased after each element to prevent leaks (see issue #82533)
-            .for_each(|_| *len += 1);
+            .for_each(|_| len.increment_len(1));
     }
 }
 
@@ -2630,7 +2694,7 @@ unsafe fn spec_extend_from_within(&mut self, src: Range<usize>) {
 
         // SAFETY:
         // - The elements were just initialized by `copy_nonoverlapping`
-        self.len += count;
+        unsafe { self.set_len(self.len + count) };
     }
 }
 
@@ -2644,6 +2708,7 @@ impl<T, A: Allocator> ops::Deref for Vec<T, A> {
 
     #[inline]
     fn deref(&self) -> &[T] {
+        self.check_shadow();
         unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
     }
 }
@@ -2652,6 +2717,7 @@ fn deref(&self) -> &[T] {
 impl<T, A: Allocator> ops::DerefMut for Vec<T, A> {
     #[inline]
     fn deref_mut(&mut self) -> &mut [T] {
+        self.check_shadow();
         unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
     }
 }
@@ -2789,6 +2855,9 @@ impl<T, A: Allocator> IntoIterator for Vec<T, A> {
     fn into_iter(self) -> Self::IntoIter {
         unsafe {
             let mut me = ManuallyDrop::new(self);
+            // The iterator is only checked against the allocation when it is
+            // dropped, so the header it starts out from is checked here.
+            me.check_shadow();
             let alloc = ManuallyDrop::new(ptr::read(me.allocator()));
             let begin = me.as_mut_ptr();
             let end = if T::IS_ZST {
@@ -3028,6 +3097,7 @@ fn cmp(&self, other: &Self) -> Ordering {
 #[stable(feature = "rust1", since = "1.0.0")]
 unsafe impl<#[may_dangle] T, A: Allocator> Drop for Vec<T, A> {
     fn drop(&mut self) {
+        self.check_shadow();
         unsafe {
             // use drop for [T]
             // use a raw slice to refer to the elements of the vector as weakest necessary type;
@@ -3273,13 +3343,46 @@ fn from(s: &str) -> Vec<u8> {
     }
 }
 
+/// The protected metadata fields of a [`Vec`].
+///
+/// The capacity and the pointer live in the buffer backing the vector, which
+/// validates them against the allocation.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VecField {
+    /// The number of initialized elements.
+    Len,
+    /// The number of elements the allocation can hold.
+    Cap,
+    /// The address of the allocation.
+    Ptr,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for VecField {
+    const FIELDS: &'static [Self] = &[VecField::Len, VecField::Cap, VecField::Ptr];
+
+    fn name(self) -> &'static str {
+        match self {
+            VecField::Len => "len",
+            VecField::Cap => "cap",
+            VecField::Ptr => "ptr",
+        }
+    }
+}
+
 #[unstable(feature = "metadata_update", issue = "none")]
 impl<T, A: Allocator> MetaUpdate for Vec<T, A> {
-    /// Synchronize new length metadata with the allocator.
-    /// Ensure the new length is not longer than the capacity. (for now)
-    /// TODO: check whether there are no legal cases of overwriting the capacity
-    /// without 'contacting' the allocator.
-    fn synchronize(&self, new: usize) -> bool {
-	    return new <= self.capacity();
+    type Field = VecField;
+
+    /// Synchronize a metadata update with the allocator.
+    /// The length must not exceed the capacity. The capacity and the pointer are
+    /// handed to the backing buffer, which checks them against the allocation
+    /// whenever they change, so the length does not query the allocator itself.
+    fn synchronize(&self, field: VecField, old: usize, new: usize) -> bool {
+        match field {
+            VecField::Len => new <= self.capacity(),
+            VecField::Cap | VecField::Ptr => self.buf.synchronize(field, old, new),
+        }
     }
 }
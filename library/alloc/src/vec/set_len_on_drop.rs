@@ -1,17 +1,24 @@
+use crate::alloc::Allocator;
+
+use super::Vec;
+
 // Set the length of the vec when the `SetLenOnDrop` value goes out of scope.
 //
 // The idea is: The length field in SetLenOnDrop is a local variable
 // that the optimizer will see does not alias with any stores through the Vec's data
 // pointer. This is a workaround for alias analysis issue #32155
-pub(super) struct SetLenOnDrop<'a> {
-    len: &'a mut usize,
+//
+// The final length is written back through `Vec::set_len`, so that the write
+// stays inside the `MetaUpdate` protection window and is synchronized.
+pub(super) struct SetLenOnDrop<'a, T, A: Allocator> {
+    vec: &'a mut Vec<T, A>,
     local_len: usize,
 }
 
-impl<'a> SetLenOnDrop<'a> {
+impl<'a, T, A: Allocator> SetLenOnDrop<'a, T, A> {
     #[inline]
-    pub(super) fn new(len: &'a mut usize) -> Self {
-        SetLenOnDrop { local_len: *len, len }
+    pub(super) fn new(vec: &'a mut Vec<T, A>) -> Self {
+        SetLenOnDrop { local_len: vec.len, vec }
     }
 
     #[inline]
@@ -20,9 +27,11 @@ pub(super) fn increment_len(&mut self, increment: usize) {
     }
 }
 
-impl Drop for SetLenOnDrop<'_> {
+impl<T, A: Allocator> Drop for SetLenOnDrop<'_, T, A> {
     #[inline]
     fn drop(&mut self) {
-        *self.len = self.local_len;
+        // SAFETY: `local_len` is only ever incremented after the corresponding
+        // element has been initialized.
+        unsafe { self.vec.set_len(self.local_len) };
     }
 }
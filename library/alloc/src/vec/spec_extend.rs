@@ -36,7 +36,7 @@ impl<T, I, A: Allocator> SpecExtend<T, I> for Vec<T, A>
             self.reserve(additional);
             unsafe {
                 let mut ptr = self.as_mut_ptr().add(self.len());
-                let mut local_len = SetLenOnDrop::new(&mut self.len);
+                let mut local_len = SetLenOnDrop::new(self);
                 iterator.for_each(move |element| {
                     ptr::write(ptr, element);
                     ptr = ptr.add(1);
@@ -1,6 +1,7 @@
 use core::ptr;
 
 use crate::alloc::Allocator;
+use crate::metadata_shadow::Indices;
 use crate::raw_vec::RawVec;
 
 use super::{ExtendElement, IsZero, Vec};
@@ -22,7 +23,9 @@ impl<T: Clone + IsZero> SpecFromElem for T {
     #[inline]
     default fn from_elem<A: Allocator>(elem: T, n: usize, alloc: A) -> Vec<T, A> {
         if elem.is_zero() {
-            return Vec { buf: RawVec::with_capacity_zeroed_in(n, alloc), len: n };
+            let buf = RawVec::with_capacity_zeroed_in(n, alloc);
+            buf.set_shadow(Indices::Len(n));
+            return Vec { buf, len: n };
         }
         let mut v = Vec::with_capacity_in(n, alloc);
         v.extend_with(n, ExtendElement(elem));
@@ -34,7 +37,9 @@ impl SpecFromElem for i8 {
     #[inline]
     fn from_elem<A: Allocator>(elem: i8, n: usize, alloc: A) -> Vec<i8, A> {
         if elem == 0 {
-            return Vec { buf: RawVec::with_capacity_zeroed_in(n, alloc), len: n };
+            let buf = RawVec::with_capacity_zeroed_in(n, alloc);
+            buf.set_shadow(Indices::Len(n));
+            return Vec { buf, len: n };
         }
         unsafe {
             let mut v = Vec::with_capacity_in(n, alloc);
@@ -49,7 +54,9 @@ impl SpecFromElem for u8 {
     #[inline]
     fn from_elem<A: Allocator>(elem: u8, n: usize, alloc: A) -> Vec<u8, A> {
         if elem == 0 {
-            return Vec { buf: RawVec::with_capacity_zeroed_in(n, alloc), len: n };
+            let buf = RawVec::with_capacity_zeroed_in(n, alloc);
+            buf.set_shadow(Indices::Len(n));
+            return Vec { buf, len: n };
         }
         unsafe {
             let mut v = Vec::with_capacity_in(n, alloc);
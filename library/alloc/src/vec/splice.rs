@@ -1,8 +1,9 @@
 use crate::alloc::{Allocator, Global};
+use core::ptr::metadata_update::{synchronize_metadata, MetaUpdate, METADATA_CHECKS};
 use core::ptr::{self};
 use core::slice::{self};
 
-use super::{Drain, Vec};
+use super::{Drain, DrainField, Vec};
 
 /// A splicing iterator for `Vec`.
 ///
@@ -98,6 +99,7 @@ impl<T, A: Allocator> Drain<'_, T, A> {
     /// Fill that range as much as possible with new elements from the `replace_with` iterator.
     /// Returns `true` if we filled the entire range. (`replace_with.next()` didn’t return `None`.)
     unsafe fn fill<I: Iterator<Item = T>>(&mut self, replace_with: &mut I) -> bool {
+        self.check_tail();
         let vec = unsafe { self.vec.as_mut() };
         let range_start = vec.len;
         let range_end = self.tail_start;
@@ -108,7 +110,7 @@ unsafe fn fill<I: Iterator<Item = T>>(&mut self, replace_with: &mut I) -> bool {
         for place in range_slice {
             if let Some(new_item) = replace_with.next() {
                 unsafe { ptr::write(place, new_item) };
-                vec.len += 1;
+                unsafe { vec.set_len(vec.len + 1) };
             } else {
                 return false;
             }
@@ -117,17 +119,30 @@ unsafe fn fill<I: Iterator<Item = T>>(&mut self, replace_with: &mut I) -> bool {
     }
 
     /// Makes room for inserting more elements before the tail.
+    #[cfg_attr(not(bootstrap), rustc_synchronizes_metadata)]
     unsafe fn move_tail(&mut self, additional: usize) {
         let vec = unsafe { self.vec.as_mut() };
         let len = self.tail_start + self.tail_len;
         vec.buf.reserve(len, additional);
 
         let new_tail_start = self.tail_start + additional;
+        // The tail is only moved to where it fits in the grown allocation.
+        let old_tail_start = self.tail_start;
+        if METADATA_CHECKS
+            && !synchronize_metadata(self, DrainField::TailStart, old_tail_start, new_tail_start)
+        {
+            self.metadata_violation(DrainField::TailStart, old_tail_start, new_tail_start);
+            return;
+        }
         unsafe {
             let src = vec.as_ptr().add(self.tail_start);
             let dst = vec.as_mut_ptr().add(new_tail_start);
             ptr::copy(src, dst, self.tail_len);
         }
+        #[cfg(bootstrap)]
+        Self::enable_metadata_update();
         self.tail_start = new_tail_start;
+        #[cfg(bootstrap)]
+        Self::disable_metadata_update();
     }
 }
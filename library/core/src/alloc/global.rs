@@ -272,4 +272,27 @@ unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut
         }
         new_ptr
     }
+
+    /// Returns the layout of the block of memory at the given `ptr` pointer
+    /// as this allocator handed it out, if the allocator can tell.
+    ///
+    /// The size of the returned layout is the usable size of the block,
+    /// which is at least the size it was allocated with, and may be larger.
+    /// Its alignment is one the block is known to satisfy, which may be less
+    /// than the alignment it was allocated with. Collections check their
+    /// capacity against this, rather than trusting their own metadata.
+    ///
+    /// The default implementation returns `None`, for allocators that do
+    /// not keep track of the size of their blocks.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because undefined behavior can result
+    /// if the caller does not ensure that `ptr` denotes a block of memory
+    /// currently allocated via this allocator.
+    #[unstable(feature = "allocation_layout", issue = "none")]
+    unsafe fn allocation_layout(&self, ptr: *mut u8) -> Option<Layout> {
+        let _ = ptr;
+        None
+    }
 }
@@ -151,6 +151,38 @@ fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
         Ok(ptr)
     }
 
+    /// Behaves like `allocate`, for a block that will hold a `T`, or an array of them.
+    ///
+    /// Allocators that segregate their blocks by type can override this to pick where the block
+    /// goes. `Box` and `Vec` allocate through it. The default implementation ignores `T` and
+    /// calls `allocate`.
+    ///
+    /// # Errors
+    ///
+    /// See `allocate`.
+    #[unstable(feature = "typed_allocation", issue = "none")]
+    fn allocate_for<T: ?Sized>(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>
+    where
+        Self: Sized,
+    {
+        self.allocate(layout)
+    }
+
+    /// Behaves like `allocate_zeroed`, for a block that will hold a `T`, or an array of them.
+    ///
+    /// The default implementation ignores `T` and calls `allocate_zeroed`.
+    ///
+    /// # Errors
+    ///
+    /// See `allocate_zeroed`.
+    #[unstable(feature = "typed_allocation", issue = "none")]
+    fn allocate_zeroed_for<T: ?Sized>(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>
+    where
+        Self: Sized,
+    {
+        self.allocate_zeroed(layout)
+    }
+
     /// Deallocates the memory referenced by `ptr`.
     ///
     /// # Safety
@@ -351,6 +383,29 @@ unsafe fn shrink(
         Ok(new_ptr)
     }
 
+    /// Returns the layout of the memory block referenced by `ptr` as this allocator handed it
+    /// out, if the allocator can tell.
+    ///
+    /// The size of the returned layout is the usable size of the block, which is at least the
+    /// size of any layout that [*fits*] it, and may be larger. Its alignment is one the block is
+    /// known to satisfy, which may be less than the alignment it was allocated with. Collections
+    /// check their capacity against this, rather than trusting their own metadata.
+    ///
+    /// The default implementation returns `None`, for allocators that do not keep track of the
+    /// size of their blocks.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must denote a block of memory [*currently allocated*] via this allocator.
+    ///
+    /// [*currently allocated*]: #currently-allocated-memory
+    /// [*fits*]: #memory-fitting
+    #[unstable(feature = "allocation_layout", issue = "none")]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        let _ = ptr;
+        None
+    }
+
     /// Creates a "by reference" adapter for this instance of `Allocator`.
     ///
     /// The returned adapter also implements `Allocator` and will simply borrow this.
@@ -416,4 +471,10 @@ unsafe fn shrink(
         // SAFETY: the safety contract must be upheld by the caller
         unsafe { (**self).shrink(ptr, old_layout, new_layout) }
     }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        // SAFETY: the safety contract must be upheld by the caller
+        unsafe { (**self).allocation_layout(ptr) }
+    }
 }
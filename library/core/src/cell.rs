@@ -197,6 +197,7 @@
 use crate::marker::{PhantomData, Unsize};
 use crate::mem;
 use crate::ops::{CoerceUnsized, Deref, DerefMut};
+use crate::ptr::metadata_update::{metadata_violation, MetaUpdate, MetadataField, METADATA_CHECKS};
 use crate::ptr::{self, NonNull};
 
 mod lazy;
@@ -1269,6 +1270,67 @@ fn from(t: T) -> RefCell<T> {
 #[unstable(feature = "coerce_unsized", issue = "27732")]
 impl<T: CoerceUnsized<U>, U> CoerceUnsized<RefCell<U>> for RefCell<T> {}
 
+/// The protected metadata of a `RefCell`.
+#[unstable(feature = "metadata_update", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefCellField {
+    /// The borrow flag, as its `isize` bit pattern.
+    Borrow,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl MetadataField for RefCellField {
+    const FIELDS: &'static [Self] = &[RefCellField::Borrow];
+
+    fn name(self) -> &'static str {
+        match self {
+            RefCellField::Borrow => "borrow",
+        }
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<T: ?Sized> MetaUpdate for RefCell<T> {
+    type Field = RefCellField;
+
+    /// The borrow flag only ever moves one borrow at a time, so `old` must be the
+    /// current flag and `new` one step away from it. `undo_leak` resets the flag
+    /// through `&mut self` and does not need synchronizing.
+    fn synchronize(&self, field: RefCellField, old: usize, new: usize) -> bool {
+        match field {
+            RefCellField::Borrow => borrow_accepts(&self.borrow, old, new),
+        }
+    }
+}
+
+// Shared by `RefCell::synchronize` and `set_borrow`: the borrow guards only
+// hold the flag, not the `RefCell` it belongs to.
+#[inline]
+fn borrow_accepts(borrow: &Cell<BorrowFlag>, old: usize, new: usize) -> bool {
+    let (old, new) = (old as BorrowFlag, new as BorrowFlag);
+    old == borrow.get() && (old.checked_add(1) == Some(new) || old.checked_sub(1) == Some(new))
+}
+
+/// Sets the borrow flag to `new`. Every update made by `BorrowRef` and
+/// `BorrowRefMut` goes through here, so that it is synchronized like
+/// `RefCell`'s `MetaUpdate` impl does.
+///
+/// Returns `false` if the update was refused, after reporting it. The flag is
+/// then left as it is: a borrow that could not be taken is not handed out, and
+/// one that could not be released stays taken, which is always safe.
+#[inline]
+#[track_caller]
+fn set_borrow(borrow: &Cell<BorrowFlag>, new: BorrowFlag) -> bool {
+    let old = borrow.get();
+    if !METADATA_CHECKS || borrow_accepts(borrow, old as usize, new as usize) {
+        borrow.set(new);
+        true
+    } else {
+        metadata_violation("RefCell", RefCellField::Borrow.name(), old as usize, new as usize);
+        false
+    }
+}
+
 struct BorrowRef<'b> {
     borrow: &'b Cell<BorrowFlag>,
 }
@@ -1292,8 +1354,9 @@ fn new(borrow: &'b Cell<BorrowFlag>) -> Option<BorrowRef<'b>> {
             // 1. It was = 0, i.e. it wasn't borrowed, and we are taking the first read borrow
             // 2. It was > 0 and < isize::MAX, i.e. there were read borrows, and isize
             //    is large enough to represent having one more read borrow
-            borrow.set(b);
-            Some(BorrowRef { borrow })
+            // The guard is only built once the borrow was counted, since
+            // dropping it releases the borrow.
+            set_borrow(borrow, b).then(|| BorrowRef { borrow })
         }
     }
 }
@@ -1303,7 +1366,7 @@ impl Drop for BorrowRef<'_> {
     fn drop(&mut self) {
         let borrow = self.borrow.get();
         debug_assert!(is_reading(borrow));
-        self.borrow.set(borrow - 1);
+        set_borrow(self.borrow, borrow - 1);
     }
 }
 
@@ -1317,7 +1380,8 @@ fn clone(&self) -> Self {
         // Prevent the borrow counter from overflowing into
         // a writing borrow.
         assert!(borrow != isize::MAX);
-        self.borrow.set(borrow + 1);
+        // A clone that was not counted must not be handed out.
+        assert!(set_borrow(self.borrow, borrow + 1), "borrow flag update refused");
         BorrowRef { borrow: self.borrow }
     }
 }
@@ -1668,7 +1732,7 @@ impl Drop for BorrowRefMut<'_> {
     fn drop(&mut self) {
         let borrow = self.borrow.get();
         debug_assert!(is_writing(borrow));
-        self.borrow.set(borrow + 1);
+        set_borrow(self.borrow, borrow + 1);
     }
 }
 
@@ -1680,10 +1744,7 @@ fn new(borrow: &'b Cell<BorrowFlag>) -> Option<BorrowRefMut<'b>> {
         // references. Thus, while clone increments the mutable refcount, here
         // we explicitly only allow going from UNUSED to UNUSED - 1.
         match borrow.get() {
-            UNUSED => {
-                borrow.set(UNUSED - 1);
-                Some(BorrowRefMut { borrow })
-            }
+            UNUSED => set_borrow(borrow, UNUSED - 1).then(|| BorrowRefMut { borrow }),
             _ => None,
         }
     }
@@ -1699,7 +1760,8 @@ fn clone(&self) -> BorrowRefMut<'b> {
         debug_assert!(is_writing(borrow));
         // Prevent the borrow counter from underflowing.
         assert!(borrow != isize::MIN);
-        self.borrow.set(borrow - 1);
+        // A clone that was not counted must not be handed out.
+        assert!(set_borrow(self.borrow, borrow - 1), "borrow flag update refused");
         BorrowRefMut { borrow: self.borrow }
     }
 }
@@ -940,6 +940,24 @@ pub unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
     #[cfg_attr(not(bootstrap), rustc_safe_intrinsic)]
     pub fn type_id<T: ?Sized + 'static>() -> u64;
 
+    /// Gets an identifier of the type family of the specified type: the item
+    /// that defines it, with its generic arguments erased. `Vec<i32>` and
+    /// `Vec<f32>` have the same type family id, while types that are not defined
+    /// by an item, like primitives or slices, form a family of their own. Like
+    /// `type_id`, this returns the same value regardless of whichever crate it is
+    /// invoked in.
+    ///
+    /// This is meant for allocators that segregate allocations by type.
+    ///
+    /// Note that, unlike most intrinsics, this is safe to call;
+    /// it does not require an `unsafe` block.
+    /// Therefore, implementations must not require the user to uphold
+    /// any safety invariants.
+    #[cfg(not(bootstrap))]
+    #[rustc_const_unstable(feature = "const_type_family_id", issue = "none")]
+    #[rustc_safe_intrinsic]
+    pub fn type_family_id<T: ?Sized>() -> u64;
+
     /// A guard for unsafe functions that cannot ever be executed if `T` is uninhabited:
     /// This will statically either panic, or do nothing.
     ///
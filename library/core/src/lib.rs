@@ -175,9 +175,11 @@
 #![feature(associated_type_bounds)]
 #![feature(auto_traits)]
 #![feature(c_unwind)]
+#![cfg_attr(not(bootstrap), feature(cfg_metadata_protection))]
 #![feature(cfg_sanitize)]
 #![feature(cfg_target_has_atomic)]
 #![feature(cfg_target_has_atomic_equal_alignment)]
+#![feature(cfg_target_thread_local)]
 #![feature(const_fn_floating_point_arithmetic)]
 #![feature(const_mut_refs)]
 #![feature(const_precise_live_drops)]
@@ -213,6 +215,7 @@
 #![feature(staged_api)]
 #![feature(stmt_expr_attributes)]
 #![feature(target_feature_11)]
+#![feature(thread_local)]
 #![feature(trait_alias)]
 #![feature(transparent_unions)]
 #![feature(try_blocks)]
@@ -1,27 +1,521 @@
-/// This trait is intended for implementation by smart pointers
-/// Each smart pointer whose metadata wishes to be protected should implement this trait, and define
+/// This trait is intended for implementation by smart pointers and containers
+/// Each type whose metadata wishes to be protected should implement this trait, and define
 /// a synchronization method.
 
-use core::ops::{Drop, Deref};
+#[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+use crate::cell::Cell;
+use crate::fmt;
+#[cfg(target_has_atomic_load_store = "ptr")]
+use crate::mem;
+use crate::panic::Location;
+#[cfg(target_has_atomic_load_store = "ptr")]
+use crate::ptr;
+#[cfg(target_has_atomic_load_store = "ptr")]
+use crate::sync::atomic::{AtomicPtr, Ordering};
 
-/// MetaUpdate trait. The trait to be implemented by smartpointers.
-/// We are using Drop + Deref with the assumption that all smart
-/// pointers implement the Deref and Drop trait.
+/// MetaUpdate trait. The trait to be implemented by smartpointers and containers.
+/// There is no supertrait: the metadata worth protecting is not tied to `Drop` or
+/// `Deref`. `RefCell` has a borrow flag but no destructor, and collections like
+/// `VecDeque` or `BTreeMap` never dereference to their contents.
 #[unstable(feature = "metadata_update", issue = "none")]
-#[allow(drop_bounds)]
-pub trait MetaUpdate: Drop + Deref{
-    /// synchronize the update metadata with the allocator.
-    fn synchronize(&self, new: usize) -> bool;
-    
+#[cfg_attr(not(bootstrap), lang = "meta_update")]
+pub trait MetaUpdate {
+    /// The protected metadata fields of the implementor, e.g. `len` and `cap`
+    /// for `Vec`, or `strong` and `weak` for `Rc`.
+    type Field: MetadataField;
+
+    /// synchronize the update of `field` from `old` to `new` with the allocator.
+    /// returns whether the update is valid.
+    fn synchronize(&self, field: Self::Field, old: usize, new: usize) -> bool;
+
     /// enable write access on the metadata memory region
     /// is static because we will be setting access rights for the whole region
     /// this actually depending on the method we choose for protection.
     /// it makes more sense if we opt for MPK protection for example.
     /// other methods like guard pages don't require this message.
-    fn enable_metadata_update(){}
-    
+    /// By default this opens the window of the protection backend of the
+    /// runtime, if one is installed.
+    fn enable_metadata_update() {
+        enable_metadata_writes();
+    }
+
     /// disable metadata write access
     /// implementation condition is same as that of enable_metadata_update
     /// only makes sense depending on the method chosen for protection
-    fn disable_metadata_update(){}
+    fn disable_metadata_update() {
+        disable_metadata_writes();
+    }
+}
+
+/// Derive macro generating an impl of the trait `MetaUpdate`.
+///
+/// The fields to protect are marked with `#[metadata]`, and what `synchronize`
+/// accepts for them is given by their kind:
+///
+/// - `#[metadata]`: any update, the field is only protected,
+/// - `#[metadata(len)]` or `#[metadata(len, bound = "self.cap")]`: a length,
+///   which may not exceed the bound, if any,
+/// - `#[metadata(count)]`: a counter, which may only change by one at a time
+///   and never wraps around.
+///
+/// Like the metadata of the standard library, the marked fields may only be
+/// assigned by the methods of the type, and those writes are synchronized and
+/// wrapped in the protection window by the compiler.
+///
+/// ```
+/// #![feature(metadata_update)]
+///
+/// use std::ptr::metadata_update::MetaUpdate;
+///
+/// #[derive(MetaUpdate)]
+/// struct Handles {
+///     cap: usize,
+///     #[metadata(len, bound = "self.cap")]
+///     len: usize,
+///     #[metadata(count)]
+///     users: usize,
+/// }
+/// ```
+#[cfg(not(bootstrap))]
+#[rustc_builtin_macro(MetaUpdate, attributes(metadata))]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub macro MetaUpdate($item:item) {
+    /* compiler built-in */
+}
+
+/// Whether metadata updates are checked at all. They are, unless the library was
+/// built with `-Z metadata-protection=none`, which also leaves the write window
+/// closed for good.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub const METADATA_CHECKS: bool = !cfg!(metadata_protection = "none");
+
+/// A protected metadata field of a `MetaUpdate` implementor.
+/// Usually implemented by a fieldless enum listing the fields.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub trait MetadataField: Copy {
+    /// every field of the implementor, used to look a field up by its name.
+    const FIELDS: &'static [Self];
+
+    /// the name of the field, as used in violation reports.
+    fn name(self) -> &'static str;
+}
+
+/// Synchronizes a write to the `#[rustc_protected_metadata]` field `field` of
+/// `owner`, and reports it if it is rejected. Calls to this are inserted by the
+/// compiler before every write to such a field, together with `protected_metadata_open`
+/// and `protected_metadata_close` around the write itself.
+/// Fields that are not listed in `T::Field::FIELDS` are not synchronized.
+///
+/// Returns whether the write is made. A rejected write is only made if the
+/// violation handler answers [`ViolationAction::Continue`], otherwise the field
+/// keeps its value.
+///
+/// The inserted calls have no cleanup, so none of these functions unwinds: a
+/// violation handler that panics aborts the process here.
+#[cfg(not(bootstrap))]
+#[lang = "protected_metadata_check"]
+#[rustc_nounwind]
+#[track_caller]
+fn protected_metadata_check<T: MetaUpdate + ?Sized>(
+    owner: &T,
+    field: &'static str,
+    old: usize,
+    new: usize,
+) -> bool {
+    if !METADATA_CHECKS {
+        return true;
+    }
+    let Some(&metadata) = T::Field::FIELDS.iter().find(|metadata| metadata.name() == field) else {
+        return true;
+    };
+    synchronize_metadata(owner, metadata, old, new)
+        || matches!(
+            metadata_violation(crate::any::type_name::<T>(), field, old, new),
+            ViolationAction::Continue
+        )
+}
+
+/// Opens the protection window around compiler-inserted metadata writes.
+#[cfg(not(bootstrap))]
+#[lang = "protected_metadata_open"]
+#[rustc_nounwind]
+#[track_caller]
+fn protected_metadata_open<T: MetaUpdate + ?Sized>() {
+    record_metadata_event(MetadataEvent::Enable, crate::any::type_name::<T>());
+    T::enable_metadata_update();
+}
+
+/// Closes the protection window around compiler-inserted metadata writes.
+#[cfg(not(bootstrap))]
+#[lang = "protected_metadata_close"]
+#[rustc_nounwind]
+#[track_caller]
+fn protected_metadata_close<T: MetaUpdate + ?Sized>() {
+    record_metadata_event(MetadataEvent::Disable, crate::any::type_name::<T>());
+    T::disable_metadata_update();
+}
+
+/// A metadata update that failed synchronization, as passed to the handler
+/// installed with [`set_metadata_violation_handler`].
+#[derive(Clone, Copy, Debug)]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub struct MetadataViolation<'a> {
+    type_name: &'static str,
+    field: &'static str,
+    old: usize,
+    new: usize,
+    location: &'a Location<'a>,
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl<'a> MetadataViolation<'a> {
+    /// The name of the type whose metadata was being updated.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The name of the metadata field that was being updated.
+    pub fn field(&self) -> &'static str {
+        self.field
+    }
+
+    /// The value the field held before the update.
+    pub fn old(&self) -> usize {
+        self.old
+    }
+
+    /// The value that was requested for the field.
+    pub fn new(&self) -> usize {
+        self.new
+    }
+
+    /// The location of the code that requested the update.
+    pub fn location(&self) -> &'a Location<'a> {
+        self.location
+    }
+}
+
+#[unstable(feature = "metadata_update", issue = "none")]
+impl fmt::Display for MetadataViolation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "metadata violation in `{}::{}`: {} -> {} at {}",
+            self.type_name, self.field, self.old, self.new, self.location
+        )
+    }
+}
+
+/// What the code that detected a metadata violation does once the handler returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub enum ViolationAction {
+    /// Leave the metadata in a safe state, e.g. clamp a length to the capacity.
+    Clamp,
+    /// Apply the requested update anyway. Code for which the update is never
+    /// safe, like a length past the capacity of a `Vec` or a pointer that does
+    /// not match its allocation, treats this like [`ViolationAction::Clamp`].
+    Continue,
+}
+
+/// Reports a metadata update that failed synchronization, and returns what the
+/// caller should do about it.
+///
+/// `type_name` and `field` name the metadata that was being updated, `old` and
+/// `new` are the value it held and the value that was requested. The violation
+/// is passed to the handler installed with [`set_metadata_violation_handler`];
+/// without one, it panics. Callers must leave their metadata in a state that is
+/// safe to keep using if this returns.
+#[cold]
+#[inline(never)]
+#[track_caller]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub fn metadata_violation(
+    type_name: &'static str,
+    field: &'static str,
+    old: usize,
+    new: usize,
+) -> ViolationAction {
+    record_metadata_event(MetadataEvent::Failure, type_name);
+    let violation = MetadataViolation { type_name, field, old, new, location: Location::caller() };
+    #[cfg(target_has_atomic_load_store = "ptr")]
+    {
+        let handler = VIOLATION_HANDLER.load(Ordering::Acquire);
+        if !handler.is_null() {
+            // SAFETY: only `set_metadata_violation_handler` stores to the handler, and it stores
+            // a `fn(&MetadataViolation<'_>) -> ViolationAction`.
+            let handler = unsafe {
+                mem::transmute::<*mut (), fn(&MetadataViolation<'_>) -> ViolationAction>(handler)
+            };
+            return handler(&violation);
+        }
+    }
+    panic!("{violation}");
+}
+
+// The violation handler of the runtime, null until one is installed.
+#[cfg(target_has_atomic_load_store = "ptr")]
+static VIOLATION_HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs the function that handles metadata violations.
+///
+/// This is meant for the runtime, which calls it once at startup to apply the
+/// violation policy of the process, see `std::alloc::set_metadata_violation_hook`.
+/// Until then, or if it is never called, violations panic. Targets without
+/// atomic pointers ignore the handler.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub fn set_metadata_violation_handler(handler: fn(&MetadataViolation<'_>) -> ViolationAction) {
+    #[cfg(target_has_atomic_load_store = "ptr")]
+    VIOLATION_HANDLER.store(handler as *mut (), Ordering::Release);
+    #[cfg(not(target_has_atomic_load_store = "ptr"))]
+    let _ = handler;
+}
+
+/// Whether protected headers are mirrored in a shadow table and compared with
+/// it when they are read. They are only if the library was built with
+/// `-Z metadata-protection=shadow`.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub const METADATA_SHADOW: bool = cfg!(metadata_protection = "shadow");
+
+/// Reports that the protected field `field` of a `type_name` holds `inline`,
+/// while its shadow copy holds `shadow`, or there is none, and aborts.
+///
+/// Unlike a [`metadata_violation`], such a mismatch does not come from an
+/// update that could be refused: the header was overwritten behind the back of
+/// its type, e.g. by an overflow out of a neighbouring allocation, and nothing
+/// it describes can be trusted anymore. So the violation handler is not
+/// consulted, and the process aborts with a message naming the field.
+#[cold]
+#[inline(never)]
+#[track_caller]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub fn metadata_corruption(
+    type_name: &'static str,
+    field: &'static str,
+    inline: usize,
+    shadow: Option<usize>,
+) -> ! {
+    record_metadata_event(MetadataEvent::Failure, type_name);
+    match shadow {
+        Some(shadow) => crate::panicking::panic_nounwind_fmt(format_args!(
+            "metadata corruption in `{type_name}::{field}`: {inline} does not match its shadow \
+             copy {shadow}"
+        )),
+        None => crate::panicking::panic_nounwind_fmt(format_args!(
+            "metadata corruption in `{type_name}::{field}`: {inline:#x} has no shadow copy"
+        )),
+    }
+}
+
+/// Whether the ownership of allocations that were turned into raw pointers is
+/// tracked. It is only if the library was built with
+/// `--cfg metadata_ownership_checks`, and then rebuilding an owner from such a
+/// pointer, e.g. with `Box::from_raw`, is reported through [`metadata_violation`]
+/// if the allocation already has an owner or a different layout, and panics
+/// once the handler returns, whatever it answers.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub const METADATA_OWNERSHIP: bool = cfg!(metadata_ownership_checks);
+
+/// Whether metadata updates are counted. They are only if the library was built
+/// with `--cfg metadata_update_stats`, otherwise [`record_metadata_event`]
+/// compiles to nothing.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub const METADATA_STATS: bool = cfg!(metadata_update_stats);
+
+/// What happened to the metadata of a type, as passed to the recorder installed
+/// with [`set_metadata_stats_recorder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub enum MetadataEvent {
+    /// An update was passed to [`MetaUpdate::synchronize`].
+    Synchronize,
+    /// An update failed synchronization and was reported through [`metadata_violation`].
+    Failure,
+    /// The protection window was opened.
+    Enable,
+    /// The protection window was closed.
+    Disable,
+}
+
+/// Counts `event` for the metadata of `type_name`, at the location of the caller.
+///
+/// The compiler-inserted checks and windows, and [`metadata_violation`], record
+/// their events through this. It does nothing unless [`METADATA_STATS`] is set
+/// and a recorder is installed.
+#[inline]
+#[track_caller]
+#[unstable(feature = "metadata_update", issue = "none")]
+pub fn record_metadata_event(event: MetadataEvent, type_name: &'static str) {
+    if !METADATA_STATS {
+        return;
+    }
+    #[cfg(target_has_atomic_load_store = "ptr")]
+    {
+        let recorder = STATS_RECORDER.load(Ordering::Acquire);
+        if !recorder.is_null() {
+            // SAFETY: only `set_metadata_stats_recorder` stores to the recorder, and it stores
+            // a `StatsRecorder`.
+            let recorder = unsafe { mem::transmute::<*mut (), StatsRecorder>(recorder) };
+            recorder(event, type_name, Location::caller());
+        }
+    }
+    #[cfg(not(target_has_atomic_load_store = "ptr"))]
+    let _ = (event, type_name);
+}
+
+#[cfg(target_has_atomic_load_store = "ptr")]
+type StatsRecorder = fn(MetadataEvent, &'static str, &'static Location<'static>);
+
+// The event recorder of the runtime, null until one is installed.
+#[cfg(target_has_atomic_load_store = "ptr")]
+static STATS_RECORDER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs the function that counts metadata events.
+///
+/// This is meant for the runtime, which installs one at startup if the
+/// statistics were asked for. The recorder is called in the middle of metadata
+/// updates, so it must not update any protected metadata itself, e.g. it must
+/// not allocate. Without [`METADATA_STATS`], or on targets without atomic
+/// pointers, the recorder is never called.
+#[unstable(feature = "metadata_update", issue = "none")]
+pub fn set_metadata_stats_recorder(
+    recorder: fn(MetadataEvent, &'static str, &'static Location<'static>),
+) {
+    #[cfg(target_has_atomic_load_store = "ptr")]
+    STATS_RECORDER.store(recorder as *mut (), Ordering::Release);
+    #[cfg(not(target_has_atomic_load_store = "ptr"))]
+    let _ = recorder;
+}
+
+// The write window hooks of the runtime backend, null until one is installed.
+#[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+static ENABLE_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+#[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+static DISABLE_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+// The number of write windows the current thread has open. Only the outermost
+// window calls the hooks, so that a nested update, e.g. of the `RawVec` inside
+// a `Vec`, does not close the window of the update around it.
+#[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+#[thread_local]
+static WINDOW_DEPTH: Cell<usize> = Cell::new(0);
+
+/// Installs the functions that open and close the metadata write window.
+///
+/// This is an internal hook for the runtime, to be called once at startup by
+/// a platform backend that write-protects metadata. Until then, or if it is
+/// never called, metadata updates are only checked through
+/// [`MetaUpdate::synchronize`]. Windows nest, and the hooks are only called
+/// when a thread opens its outermost window and when it closes it again.
+/// Targets without atomic pointers or native thread locals ignore the hooks.
+///
+/// # Safety
+///
+/// This must be called at most once, before any other thread is spawned and
+/// while no window is open. The hooks must not panic, and must not update any
+/// protected metadata themselves.
+#[doc(hidden)]
+#[unstable(
+    feature = "metadata_update_internals",
+    reason = "internal hook of the standard library runtime",
+    issue = "none"
+)]
+pub unsafe fn set_metadata_write_hooks(enable: fn(), disable: fn()) {
+    #[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+    {
+        ENABLE_HOOK.store(enable as *mut (), Ordering::SeqCst);
+        DISABLE_HOOK.store(disable as *mut (), Ordering::SeqCst);
+    }
+    #[cfg(not(all(target_has_atomic_load_store = "ptr", target_thread_local)))]
+    let _ = (enable, disable);
+}
+
+/// Opens a metadata write window on the current thread.
+#[inline]
+#[rustc_diagnostic_item = "enable_metadata_writes"]
+fn enable_metadata_writes() {
+    #[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+    {
+        let hook = ENABLE_HOOK.load(Ordering::Relaxed);
+        if !hook.is_null() {
+            let depth = WINDOW_DEPTH.get();
+            WINDOW_DEPTH.set(depth + 1);
+            if depth == 0 {
+                // SAFETY: only `set_metadata_write_hooks` stores to the hook, and it stores a `fn()`.
+                unsafe { mem::transmute::<*mut (), fn()>(hook)() }
+            }
+        }
+    }
+}
+
+/// Closes a metadata write window on the current thread. Closing a window
+/// that was never opened does nothing.
+#[inline]
+#[rustc_diagnostic_item = "disable_metadata_writes"]
+fn disable_metadata_writes() {
+    #[cfg(all(target_has_atomic_load_store = "ptr", target_thread_local))]
+    {
+        let hook = DISABLE_HOOK.load(Ordering::Relaxed);
+        if !hook.is_null() {
+            match WINDOW_DEPTH.get() {
+                0 => {}
+                1 => {
+                    WINDOW_DEPTH.set(0);
+                    // SAFETY: only `set_metadata_write_hooks` stores to the hook, and it stores a `fn()`.
+                    unsafe { mem::transmute::<*mut (), fn()>(hook)() }
+                }
+                depth => WINDOW_DEPTH.set(depth - 1),
+            }
+        }
+    }
+}
+
+/// Passes an update of `field` to `owner.synchronize`, and counts it like the
+/// compiler-inserted checks do.
+///
+/// This is for the setters marked `#[rustc_synchronizes_metadata]`, which
+/// synchronize their writes themselves, and whose writes the compiler
+/// therefore does not check again.
+#[doc(hidden)]
+#[unstable(
+    feature = "metadata_update_internals",
+    reason = "internal hook of the standard library runtime",
+    issue = "none"
+)]
+#[inline]
+#[track_caller]
+pub fn synchronize_metadata<T: MetaUpdate + ?Sized>(
+    owner: &T,
+    field: T::Field,
+    old: usize,
+    new: usize,
+) -> bool {
+    record_metadata_event(MetadataEvent::Synchronize, crate::any::type_name::<T>());
+    owner.synchronize(field, old, new)
+}
+
+/// Runs `f` with the metadata write window of the current thread open.
+///
+/// This is for the side tables the library keeps about metadata, like the
+/// records of the reference counts of `Rc` and `Arc`, which the runtime backend
+/// write-protects along with the metadata itself. The window is closed again
+/// when `f` returns or unwinds.
+#[doc(hidden)]
+#[unstable(
+    feature = "metadata_update_internals",
+    reason = "internal hook of the standard library runtime",
+    issue = "none"
+)]
+#[inline]
+pub fn with_metadata_writes<R>(f: impl FnOnce() -> R) -> R {
+    struct Close;
+    impl Drop for Close {
+        #[inline]
+        fn drop(&mut self) {
+            disable_metadata_writes();
+        }
+    }
+    enable_metadata_writes();
+    let _close = Close;
+    f()
 }
@@ -449,6 +449,24 @@ fn refcell_replace_borrows() {
     x.replace(1);
 }
 
+#[test]
+fn refcell_synchronize_borrow_flag() {
+    use core::ptr::metadata_update::MetaUpdate;
+
+    let cell = RefCell::new(0);
+    assert!(cell.synchronize(RefCellField::Borrow, 0, 1));
+    assert!(cell.synchronize(RefCellField::Borrow, 0, usize::MAX));
+    assert!(!cell.synchronize(RefCellField::Borrow, 0, 2));
+    assert!(!cell.synchronize(RefCellField::Borrow, 1, 2));
+
+    let first = cell.borrow();
+    let second = Ref::clone(&first);
+    assert!(cell.synchronize(RefCellField::Borrow, 2, 1));
+    drop((first, second));
+    let _write = cell.borrow_mut();
+    assert!(cell.synchronize(RefCellField::Borrow, usize::MAX, 0));
+}
+
 #[test]
 fn refcell_format() {
     let name = RefCell::new("rust");
@@ -83,6 +83,7 @@
 #![feature(pointer_byte_offsets)]
 #![feature(portable_simd)]
 #![feature(ptr_metadata)]
+#![feature(metadata_update)]
 #![feature(once_cell)]
 #![feature(option_result_contains)]
 #![feature(unsized_tuple_coercion)]
@@ -57,14 +57,22 @@
 #![stable(feature = "alloc_module", since = "1.28.0")]
 
 use core::intrinsics;
+use core::ptr::metadata_update::{self, MetadataViolation, ViolationAction};
 use core::ptr::NonNull;
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
 use core::{mem, ptr};
 
 #[stable(feature = "alloc_module", since = "1.28.0")]
 #[doc(inline)]
 pub use alloc_crate::alloc::*;
 
+mod metadata_sanitizer;
+pub(crate) mod metadata_stats;
+mod tdi;
+
+#[unstable(feature = "tdi_alloc", issue = "none")]
+pub use self::tdi::{Tdi, TdiGlobal};
+
 /// The default memory allocator provided by the operating system.
 ///
 /// This is based on `malloc` on Unix platforms and `HeapAlloc` on Windows,
@@ -284,6 +292,144 @@ unsafe fn shrink(
             },
         }
     }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { GlobalAlloc::allocation_layout(self, ptr.as_ptr()) }
+    }
+}
+
+/// An allocator that keeps its allocations away from the rest of the heap.
+///
+/// On Linux, every allocation gets pages of its own, with a guard page that
+/// cannot be written on either side, and ends right at the guard page behind
+/// it. A linear overflow out of a buffer, on the ordinary heap or in this
+/// allocator, therefore faults on a guard page before it can reach anything
+/// allocated here. This is meant for values whose metadata must not be corrupted, such
+/// as `Vec` headers:
+///
+/// ```rust
+/// #![feature(allocator_api, guarded_arena)]
+///
+/// use std::alloc::GuardedArena;
+///
+/// let mut v = Vec::new_in(GuardedArena);
+/// v.push(1);
+/// let header = Box::new_in(v, GuardedArena);
+/// assert_eq!(*header, [1]);
+/// ```
+///
+/// Isolation costs at least a page per allocation, and pages are returned to
+/// the system as soon as nothing is allocated in them anymore. With
+/// `-Z metadata-protection=guard`, the runtime allocates every `Rc` and `Arc`
+/// here: not only their counters, but the whole allocation, value included,
+/// so each takes at least a page.
+///
+/// Alignments above the page size are not supported. On other platforms,
+/// `GuardedArena` forwards to [`System`].
+#[unstable(feature = "guarded_arena", issue = "none")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GuardedArena;
+
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+#[unstable(feature = "guarded_arena", issue = "none")]
+unsafe impl GlobalAlloc for GuardedArena {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { crate::sys::guarded_arena::alloc(layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { crate::sys::guarded_arena::dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: *mut u8) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { crate::sys::guarded_arena::allocation_layout(ptr) }
+    }
+}
+
+#[cfg(not(all(any(target_os = "linux", target_os = "android"), not(miri))))]
+#[unstable(feature = "guarded_arena", issue = "none")]
+unsafe impl GlobalAlloc for GuardedArena {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { System.alloc(layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: *mut u8) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { System.allocation_layout(ptr) }
+    }
+}
+
+// The Allocator impl checks the layout size to be non-zero and forwards to the GlobalAlloc impl.
+// Growing and shrinking always move the allocation, as blocks end at their last page.
+#[unstable(feature = "allocator_api", issue = "32838")]
+unsafe impl Allocator for GuardedArena {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match layout.size() {
+            0 => Ok(NonNull::slice_from_raw_parts(layout.dangling(), 0)),
+            // SAFETY: `layout` is non-zero in size,
+            size => unsafe {
+                let ptr = NonNull::new(GlobalAlloc::alloc(self, layout)).ok_or(AllocError)?;
+                Ok(NonNull::slice_from_raw_parts(ptr, size))
+            },
+        }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            // SAFETY: `layout` is non-zero in size,
+            // other conditions must be upheld by the caller
+            unsafe { GlobalAlloc::dealloc(self, ptr.as_ptr(), layout) }
+        }
+    }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { GlobalAlloc::allocation_layout(self, ptr.as_ptr()) }
+    }
+}
+
+/// Allocates the metadata compartment, which holds the headers relocated out
+/// of `#[metadata_compartment]` structs, and with guard page metadata
+/// protection the whole allocations of `Rc` and `Arc`, from [`GuardedArena`].
+///
+/// Called once during runtime initialization, before the compartment is
+/// first allocated from, which would settle it on the global allocator.
+pub(crate) fn init_metadata_compartment() {
+    // SAFETY: `GuardedArena` is a `GlobalAlloc`, and never unmaps blocks that are
+    // still allocated.
+    unsafe {
+        alloc_crate::compartment::set_compartment_allocator(compartment_alloc, compartment_dealloc);
+    }
+}
+
+unsafe fn compartment_alloc(layout: Layout) -> *mut u8 {
+    // SAFETY: the compartment only allocates layouts that are non-zero in size
+    unsafe { GuardedArena.alloc(layout) }
+}
+
+unsafe fn compartment_dealloc(ptr: *mut u8, layout: Layout) {
+    // SAFETY: all conditions must be upheld by the caller
+    unsafe { GuardedArena.dealloc(ptr, layout) }
 }
 
 static HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
@@ -356,6 +502,165 @@ pub fn rust_oom(layout: Layout) -> ! {
     crate::process::abort()
 }
 
+static METADATA_VIOLATION_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// The built-in ways of handling a metadata violation, i.e. an update of the
+/// metadata of a smart pointer or collection that failed synchronization, like a
+/// `Vec::set_len` beyond the capacity.
+///
+/// The default hook applies the policy named by the `RUST_METADATA_VIOLATION`
+/// environment variable at startup: `abort`, `panic`, `log-and-clamp` or
+/// `log-and-continue`. Without it, the policy the standard library was built
+/// with is used, which is selected with `--cfg metadata_violation_policy="..."`
+/// and defaults to `panic`.
+#[unstable(feature = "metadata_violation_hook", issue = "none")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataViolationPolicy {
+    /// Print the violation to standard error and abort the process.
+    Abort,
+    /// Panic with a message describing the violation.
+    Panic,
+    /// Print the violation to standard error, and leave the metadata in a safe
+    /// state, e.g. clamp a length to the capacity.
+    LogAndClamp,
+    /// Print the violation to standard error, and apply the update anyway where
+    /// that is possible at all.
+    LogAndContinue,
+}
+
+impl MetadataViolationPolicy {
+    /// Returns the hook implementing this policy, for [`set_metadata_violation_hook`].
+    #[unstable(feature = "metadata_violation_hook", issue = "none")]
+    pub fn hook(self) -> fn(&MetadataViolation<'_>) -> ViolationAction {
+        match self {
+            MetadataViolationPolicy::Abort => |violation| {
+                rtprintpanic!("{violation}\n");
+                crate::process::abort()
+            },
+            MetadataViolationPolicy::Panic => |violation| panic!("{violation}"),
+            MetadataViolationPolicy::LogAndClamp => |violation| {
+                rtprintpanic!("{violation}\n");
+                ViolationAction::Clamp
+            },
+            MetadataViolationPolicy::LogAndContinue => |violation| {
+                rtprintpanic!("{violation}\n");
+                ViolationAction::Continue
+            },
+        }
+    }
+
+    fn from_name(name: &str) -> Option<MetadataViolationPolicy> {
+        match name {
+            "abort" => Some(MetadataViolationPolicy::Abort),
+            "panic" => Some(MetadataViolationPolicy::Panic),
+            "log-and-clamp" => Some(MetadataViolationPolicy::LogAndClamp),
+            "log-and-continue" => Some(MetadataViolationPolicy::LogAndContinue),
+            _ => None,
+        }
+    }
+
+    // The policy selected when the standard library was built.
+    const BUILTIN: MetadataViolationPolicy = if cfg!(metadata_violation_policy = "abort") {
+        MetadataViolationPolicy::Abort
+    } else if cfg!(metadata_violation_policy = "log-and-clamp") {
+        MetadataViolationPolicy::LogAndClamp
+    } else if cfg!(metadata_violation_policy = "log-and-continue") {
+        MetadataViolationPolicy::LogAndContinue
+    } else {
+        MetadataViolationPolicy::Panic
+    };
+}
+
+/// Registers a custom metadata violation hook, replacing any that was previously registered.
+///
+/// The hook is invoked when an update of the metadata of a smart pointer or
+/// collection fails synchronization, with the type name, the field, the old and
+/// the requested value, and the location of the update. What it returns decides
+/// whether the metadata is clamped to a safe value, or updated anyway where that
+/// is possible. It may also panic or abort instead of returning. The default hook
+/// applies a [`MetadataViolationPolicy`].
+///
+/// Some violations leave nothing to clamp. With ownership checks, rebuilding an
+/// owner from a raw pointer it may not own, with [`Box::from_raw`],
+/// [`Vec::from_raw_parts`], [`Rc::from_raw`] or [`Arc::from_raw`], panics once
+/// the hook returns, even if it returns [`ViolationAction::Continue`]: the
+/// allocation may already be owned, and rebuilding another owner would set up a
+/// double free.
+///
+/// [`Rc::from_raw`]: crate::rc::Rc::from_raw
+/// [`Arc::from_raw`]: crate::sync::Arc::from_raw
+///
+/// The metadata violation hook is a global resource.
+///
+/// # Examples
+///
+/// ```
+/// #![feature(metadata_update, metadata_violation_hook)]
+///
+/// use std::alloc::set_metadata_violation_hook;
+/// use std::ptr::metadata_update::{MetadataViolation, ViolationAction};
+///
+/// fn count_and_clamp(violation: &MetadataViolation<'_>) -> ViolationAction {
+///     eprintln!("{} at {}", violation.field(), violation.location());
+///     ViolationAction::Clamp
+/// }
+///
+/// set_metadata_violation_hook(count_and_clamp);
+/// ```
+#[unstable(feature = "metadata_violation_hook", issue = "none")]
+pub fn set_metadata_violation_hook(hook: fn(&MetadataViolation<'_>) -> ViolationAction) {
+    METADATA_VIOLATION_HOOK.store(hook as *mut (), Ordering::SeqCst);
+}
+
+/// Unregisters the current metadata violation hook, returning it.
+///
+/// *See also the function [`set_metadata_violation_hook`].*
+///
+/// If no custom hook is registered, the default hook will be returned.
+#[unstable(feature = "metadata_violation_hook", issue = "none")]
+pub fn take_metadata_violation_hook() -> fn(&MetadataViolation<'_>) -> ViolationAction {
+    let hook = METADATA_VIOLATION_HOOK.swap(ptr::null_mut(), Ordering::SeqCst);
+    if hook.is_null() { default_metadata_violation_hook } else { unsafe { mem::transmute(hook) } }
+}
+
+// The policy of the default hook, as `MetadataViolationPolicy as u8 + 1`, or 0
+// for the built-in policy.
+static DEFAULT_POLICY: AtomicU8 = AtomicU8::new(0);
+
+fn default_metadata_violation_hook(violation: &MetadataViolation<'_>) -> ViolationAction {
+    let policy = match DEFAULT_POLICY.load(Ordering::Relaxed) {
+        1 => MetadataViolationPolicy::Abort,
+        2 => MetadataViolationPolicy::Panic,
+        3 => MetadataViolationPolicy::LogAndClamp,
+        4 => MetadataViolationPolicy::LogAndContinue,
+        _ => MetadataViolationPolicy::BUILTIN,
+    };
+    policy.hook()(violation)
+}
+
+// Dispatches the violations reported by `core` and `alloc` to the registered hook.
+fn rust_metadata_violation(violation: &MetadataViolation<'_>) -> ViolationAction {
+    let hook = METADATA_VIOLATION_HOOK.load(Ordering::SeqCst);
+    let hook: fn(&MetadataViolation<'_>) -> ViolationAction = if hook.is_null() {
+        default_metadata_violation_hook
+    } else {
+        unsafe { mem::transmute(hook) }
+    };
+    hook(violation)
+}
+
+/// Reads the default policy from the environment, and routes metadata violations
+/// through the hook.
+///
+/// Called once during runtime initialization.
+pub(crate) fn init_metadata_violation_hook() {
+    let policy = crate::env::var("RUST_METADATA_VIOLATION").ok();
+    if let Some(policy) = policy.as_deref().and_then(MetadataViolationPolicy::from_name) {
+        DEFAULT_POLICY.store(policy as u8 + 1, Ordering::Relaxed);
+    }
+    metadata_update::set_metadata_violation_handler(rust_metadata_violation);
+}
+
 #[cfg(not(test))]
 #[doc(hidden)]
 #[allow(unused_attributes)]
@@ -413,4 +718,10 @@ pub mod __default_lib_allocator {
             System.alloc_zeroed(layout)
         }
     }
+
+    #[rustc_std_internal_symbol]
+    pub unsafe extern "C" fn __rdl_allocation_layout(ptr: *mut u8) -> usize {
+        // SAFETY: see the guarantees expected by `GlobalAlloc::allocation_layout`.
+        unsafe { System.allocation_layout(ptr).map_or(0, |layout| layout.size()) }
+    }
 }
@@ -0,0 +1,183 @@
+//! The runtime of the metadata sanitizer, `-Zsanitizer=metadata`.
+//!
+//! Code compiled with the sanitizer reports every typed store of a protected
+//! metadata field, like the length of a `Vec`, to `__rust_metadata_sanitizer_store`,
+//! which keeps the stored value in a shadow table keyed by the address of the
+//! field. Every load of such a field is reported to `__rust_metadata_sanitizer_load`
+//! along with the loaded value. If the field has a shadow copy and the value does
+//! not match it, the field was overwritten behind the back of its type, and the
+//! process aborts through [`metadata_corruption`].
+//!
+//! Locals that die, blocks the global allocator releases or moves, and the
+//! fields behind the mutable pointers passed to code that is not instrumented,
+//! which may write them without reporting it, are dropped from the table through
+//! `__rust_metadata_sanitizer_forget`. Typed copies move their entries along
+//! through `__rust_metadata_sanitizer_copy`. Fields without a shadow copy are not
+//! checked, as they were last written by code that is not instrumented.
+//!
+//! The table is an [`AddrTable`] of `alloc`, allocated from [`System`] on first
+//! use, so that the runtime never goes through instrumented code itself, and has
+//! a fixed size. Stores that no longer fit in it are dropped, and the fields they
+//! stored are not checked.
+//!
+//! [`metadata_corruption`]: core::ptr::metadata_update::metadata_corruption
+
+use alloc_crate::metadata_table::{AddrEntry, AddrTable, Locked};
+
+use crate::alloc::{GlobalAlloc, Layout, System};
+use crate::mem;
+use crate::ptr::metadata_update::metadata_corruption;
+use crate::slice;
+use crate::str;
+
+#[cfg(test)]
+mod tests;
+
+// The number of entries of the table, a power of two.
+const CAPACITY: usize = 1 << 16;
+
+const WORD: usize = mem::size_of::<usize>();
+
+/// Checks the `value` loaded out of the field `field` of a `type_name` at
+/// `addr` against its shadow copy. The names are passed as pointers to, and
+/// lengths of, strings the compiler emitted.
+#[rustc_std_internal_symbol]
+pub unsafe extern "C" fn __rust_metadata_sanitizer_load(
+    addr: usize,
+    value: usize,
+    type_name: usize,
+    type_name_len: usize,
+    field: usize,
+    field_len: usize,
+) {
+    // The lock is released before aborting, so that the panic hook can still
+    // use collections.
+    if let Some(Some(shadow)) = with_table(|table| get(table, addr)) {
+        if shadow != value {
+            // SAFETY: the compiler passes string constants.
+            let (type_name, field) =
+                unsafe { (static_str(type_name, type_name_len), static_str(field, field_len)) };
+            metadata_corruption(type_name, field, value, Some(shadow));
+        }
+    }
+}
+
+/// Records that `value` was stored to the protected field at `addr`.
+#[rustc_std_internal_symbol]
+pub extern "C" fn __rust_metadata_sanitizer_store(addr: usize, value: usize) {
+    with_table(|table| insert(table, addr, value));
+}
+
+/// Drops the fields in the `size` bytes at `addr` from the table.
+#[rustc_std_internal_symbol]
+pub extern "C" fn __rust_metadata_sanitizer_forget(addr: usize, size: usize) {
+    with_table(|table| forget(table, addr, size));
+}
+
+/// Moves the fields in the `size` bytes at `src` to `dst`. The ranges may
+/// overlap.
+#[rustc_std_internal_symbol]
+pub extern "C" fn __rust_metadata_sanitizer_copy(dst: usize, src: usize, size: usize) {
+    with_table(|table| copy(table, dst, src, size));
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must describe a string that lives for the whole program.
+unsafe fn static_str(ptr: usize, len: usize) -> &'static str {
+    unsafe { str::from_utf8_unchecked(slice::from_raw_parts(ptr as *const u8, len)) }
+}
+
+// The table, null until the first use.
+struct TablePtr(*mut Table);
+
+// SAFETY: the table is only accessed with the lock held.
+unsafe impl Send for TablePtr {}
+
+static SHADOW: Locked<TablePtr> = Locked::new(TablePtr(crate::ptr::null_mut()));
+
+/// Runs `f` on the table with the lock held, or returns `None` if the table
+/// cannot be allocated.
+fn with_table<R>(f: impl FnOnce(&mut Table) -> R) -> Option<R> {
+    SHADOW.with(|table| {
+        if table.0.is_null() {
+            // SAFETY: the layout has a non-zero size, and all zeros is an
+            // empty table.
+            table.0 = unsafe { System.alloc_zeroed(Layout::new::<Table>()) }.cast();
+        }
+        // SAFETY: the table was allocated above, is never freed, and is only
+        // accessed with the lock held.
+        unsafe { table.0.as_mut() }.map(f)
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    // The address of the field, 0 if the entry is free.
+    addr: usize,
+    value: usize,
+}
+
+impl AddrEntry for Entry {
+    const FREE: Self = Entry { addr: 0, value: 0 };
+
+    fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+type Table = AddrTable<Entry, CAPACITY>;
+
+/// Returns the value stored at `addr`.
+fn get<const N: usize>(table: &AddrTable<Entry, N>, addr: usize) -> Option<usize> {
+    table.get(addr).map(|entry| entry.value)
+}
+
+/// Records that `value` was stored at `addr`.
+fn insert<const N: usize>(table: &mut AddrTable<Entry, N>, addr: usize, value: usize) {
+    // Stores that do not fit are dropped.
+    let _ = table.insert(Entry { addr, value });
+}
+
+/// Drops the fields in the `size` bytes at `addr`.
+fn forget<const N: usize>(table: &mut AddrTable<Entry, N>, addr: usize, size: usize) {
+    if table.is_empty() {
+        return;
+    }
+    // Large blocks, like the buffers the allocator releases, have more words
+    // than the table has entries.
+    if size / WORD > N {
+        table.retain(|entry| entry.addr.wrapping_sub(addr) >= size);
+        return;
+    }
+    for offset in (0..size).step_by(WORD) {
+        table.remove(addr.wrapping_add(offset));
+    }
+}
+
+/// Moves the fields in the `size` bytes at `src` to `dst`.
+fn copy<const N: usize>(table: &mut AddrTable<Entry, N>, dst: usize, src: usize, size: usize) {
+    if table.is_empty() || dst == src {
+        return;
+    }
+    let step = |table: &mut AddrTable<Entry, N>, offset: usize| {
+        let dst = dst.wrapping_add(offset);
+        match table.remove(src.wrapping_add(offset)) {
+            Some(entry) => insert(table, dst, entry.value),
+            None => {
+                table.remove(dst);
+            }
+        }
+    };
+    // Like `memmove`, the words are moved starting from the end the
+    // destination overlaps, so that none is overwritten before it was moved.
+    if dst > src {
+        for offset in (0..size).step_by(WORD).rev() {
+            step(table, offset);
+        }
+    } else {
+        for offset in (0..size).step_by(WORD) {
+            step(table, offset);
+        }
+    }
+}
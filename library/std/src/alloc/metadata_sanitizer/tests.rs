@@ -0,0 +1,52 @@
+use super::*;
+
+#[test]
+fn stores_and_forgets() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    insert(&mut table, 0x1000, 3);
+    insert(&mut table, 0x1008, 8);
+    insert(&mut table, 0x1000, 4);
+    assert_eq!(get(&table, 0x1000), Some(4));
+    assert_eq!(get(&table, 0x1008), Some(8));
+    assert_eq!(get(&table, 0x1010), None);
+
+    forget(&mut table, 0x1000, 2 * WORD);
+    assert_eq!(get(&table, 0x1000), None);
+    assert_eq!(get(&table, 0x1008), None);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn forgets_blocks_larger_than_the_table() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    let block = 0x10000;
+    insert(&mut table, block, 1);
+    insert(&mut table, block + 100 * WORD, 2);
+    insert(&mut table, block + 200 * WORD, 3);
+
+    forget(&mut table, block, 200 * WORD);
+    assert_eq!(get(&table, block), None);
+    assert_eq!(get(&table, block + 100 * WORD), None);
+    assert_eq!(get(&table, block + 200 * WORD), Some(3));
+}
+
+#[test]
+fn copies_overlapping_ranges() {
+    let mut table = AddrTable::<Entry, 16>::new();
+    let (a, b, c) = (0x2000, 0x2000 + WORD, 0x2000 + 2 * WORD);
+    insert(&mut table, a, 1);
+    insert(&mut table, c, 3);
+    // Shifts the three words up by one, like `Vec::insert` at the front.
+    copy(&mut table, b, a, 3 * WORD);
+    assert_eq!(get(&table, a), None);
+    assert_eq!(get(&table, b), Some(1));
+    assert_eq!(get(&table, c), None);
+    assert_eq!(get(&table, c + WORD), Some(3));
+
+    // And back down, like `Vec::remove`.
+    copy(&mut table, a, b, 3 * WORD);
+    assert_eq!(get(&table, a), Some(1));
+    assert_eq!(get(&table, b), None);
+    assert_eq!(get(&table, c), Some(3));
+    assert_eq!(table.len(), 2);
+}
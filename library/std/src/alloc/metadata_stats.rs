@@ -0,0 +1,183 @@
+//! Counters of metadata updates, dumped as JSON at exit.
+//!
+//! With a standard library built with `--cfg metadata_update_stats`, setting
+//! `RUST_METAUPDATE_STATS` counts, per type and per call site, how often
+//! metadata updates were synchronized, how often synchronization failed, and
+//! how often the protection window was opened and closed. The counts are
+//! written to the file the variable names when the process exits, or to
+//! standard error if it is `-`.
+//!
+//! The recorder runs in the middle of metadata updates, so it must not update
+//! any protected metadata itself: the sites live in a fixed table, and sites
+//! beyond its capacity are only counted as dropped.
+
+use crate::env;
+use crate::ffi::OsString;
+use crate::fmt::{self, Write as _};
+use crate::fs::File;
+use crate::io::{self, Write as _};
+use crate::panic::Location;
+use crate::ptr::metadata_update::{self, MetadataEvent, METADATA_STATS};
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{Mutex, PoisonError};
+
+#[cfg(test)]
+mod tests;
+
+const MAX_SITES: usize = 1024;
+const EVENTS: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Site {
+    type_name: &'static str,
+    location: &'static Location<'static>,
+    counts: [u64; EVENTS],
+}
+
+struct Table {
+    sites: [Option<Site>; MAX_SITES],
+    // The events of the sites that did not fit.
+    dropped: u64,
+}
+
+static TABLE: Mutex<Table> = Mutex::new(Table { sites: [None; MAX_SITES], dropped: 0 });
+// Cleared before the dump, which allocates, so that it does not count itself.
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// Starts counting if the statistics were asked for.
+///
+/// Called once during runtime initialization.
+pub(crate) fn init() {
+    if METADATA_STATS && env::var_os("RUST_METAUPDATE_STATS").is_some() {
+        RECORDING.store(true, Ordering::Relaxed);
+        metadata_update::set_metadata_stats_recorder(record);
+    }
+}
+
+fn record(event: MetadataEvent, type_name: &'static str, location: &'static Location<'static>) {
+    if !RECORDING.load(Ordering::Relaxed) {
+        return;
+    }
+    let event = match event {
+        MetadataEvent::Synchronize => 0,
+        MetadataEvent::Failure => 1,
+        MetadataEvent::Enable => 2,
+        MetadataEvent::Disable => 3,
+    };
+    let mut table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+    // Open addressing, keyed by the site. `Location::caller` hands out the same
+    // location for every call from the same site.
+    let hash = (location as *const Location<'_>).addr() ^ type_name.as_ptr().addr();
+    let start = hash.wrapping_mul(0x9e37_79b9) % MAX_SITES;
+    for i in 0..MAX_SITES {
+        let slot = &mut table.sites[(start + i) % MAX_SITES];
+        match slot {
+            Some(site) if site.type_name == type_name && site.location == location => {
+                site.counts[event] += 1;
+                return;
+            }
+            Some(_) => {}
+            None => {
+                let mut counts = [0; EVENTS];
+                counts[event] = 1;
+                *slot = Some(Site { type_name, location, counts });
+                return;
+            }
+        }
+    }
+    table.dropped += 1;
+}
+
+/// Writes the counts to the destination named by `RUST_METAUPDATE_STATS`, if
+/// any.
+///
+/// Called once during runtime cleanup.
+pub(crate) fn dump() {
+    if !METADATA_STATS || !RECORDING.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    let Some(destination) = env::var_os("RUST_METAUPDATE_STATS") else { return };
+    let json = {
+        let table = TABLE.lock().unwrap_or_else(PoisonError::into_inner);
+        to_json(&table)
+    };
+    // The process is exiting, there is nobody to report a failed dump to.
+    let _ = write_to(destination, json);
+}
+
+fn write_to(destination: OsString, json: String) -> io::Result<()> {
+    if destination == "-" {
+        io::stderr().write_all(json.as_bytes())
+    } else {
+        File::create(destination)?.write_all(json.as_bytes())
+    }
+}
+
+fn to_json(table: &Table) -> String {
+    let mut sites: Vec<&Site> = table.sites.iter().flatten().collect();
+    sites.sort_by_key(|site| {
+        (site.type_name, site.location.file(), site.location.line(), site.location.column())
+    });
+    // `sites` is sorted by type, so the sites of a type are adjacent.
+    let mut types: Vec<(&str, [u64; EVENTS])> = Vec::new();
+    for site in &sites {
+        match types.last_mut() {
+            Some((type_name, counts)) if *type_name == site.type_name => {
+                counts.iter_mut().zip(site.counts).for_each(|(count, n)| *count += n);
+            }
+            _ => types.push((site.type_name, site.counts)),
+        }
+    }
+
+    let mut json = String::new();
+    json.push_str("{\n  \"types\": [");
+    for (i, (type_name, counts)) in types.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let _ =
+            write!(json, "{separator}\n    {{\"type\": {}, {}}}", Json(type_name), Counts(counts));
+    }
+    json.push_str("\n  ],\n  \"sites\": [");
+    for (i, site) in sites.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let _ = write!(
+            json,
+            "{separator}\n    {{\"type\": {}, \"location\": {}, {}}}",
+            Json(site.type_name),
+            Json(&site.location.to_string()),
+            Counts(&site.counts),
+        );
+    }
+    let _ = write!(json, "\n  ],\n  \"dropped\": {}\n}}\n", table.dropped);
+    json
+}
+
+struct Counts<'a>(&'a [u64; EVENTS]);
+
+impl fmt::Display for Counts<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [synchronize, failure, enable, disable] = self.0;
+        write!(
+            f,
+            "\"synchronize\": {synchronize}, \"failure\": {failure}, \
+             \"enable\": {enable}, \"disable\": {disable}"
+        )
+    }
+}
+
+/// A string as a JSON string literal.
+struct Json<'a>(&'a str);
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
@@ -0,0 +1,29 @@
+use super::*;
+
+#[test]
+fn sites_are_aggregated_per_type() {
+    let first = Location::caller();
+    let second = Location::caller();
+    let mut table = Table { sites: [None; MAX_SITES], dropped: 2 };
+    table.sites[7] = Some(Site { type_name: "Vec<u8>", location: first, counts: [3, 1, 2, 2] });
+    table.sites[1] = Some(Site { type_name: "Vec<u8>", location: second, counts: [1, 0, 1, 1] });
+    table.sites[4] = Some(Site { type_name: "Rc<str>", location: first, counts: [5, 0, 0, 0] });
+
+    let json = to_json(&table);
+    assert!(json.contains(
+        "{\"type\": \"Rc<str>\", \"synchronize\": 5, \"failure\": 0, \"enable\": 0, \"disable\": 0}"
+    ));
+    assert!(json.contains(
+        "{\"type\": \"Vec<u8>\", \"synchronize\": 4, \"failure\": 1, \"enable\": 3, \"disable\": 3}"
+    ));
+    let location = format!("\"location\": \"{}\"", second.to_string().replace('\\', "\\\\"));
+    assert!(json.contains(&location));
+    assert!(json.contains("\"dropped\": 2"));
+    // Types, and the sites of each type, are listed in order.
+    assert!(json.find("Rc<str>").unwrap() < json.find("Vec<u8>").unwrap());
+}
+
+#[test]
+fn strings_are_escaped() {
+    assert_eq!(Json("a\"b\\c\nd").to_string(), "\"a\\\"b\\\\c\\u000ad\"");
+}
@@ -0,0 +1,598 @@
+//! Type dependent isolation (TDI): allocations segregated by type family.
+//!
+//! A type family is a type constructor with its generic arguments erased, so
+//! `Type1<i32>` and `Type1<f32>` belong to the same family. Every family that
+//! allocates often enough gets a heap of its own, whose memory is only ever
+//! reused for allocations of that family. A dangling or repacked pointer can
+//! therefore only ever alias values of its own family.
+//!
+//! Giving every family its own heap from the first allocation would waste a
+//! segment per size class for every type that allocates once. Families start
+//! out in the shared heap, and are only promoted to a heap of their own after
+//! `PROMOTE_AFTER` allocations. Once `MAX_FAMILIES` families have been
+//! promoted, the others stay in the shared heap for good, which keeps the
+//! memory overhead bounded.
+//!
+//! Heaps carve blocks of power of two size classes out of `SEGMENT_SIZE`
+//! segments aligned to their size, and every segment serves a single heap and
+//! size class. Its header records the heap and counts the blocks in use, so a
+//! block is always freed back to the heap it came from, and a segment whose
+//! blocks are all free is returned to [`System`], unless it is the last one
+//! its heap and class have room in. Allocations larger than the largest size
+//! class go straight to [`System`], and are recorded in a table of a fixed
+//! size, so that [`GlobalAlloc::allocation_layout`] can tell them apart from
+//! blocks of the heaps. Once the table is full, the layouts of blocks it does
+//! not know are not reported until the large allocations that did not fit are
+//! freed again.
+//!
+//! The heaps are guarded by a spin lock rather than a `Mutex`, which allocates
+//! on some platforms and could not be used by a global allocator.
+
+use alloc_crate::metadata_table::{AddrEntry, AddrTable};
+
+use super::{AllocError, Allocator, GlobalAlloc, Layout, System};
+#[cfg(bootstrap)]
+use crate::any::type_name;
+use crate::cell::UnsafeCell;
+use crate::hint;
+use crate::mem;
+use crate::ops::{Deref, DerefMut};
+use crate::ptr::{self, NonNull};
+use crate::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod tests;
+
+const SEGMENT_SIZE: usize = 32 * 1024;
+// Every block must be able to hold the free list link.
+const MIN_CLASS: usize = 16;
+const MAX_CLASS: usize = 2048;
+const CLASSES: usize = (MAX_CLASS.trailing_zeros() - MIN_CLASS.trailing_zeros() + 1) as usize;
+const PROMOTE_AFTER: usize = 32;
+const MAX_FAMILIES: usize = 64;
+// The index of the shared heap, the others belong to the promoted families.
+const SHARED_HEAP: usize = 0;
+// The number of entries of the table of large allocations, a power of two.
+const LARGE_CAPACITY: usize = 1024;
+
+/// An allocator that serves every allocation from the heap of the type family
+/// of the allocated type.
+///
+/// The type is the one [`Allocator::allocate_for`] is called with, which is
+/// the element type for `Box` and `Vec`:
+///
+/// ```rust
+/// #![feature(allocator_api, tdi_alloc)]
+///
+/// use std::alloc::Tdi;
+///
+/// // The buffer is allocated from the heap of `u32`, the box from the heap of
+/// // `String`.
+/// let mut v: Vec<u32, Tdi> = Vec::new_in(Tdi);
+/// v.push(1);
+/// let b = Box::new_in(String::from("tdi"), Tdi);
+/// assert_eq!(*b, "tdi");
+/// ```
+///
+/// Untyped allocations, through [`Allocator::allocate`], come from the shared
+/// heap, and growing or shrinking a block keeps it in the heap it is in. Types
+/// without an allocator parameter, like `Rc`, can be routed through the shared
+/// heap by making [`TdiGlobal`] the global allocator.
+#[unstable(feature = "tdi_alloc", issue = "none")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Tdi;
+
+#[unstable(feature = "allocator_api", issue = "32838")]
+unsafe impl Allocator for Tdi {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        allocate(None, layout)
+    }
+
+    #[inline]
+    fn allocate_for<T: ?Sized>(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        allocate(Some(family_of::<T>()), layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed_for<T: ?Sized>(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = self.allocate_for::<T>(layout)?;
+        // SAFETY: `allocate_for` returns a valid memory block
+        unsafe { ptr.as_non_null_ptr().as_ptr().write_bytes(0, ptr.len()) }
+        Ok(ptr)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            // SAFETY: `layout` is non-zero in size,
+            // other conditions must be upheld by the caller
+            unsafe { dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { reallocate(ptr, old_layout, new_layout) }
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe {
+            let new_ptr = reallocate(ptr, old_layout, new_layout)?;
+            let tail = new_ptr.as_mut_ptr().add(old_layout.size());
+            tail.write_bytes(0, new_ptr.len() - old_layout.size());
+            Ok(new_ptr)
+        }
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { reallocate(ptr, old_layout, new_layout) }
+    }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: NonNull<u8>) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { allocation_layout(ptr.as_ptr()) }
+    }
+}
+
+/// The entry point of the TDI allocator for untyped allocations.
+///
+/// The global allocator interface carries no type, so everything allocated
+/// through `TdiGlobal` lives in the shared heap:
+///
+/// ```rust
+/// #![feature(tdi_alloc)]
+///
+/// use std::alloc::TdiGlobal;
+///
+/// #[global_allocator]
+/// static GLOBAL: TdiGlobal = TdiGlobal;
+///
+/// fn main() {
+///     let rc = std::rc::Rc::new(5);
+///     assert_eq!(*rc, 5);
+/// }
+/// ```
+#[unstable(feature = "tdi_alloc", issue = "none")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TdiGlobal;
+
+#[unstable(feature = "tdi_alloc", issue = "none")]
+unsafe impl GlobalAlloc for TdiGlobal {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { alloc(None, layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: *mut u8) -> Option<Layout> {
+        // SAFETY: all conditions must be upheld by the caller
+        unsafe { allocation_layout(ptr) }
+    }
+}
+
+/// Returns the type family of `T`.
+#[cfg(not(bootstrap))]
+fn family_of<T: ?Sized>() -> u64 {
+    crate::intrinsics::type_family_id::<T>()
+}
+
+/// Returns the type family of `T`: its type name up to the generic arguments,
+/// hashed with FNV-1a.
+#[cfg(bootstrap)]
+fn family_of<T: ?Sized>() -> u64 {
+    let name = type_name::<T>();
+    let constructor = name.split('<').next().unwrap_or(name);
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in constructor.bytes() {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Returns the index of the size class serving `layout`, or `None` if it is
+/// too large for the heaps.
+fn class_of(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(MIN_CLASS).checked_next_power_of_two()?;
+    if size > MAX_CLASS {
+        return None;
+    }
+    Some((size.trailing_zeros() - MIN_CLASS.trailing_zeros()) as usize)
+}
+
+fn segment_layout() -> Layout {
+    // SAFETY: the segment size is a non-zero power of two
+    unsafe { Layout::from_size_align_unchecked(SEGMENT_SIZE, SEGMENT_SIZE) }
+}
+
+/// The header of a segment, at its start.
+struct Segment {
+    heap: usize,
+    class: usize,
+    // The number of blocks handed out and not freed yet.
+    live: usize,
+    free: *mut FreeBlock,
+    // The unused tail of the segment.
+    bump: *mut u8,
+    // The neighbours of the segment in the list of segments of its heap and
+    // class that have room.
+    prev: *mut Segment,
+    next: *mut Segment,
+}
+
+impl Segment {
+    fn is_full(&self) -> bool {
+        self.free.is_null() && self.bump.addr() & (SEGMENT_SIZE - 1) == 0
+    }
+}
+
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+/// The segments of a heap that have room for blocks of a size class.
+#[derive(Clone, Copy)]
+struct SizeClass {
+    available: *mut Segment,
+}
+
+impl SizeClass {
+    unsafe fn link(&mut self, segment: *mut Segment) {
+        unsafe {
+            (*segment).prev = ptr::null_mut();
+            (*segment).next = self.available;
+            if !self.available.is_null() {
+                (*self.available).prev = segment;
+            }
+        }
+        self.available = segment;
+    }
+
+    unsafe fn unlink(&mut self, segment: *mut Segment) {
+        unsafe {
+            let Segment { prev, next, .. } = *segment;
+            if prev.is_null() {
+                self.available = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Heap([SizeClass; CLASSES]);
+
+impl Heap {
+    const EMPTY: Heap = Heap([SizeClass { available: ptr::null_mut() }; CLASSES]);
+
+    unsafe fn alloc(&mut self, index: usize, class: usize) -> *mut u8 {
+        let block_size = MIN_CLASS << class;
+        let state = &mut self.0[class];
+        if state.available.is_null() {
+            let segment = unsafe { System.alloc(segment_layout()) };
+            if segment.is_null() {
+                return ptr::null_mut();
+            }
+            // The header takes up the first blocks, the rest stay aligned to their size.
+            let first = (mem::size_of::<Segment>() + block_size - 1) & !(block_size - 1);
+            unsafe {
+                let segment = segment.cast::<Segment>();
+                segment.write(Segment {
+                    heap: index,
+                    class,
+                    live: 0,
+                    free: ptr::null_mut(),
+                    bump: segment.cast::<u8>().add(first),
+                    prev: ptr::null_mut(),
+                    next: ptr::null_mut(),
+                });
+                state.link(segment);
+            }
+        }
+        unsafe {
+            let segment = &mut *state.available;
+            let block = if !segment.free.is_null() {
+                let block = segment.free;
+                segment.free = (*block).next;
+                block.cast()
+            } else {
+                let block = segment.bump;
+                segment.bump = block.add(block_size);
+                block
+            };
+            segment.live += 1;
+            if segment.is_full() {
+                state.unlink(segment);
+            }
+            block
+        }
+    }
+
+    unsafe fn dealloc(&mut self, segment: *mut Segment, ptr: *mut u8, class: usize) {
+        let state = &mut self.0[class];
+        unsafe {
+            let was_full = (*segment).is_full();
+            let block = ptr.cast::<FreeBlock>();
+            block.write(FreeBlock { next: (*segment).free });
+            (*segment).free = block;
+            (*segment).live -= 1;
+            if was_full {
+                state.link(segment);
+            }
+            // Keep the segment if it is the only one with room, so that a
+            // block freed and allocated in turn does not map it every time.
+            if (*segment).live == 0 && !(state.available == segment && (*segment).next.is_null()) {
+                state.unlink(segment);
+                System.dealloc(segment.cast(), segment_layout());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Family {
+    id: u64,
+    allocs: usize,
+}
+
+/// A live allocation too large for the heaps.
+#[derive(Clone, Copy)]
+struct Large {
+    // The address of the block, 0 if the slot is free.
+    addr: usize,
+    size: usize,
+    align: usize,
+}
+
+impl AddrEntry for Large {
+    const FREE: Self = Large { addr: 0, size: 0, align: 0 };
+
+    fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+struct Registry {
+    families: [Family; MAX_FAMILIES],
+    len: usize,
+    // `heaps[SHARED_HEAP]` is the shared heap, `heaps[i + 1]` belongs to `families[i]`.
+    heaps: [Heap; MAX_FAMILIES + 1],
+    large: AddrTable<Large, LARGE_CAPACITY>,
+    // The number of live large allocations that did not fit in `large`.
+    untracked: usize,
+}
+
+// SAFETY: the pointers are only ever dereferenced while the lock is held.
+unsafe impl Send for Registry {}
+
+impl Registry {
+    /// Returns the heap `family` currently allocates from, and counts the allocation.
+    fn heap_for(&mut self, family: Option<u64>) -> usize {
+        let Some(id) = family else {
+            return SHARED_HEAP;
+        };
+        let index = match self.families[..self.len].iter().position(|family| family.id == id) {
+            Some(index) => index,
+            None if self.len < MAX_FAMILIES => {
+                self.families[self.len] = Family { id, allocs: 0 };
+                self.len += 1;
+                self.len - 1
+            }
+            None => return SHARED_HEAP,
+        };
+        let family = &mut self.families[index];
+        family.allocs = family.allocs.saturating_add(1);
+        if family.allocs > PROMOTE_AFTER { index + 1 } else { SHARED_HEAP }
+    }
+}
+
+/// A lock that spins instead of parking.
+///
+/// Unlike `Mutex`, it never allocates, so it can guard the heaps of a global
+/// allocator. The critical sections are a few pointer updates long.
+struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: the value is only ever accessed through a guard, which is exclusive.
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    const fn new(value: T) -> Self {
+        SpinLock { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    fn lock(&self) -> SpinLockGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+        SpinLockGuard { lock: self }
+    }
+}
+
+struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+static REGISTRY: SpinLock<Registry> = SpinLock::new(Registry {
+    families: [Family { id: 0, allocs: 0 }; MAX_FAMILIES],
+    len: 0,
+    heaps: [Heap::EMPTY; MAX_FAMILIES + 1],
+    large: AddrTable::new(),
+    untracked: 0,
+});
+
+fn allocate(family: Option<u64>, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    match layout.size() {
+        0 => Ok(NonNull::slice_from_raw_parts(layout.dangling(), 0)),
+        // SAFETY: `layout` is non-zero in size
+        size => unsafe {
+            let ptr = NonNull::new(alloc(family, layout)).ok_or(AllocError)?;
+            Ok(NonNull::slice_from_raw_parts(ptr, size))
+        },
+    }
+}
+
+/// Allocates a block for `layout`, from the heap of `family` if it has been
+/// promoted, and from the shared heap otherwise.
+///
+/// # Safety
+///
+/// `layout` must be non-zero in size.
+unsafe fn alloc(family: Option<u64>, layout: Layout) -> *mut u8 {
+    let Some(class) = class_of(layout) else {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let large = Large { addr: ptr.addr(), size: layout.size(), align: layout.align() };
+            let mut registry = REGISTRY.lock();
+            if registry.large.insert(large).is_err() {
+                registry.untracked += 1;
+            }
+        }
+        return ptr;
+    };
+    let mut registry = REGISTRY.lock();
+    let heap = registry.heap_for(family);
+    unsafe { registry.heaps[heap].alloc(heap, class) }
+}
+
+/// Frees a block back to the heap it was allocated from.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `alloc` for the same `layout`.
+unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
+    let Some(class) = class_of(layout) else {
+        let mut registry = REGISTRY.lock();
+        if registry.large.remove(ptr.addr()).is_none() {
+            registry.untracked = registry.untracked.saturating_sub(1);
+        }
+        drop(registry);
+        return unsafe { System.dealloc(ptr, layout) };
+    };
+    let segment = ptr.map_addr(|addr| addr & !(SEGMENT_SIZE - 1)).cast::<Segment>();
+    let mut registry = REGISTRY.lock();
+    unsafe {
+        let heap = (*segment).heap;
+        registry.heaps[heap].dealloc(segment, ptr, class)
+    }
+}
+
+/// Moves a block to one of `new_layout`, in the heap it was allocated from.
+///
+/// # Safety
+///
+/// `ptr` must have been allocated by `Tdi` with `old_layout`, which may be
+/// zero in size.
+unsafe fn reallocate(
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+) -> Result<NonNull<[u8]>, AllocError> {
+    if new_layout.size() == 0 {
+        unsafe { Tdi.deallocate(ptr, old_layout) };
+        return Ok(NonNull::slice_from_raw_parts(new_layout.dangling(), 0));
+    }
+    let new_ptr = match (class_of(old_layout), class_of(new_layout)) {
+        (Some(_), Some(class)) if old_layout.size() != 0 => {
+            let segment = ptr.as_ptr().map_addr(|addr| addr & !(SEGMENT_SIZE - 1));
+            let mut registry = REGISTRY.lock();
+            unsafe {
+                let heap = (*segment.cast::<Segment>()).heap;
+                registry.heaps[heap].alloc(heap, class)
+            }
+        }
+        _ => unsafe { alloc(None, new_layout) },
+    };
+    let new_ptr = NonNull::new(new_ptr).ok_or(AllocError)?;
+    unsafe {
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), len);
+        Tdi.deallocate(ptr, old_layout);
+    }
+    Ok(NonNull::slice_from_raw_parts(new_ptr, new_layout.size()))
+}
+
+/// Returns the layout of the block at `ptr`: the one it was allocated with if
+/// it is too large for the heaps, and its whole size class otherwise.
+///
+/// # Safety
+///
+/// `ptr` must denote a block currently allocated by `alloc`.
+unsafe fn allocation_layout(ptr: *mut u8) -> Option<Layout> {
+    let registry = REGISTRY.lock();
+    if let Some(large) = registry.large.get(ptr.addr()) {
+        return Layout::from_size_align(large.size, large.align).ok();
+    }
+    // A large allocation that did not fit in the table could be mistaken for a
+    // block of a heap, whose segment header it does not have.
+    if registry.untracked > 0 {
+        return None;
+    }
+    let segment = ptr.map_addr(|addr| addr & !(SEGMENT_SIZE - 1)).cast::<Segment>();
+    // SAFETY: `ptr` is a block of a heap, so `segment` is its header. Blocks
+    // are aligned to their size within segments aligned to theirs.
+    let block_size = MIN_CLASS << unsafe { (*segment).class };
+    Layout::from_size_align(block_size, block_size).ok()
+}
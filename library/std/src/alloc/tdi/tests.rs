@@ -0,0 +1,109 @@
+use super::*;
+
+struct Family1<T>(T);
+struct Family2<T>(T);
+struct Promoted;
+struct Other;
+struct Element(u64);
+struct Recycled;
+
+fn heap_of(block: *const u8) -> usize {
+    let segment = block.map_addr(|addr| addr & !(SEGMENT_SIZE - 1)).cast::<Segment>();
+    unsafe { (*segment).heap }
+}
+
+#[test]
+fn generic_instantiations_share_a_family() {
+    assert_eq!(family_of::<Family1<i32>>(), family_of::<Family1<f32>>());
+    assert_ne!(family_of::<Family1<i32>>(), family_of::<Family2<i32>>());
+}
+
+#[test]
+fn promoted_families_do_not_share_memory() {
+    let layout = Layout::new::<[u64; 4]>();
+    let blocks: crate::vec::Vec<_> =
+        (0..=PROMOTE_AFTER).map(|_| Tdi.allocate_for::<Promoted>(layout).unwrap()).collect();
+    let last = blocks.last().unwrap().as_non_null_ptr();
+    unsafe { Tdi.deallocate(last, layout) };
+
+    // The block freed by the promoted family is only handed out to it again.
+    let block = Tdi.allocate_for::<Other>(layout).unwrap().as_non_null_ptr();
+    assert_ne!(block, last);
+    let again = Tdi.allocate_for::<Promoted>(layout).unwrap().as_non_null_ptr();
+    assert_eq!(again, last);
+
+    unsafe {
+        Tdi.deallocate(block, layout);
+        for block in &blocks {
+            Tdi.deallocate(block.as_non_null_ptr(), layout);
+        }
+    }
+}
+
+#[test]
+fn collections_allocate_from_the_family_of_their_elements() {
+    let mut boxes: crate::vec::Vec<_> = crate::vec::Vec::new();
+    for i in 0..PROMOTE_AFTER {
+        boxes.push(crate::boxed::Box::new_in(Element(i as u64), Tdi));
+    }
+    let mut elements: crate::vec::Vec<Element, Tdi> = crate::vec::Vec::new_in(Tdi);
+    elements.push(Element(0));
+
+    // The buffer comes from the heap of `Element` now that it is promoted, and
+    // growing it keeps it there.
+    let heap = heap_of(elements.as_ptr().cast());
+    assert_ne!(heap, SHARED_HEAP);
+    elements.reserve_exact(16);
+    assert_eq!(heap_of(elements.as_ptr().cast()), heap);
+}
+
+#[test]
+fn empty_segments_are_returned() {
+    let layout = Layout::new::<[u64; 8]>();
+    let class = class_of(layout).unwrap();
+    let per_segment = SEGMENT_SIZE / layout.size();
+    let blocks: crate::vec::Vec<_> = (0..PROMOTE_AFTER + 3 * per_segment)
+        .map(|_| Tdi.allocate_for::<Recycled>(layout).unwrap().as_non_null_ptr())
+        .collect();
+    for &block in &blocks {
+        unsafe { Tdi.deallocate(block, layout) };
+    }
+
+    // Only the segment the last block went back to is kept.
+    let heap = heap_of(blocks.last().unwrap().as_ptr());
+    assert_ne!(heap, SHARED_HEAP);
+    let registry = REGISTRY.lock();
+    let available = registry.heaps[heap].0[class].available;
+    assert!(!available.is_null());
+    unsafe {
+        assert_eq!((*available).live, 0);
+        assert!((*available).next.is_null());
+    }
+}
+
+#[test]
+fn large_allocations_bypass_the_heaps() {
+    let layout = Layout::from_size_align(4 * MAX_CLASS, 8).unwrap();
+    unsafe {
+        let ptr = TdiGlobal.alloc(layout);
+        assert!(!ptr.is_null());
+        ptr.write_bytes(0x5A, layout.size());
+        TdiGlobal.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+fn reports_the_layout_of_blocks() {
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let large = Layout::from_size_align(4 * MAX_CLASS, 16).unwrap();
+    unsafe {
+        let block = TdiGlobal.alloc(small);
+        assert_eq!(TdiGlobal.allocation_layout(block), Layout::from_size_align(32, 32).ok());
+        TdiGlobal.dealloc(block, small);
+
+        let block = TdiGlobal.alloc(large);
+        assert_eq!(TdiGlobal.allocation_layout(block), Some(large));
+        TdiGlobal.dealloc(block, large);
+        assert!(REGISTRY.lock().large.get(block.addr()).is_none());
+    }
+}
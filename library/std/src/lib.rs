@@ -239,6 +239,7 @@
 #![feature(allow_internal_unstable)]
 #![feature(box_syntax)]
 #![feature(c_unwind)]
+#![cfg_attr(not(bootstrap), feature(cfg_metadata_protection))]
 #![feature(cfg_target_thread_local)]
 #![feature(concat_idents)]
 #![feature(const_mut_refs)]
@@ -295,6 +296,8 @@
 #![feature(is_some_and)]
 #![feature(maybe_uninit_slice)]
 #![feature(maybe_uninit_write_slice)]
+#![feature(metadata_update)]
+#![feature(metadata_update_internals)]
 #![feature(nonnull_slice_from_raw_parts)]
 #![feature(panic_can_unwind)]
 #![feature(panic_info_message)]
@@ -317,12 +320,16 @@
 //
 // Library features (alloc):
 #![feature(alloc_layout_extra)]
+#![feature(allocation_layout)]
 #![feature(allocator_api)]
 #![feature(get_mut_unchecked)]
+#![feature(liballoc_internals)]
 #![feature(map_try_insert)]
+#![feature(metadata_compartment)]
 #![feature(new_uninit)]
 #![feature(thin_box)]
 #![feature(try_reserve_kind)]
+#![feature(typed_allocation)]
 #![feature(vec_into_raw_parts)]
 #![feature(slice_concat_trait)]
 //
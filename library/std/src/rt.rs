@@ -95,6 +95,9 @@ macro_rules! rtunwrap {
 unsafe fn init(argc: isize, argv: *const *const u8, sigpipe: u8) {
     unsafe {
         sys::init(argc, argv, sigpipe);
+        crate::alloc::init_metadata_compartment();
+        crate::alloc::init_metadata_violation_hook();
+        crate::alloc::metadata_stats::init();
 
         let main_guard = sys::thread::guard::init();
         // Next, set up the current Thread with the guard information we just
@@ -112,6 +115,7 @@ unsafe fn init(argc: isize, argv: *const *const u8, sigpipe: u8) {
 pub(crate) fn cleanup() {
     static CLEANUP: Once = Once::new();
     CLEANUP.call_once(|| unsafe {
+        crate::alloc::metadata_stats::dump();
         // Flush stdout and disable buffering.
         crate::io::cleanup();
         // SAFETY: Only called once during runtime cleanup.
@@ -50,6 +50,16 @@ unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut
             realloc_fallback(self, ptr, layout, new_size)
         }
     }
+
+    // `malloc_usable_size` accepts the blocks of `posix_memalign` as well as
+    // those of `malloc`, `calloc` and `realloc`.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    unsafe fn allocation_layout(&self, ptr: *mut u8) -> Option<Layout> {
+        let size = libc::malloc_usable_size(ptr as *mut libc::c_void);
+        // The block is aligned to at least the alignment of its address.
+        Layout::from_size_align(size, 1 << ptr.addr().trailing_zeros()).ok()
+    }
 }
 
 cfg_if::cfg_if! {
@@ -0,0 +1,279 @@
+//! Guard page isolated blocks backing `std::alloc::GuardedArena`.
+//!
+//! Every block gets pages of its own, between two guard pages that cannot be
+//! written, and is placed at the end of its pages. A linear overflow out of a
+//! block faults on the guard page right behind it, and an overflow out of any
+//! other allocation, in this allocator or on the ordinary heap, faults on the
+//! guard page in front of it. Neighbouring blocks can therefore not reach each
+//! other, even if one of them is a buffer and the other the header of a `Vec`.
+//!
+//! Blocks that fit in a page are carved out of arenas: runs of `ARENA_PAGES`
+//! pages, aligned to their size, that alternate guard pages and slots of one
+//! page each. The first usable page of an arena holds its bookkeeping, the
+//! others are the slots. An arena is unmapped as soon as its last block is
+//! freed. Larger blocks get a mapping of their own, which is unmapped when the
+//! block is freed. It is aligned like an arena, and its leading guard page is
+//! read-only rather than inaccessible and holds the length of the block's
+//! pages. A block in the first usable page of such a window is therefore a
+//! large one, and the layout of any block can be told from its address alone.
+//!
+//! Isolation costs a page per block, so this is meant for headers, not for
+//! bulk data. `-Z metadata-protection=guard` is the exception: it allocates
+//! every `RcBox` and `ArcInner` here, with the value next to the counters.
+
+use crate::alloc::Layout;
+use crate::ptr;
+use crate::sync::Mutex;
+use crate::sys::unix::os::page_size;
+
+#[cfg(test)]
+mod tests;
+
+// Page 0 of an arena is a guard page, page 1 its bookkeeping, and from page 2
+// on guard pages and slots alternate, ending with a guard page.
+const ARENA_PAGES: usize = 64;
+const SLOTS: usize = (ARENA_PAGES - 3) / 2;
+
+/// The bookkeeping of an arena, in its first usable page.
+struct Arena {
+    // The neighbours of the arena in the list of arenas with free slots.
+    prev: *mut Arena,
+    next: *mut Arena,
+    // Bit `i` is set if slot `i` is free.
+    free: u32,
+}
+
+const ALL_FREE: u32 = (1 << SLOTS) - 1;
+
+struct Arenas {
+    // The arenas that have a free slot.
+    partial: *mut Arena,
+}
+
+// SAFETY: the arenas are only ever accessed while the mutex is held.
+unsafe impl Send for Arenas {}
+
+static ARENAS: Mutex<Arenas> = Mutex::new(Arenas { partial: ptr::null_mut() });
+
+fn arena_len() -> usize {
+    ARENA_PAGES * page_size()
+}
+
+/// Returns the offset of a block of `layout` from the start of `len` bytes of
+/// pages, so that it ends as close to the end as its alignment allows.
+fn block_offset(len: usize, layout: Layout) -> usize {
+    (len - layout.size()) & !(layout.align() - 1)
+}
+
+pub unsafe fn alloc(layout: Layout) -> *mut u8 {
+    // A mapping is only page aligned.
+    if layout.align() > page_size() {
+        return ptr::null_mut();
+    }
+    if layout.size() > page_size() {
+        return unsafe { alloc_large(layout) };
+    }
+    let mut arenas = ARENAS.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe {
+        if arenas.partial.is_null() {
+            let arena = map_arena();
+            if arena.is_null() {
+                return ptr::null_mut();
+            }
+            arenas.partial = arena;
+        }
+        let arena = arenas.partial;
+        let slot = (*arena).free.trailing_zeros() as usize;
+        (*arena).free &= !(1 << slot);
+        if (*arena).free == 0 {
+            unlink(&mut arenas, arena);
+        }
+        slot_page(arena, slot).add(block_offset(page_size(), layout))
+    }
+}
+
+pub unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
+    if layout.size() > page_size() {
+        return unsafe { dealloc_large(ptr, layout) };
+    }
+    let base = ptr.map_addr(|addr| addr & !(arena_len() - 1));
+    let slot = ((ptr.addr() - base.addr()) / page_size() - 3) / 2;
+    let mut arenas = ARENAS.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe {
+        let arena = base.add(page_size()).cast::<Arena>();
+        if (*arena).free == 0 {
+            link(&mut arenas, arena);
+        }
+        (*arena).free |= 1 << slot;
+        if (*arena).free == ALL_FREE {
+            unlink(&mut arenas, arena);
+            libc::munmap(base.cast(), arena_len());
+        }
+    }
+}
+
+// Returns the first page of slot `slot` of `arena`.
+unsafe fn slot_page(arena: *mut Arena, slot: usize) -> *mut u8 {
+    // The bookkeeping is in page 1, the slots in pages 3, 5, ...
+    unsafe { arena.cast::<u8>().add((2 + 2 * slot) * page_size()) }
+}
+
+unsafe fn link(arenas: &mut Arenas, arena: *mut Arena) {
+    unsafe {
+        (*arena).prev = ptr::null_mut();
+        (*arena).next = arenas.partial;
+        if !arenas.partial.is_null() {
+            (*arenas.partial).prev = arena;
+        }
+    }
+    arenas.partial = arena;
+}
+
+unsafe fn unlink(arenas: &mut Arenas, arena: *mut Arena) {
+    unsafe {
+        let Arena { prev, next, .. } = *arena;
+        if prev.is_null() {
+            arenas.partial = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+}
+
+/// Maps a fresh arena, with all of its slots free, and returns its
+/// bookkeeping. Returns null on failure.
+unsafe fn map_arena() -> *mut Arena {
+    let page = page_size();
+    let len = arena_len();
+    unsafe {
+        // Over-allocate, so that an aligned arena can be cut out of the mapping.
+        let mapping = libc::mmap(
+            ptr::null_mut(),
+            2 * len,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if mapping == libc::MAP_FAILED {
+            return ptr::null_mut();
+        }
+        let mapping = mapping.cast::<u8>();
+        let base = mapping.map_addr(|addr| (addr + len - 1) & !(len - 1));
+        let head = base.addr() - mapping.addr();
+        if head != 0 {
+            libc::munmap(mapping.cast(), head);
+        }
+        libc::munmap(base.add(len).cast(), len - head);
+
+        // Everything but the bookkeeping and the slots stays a guard page.
+        for page_index in (1..ARENA_PAGES - 1).step_by(2) {
+            let usable = base.add(page_index * page);
+            if libc::mprotect(usable.cast(), page, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+                libc::munmap(base.cast(), len);
+                return ptr::null_mut();
+            }
+        }
+        let arena = base.add(page).cast::<Arena>();
+        arena.write(Arena { prev: ptr::null_mut(), next: ptr::null_mut(), free: ALL_FREE });
+        arena
+    }
+}
+
+unsafe fn alloc_large(layout: Layout) -> *mut u8 {
+    match round_to_pages(layout.size()) {
+        Some(len) => unsafe {
+            let usable = map_guarded(len);
+            if usable.is_null() { usable } else { usable.add(block_offset(len, layout)) }
+        },
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns the layout of the block at `ptr`, up to the guard page behind it.
+pub unsafe fn allocation_layout(ptr: *mut u8) -> Option<Layout> {
+    let page = page_size();
+    let base = ptr.map_addr(|addr| addr & !(arena_len() - 1));
+    let end = if ptr.addr() - base.addr() < 2 * page {
+        // SAFETY: a large block, whose mapping starts at `base` with a
+        // read-only page holding the length of its usable pages.
+        base.addr() + page + unsafe { base.cast::<usize>().read() }
+    } else {
+        // A block in a slot, which ends with its page.
+        (ptr.addr() | (page - 1)) + 1
+    };
+    // The block is aligned to at least the alignment of its address, and
+    // alignments above the page size are not supported.
+    let align = 1 << ptr.addr().trailing_zeros().min(page.trailing_zeros());
+    Layout::from_size_align(end - ptr.addr(), align).ok()
+}
+
+unsafe fn dealloc_large(ptr: *mut u8, layout: Layout) {
+    let page = page_size();
+    // `alloc_large` succeeded for this layout, so the length can not overflow,
+    // and the block starts in the first usable page.
+    let len = round_to_pages(layout.size()).unwrap() + 2 * page;
+    let usable = ptr.map_addr(|addr| addr & !(page - 1));
+    unsafe {
+        libc::munmap(usable.sub(page).cast(), len);
+    }
+}
+
+fn round_to_pages(size: usize) -> Option<usize> {
+    let page = page_size();
+    let len = size.checked_add(page - 1)? & !(page - 1);
+    // Leave room for the guard pages.
+    len.checked_add(2 * page)?;
+    Some(len)
+}
+
+/// Maps `len` bytes, which must be a multiple of the page size, with a guard
+/// page on either side, starting at an address aligned like an arena. The
+/// leading guard page is read-only and holds `len`, the trailing one is
+/// `PROT_NONE`. Returns null on failure.
+unsafe fn map_guarded(len: usize) -> *mut u8 {
+    let page = page_size();
+    let align = arena_len();
+    let total = len + 2 * page;
+    unsafe {
+        // Over-allocate, so that an aligned start can be cut out of the mapping.
+        let Some(mapped) = total.checked_add(align) else {
+            return ptr::null_mut();
+        };
+        let mapping = libc::mmap(
+            ptr::null_mut(),
+            mapped,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if mapping == libc::MAP_FAILED {
+            return ptr::null_mut();
+        }
+        let mapping = mapping.cast::<u8>();
+        let base = mapping.map_addr(|addr| (addr + align - 1) & !(align - 1));
+        let head = base.addr() - mapping.addr();
+        if head != 0 {
+            libc::munmap(mapping.cast(), head);
+        }
+        libc::munmap(base.add(total).cast(), align - head);
+
+        let usable = base.add(page);
+        if libc::mprotect(base.cast(), page, libc::PROT_READ | libc::PROT_WRITE) != 0
+            || libc::mprotect(usable.cast(), len, libc::PROT_READ | libc::PROT_WRITE) != 0
+        {
+            libc::munmap(base.cast(), total);
+            return ptr::null_mut();
+        }
+        base.cast::<usize>().write(len);
+        if libc::mprotect(base.cast(), page, libc::PROT_READ) != 0 {
+            libc::munmap(base.cast(), total);
+            return ptr::null_mut();
+        }
+        usable
+    }
+}
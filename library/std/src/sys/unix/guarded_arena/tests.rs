@@ -0,0 +1,86 @@
+use super::*;
+
+#[test]
+fn small_blocks_end_at_their_page_and_are_reused() {
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    unsafe {
+        let a = alloc(layout);
+        let b = alloc(layout);
+        assert!(!a.is_null() && !b.is_null());
+        assert_eq!(a.addr() % 8, 0);
+        assert_eq!((a.addr() + layout.size()) % page_size(), 0);
+        // Every block has a page to itself, with a guard page in between.
+        assert!(a.addr().abs_diff(b.addr()) >= 2 * page_size());
+        a.write_bytes(0xAA, layout.size());
+        dealloc(a, layout);
+        assert_eq!(alloc(layout), a);
+        dealloc(a, layout);
+        dealloc(b, layout);
+    }
+}
+
+#[test]
+fn arenas_are_unmapped_when_empty() {
+    let layout = Layout::from_size_align(64, 16).unwrap();
+    unsafe {
+        let blocks: crate::vec::Vec<_> = (0..SLOTS + 1).map(|_| alloc(layout)).collect();
+        assert!(blocks.iter().all(|b| !b.is_null()));
+        for &block in &blocks {
+            dealloc(block, layout);
+        }
+        // Other tests may share arenas, so only check that the partial list
+        // is consistent.
+        let arenas = ARENAS.lock().unwrap();
+        let mut arena = arenas.partial;
+        while !arena.is_null() {
+            assert_ne!((*arena).free, 0);
+            assert_ne!((*arena).free, ALL_FREE);
+            arena = (*arena).next;
+        }
+    }
+}
+
+#[test]
+fn large_allocations_get_their_own_mapping() {
+    let layout = Layout::from_size_align(3 * page_size() + 1, 16).unwrap();
+    unsafe {
+        let p = alloc(layout);
+        assert!(!p.is_null());
+        assert_eq!(p.addr() % 16, 0);
+        // The block ends as close to its guard page as the alignment allows.
+        let end = p.addr() + layout.size();
+        assert!(end % page_size() == 0 || page_size() - end % page_size() < 16);
+        p.write_bytes(0x55, layout.size());
+        dealloc(p, layout);
+    }
+}
+
+#[test]
+fn over_aligned_allocations_fail() {
+    let layout = Layout::from_size_align(16, 2 * page_size()).unwrap();
+    unsafe {
+        assert!(alloc(layout).is_null());
+    }
+}
+
+#[test]
+fn reports_the_layout_of_blocks() {
+    let page = page_size();
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let large = Layout::from_size_align(3 * page + 1, 16).unwrap();
+    unsafe {
+        let a = alloc(small);
+        let layout = allocation_layout(a).unwrap();
+        assert_eq!(layout.size(), small.size());
+        assert!(layout.align() >= small.align());
+        dealloc(a, small);
+
+        // The block may start up to an alignment early, but ends at its pages.
+        let b = alloc(large);
+        let layout = allocation_layout(b).unwrap();
+        assert!(layout.size() >= large.size() && layout.size() < large.size() + 16);
+        assert_eq!((b.addr() + layout.size()) % page, 0);
+        assert!(layout.align() >= large.align());
+        dealloc(b, large);
+    }
+}
@@ -0,0 +1,269 @@
+//! Write protection for `MetaUpdate` metadata.
+//!
+//! In `mpk` mode of `-Z metadata-protection`, which the library sees as
+//! `cfg(metadata_protection = "mpk")`, on x86_64 Linux, if both the CPU and
+//! the kernel support memory protection keys (CPUID reports OSPKE), a
+//! protection key is allocated at startup. The side tables of `alloc` that
+//! record metadata, i.e. the records of the reference counts of `Rc` and
+//! `Arc`, are tagged with it through `pkey_mprotect`, as is every region
+//! handed out by `alloc_region`. The key is write-disabled in PKRU, and the
+//! metadata write window of `MetaUpdate::enable_metadata_update` and
+//! `disable_metadata_update` flips its write-disable bit with WRPKRU. PKRU is
+//! per thread, but threads inherit it from the thread that spawned them, so
+//! setting it once on the main thread is enough.
+//!
+//! Everywhere else, or when no key can be had, the same binary runs in
+//! checks-only mode: regions are plain mappings, the window does nothing, and
+//! metadata updates are only validated through `MetaUpdate::synchronize`.
+//!
+//! In `guard` mode, no key is allocated and every region is surrounded by
+//! inaccessible guard pages instead. In `check`, `shadow` and `none` mode,
+//! regions are plain mappings, and in `none` mode updates are not even
+//! validated. `shadow` mode is implemented by `alloc`, which mirrors the
+//! headers of its collections.
+
+#![allow(dead_code)] // the region helpers are only used by metadata allocators
+
+use crate::ptr;
+use crate::sys::os;
+
+#[cfg(test)]
+mod tests;
+
+// Whether the library was built to protect metadata with protection keys.
+const USE_PKEYS: bool = cfg!(metadata_protection = "mpk");
+
+// Whether the library was built to surround metadata regions with guard pages.
+const USE_GUARD_PAGES: bool = cfg!(metadata_protection = "guard");
+
+/// How metadata updates are protected in this process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Metadata regions are tagged with a protection key that is only
+    /// writable while a metadata update is in progress.
+    Mpk,
+    /// Metadata updates are validated, and metadata regions are surrounded
+    /// by guard pages.
+    Guard,
+    /// Metadata updates are validated, and the headers of collections are
+    /// compared with a shadow copy when they are read.
+    Shadow,
+    /// Metadata updates are validated, but the memory is not write protected.
+    ChecksOnly,
+    /// Metadata updates are neither validated nor write protected.
+    Unprotected,
+}
+
+// SAFETY: must be called only once during runtime initialization, before
+// any other thread is spawned.
+pub unsafe fn init() {
+    if USE_PKEYS {
+        unsafe { imp::init() }
+    }
+}
+
+/// Returns how metadata updates are protected. Before `init` has run, this is
+/// never `Mode::Mpk`.
+pub fn mode() -> Mode {
+    mode_with(imp::pkey())
+}
+
+// How metadata updates are protected with the protection key `pkey`, if any.
+fn mode_with(pkey: Option<i32>) -> Mode {
+    if !core::ptr::metadata_update::METADATA_CHECKS {
+        Mode::Unprotected
+    } else if USE_GUARD_PAGES {
+        Mode::Guard
+    } else if core::ptr::metadata_update::METADATA_SHADOW {
+        Mode::Shadow
+    } else if pkey.is_some() {
+        Mode::Mpk
+    } else {
+        Mode::ChecksOnly
+    }
+}
+
+/// Maps a fresh, page aligned region of `len` bytes for metadata, tagged with
+/// the metadata protection key if there is one, or between two guard pages in
+/// `Mode::Guard`. Returns null on failure.
+///
+/// The region starts out write protected in `Mode::Mpk`, so it must only be
+/// written to while the metadata write window is open.
+pub fn alloc_region(len: usize) -> *mut u8 {
+    alloc_region_with(len, imp::pkey())
+}
+
+// Maps a region as `alloc_region` does, tagged with `pkey` if there is one.
+fn alloc_region_with(len: usize, pkey: Option<i32>) -> *mut u8 {
+    let guard = guard_len();
+    let Some(mapping_len) = len.checked_add(2 * guard) else {
+        return ptr::null_mut();
+    };
+    unsafe {
+        let mapping = libc::mmap(
+            ptr::null_mut(),
+            mapping_len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if mapping == libc::MAP_FAILED {
+            return ptr::null_mut();
+        }
+        let region = mapping.cast::<u8>().add(guard);
+        if guard != 0
+            && (libc::mprotect(mapping, guard, libc::PROT_NONE) != 0
+                || libc::mprotect(region.add(len).cast(), guard, libc::PROT_NONE) != 0)
+        {
+            libc::munmap(mapping, mapping_len);
+            return ptr::null_mut();
+        }
+        if let Some(pkey) = pkey {
+            if !imp::tag_region(region.cast(), len, pkey) {
+                libc::munmap(mapping, mapping_len);
+                return ptr::null_mut();
+            }
+        }
+        region
+    }
+}
+
+/// Unmaps a region returned by `alloc_region`.
+///
+/// # Safety
+///
+/// `region` and `len` must describe a region returned by `alloc_region` that
+/// has not been freed yet.
+pub unsafe fn free_region(region: *mut u8, len: usize) {
+    let guard = guard_len();
+    unsafe {
+        libc::munmap(region.sub(guard).cast(), len + 2 * guard);
+    }
+}
+
+// The length of the guard mapping on either side of a region.
+fn guard_len() -> usize {
+    if USE_GUARD_PAGES { os::page_size() } else { 0 }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(miri)))]
+mod imp {
+    use crate::arch::asm;
+    use crate::arch::x86_64::{__cpuid, __cpuid_count};
+    use crate::ptr::metadata_update;
+    use crate::sync::atomic::{AtomicI32, Ordering};
+    use alloc_crate::metadata_table;
+
+    // The bits of a key in PKRU are `PKEY_DISABLE_* << (2 * pkey)`.
+    const PKEY_DISABLE_ACCESS: u32 = 0x1;
+    const PKEY_DISABLE_WRITE: u32 = 0x2;
+
+    // The metadata protection key, or -1 if there is none.
+    static PKEY: AtomicI32 = AtomicI32::new(-1);
+
+    // SAFETY: must be called only once during runtime initialization, before
+    // any other thread is spawned.
+    pub unsafe fn init() {
+        // If the CPU or the kernel lack pkeys support, all keys are taken, or
+        // the tables cannot be tagged, we stay in checks-only mode.
+        let Some(pkey) = alloc_key() else { return };
+        let (tables, len) = metadata_table::protected_region();
+        if unsafe { !tag_region(tables.cast(), len, pkey) } {
+            unsafe { free_key(pkey) };
+            return;
+        }
+        PKEY.store(pkey, Ordering::Relaxed);
+        close_window();
+        // SAFETY: no other thread exists yet, and no window is open.
+        unsafe { metadata_update::set_metadata_write_hooks(open_window, close_window) };
+    }
+
+    pub fn pkey() -> Option<i32> {
+        let pkey = PKEY.load(Ordering::Relaxed);
+        if pkey < 0 { None } else { Some(pkey) }
+    }
+
+    /// Allocates a protection key, with full access for now.
+    pub fn alloc_key() -> Option<i32> {
+        if !ospke_enabled() {
+            return None;
+        }
+        let pkey = unsafe { libc::syscall(libc::SYS_pkey_alloc, 0, 0) };
+        if pkey < 0 { None } else { Some(pkey as i32) }
+    }
+
+    pub unsafe fn free_key(pkey: i32) {
+        unsafe { libc::syscall(libc::SYS_pkey_free, pkey) };
+    }
+
+    pub unsafe fn tag_region(region: *mut libc::c_void, len: usize, pkey: i32) -> bool {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        unsafe { libc::syscall(libc::SYS_pkey_mprotect, region, len, prot, pkey) == 0 }
+    }
+
+    // CPUID.(EAX=07H, ECX=0H):ECX.OSPKE[bit 4] is set if the CPU supports
+    // protection keys and the OS has enabled them, i.e. RDPKRU and WRPKRU are
+    // usable.
+    fn ospke_enabled() -> bool {
+        // SAFETY: CPUID is available on every x86_64 CPU.
+        unsafe { __cpuid(0).eax >= 7 && __cpuid_count(7, 0).ecx & (1 << 4) != 0 }
+    }
+
+    /// Allows or disallows writes to memory tagged with `pkey` on the current
+    /// thread. Reads are always allowed: access is disabled for every key but
+    /// the default one while a signal handler runs, which the handler may
+    /// well need the window for.
+    ///
+    /// # Safety
+    ///
+    /// `pkey` must be a key returned by `alloc_key`.
+    pub unsafe fn set_writable(pkey: i32, writable: bool) {
+        let shift = 2 * pkey as u32;
+        let pkru = unsafe { rdpkru() } & !((PKEY_DISABLE_ACCESS | PKEY_DISABLE_WRITE) << shift);
+        let pkru = if writable { pkru } else { pkru | PKEY_DISABLE_WRITE << shift };
+        unsafe { wrpkru(pkru) }
+    }
+
+    // Only installed as hooks once the key was allocated.
+    fn open_window() {
+        unsafe { set_writable(PKEY.load(Ordering::Relaxed), true) }
+    }
+
+    fn close_window() {
+        unsafe { set_writable(PKEY.load(Ordering::Relaxed), false) }
+    }
+
+    // Only called once OSPKE has been detected.
+    pub unsafe fn rdpkru() -> u32 {
+        let pkru;
+        unsafe {
+            asm!("rdpkru", in("ecx") 0, out("eax") pkru, out("edx") _,
+                 options(nomem, nostack, preserves_flags));
+        }
+        pkru
+    }
+
+    // Only called once OSPKE has been detected. Not `nomem`: changing PKRU
+    // changes which memory accesses fault, so the compiler must not move memory
+    // accesses across it.
+    unsafe fn wrpkru(pkru: u32) {
+        unsafe {
+            asm!("wrpkru", in("eax") pkru, in("ecx") 0, in("edx") 0,
+                 options(nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64", not(miri))))]
+mod imp {
+    pub unsafe fn init() {}
+
+    pub fn pkey() -> Option<i32> {
+        None
+    }
+
+    pub unsafe fn tag_region(_region: *mut libc::c_void, _len: usize, _pkey: i32) -> bool {
+        false
+    }
+}
@@ -0,0 +1,57 @@
+use super::*;
+use alloc_crate::metadata_table::protected_region;
+
+#[test]
+fn checks_only_without_a_key() {
+    // Without a key, e.g. on a CPU or kernel without pkeys support, metadata
+    // updates are still validated.
+    if !USE_GUARD_PAGES && core::ptr::metadata_update::METADATA_SHADOW {
+        assert_eq!(mode_with(None), Mode::Shadow);
+    } else if !USE_GUARD_PAGES && core::ptr::metadata_update::METADATA_CHECKS {
+        assert_eq!(mode_with(None), Mode::ChecksOnly);
+    }
+    assert_ne!(mode_with(None), Mode::Mpk);
+
+    // Regions are plain mappings, writable without opening a window.
+    let len = os::page_size();
+    let region = alloc_region_with(len, None);
+    assert!(!region.is_null());
+    unsafe {
+        region.write_bytes(0xAA, len);
+        assert_eq!(*region.add(len - 1), 0xAA);
+        free_region(region, len);
+    }
+}
+
+#[test]
+fn protected_tables_fill_whole_pages() {
+    let (tables, len) = protected_region();
+    assert_eq!(tables.addr() % os::page_size(), 0);
+    assert_eq!(len % os::page_size(), 0);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(miri)))]
+fn write_inside_the_window() {
+    // Skipped where the CPU or the kernel do not support protection keys.
+    let Some(pkey) = imp::alloc_key() else { return };
+    let write_disabled = || unsafe { imp::rdpkru() } & (2u32 << (2 * pkey)) != 0;
+    let len = os::page_size();
+    let region = alloc_region_with(len, Some(pkey));
+    assert!(!region.is_null());
+    unsafe {
+        imp::set_writable(pkey, false);
+        assert!(write_disabled());
+        imp::set_writable(pkey, true);
+        assert!(!write_disabled());
+        region.write(7);
+        imp::set_writable(pkey, false);
+        assert!(write_disabled());
+        // Reads do not need the window.
+        assert_eq!(region.read(), 7);
+        // Leave the thread as it found the key before giving it back.
+        imp::set_writable(pkey, true);
+        free_region(region, len);
+        imp::free_key(pkey);
+    }
+}
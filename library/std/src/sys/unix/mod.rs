@@ -18,6 +18,8 @@
 pub mod fd;
 pub mod fs;
 pub mod futex;
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+pub mod guarded_arena;
 pub mod io;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub mod kernel_copy;
@@ -25,6 +27,7 @@
 mod l4re;
 pub mod locks;
 pub mod memchr;
+pub mod metadata_protection;
 #[cfg(not(target_os = "l4re"))]
 pub mod net;
 #[cfg(target_os = "l4re")]
@@ -68,6 +71,7 @@ pub unsafe fn init(argc: isize, argv: *const *const u8, sigpipe: u8) {
 
     stack_overflow::init();
     args::init(argc, argv);
+    metadata_protection::init();
 
     // Normally, `thread::spawn` will call `Thread::set_name` but since this thread
     // already exists, we have to call it ourselves. We only do this on macos
@@ -1994,6 +1994,27 @@ pub fn cargo(
             rustflags.arg("-Ccontrol-flow-guard");
         }
 
+        // Select how the standard library protects the metadata of smart pointers and
+        // collections. The stage0 compiler does not know the flag, and its standard library is
+        // only used to build the stage 1 compiler anyway.
+        if mode == Mode::Std && compiler.stage >= 1 {
+            rustflags.arg(&format!(
+                "-Zmetadata-protection={}",
+                self.config.rust_metadata_protection.as_str()
+            ));
+        }
+
+        // Count metadata updates in the standard library, see `RUST_METAUPDATE_STATS`.
+        if mode == Mode::Std && self.config.rust_metadata_update_stats {
+            rustflags.arg("--cfg=metadata_update_stats");
+        }
+
+        // Track the ownership of raw allocations in the standard library, see
+        // `alloc::metadata_ownership`.
+        if mode == Mode::Std && self.config.rust_metadata_ownership_checks {
+            rustflags.arg("--cfg=metadata_ownership_checks");
+        }
+
         // For `cargo doc` invocations, make rustdoc print the Rust version into the docs
         // This replaces spaces with newlines because RUSTDOCFLAGS does not
         // support arguments with regular spaces. Hopefully someday Cargo will
@@ -157,6 +157,9 @@ pub struct Config {
     pub rust_profile_use: Option<String>,
     pub rust_profile_generate: Option<String>,
     pub rust_lto: RustcLto,
+    pub rust_metadata_protection: MetadataProtection,
+    pub rust_metadata_update_stats: bool,
+    pub rust_metadata_ownership_checks: bool,
     pub llvm_profile_use: Option<String>,
     pub llvm_profile_generate: bool,
     pub llvm_libunwind_default: Option<LlvmLibunwind>,
@@ -340,6 +343,44 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
     }
 }
 
+/// Metadata protection mode the standard library is built with.
+#[derive(Default, Clone, Copy)]
+pub enum MetadataProtection {
+    None,
+    #[default]
+    Check,
+    Mpk,
+    Guard,
+    Shadow,
+}
+
+impl MetadataProtection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetadataProtection::None => "none",
+            MetadataProtection::Check => "check",
+            MetadataProtection::Mpk => "mpk",
+            MetadataProtection::Guard => "guard",
+            MetadataProtection::Shadow => "shadow",
+        }
+    }
+}
+
+impl std::str::FromStr for MetadataProtection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(MetadataProtection::None),
+            "check" => Ok(MetadataProtection::Check),
+            "mpk" => Ok(MetadataProtection::Mpk),
+            "guard" => Ok(MetadataProtection::Guard),
+            "shadow" => Ok(MetadataProtection::Shadow),
+            _ => Err(format!("Invalid value for metadata protection: {}", s)),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TargetSelection {
     pub triple: Interned<String>,
@@ -747,6 +788,9 @@ struct Rust {
         // ignored; this is set from an env var set by bootstrap.py
         download_rustc: Option<StringOrBool> = "download-rustc",
         lto: Option<String> = "lto",
+        metadata_protection: Option<String> = "metadata-protection",
+        metadata_update_stats: Option<bool> = "metadata-update-stats",
+        metadata_ownership_checks: Option<bool> = "metadata-ownership-checks",
     }
 }
 
@@ -1199,6 +1243,13 @@ pub fn parse(args: &[String]) -> Config {
                 .as_deref()
                 .map(|value| RustcLto::from_str(value).unwrap())
                 .unwrap_or_default();
+            config.rust_metadata_protection = rust
+                .metadata_protection
+                .as_deref()
+                .map(|value| MetadataProtection::from_str(value).unwrap())
+                .unwrap_or_default();
+            set(&mut config.rust_metadata_update_stats, rust.metadata_update_stats);
+            set(&mut config.rust_metadata_ownership_checks, rust.metadata_ownership_checks);
         } else {
             config.rust_profile_use = flags.rust_profile_use;
             config.rust_profile_generate = flags.rust_profile_generate;
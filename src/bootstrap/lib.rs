@@ -207,6 +207,18 @@ pub unsafe fn setup(_build: &mut crate::Build) {}
     (Some(Mode::Std), "no_sync", None),
     (Some(Mode::Std), "freebsd12", None),
     (Some(Mode::Std), "backtrace_in_libstd", None),
+    // Set by `-Zmetadata-protection`, which the stage0 compiler does not know.
+    (Some(Mode::Std), "metadata_protection", Some(&["none", "check", "mpk", "guard", "shadow"])),
+    // Set by `rust.metadata-update-stats`.
+    (Some(Mode::Std), "metadata_update_stats", None),
+    // Set by `rust.metadata-ownership-checks`.
+    (Some(Mode::Std), "metadata_ownership_checks", None),
+    // Passed with `--cfg` to select the default metadata violation policy of std.
+    (
+        Some(Mode::Std),
+        "metadata_violation_policy",
+        Some(&["abort", "panic", "log-and-clamp", "log-and-continue"]),
+    ),
     /* Extra values not defined in the built-in targets yet, but used in std */
     (Some(Mode::Std), "target_env", Some(&["libnx"])),
     (Some(Mode::Std), "target_os", Some(&["watchos"])),
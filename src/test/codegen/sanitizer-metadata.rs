@@ -0,0 +1,89 @@
+// Checks that `-Zsanitizer=metadata` reports the stores and loads of protected fields to its
+// runtime, that `#[no_sanitize(metadata)]` only turns off the checks of the loads, and that
+// calls which may write protected fields without reporting it drop their shadow copies.
+//
+// only-x86_64
+// only-linux
+// compile-flags: -Zsanitizer=metadata -Copt-level=0
+
+#![crate_type = "lib"]
+#![feature(metadata_update, no_sanitize, rustc_attrs)]
+
+use std::ptr::metadata_update::{MetaUpdate, MetadataField};
+
+#[derive(Clone, Copy)]
+pub enum CounterField {
+    Count,
+}
+
+impl MetadataField for CounterField {
+    const FIELDS: &'static [Self] = &[CounterField::Count];
+
+    fn name(self) -> &'static str {
+        "count"
+    }
+}
+
+pub struct Counter {
+    #[rustc_protected_metadata]
+    count: usize,
+    limit: usize,
+}
+
+impl MetaUpdate for Counter {
+    type Field = CounterField;
+
+    fn synchronize(&self, _field: CounterField, old: usize, new: usize) -> bool {
+        old == self.count && new <= self.limit
+    }
+}
+
+impl Counter {
+    // CHECK: ; sanitizer_metadata::Counter::set
+    // CHECK: call void @__rust_metadata_sanitizer_store
+    pub fn set(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    // CHECK: ; sanitizer_metadata::Counter::count
+    // CHECK: call void @__rust_metadata_sanitizer_load
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // CHECK: ; sanitizer_metadata::Counter::limit
+    // CHECK-NOT: call void @__rust_metadata_sanitizer_load
+    // CHECK: ret
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // CHECK: ; sanitizer_metadata::Counter::count_unchecked
+    // CHECK-NOT: call void @__rust_metadata_sanitizer_load
+    // CHECK: ret
+    #[no_sanitize(metadata)]
+    pub fn count_unchecked(&self) -> usize {
+        self.count
+    }
+}
+
+// CHECK: ; sanitizer_metadata::replace
+// CHECK: call void @__rust_metadata_sanitizer_store
+#[no_sanitize(metadata)]
+pub fn replace(counter: &mut Counter, limit: usize) {
+    *counter = Counter { count: 0, limit };
+}
+
+// CHECK: ; sanitizer_metadata::reset_through
+// CHECK: call void @__rust_metadata_sanitizer_forget
+// CHECK: call void %
+pub fn reset_through(reset: fn(&mut Counter), counter: &mut Counter) {
+    reset(counter);
+}
+
+// CHECK: ; sanitizer_metadata::set_locally
+// CHECK-NOT: call void @__rust_metadata_sanitizer_forget
+// CHECK: call {{.*}}Counter3set
+pub fn set_locally(counter: &mut Counter) {
+    counter.set(1);
+}
@@ -0,0 +1,3 @@
+#[cfg(not(metadata_protection = "none"))]
+//~^ ERROR `cfg(metadata_protection)` is experimental
+fn main() {}
@@ -0,0 +1,7 @@
+#[metadata_compartment]
+//~^ ERROR the `#[metadata_compartment]` attribute is an experimental feature
+struct Relocated {
+    ids: Vec<u32>,
+}
+
+fn main() {}
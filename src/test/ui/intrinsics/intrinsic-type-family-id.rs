@@ -0,0 +1,17 @@
+// run-pass
+
+#![feature(core_intrinsics, const_type_family_id)]
+
+use std::intrinsics::type_family_id;
+
+struct Wrapper<T>(T);
+
+const WRAPPER_I32: u64 = type_family_id::<Wrapper<i32>>();
+
+fn main() {
+    assert_eq!(WRAPPER_I32, type_family_id::<Wrapper<f32>>());
+    assert_eq!(type_family_id::<Vec<u8>>(), type_family_id::<Vec<String>>());
+    assert_eq!(type_family_id::<Wrapper<&'static str>>(), type_family_id::<Wrapper<&str>>());
+    assert_ne!(type_family_id::<Vec<u8>>(), type_family_id::<Wrapper<u8>>());
+    assert_ne!(type_family_id::<u8>(), type_family_id::<u16>());
+}
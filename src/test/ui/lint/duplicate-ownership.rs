@@ -0,0 +1,62 @@
+// Checks the allocations that the `duplicate_ownership` lint considers to have two owners.
+#![deny(duplicate_ownership)]
+
+use std::mem;
+use std::ptr;
+
+fn box_rebuilt_twice() {
+    let p = Box::into_raw(Box::new(1));
+    let a = unsafe { Box::from_raw(p) };
+    let b = unsafe { Box::from_raw(p) };
+    drop(a);
+    drop(b); //~ ERROR may already have been freed
+}
+
+fn vec_rebuilt_while_owned() {
+    let mut v = vec![1, 2, 3];
+    let p = v.as_mut_ptr();
+    let w = unsafe { Vec::from_raw_parts(p, 3, 3) };
+    drop(w);
+} //~ ERROR may already have been freed
+
+fn consume(v: Vec<u8>) {
+    let _ = v.len();
+}
+
+fn vec_read_and_consumed(v: Vec<u8>) {
+    let w: Vec<u8> = unsafe { ptr::read(&v) };
+    consume(w);
+} //~ ERROR may already have been freed
+
+// Each owner is forgotten or the previous one is gone, so there is only ever one owner.
+fn single_owners() {
+    let mut v = vec![1u8, 2, 3];
+    let p = v.as_mut_ptr();
+    mem::forget(v);
+    let w = unsafe { Vec::from_raw_parts(p, 3, 3) };
+    drop(w);
+
+    for i in 0..3 {
+        let p = Box::into_raw(Box::new(i));
+        let b = unsafe { Box::from_raw(p) };
+        drop(b);
+    }
+}
+
+// Not analyzed at all where the lint is allowed.
+#[allow(duplicate_ownership)]
+fn allowed() {
+    let p = Box::into_raw(Box::new(1));
+    let a = unsafe { Box::from_raw(p) };
+    let b = unsafe { Box::from_raw(p) };
+    drop(a);
+    drop(b);
+}
+
+fn main() {
+    box_rebuilt_twice();
+    vec_rebuilt_while_owned();
+    vec_read_and_consumed(vec![1]);
+    single_owners();
+    allowed();
+}
@@ -0,0 +1,54 @@
+#![feature(vec_into_raw_parts)]
+#![deny(metadata_repacking)]
+
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+use std::rc::Rc;
+
+fn from_into_raw_parts(v: Vec<u8>) -> Vec<u8> {
+    let (ptr, len, cap) = v.into_raw_parts();
+    unsafe { Vec::from_raw_parts(ptr, len, cap) }
+}
+
+fn from_manually_drop(v: Vec<u8>) -> Vec<u8> {
+    let mut v = ManuallyDrop::new(v);
+    let len = v.len();
+    unsafe { Vec::from_raw_parts(v.as_mut_ptr(), len, v.capacity()) }
+}
+
+fn from_mixed_sources(v: Vec<u8>, len: usize) -> Vec<u8> {
+    let (ptr, _, cap) = v.into_raw_parts();
+    unsafe { Vec::from_raw_parts(ptr, len, cap) }
+    //~^ ERROR `Vec<u8>` is rebuilt from raw parts that do not all come from the same source
+}
+
+fn from_live_vec(v: &mut Vec<u8>) -> String {
+    unsafe { String::from_raw_parts(v.as_mut_ptr(), v.len(), v.capacity()) }
+    //~^ ERROR `String` is rebuilt from raw parts that do not all come from the same source
+}
+
+fn overwrite(dst: *mut Rc<u8>, v: Vec<u8>) -> Vec<i8> {
+    unsafe {
+        ptr::write(dst, Rc::new(0));
+        //~^ ERROR `Rc<u8>` is written as a whole, which replaces its metadata
+        mem::transmute(v)
+        //~^ ERROR `Vec<u8>` is written as a whole, which replaces its metadata
+        //~| ERROR `Vec<i8>` is written as a whole, which replaces its metadata
+    }
+}
+
+fn set_len_checked(v: &mut Vec<u8>, len: usize) {
+    v.reserve(len);
+    unsafe { v.set_len(len) };
+    if len <= v.capacity() {
+        unsafe { v.set_len(len) };
+    }
+    unsafe { v.set_len(0) };
+}
+
+fn set_len_unchecked(v: &mut Vec<u8>, len: usize) {
+    unsafe { v.set_len(len) };
+    //~^ ERROR `set_len` is not preceded by a capacity check
+}
+
+fn main() {}
@@ -0,0 +1,26 @@
+#![feature(metadata_update)]
+#![allow(dead_code)]
+
+use std::ptr::metadata_update::MetaUpdate;
+
+#[derive(MetaUpdate)]
+//~^ ERROR `#[derive(MetaUpdate)]` can only be used on structs with named fields
+struct Tuple(usize);
+
+#[derive(MetaUpdate)]
+//~^ ERROR `#[derive(MetaUpdate)]` needs a field annotated with `#[metadata]`
+struct Unmarked {
+    len: usize,
+}
+
+#[derive(MetaUpdate)]
+struct Malformed {
+    #[metadata(size)]
+    //~^ ERROR unexpected `#[metadata]` argument
+    len: usize,
+    #[metadata(count, bound = "8")]
+    //~^ ERROR `bound` is only supported for `len` metadata
+    count: usize,
+}
+
+fn main() {}
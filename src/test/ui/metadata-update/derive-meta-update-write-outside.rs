@@ -0,0 +1,19 @@
+// The `#[metadata]` fields of a type deriving `MetaUpdate` are protected metadata.
+
+#![feature(metadata_update)]
+
+use std::ptr::metadata_update::MetaUpdate;
+
+#[derive(MetaUpdate)]
+struct Arena {
+    cap: usize,
+    #[metadata(len, bound = "self.cap")]
+    len: usize,
+}
+
+fn main() {
+    let mut arena = Arena { cap: 4, len: 0 };
+    arena.len = 8;
+    //~^ ERROR cannot write protected metadata field `len` of `Arena` here
+    arena.cap = 2;
+}
@@ -0,0 +1,74 @@
+// run-pass
+// `#[derive(MetaUpdate)]` synchronizes and protects the fields marked `#[metadata]`.
+
+#![feature(metadata_update, metadata_violation_hook)]
+
+use std::alloc::set_metadata_violation_hook;
+use std::ptr::metadata_update::{MetaUpdate, MetadataField, MetadataViolation, ViolationAction};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+fn clamp(_: &MetadataViolation<'_>) -> ViolationAction {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Clamp
+}
+
+const MAX_LEN: usize = 8;
+
+#[derive(MetaUpdate)]
+struct Handles<T> {
+    items: Vec<T>,
+    #[metadata(len, bound = "self.items.len().min(MAX_LEN)")]
+    live: usize,
+    #[metadata(count)]
+    ref_count: usize,
+    #[metadata]
+    generation: usize,
+}
+
+impl<T> Handles<T> {
+    fn set_live(&mut self, live: usize) {
+        self.live = live;
+    }
+
+    fn set_ref_count(&mut self, ref_count: usize) {
+        self.ref_count = ref_count;
+    }
+
+    fn bump(&mut self) {
+        self.generation += 7;
+    }
+}
+
+fn field<T>(name: &str) -> <Handles<T> as MetaUpdate>::Field {
+    *<Handles<T> as MetaUpdate>::Field::FIELDS.iter().find(|field| field.name() == name).unwrap()
+}
+
+fn main() {
+    let names: Vec<_> =
+        <Handles<u8> as MetaUpdate>::Field::FIELDS.iter().map(|field| field.name()).collect();
+    assert_eq!(names, ["live", "ref_count", "generation"]);
+
+    let mut handles = Handles { items: vec![0u8; 3], live: 0, ref_count: 1, generation: 0 };
+    assert!(handles.synchronize(field::<u8>("live"), 0, 3));
+    assert!(!handles.synchronize(field::<u8>("live"), 0, 4));
+    assert!(handles.synchronize(field::<u8>("ref_count"), 1, 2));
+    assert!(handles.synchronize(field::<u8>("ref_count"), 1, 0));
+    assert!(!handles.synchronize(field::<u8>("ref_count"), 1, 3));
+    assert!(!handles.synchronize(field::<u8>("ref_count"), 0, usize::MAX));
+    assert!(handles.synchronize(field::<u8>("generation"), 0, 100));
+
+    handles.set_live(3);
+    handles.set_ref_count(2);
+    handles.bump();
+    assert_eq!((handles.live, handles.ref_count, handles.generation), (3, 2, 7));
+
+    // Rejected writes are reported, and not made unless the hook lets them through.
+    set_metadata_violation_hook(clamp);
+    handles.set_live(4);
+    assert_eq!(handles.live, 3);
+    handles.set_ref_count(5);
+    assert_eq!(handles.ref_count, 2);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 2);
+}
@@ -0,0 +1,37 @@
+#![feature(metadata_compartment)]
+#![allow(dead_code)]
+
+#[metadata_compartment]
+struct Relocated {
+    ids: Vec<u32>,
+}
+
+#[metadata_compartment(inline)]
+struct Inline {
+    ids: Vec<u32>,
+}
+
+#[metadata_compartment]
+//~^ ERROR `metadata_compartment` attribute should be applied to a struct or to the crate
+fn not_a_struct() {}
+
+#[metadata_compartment(relocate)]
+//~^ ERROR invalid `metadata_compartment` option
+struct UnknownOption {
+    ids: Vec<u32>,
+}
+
+#[metadata_compartment]
+//~^ ERROR `metadata_compartment` attribute conflicts with the `repr` of the struct
+#[repr(C)]
+struct FixedLayout {
+    ids: Vec<u32>,
+}
+
+#[metadata_compartment(inline)]
+#[repr(C)]
+struct InlineFixedLayout {
+    ids: Vec<u32>,
+}
+
+fn main() {}
@@ -0,0 +1,36 @@
+// Relocated fields can not be reached through a raw address, and structs relocating fields can
+// not be constructed in const contexts.
+
+#![feature(metadata_compartment)]
+#![allow(dead_code)]
+
+use std::mem::MaybeUninit;
+use std::ptr::addr_of_mut;
+
+#[metadata_compartment]
+struct Session {
+    ids: Vec<u32>,
+    generation: u64,
+}
+
+#[metadata_compartment]
+struct Pair(Vec<u8>, u64);
+
+fn init(session: &mut MaybeUninit<Session>) {
+    let session = session.as_mut_ptr();
+    unsafe {
+        addr_of_mut!((*session).ids).write(Vec::new());
+        //~^ ERROR cannot take the raw address of field `ids` of `Session`
+        addr_of_mut!((*session).generation).write(0);
+    }
+}
+
+const fn empty() -> Session {
+    Session { ids: Vec::new(), generation: 0 }
+    //~^ ERROR cannot construct `Session` in constant functions
+}
+
+static PAIR: Pair = Pair(Vec::new(), 0);
+//~^ ERROR cannot construct `Pair` in statics
+
+fn main() {}
@@ -0,0 +1,81 @@
+// run-pass
+// `#[metadata_compartment]` moves the smart pointers and collections among the fields of a
+// struct out of line, transparently to the code using the struct.
+
+#![feature(metadata_compartment)]
+
+use std::cell::Cell;
+use std::mem::size_of;
+use std::rc::Rc;
+
+struct DropCounter<'a>(&'a Cell<usize>);
+
+impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[metadata_compartment]
+struct Session<'a> {
+    ids: Vec<u32>,
+    shared: Rc<DropCounter<'a>>,
+    generation: u64,
+}
+
+#[metadata_compartment]
+struct Pair(Vec<u8>, String);
+
+#[metadata_compartment(inline)]
+struct Inline {
+    ids: Vec<u32>,
+    generation: u64,
+}
+
+fn take_ids(ids: Vec<u32>) -> usize {
+    ids.len()
+}
+
+fn main() {
+    assert!(size_of::<Session<'_>>() < size_of::<Vec<u32>>() + size_of::<Rc<()>>() + 8);
+    assert_eq!(size_of::<Inline>(), size_of::<Vec<u32>>() + 8);
+
+    let drops = Cell::new(0);
+    let mut session =
+        Session { ids: vec![1, 2], shared: Rc::new(DropCounter(&drops)), generation: 0 };
+    session.ids.push(3);
+    session.generation += 1;
+    assert_eq!(session.ids, [1, 2, 3]);
+    assert_eq!(session.ids[2], 3);
+    assert_eq!(Rc::strong_count(&session.shared), 1);
+
+    // Moving a field out and initializing it again.
+    let ids = session.ids;
+    assert_eq!(ids, [1, 2, 3]);
+    session.ids = vec![4];
+    assert_eq!(take_ids(session.ids), 1);
+    session.ids = Vec::with_capacity(4);
+    assert!(session.ids.is_empty());
+
+    // Overwriting a field drops its old value.
+    let clone = Rc::clone(&session.shared);
+    session.shared = Rc::new(DropCounter(&drops));
+    assert_eq!(drops.get(), 0);
+    drop(clone);
+    assert_eq!(drops.get(), 1);
+
+    drop(session);
+    assert_eq!(drops.get(), 2);
+
+    // Tuple structs are built by their constructor.
+    let make = Pair;
+    let mut pair = make(vec![1], String::from("a"));
+    pair.1.push('b');
+    let Pair(bytes, text) = pair;
+    assert_eq!((bytes, text.as_str()), (vec![1], "ab"));
+
+    let mut inline = Inline { ids: vec![1], generation: 0 };
+    inline.ids.push(2);
+    inline.generation += 1;
+    assert_eq!(inline.ids, [1, 2]);
+}
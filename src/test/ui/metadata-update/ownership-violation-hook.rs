@@ -0,0 +1,54 @@
+// run-pass
+// needs-unwind
+// Rebuilding an owner from an allocation it may not own is reported through the hook, and then
+// panics whatever the hook answers: there is no owner that could be returned instead.
+
+#![feature(metadata_update, metadata_violation_hook, vec_into_raw_parts)]
+
+use std::alloc::set_metadata_violation_hook;
+use std::panic;
+use std::ptr::metadata_update::{MetadataViolation, ViolationAction, METADATA_OWNERSHIP};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+fn proceed(_: &MetadataViolation<'_>) -> ViolationAction {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Continue
+}
+
+fn main() {
+    // Ownership is only tracked if the standard library was built to.
+    if !METADATA_OWNERSHIP {
+        return;
+    }
+    set_metadata_violation_hook(proceed);
+
+    // One raw pointer was handed out, but two `Rc`s are rebuilt from it.
+    let raw = Rc::into_raw(Rc::new(1));
+    let rc = unsafe { Rc::from_raw(raw) };
+    assert!(panic::catch_unwind(|| unsafe { Rc::from_raw(raw) }).is_err());
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 1);
+    assert_eq!(Rc::strong_count(&rc), 1);
+
+    let raw = Arc::into_raw(Arc::new(2));
+    let arc = unsafe { Arc::from_raw(raw) };
+    assert!(panic::catch_unwind(|| unsafe { Arc::from_raw(raw) }).is_err());
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 2);
+    assert_eq!(Arc::strong_count(&arc), 1);
+
+    // A capacity other than the one the buffer was released with.
+    let (ptr, len, capacity) = vec![3u8; 8].into_raw_parts();
+    let larger = panic::catch_unwind(|| unsafe { Vec::from_raw_parts(ptr, len, capacity * 2) });
+    assert!(larger.is_err());
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 3);
+    drop(unsafe { Vec::from_raw_parts(ptr, len, capacity) });
+
+    // A box of a smaller type than the one that released the allocation.
+    let raw = Box::into_raw(Box::new([4u8; 16]));
+    assert!(panic::catch_unwind(|| unsafe { Box::from_raw(raw.cast::<[u8; 8]>()) }).is_err());
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 4);
+    drop(unsafe { Box::from_raw(raw) });
+}
@@ -0,0 +1,47 @@
+#![feature(rustc_attrs, metadata_update)]
+#![allow(dead_code)]
+
+use std::ptr::metadata_update::{MetaUpdate, MetadataField};
+
+struct Valid {
+    #[rustc_protected_metadata]
+    len: usize,
+}
+
+#[derive(Clone, Copy)]
+enum ValidField {
+    Len,
+}
+
+impl MetadataField for ValidField {
+    const FIELDS: &'static [Self] = &[ValidField::Len];
+
+    fn name(self) -> &'static str {
+        "len"
+    }
+}
+
+impl MetaUpdate for Valid {
+    type Field = ValidField;
+
+    fn synchronize(&self, _: ValidField, old: usize, _: usize) -> bool {
+        old == self.len
+    }
+}
+
+struct Unsynchronized {
+    #[rustc_protected_metadata]
+    //~^ ERROR `rustc_protected_metadata` attribute should be applied to a field of a type implementing `MetaUpdate`
+    len: usize,
+}
+
+#[rustc_protected_metadata]
+//~^ ERROR `rustc_protected_metadata` attribute should be applied to a struct field
+struct NotAField;
+
+enum NotAStruct {
+    Variant(#[rustc_protected_metadata] usize),
+    //~^ ERROR `rustc_protected_metadata` attribute should be applied to a struct field
+}
+
+fn main() {}
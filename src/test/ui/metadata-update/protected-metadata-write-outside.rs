@@ -0,0 +1,62 @@
+// Protected metadata can only be written by inherent and `MetaUpdate` impls of its type.
+
+#![feature(rustc_attrs, metadata_update)]
+
+use std::ptr::metadata_update::{MetaUpdate, MetadataField};
+
+pub struct Counter {
+    #[rustc_protected_metadata]
+    pub count: usize,
+}
+
+#[derive(Clone, Copy)]
+pub enum CounterField {
+    Count,
+}
+
+impl MetadataField for CounterField {
+    const FIELDS: &'static [Self] = &[CounterField::Count];
+
+    fn name(self) -> &'static str {
+        "count"
+    }
+}
+
+impl MetaUpdate for Counter {
+    type Field = CounterField;
+
+    fn synchronize(&self, _: CounterField, _: usize, _: usize) -> bool {
+        true
+    }
+}
+
+impl Counter {
+    fn reset(&mut self) {
+        self.count = 0;
+        let mut bump = || self.count += 1;
+        bump();
+    }
+}
+
+fn set(counter: &mut Counter, count: usize) {
+    counter.count = count;
+    //~^ ERROR cannot write protected metadata field `count` of `Counter` here
+}
+
+trait Bump {
+    fn bump(&mut self);
+}
+
+impl Bump for Counter {
+    fn bump(&mut self) {
+        self.count += 1;
+        //~^ ERROR cannot write protected metadata field `count` of `Counter` here
+    }
+}
+
+fn main() {
+    let mut counter = Counter { count: 0 };
+    counter.reset();
+    set(&mut counter, 2);
+    counter.bump();
+}
@@ -0,0 +1,87 @@
+// run-pass
+// revisions: default none
+//[none] compile-flags: -Zmetadata-protection=none
+// Writes to `#[rustc_protected_metadata]` fields are synchronized with the owner, and rejected
+// writes are only made if the violation hook lets them through. Whether writes are checked
+// follows the mode `core` was built with, not the flag of this crate. Functions marked
+// `#[rustc_synchronizes_metadata]` check their writes themselves, and are not checked again.
+
+#![feature(rustc_attrs, metadata_update, metadata_violation_hook)]
+
+use std::alloc::set_metadata_violation_hook;
+use std::ptr::metadata_update::{MetaUpdate, MetadataField, MetadataViolation, ViolationAction};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+fn clamp(violation: &MetadataViolation<'_>) -> ViolationAction {
+    assert_eq!((violation.field(), violation.old(), violation.new()), ("count", 4, 5));
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Clamp
+}
+
+fn proceed(_: &MetadataViolation<'_>) -> ViolationAction {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Continue
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CounterField {
+    Count,
+}
+
+impl MetadataField for CounterField {
+    const FIELDS: &'static [Self] = &[CounterField::Count];
+
+    fn name(self) -> &'static str {
+        "count"
+    }
+}
+
+struct Counter {
+    #[rustc_protected_metadata]
+    count: usize,
+    limit: usize,
+}
+
+impl Counter {
+    fn set(&mut self, count: usize) {
+        self.count = count;
+    }
+
+    #[rustc_synchronizes_metadata]
+    fn set_unchecked(&mut self, count: usize) {
+        self.count = count;
+    }
+}
+
+impl MetaUpdate for Counter {
+    type Field = CounterField;
+
+    fn synchronize(&self, field: CounterField, old: usize, new: usize) -> bool {
+        match field {
+            CounterField::Count => old == self.count && new <= self.limit,
+        }
+    }
+}
+
+fn main() {
+    set_metadata_violation_hook(clamp);
+    let mut counter = Counter { count: 0, limit: 4 };
+    counter.set(4);
+    assert_eq!(counter.count, 4);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 0);
+
+    counter.set(5);
+    assert_eq!(counter.count, 4);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 1);
+
+    set_metadata_violation_hook(proceed);
+    counter.set(5);
+    assert_eq!(counter.count, 5);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 2);
+
+    counter.set_unchecked(7);
+    assert_eq!(counter.count, 7);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 2);
+}
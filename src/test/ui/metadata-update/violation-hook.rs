@@ -0,0 +1,55 @@
+// run-pass
+// Metadata violations are passed to the hook, whose answer decides the update where the update
+// can be made safely.
+
+#![feature(metadata_update, metadata_violation_hook)]
+
+use std::alloc::{set_metadata_violation_hook, take_metadata_violation_hook};
+use std::ptr::metadata_update::{MetadataViolation, ViolationAction};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+static LINE: AtomicUsize = AtomicUsize::new(0);
+
+fn clamp(violation: &MetadataViolation<'_>) -> ViolationAction {
+    assert_eq!(violation.type_name(), "Vec");
+    assert_eq!(violation.field(), "len");
+    assert_eq!(violation.old(), 0);
+    assert_eq!(violation.new(), 9);
+    assert_eq!(violation.location().file(), file!());
+    LINE.store(violation.location().line() as usize, Ordering::Relaxed);
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Clamp
+}
+
+fn proceed(_: &MetadataViolation<'_>) -> ViolationAction {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    ViolationAction::Continue
+}
+
+fn main() {
+    set_metadata_violation_hook(clamp);
+    let mut v: Vec<u8> = Vec::with_capacity(4);
+    let capacity = v.capacity();
+    let line = line!() + 1;
+    unsafe { v.set_len(9) };
+    assert_eq!(v.len(), capacity);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 1);
+    assert_eq!(LINE.load(Ordering::Relaxed), line as usize);
+    unsafe { v.set_len(0) };
+
+    // Lengths within the capacity are not violations.
+    unsafe { v.set_len(capacity) };
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 1);
+
+    // A length past the capacity is never let through, even if the hook asks for it.
+    set_metadata_violation_hook(proceed);
+    let mut v: Vec<u8> = Vec::with_capacity(4);
+    let capacity = v.capacity();
+    unsafe { v.set_len(capacity + 1) };
+    assert_eq!(v.len(), capacity);
+    assert_eq!(REPORTS.load(Ordering::Relaxed), 2);
+    unsafe { v.set_len(0) };
+
+    assert_eq!(take_metadata_violation_hook() as usize, proceed as usize);
+}
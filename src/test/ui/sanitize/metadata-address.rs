@@ -0,0 +1,9 @@
+// The metadata sanitizer composes with the address sanitizer.
+//
+// compile-flags: -Z sanitizer=address -Z sanitizer=metadata --target x86_64-unknown-linux-gnu
+// needs-llvm-components: x86
+// check-pass
+
+#![feature(no_core)]
+#![no_core]
+#![no_main]
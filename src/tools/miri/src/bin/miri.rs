@@ -377,6 +377,8 @@ fn main() {
             miri_config.provenance_mode = ProvenanceMode::Permissive;
         } else if arg == "-Zmiri-mute-stdout-stderr" {
             miri_config.mute_stdout_stderr = true;
+        } else if arg == "-Zmiri-protected-metadata" {
+            miri_config.protected_metadata = true;
         } else if arg == "-Zmiri-retag-fields" {
             miri_config.retag_fields = RetagFields::Yes;
         } else if let Some(retag_fields) = arg.strip_prefix("-Zmiri-retag-fields=") {
@@ -19,6 +19,9 @@ pub enum TerminationInfo {
         help: Option<String>,
         history: Option<TagHistory>,
     },
+    ProtectedMetadataUb {
+        msg: String,
+    },
     Int2PtrWithStrictProvenance,
     Deadlock,
     MultipleSymbolDefinitions {
@@ -47,6 +50,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                     "integer-to-pointer casts and `ptr::from_exposed_addr` are not supported with `-Zmiri-strict-provenance`"
                 ),
             StackedBorrowsUb { msg, .. } => write!(f, "{msg}"),
+            ProtectedMetadataUb { msg } => write!(f, "{msg}"),
             Deadlock => write!(f, "the evaluated program deadlocked"),
             MultipleSymbolDefinitions { link_name, .. } =>
                 write!(f, "multiple definitions of symbol `{link_name}`"),
@@ -164,7 +168,7 @@ pub fn report_error<'tcx, 'mir>(
                 Abort(_) => Some("abnormal termination"),
                 UnsupportedInIsolation(_) | Int2PtrWithStrictProvenance =>
                     Some("unsupported operation"),
-                StackedBorrowsUb { .. } => Some("Undefined Behavior"),
+                StackedBorrowsUb { .. } | ProtectedMetadataUb { .. } => Some("Undefined Behavior"),
                 Deadlock => Some("deadlock"),
                 MultipleSymbolDefinitions { .. } | SymbolShimClashing { .. } => None,
             };
@@ -193,6 +197,11 @@ pub fn report_error<'tcx, 'mir>(
                     }
                     helps
                 }
+                ProtectedMetadataUb { .. } =>
+                    vec![
+                        (None, format!("this indicates a bug in the program: it wrote the metadata of a `MetaUpdate` type without going through the methods of that type")),
+                        (None, format!("such a write bypasses `synchronize`, and faults where the metadata is write-protected natively")),
+                    ],
                 MultipleSymbolDefinitions { first, first_crate, second, second_crate, .. } =>
                     vec![
                         (Some(*first), format!("it's first defined here, in crate `{first_crate}`")),
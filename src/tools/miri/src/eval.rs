@@ -127,6 +127,8 @@ pub struct MiriConfig {
     pub report_progress: Option<u32>,
     /// Whether Stacked Borrows retagging should recurse into fields of datatypes.
     pub retag_fields: RetagFields,
+    /// Determines if writes to protected metadata outside of `MetaUpdate` windows are reported.
+    pub protected_metadata: bool,
     /// The location of a shared object file to load when calling external functions
     /// FIXME! consider allowing users to specify paths to multiple SO files, or to a directory
     pub external_so_file: Option<PathBuf>,
@@ -164,6 +166,7 @@ fn default() -> MiriConfig {
             preemption_rate: 0.01, // 1%
             report_progress: None,
             retag_fields: RetagFields::No,
+            protected_metadata: false,
             external_so_file: None,
             gc_interval: 10_000,
             num_cpus: 1,
@@ -62,6 +62,7 @@
 mod machine;
 mod mono_hash_map;
 mod operator;
+mod protected_metadata;
 mod range_map;
 mod shims;
 mod stacked_borrows;
@@ -105,6 +106,7 @@
 };
 pub use crate::mono_hash_map::MonoHashMap;
 pub use crate::operator::EvalContextExt as _;
+pub use crate::protected_metadata::EvalContextExt as _;
 pub use crate::range_map::RangeMap;
 pub use crate::stacked_borrows::{
     CallId, EvalContextExt as _, Item, Permission, RetagFields, SbTag, Stack, Stacks,
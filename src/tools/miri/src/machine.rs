@@ -258,11 +258,14 @@ pub struct AllocExtra {
     /// Weak memory emulation via the use of store buffers,
     ///  this is only added if it is enabled.
     pub weak_memory: Option<weak_memory::AllocExtra>,
+    /// The values with protected metadata in the allocation,
+    /// only tracked if protected metadata writes are checked.
+    pub protected_metadata: Option<RefCell<protected_metadata::AllocExtra>>,
 }
 
 impl VisitTags for AllocExtra {
     fn visit_tags(&self, visit: &mut dyn FnMut(SbTag)) {
-        let AllocExtra { stacked_borrows, data_race, weak_memory } = self;
+        let AllocExtra { stacked_borrows, data_race, weak_memory, protected_metadata: _ } = self;
 
         stacked_borrows.visit_tags(visit);
         data_race.visit_tags(visit);
@@ -355,6 +358,9 @@ pub struct MiriMachine<'mir, 'tcx> {
     /// Ptr-int-cast module global data.
     pub intptrcast: intptrcast::GlobalState,
 
+    /// The open `MetaUpdate` windows, if protected metadata writes are checked.
+    pub protected_metadata: Option<protected_metadata::GlobalState>,
+
     /// Environment variables set by `setenv`.
     /// Miri does not expose env vars from the host to the emulated program.
     pub(crate) env_vars: EnvVars<'tcx>,
@@ -486,6 +492,7 @@ pub(crate) fn new(config: &MiriConfig, layout_cx: LayoutCx<'tcx, TyCtxt<'tcx>>)
             stacked_borrows,
             data_race,
             intptrcast: RefCell::new(intptrcast::GlobalStateInner::new(config)),
+            protected_metadata: config.protected_metadata.then(Default::default),
             // `env_vars` depends on a full interpreter so we cannot properly initialize it yet.
             env_vars: EnvVars::default(),
             argc: None,
@@ -662,6 +669,7 @@ fn visit_tags(&self, visit: &mut dyn FnMut(SbTag)) {
             data_race,
             intptrcast,
             file_handler,
+            protected_metadata: _,
             tcx: _,
             isolated_op: _,
             validate: _,
@@ -914,12 +922,14 @@ fn adjust_allocation<'b>(
             )
         });
         let buffer_alloc = ecx.machine.weak_memory.then(weak_memory::AllocExtra::new_allocation);
+        let protected_metadata = ecx.machine.protected_metadata.is_some().then(Default::default);
         let alloc: Allocation<Provenance, Self::AllocExtra> = alloc.adjust_from_tcx(
             &ecx.tcx,
             AllocExtra {
                 stacked_borrows: stacks.map(RefCell::new),
                 data_race: race_alloc,
                 weak_memory: buffer_alloc,
+                protected_metadata,
             },
             |ptr| ecx.global_base_pointer(ptr),
         )?;
@@ -1025,6 +1035,9 @@ fn before_memory_read(
         if let Some(weak_memory) = &alloc_extra.weak_memory {
             weak_memory.memory_accessed(range, machine.data_race.as_ref().unwrap());
         }
+        if let Some(protected_metadata) = &alloc_extra.protected_metadata {
+            protected_metadata.borrow_mut().before_memory_read(range);
+        }
         Ok(())
     }
 
@@ -1057,6 +1070,14 @@ fn before_memory_write(
         if let Some(weak_memory) = &alloc_extra.weak_memory {
             weak_memory.memory_accessed(range, machine.data_race.as_ref().unwrap());
         }
+        if let Some(protected_metadata) = &alloc_extra.protected_metadata {
+            protected_metadata.get_mut().before_memory_write(
+                alloc_id,
+                range,
+                machine.protected_metadata.as_ref().unwrap(),
+                &machine.threads,
+            )?;
+        }
         Ok(())
     }
 
@@ -1099,6 +1120,9 @@ fn retag(
         kind: mir::RetagKind,
         place: &PlaceTy<'tcx, Provenance>,
     ) -> InterpResult<'tcx> {
+        if ecx.machine.protected_metadata.is_some() {
+            ecx.track_protected_metadata(place)?;
+        }
         if ecx.machine.stacked_borrows.is_some() { ecx.retag(kind, place) } else { Ok(()) }
     }
 
@@ -0,0 +1,230 @@
+//! Checking of protected metadata writes, enabled with `-Zmiri-protected-metadata`.
+//!
+//! The fields of a type that are `#[rustc_protected_metadata]`, like the length of a `Vec` or the
+//! head of a `VecDeque`, may only be written while a `MetaUpdate` window is open. The compiler
+//! opens one around every assignment to such a field, after `synchronize` vetted the new value.
+//! Unsafe code writing the field through a raw pointer bypasses both, and natively nothing catches
+//! that unless the field is on write-protected pages.
+//!
+//! The reference counts of an `Rc` are protected as well, although they are `Cell`s the attribute
+//! does not apply to: `Rc` updates them with the window open, but `Cell::set` is not an assignment
+//! the compiler could wrap. So the `strong` and `weak` fields of `RcBox`, found through its
+//! diagnostic item, are tracked like protected fields.
+//!
+//! Miri keeps track of the windows each thread has open, by watching the calls to the functions
+//! of `core` that open and close them, found through their diagnostic items, and to the
+//! `MetaUpdate` impls that override `enable_metadata_update` and `disable_metadata_update`, and of
+//! where the protected fields are in memory. A value is tracked from the moment a reference to it is
+//! retagged, which happens whenever one of its methods is called, until it is read as a whole,
+//! which is how it is moved out of its place, or its allocation is freed. A write to a protected
+//! field of a tracked value while the thread has no window open is Undefined Behavior, unless the
+//! write overwrites the whole value: that is a new value being moved in. Memory that held a value
+//! which was dropped in place must likewise be overwritten as a whole before it is reused for
+//! something else.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::ty;
+use rustc_span::Symbol;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+pub type GlobalState = RefCell<GlobalStateInner>;
+
+/// The windows open in the whole program.
+#[derive(Clone, Debug, Default)]
+pub struct GlobalStateInner {
+    /// How many windows each thread has open.
+    windows: FxHashMap<ThreadId, usize>,
+}
+
+impl GlobalStateInner {
+    fn is_open(&self, thread: ThreadId) -> bool {
+        self.windows.get(&thread).map_or(false, |&windows| windows > 0)
+    }
+}
+
+/// The tracked values of an allocation.
+#[derive(Clone, Debug, Default)]
+pub struct AllocExtra {
+    /// The values, by their offset in the allocation. Values nested in one another may share an
+    /// offset.
+    values: BTreeMap<Size, Vec<TrackedValue>>,
+    /// The size of the largest value ever tracked, which bounds how far before a write the
+    /// values it may overlap start.
+    max_size: Size,
+}
+
+#[derive(Clone, Debug)]
+struct TrackedValue {
+    ty: Symbol,
+    size: Size,
+    /// The protected fields, by their offset in the value, their size and their name.
+    fields: Vec<(Size, Size, Symbol)>,
+}
+
+impl AllocExtra {
+    fn track(&mut self, offset: Size, value: TrackedValue) {
+        self.max_size = self.max_size.max(value.size);
+        let values = self.values.entry(offset).or_default();
+        if !values.iter().any(|tracked| tracked.ty == value.ty) {
+            values.push(value);
+        }
+    }
+
+    /// Stops tracking the values `range` covers as a whole: they are moved out of their place.
+    pub fn before_memory_read(&mut self, range: AllocRange) {
+        if self.values.is_empty() {
+            return;
+        }
+        let offsets: Vec<Size> =
+            self.values.range(range.start..range.end()).map(|(&o, _)| o).collect();
+        for offset in offsets {
+            let values = self.values.get_mut(&offset).unwrap();
+            values.retain(|value| offset + value.size > range.end());
+            if values.is_empty() {
+                self.values.remove(&offset);
+            }
+        }
+    }
+
+    /// Reports a write to a protected field that `range` overlaps without covering its whole
+    /// value, unless the active thread has a window open.
+    pub fn before_memory_write<'tcx>(
+        &self,
+        alloc_id: AllocId,
+        range: AllocRange,
+        global: &GlobalState,
+        threads: &ThreadManager<'_, '_>,
+    ) -> InterpResult<'tcx> {
+        if self.values.is_empty() || global.borrow().is_open(threads.get_active_thread_id()) {
+            return Ok(());
+        }
+        let first = Size::from_bytes(range.start.bytes().saturating_sub(self.max_size.bytes()));
+        for (&offset, values) in self.values.range(first..range.end()) {
+            for value in values {
+                if range.start <= offset && offset + value.size <= range.end() {
+                    continue;
+                }
+                for &(field_offset, field_size, field) in &value.fields {
+                    let start = offset + field_offset;
+                    if start < range.end() && range.start < start + field_size {
+                        throw_machine_stop!(TerminationInfo::ProtectedMetadataUb {
+                            msg: format!(
+                                "writing to protected metadata `{}::{field}` at {alloc_id:?}[{:#x}] \
+                                outside of a `MetaUpdate` window",
+                                value.ty,
+                                start.bytes(),
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'mir, 'tcx: 'mir> EvalContextExt<'mir, 'tcx> for crate::MiriInterpCx<'mir, 'tcx> {}
+pub trait EvalContextExt<'mir, 'tcx: 'mir>: crate::MiriInterpCxExt<'mir, 'tcx> {
+    /// Opens or closes a window of the active thread if `instance` is one of the functions doing
+    /// so.
+    fn protected_metadata_call(&mut self, instance: ty::Instance<'tcx>) {
+        let this = self.eval_context_mut();
+        let tcx = this.tcx.tcx;
+        let def_id = instance.def_id();
+        let open = if tcx.is_diagnostic_item(Symbol::intern("enable_metadata_writes"), def_id) {
+            true
+        } else if tcx.is_diagnostic_item(Symbol::intern("disable_metadata_writes"), def_id) {
+            false
+        } else {
+            let Some(meta_update) = tcx.lang_items().meta_update_trait() else {
+                return;
+            };
+            let Some(item) = tcx.opt_associated_item(def_id) else {
+                return;
+            };
+            // Default methods are items of the trait, and open the window through the functions
+            // above. Overriding ones refer to them.
+            let Some(trait_item) = item.trait_item_def_id else {
+                return;
+            };
+            if tcx.trait_of_item(trait_item) != Some(meta_update) {
+                return;
+            }
+            match item.name.as_str() {
+                "enable_metadata_update" => true,
+                "disable_metadata_update" => false,
+                _ => return,
+            }
+        };
+        let thread = this.get_active_thread();
+        let mut global = this.machine.protected_metadata.as_ref().unwrap().borrow_mut();
+        let windows = global.windows.entry(thread).or_default();
+        if open {
+            *windows += 1;
+        } else {
+            *windows = windows.saturating_sub(1);
+        }
+    }
+
+    /// Starts tracking the value the reference in `place` points to, if it has protected fields.
+    fn track_protected_metadata(
+        &mut self,
+        place: &PlaceTy<'tcx, Provenance>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // Raw pointers may point to values that are not initialized yet.
+        let ty::Ref(_, pointee, _) = place.layout.ty.kind() else {
+            return Ok(());
+        };
+        let Some(adt) = pointee.ty_adt_def() else {
+            return Ok(());
+        };
+        if !adt.is_struct() {
+            return Ok(());
+        }
+        let protected: Vec<usize> =
+            if this.tcx.is_diagnostic_item(Symbol::intern("RcBox"), adt.did()) {
+                let variant = adt.non_enum_variant();
+                let counters = [Symbol::intern("strong"), Symbol::intern("weak")];
+                (0..variant.fields.len())
+                    .filter(|&index| counters.contains(&variant.fields[index].name))
+                    .collect()
+            } else {
+                this.tcx.protected_metadata_fields(adt.did()).iter().map(|f| f.index()).collect()
+            };
+        if protected.is_empty() {
+            return Ok(());
+        }
+        let value = this.deref_operand(&this.place_to_op(place)?)?;
+        let Ok((alloc_id, offset, _)) = this.ptr_try_get_alloc_id(value.ptr) else {
+            return Ok(());
+        };
+        let size =
+            this.size_and_align_of_mplace(&value)?.map_or(value.layout.size, |(size, _)| size);
+        // References to zero-sized values may point anywhere.
+        let (alloc_size, _, kind) = this.get_alloc_info(alloc_id);
+        if kind != AllocKind::LiveData || offset + size > alloc_size {
+            return Ok(());
+        }
+        let fields = protected
+            .into_iter()
+            .map(|index| {
+                (
+                    value.layout.fields.offset(index),
+                    value.layout.field(this, index).size,
+                    adt.non_enum_variant().fields[index].name,
+                )
+            })
+            .collect();
+        let tracked = TrackedValue { ty: Symbol::intern(&pointee.to_string()), size, fields };
+        if let Some(extra) = &this.get_alloc_extra(alloc_id)?.protected_metadata {
+            extra.borrow_mut().track(offset, tracked);
+        }
+        Ok(())
+    }
+}
@@ -586,6 +586,17 @@ fn emulate_foreign_item_by_name(
                     this.write_pointer(new_ptr, dest)
                 });
             }
+            "__rust_allocation_layout" => {
+                let [ptr] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+
+                return this.emulate_allocator(Symbol::intern("__rg_allocation_layout"), |this| {
+                    // The block is the whole allocation `ptr` points into, so its size is exact.
+                    let (alloc_id, _, _) = this.ptr_get_alloc_id(ptr)?;
+                    let (size, _, _) = this.get_alloc_info(alloc_id);
+                    this.write_scalar(Scalar::from_machine_usize(size.bytes(), this), dest)
+                });
+            }
 
             // C memory handling functions
             "memcmp" => {
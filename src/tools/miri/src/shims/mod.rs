@@ -47,6 +47,11 @@ fn find_mir_or_eval_fn(
             }
         }
 
+        // Keep track of the `MetaUpdate` windows, the function then runs as usual.
+        if this.machine.protected_metadata.is_some() {
+            this.protected_metadata_call(instance);
+        }
+
         // Try to see if we can do something about foreign items.
         if this.tcx.is_foreign_item(instance.def_id()) {
             // An external function call that does not have a MIR body. We either find MIR elsewhere
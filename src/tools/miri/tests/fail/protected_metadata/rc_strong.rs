@@ -0,0 +1,16 @@
+//@compile-flags: -Zmiri-protected-metadata
+
+use std::rc::Rc;
+
+fn main() {
+    let rc = Rc::new(7u32);
+    let clone = Rc::clone(&rc);
+    assert_eq!(Rc::strong_count(&rc), 2);
+    // The counters come before the value in the allocation, strong first: bump the strong count
+    // behind the back of `Rc`, like `Rc::increment_strong_count` would without its window.
+    let value = Rc::into_raw(clone);
+    unsafe {
+        let strong = value.cast::<usize>().sub(2);
+        *strong += 1; //~ ERROR: writing to protected metadata
+    }
+}
@@ -0,0 +1,13 @@
+//@compile-flags: -Zmiri-protected-metadata
+
+fn main() {
+    let mut v: Vec<i32> = Vec::with_capacity(8);
+    v.extend([1, 2, 3]);
+    assert_eq!(v.len(), 3);
+    // Find the length in the header, and grow it behind the back of `Vec`.
+    let header = &mut v as *mut Vec<i32> as *mut usize;
+    unsafe {
+        let len = (0..3).map(|i| header.add(i)).find(|&word| *word == 3).unwrap();
+        *len = 8; //~ ERROR: writing to protected metadata
+    }
+}
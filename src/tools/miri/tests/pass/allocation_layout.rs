@@ -0,0 +1,35 @@
+#![feature(allocator_api, allocation_layout)]
+// The global allocator reports the blocks it handed out, and collections rebuilt from them are
+// checked against those blocks.
+
+use std::alloc::{Allocator, Global, Layout};
+use std::mem::ManuallyDrop;
+
+fn global() {
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let ptr = Global.allocate(layout).unwrap().cast::<u8>();
+    let block = unsafe { Global.allocation_layout(ptr) }.unwrap();
+    assert_eq!(block.size(), 24);
+    assert_eq!(ptr.as_ptr() as usize % block.align(), 0);
+    unsafe { Global.deallocate(ptr, layout) };
+}
+
+fn vec() {
+    let mut v = ManuallyDrop::new(Vec::<u32>::with_capacity(10));
+    v.push(1);
+    let mut v = unsafe { Vec::from_raw_parts(v.as_mut_ptr(), 1, 10) };
+    v.extend(2..=10);
+    assert_eq!(v.len(), 10);
+}
+
+fn boxed() {
+    let b = Box::into_raw(Box::new([1u64; 4]));
+    let b = unsafe { Box::from_raw(b) };
+    assert_eq!(b.iter().sum::<u64>(), 4);
+}
+
+fn main() {
+    global();
+    vec();
+    boxed();
+}
@@ -0,0 +1,72 @@
+//@compile-flags: -Zmiri-protected-metadata
+#![feature(drain_filter)]
+// Writes to protected metadata through the methods of the collections, their iterators and smart
+// pointers, and moves of whole values, are all fine.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+fn vec() {
+    let mut v = Vec::new();
+    for i in 0..20 {
+        v.push(i);
+    }
+    v.truncate(10);
+    v.shrink_to_fit();
+    v.retain(|&i| i % 2 == 0);
+    assert_eq!(v, [0, 2, 4, 6, 8]);
+
+    let mut w = vec![1, 2, 3];
+    mem::swap(&mut v, &mut w);
+    w = mem::take(&mut v);
+    assert_eq!((v.len(), w.len()), (0, 3));
+
+    let s = String::from("protected");
+    assert_eq!(s + " metadata", "protected metadata");
+}
+
+fn rc() {
+    let rc = Rc::new(RefCell::new(vec![1]));
+    let clone = Rc::clone(&rc);
+    clone.borrow_mut().push(2);
+    let weak = Rc::downgrade(&rc);
+    drop(rc);
+    assert_eq!(Rc::strong_count(&clone), 1);
+    drop(clone);
+    assert!(weak.upgrade().is_none());
+
+    struct Node(Weak<Node>);
+    let cyclic = Rc::new_cyclic(|this| Node(this.clone()));
+    assert!(Rc::ptr_eq(&cyclic.0.upgrade().unwrap(), &cyclic));
+}
+
+fn iterators() {
+    let mut it = (0..10).collect::<Vec<_>>().into_iter();
+    assert_eq!(it.next(), Some(0));
+    assert_eq!(it.next_back(), Some(9));
+    assert_eq!(it.nth(2), Some(3));
+    assert_eq!(it.len(), 5);
+    let in_place: Vec<u32> = it.map(|i| i * 2).collect();
+    assert_eq!(in_place, [8, 10, 12, 14, 16]);
+
+    let mut v = (0..10).collect::<Vec<_>>();
+    assert_eq!(v.drain(2..4).collect::<Vec<_>>(), [2, 3]);
+    assert_eq!(v.splice(1..3, [20, 21, 22, 23]).collect::<Vec<_>>(), [1, 4]);
+    assert_eq!(v.drain_filter(|i| *i % 2 == 1).count(), 5);
+    assert_eq!(v, [0, 20, 22, 6, 8]);
+
+    let mut deque = (0..8).collect::<VecDeque<_>>();
+    deque.rotate_left(3);
+    let mut drain = deque.drain(1..6);
+    assert_eq!((drain.next(), drain.next_back()), (Some(4), Some(0)));
+    drop(drain);
+    assert_eq!(deque, [3, 1, 2]);
+}
+
+fn main() {
+    vec();
+    rc();
+    iterators();
+}